@@ -0,0 +1,171 @@
+//! Benchmarks del núcleo: decode, execute sobre mezclas de instrucciones
+//! típicas, el camino rápido de execute contra decode + execute_instr,
+//! frames completos y el dispatch de la MMU por regiones. Sirven
+//! para comparar cambios de rendimiento (tablas de dispatch, tablas de
+//! páginas, JIT) antes y después
+//!
+//! `cargo bench` o `cargo bench -- execute` para un solo grupo
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use gameboi::{Bus, Cpu, GameBoy};
+
+/// Programas de prueba, solo con opcodes que la CPU ya ejecuta
+const MIXES: [(&str, &[u8]); 4] = [
+    ("nop", &[0x00; 64]),
+
+    // LD r, r y LD r, n
+    ("loads", &[
+        0x41, 0x42, 0x43, 0x44, 0x45, 0x47, 0x48, 0x4A, 0x4B, 0x4C, 0x4D, 0x4F,
+        0x06, 0x12, 0x16, 0x34, 0x26, 0x56, 0x50, 0x51, 0x53, 0x54, 0x55, 0x57,
+        0x58, 0x59, 0x5A, 0x5C, 0x5D, 0x5F, 0x60, 0x61, 0x62, 0x63, 0x65, 0x67,
+        0x68, 0x69, 0x6A, 0x6B, 0x6C, 0x6F, 0x78, 0x79, 0x7A, 0x7B, 0x7C, 0x7D,
+    ]),
+
+    // ADD, ADC, SUB, SBC, AND, OR, CP, INC y DEC sobre registros
+    ("alu", &[
+        0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x87, 0x88, 0x89, 0x8A, 0x8B, 0x8C,
+        0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x98, 0x99, 0x9A, 0x9B, 0x9C, 0x9D,
+        0xA0, 0xA1, 0xA2, 0xA3, 0xB0, 0xB1, 0xB2, 0xB3, 0xB8, 0xB9, 0xBA, 0xBB,
+        0x04, 0x0C, 0x14, 0x1C, 0x05, 0x0D, 0x15, 0x1D, 0x03, 0x13, 0x0B, 0x1B,
+    ]),
+
+    // Parecido a un bucle de un juego: cargas, aritmética y 16 bits
+    ("mixed", &[
+        0x06, 0x10, 0x78, 0x80, 0x47, 0x0C, 0x79, 0xA0, 0x4F, 0x21, 0x00, 0xC0,
+        0x23, 0x09, 0x7C, 0xB5, 0x16, 0x00, 0x5F, 0x83, 0x1D, 0x13, 0x7A, 0xB8,
+    ]),
+];
+
+/// Número de instrucciones de un programa
+fn instr_count(program: &[u8]) -> u64 {
+    let mut cpu = Cpu::new();
+    let mut count = 0;
+    while (cpu.pc() as usize) < program.len() {
+        cpu.decode(program).unwrap();
+        count += 1;
+    }
+    count
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, program) in MIXES {
+        let count = instr_count(program);
+        group.throughput(Throughput::Elements(count));
+        group.bench_function(name, |b| {
+            let mut cpu = Cpu::new();
+            b.iter(|| {
+                cpu.set_pc(0);
+                for _ in 0..count {
+                    let _ = black_box(cpu.decode(black_box(program)));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn execute(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute");
+    for (name, program) in MIXES {
+        let count = instr_count(program);
+        let mut memory = vec![0; 0x10000];
+        memory[..program.len()].copy_from_slice(program);
+        group.throughput(Throughput::Elements(count));
+        group.bench_function(name, |b| {
+            let mut cpu = Cpu::new();
+            b.iter(|| {
+                cpu.set_pc(0);
+                for _ in 0..count {
+                    cpu.execute(memory.as_mut_slice()).unwrap();
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+/// `execute` va por el camino rápido sin construir `Instr`, aquí se compara
+/// con `decode` y `execute_instr`, que es lo que hace `GameBoy::step` para
+/// poder dar la instrucción a las herramientas
+fn dispatch(c: &mut Criterion) {
+    let (_, program) = MIXES[3];
+    let count = instr_count(program);
+    let mut memory = vec![0; 0x10000];
+    memory[..program.len()].copy_from_slice(program);
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(count));
+    group.bench_function("fast", |b| {
+        let mut cpu = Cpu::new();
+        b.iter(|| {
+            cpu.set_pc(0);
+            for _ in 0..count {
+                cpu.execute(memory.as_mut_slice()).unwrap();
+            }
+        });
+    });
+    group.bench_function("decoded", |b| {
+        let mut cpu = Cpu::new();
+        b.iter(|| {
+            cpu.set_pc(0);
+            for _ in 0..count {
+                let instr = cpu.decode(memory.as_slice()).unwrap();
+                black_box(cpu.execute_instr(instr, memory.as_mut_slice()).unwrap());
+            }
+        });
+    });
+    group.finish();
+}
+
+fn frame(c: &mut Criterion) {
+    // Una ROM de NOPs, mide el coste fijo de la MMU, el reloj y el frame
+    let rom = vec![0; 0x8000];
+    let mut group = c.benchmark_group("frame");
+    group.throughput(Throughput::Elements(1));
+    for (name, rendering) in [("rendering", true), ("headless", false)] {
+        group.bench_function(name, |b| {
+            b.iter_batched_ref(
+                || GameBoy::builder().rom(rom.clone()).rendering(rendering).build().unwrap(),
+                |gb| gb.step_frame().unwrap(),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn mmu(c: &mut Criterion) {
+    let mut gb = GameBoy::new();
+    gb.load_rom(&vec![0; 0x8000]).unwrap();
+    let mmu = gb.mmu_mut();
+
+    // Una dirección por región del mapa de memoria
+    let regions = [("rom", 0x0150), ("vram", 0x8800), ("wram", 0xC100), ("io", 0xFF44),
+        ("hram", 0xFF90)];
+    let mut group = c.benchmark_group("mmu");
+    group.throughput(Throughput::Elements(256));
+    for (name, addr) in regions {
+        group.bench_function(format!("read_{name}"), |b| {
+            b.iter(|| {
+                for offset in 0..256u16 {
+                    black_box(mmu.read(black_box(addr + offset % 16)));
+                }
+            });
+        });
+    }
+    for (name, addr) in [("vram", 0x8800), ("wram", 0xC100), ("hram", 0xFF90)] {
+        group.bench_function(format!("write_{name}"), |b| {
+            b.iter(|| {
+                for offset in 0..256u16 {
+                    mmu.write(black_box(addr + offset % 16), offset as u8);
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, execute, dispatch, frame, mmu);
+criterion_main!(benches);
@@ -0,0 +1,20 @@
+fn main() {
+    // Scaffolding de UniFFI que incluye src/lib.rs
+    #[cfg(feature = "mobile")]
+    uniffi::generate_scaffolding("src/gameboi.udl").unwrap();
+
+    // DAT que se incluye para `identify_rom`, vacío si no se pasa ninguno
+    #[cfg(feature = "romdb")]
+    {
+        println!("cargo:rerun-if-env-changed=GAMEBOI_ROMDB");
+        let dat = match std::env::var("GAMEBOI_ROMDB") {
+            Ok(path) => {
+                println!("cargo:rerun-if-changed={path}");
+                std::fs::read_to_string(&path).unwrap()
+            },
+            Err(_) => String::new(),
+        };
+        let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("romdb.dat");
+        std::fs::write(out, dat).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+//! Frontend mínimo con minifb, solo vídeo y teclado. No necesita SDL
+//! instalado, pensado para probar a mano cambios en el renderizado
+//!
+//! `cargo run --release --example minifb --features minifb -- <rom>`
+//!
+//! Controles: flechas, Z (A), X (B), Enter (Start), Retroceso (Select) y
+//! Escape para salir
+
+use std::sync::{Arc, Mutex};
+
+use gameboi::{Button, Frame, GameBoy, Model, VideoSink, SCREEN_HEIGHT, SCREEN_WIDTH};
+use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
+
+const KEYS: [(Key, Button); 8] = [
+    (Key::Right, Button::Right),
+    (Key::Left, Button::Left),
+    (Key::Up, Button::Up),
+    (Key::Down, Button::Down),
+    (Key::Z, Button::A),
+    (Key::X, Button::B),
+    (Key::Enter, Button::Start),
+    (Key::Backspace, Button::Select),
+];
+
+/// Último frame terminado en el formato de minifb, un `u32` 0RGB por píxel
+#[derive(Clone)]
+struct SharedBuffer(Arc<Mutex<Vec<u32>>>);
+
+impl VideoSink for SharedBuffer {
+    fn present(&mut self, frame: &Frame) {
+        let mut buffer = self.0.lock().unwrap();
+        for (out, pixel) in buffer.iter_mut().zip(frame.pixels().chunks_exact(4)) {
+            *out = u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]]);
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    let path = std::env::args().nth(1).ok_or("uso: minifb <rom>")?;
+    let rom = std::fs::read(&path).map_err(|err| format!("{path}: {err}"))?;
+
+    let buffer = SharedBuffer(Arc::new(Mutex::new(vec![0; SCREEN_WIDTH * SCREEN_HEIGHT])));
+    let mut gb = GameBoy::builder()
+        .model(Model::preferred_for(&rom))
+        .rom(rom)
+        .video_sink(buffer.clone())
+        .build()
+        .map_err(|err| format!("{path}: {err}"))?;
+
+    let options = WindowOptions {
+        resize: true,
+        scale: Scale::X4,
+        scale_mode: ScaleMode::AspectRatioStretch,
+        ..WindowOptions::default()
+    };
+    let mut window = Window::new("gameboi", SCREEN_WIDTH, SCREEN_HEIGHT, options)
+        .map_err(|err| err.to_string())?;
+    // El ritmo lo marca el limitador de la Game Boy
+    window.set_target_fps(0);
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        for (key, button) in KEYS {
+            let pressed = window.is_key_down(key);
+            if gb.joypad().is_pressed(button) != pressed {
+                gb.set_button(button, pressed);
+            }
+        }
+
+        if gb.run_frame_realtime().map_err(|err| err.to_string())? {
+            window.update_with_buffer(&buffer.0.lock().unwrap(), SCREEN_WIDTH, SCREEN_HEIGHT)
+                .map_err(|err| err.to_string())?;
+        } else {
+            window.update();
+        }
+    }
+
+    Ok(())
+}
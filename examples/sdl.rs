@@ -0,0 +1,209 @@
+//! Frontend de referencia con SDL2: ventana escalada, teclado y audio. Sirve
+//! para jugar y como ejemplo de cómo conectar `VideoSink` y `AudioSink`
+//!
+//! `cargo run --release --example sdl --features sdl -- <rom> [escala] [controles.toml]`
+//!
+//! Controles por defecto (ver `InputMap`): flechas, Z (A), X (B), Enter
+//! (Start) y Retroceso (Select). Los mandos se pueden conectar y desconectar
+//! en cualquier momento: cruceta o stick izquierdo, el botón derecho (B de
+//! Xbox) es A y el de abajo es B, como en la Game Boy. Tab mantenido activa
+//! el modo turbo y Escape sale
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use gameboi::{AudioSink, Frame, GameBoy, InputMap, Model, VideoSink, SCREEN_HEIGHT, SCREEN_WIDTH};
+use gilrs::{Axis, EventType, Gilrs};
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+
+/// Escala de la ventana si no se pasa otra
+const DEFAULT_SCALE: u32 = 3;
+
+/// Frecuencia de muestreo que se le pide a SDL
+const SAMPLE_RATE: i32 = 48000;
+
+/// Inclinación del stick a partir de la que cuenta como pulsar la cruceta
+const STICK_DEADZONE: f32 = 0.5;
+
+/// Muestras que se guardan como mucho si SDL no las consume, con el modo
+/// turbo se generan más rápido de lo que se reproducen
+const MAX_QUEUED_SAMPLES: usize = 1 << 14;
+
+/// Último frame terminado. Los sinks tienen que ser `Send` y las texturas de
+/// SDL no lo son, así que el sink copia los píxeles y el bucle principal los
+/// sube a la textura
+#[derive(Clone)]
+struct SharedFrame(Arc<Mutex<Frame>>);
+
+impl VideoSink for SharedFrame {
+    fn present(&mut self, frame: &Frame) {
+        self.0.lock().unwrap().clone_from(frame);
+    }
+}
+
+/// Cola de muestras entre la emulación y el callback de audio de SDL
+#[derive(Clone, Default)]
+struct SharedAudio(Arc<Mutex<VecDeque<i16>>>);
+
+impl AudioSink for SharedAudio {
+    fn queue_samples(&mut self, samples: &[i16]) {
+        let mut queue = self.0.lock().unwrap();
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(MAX_QUEUED_SAMPLES);
+        queue.drain(..excess);
+    }
+
+    fn buffered(&self) -> Option<usize> {
+        Some(self.0.lock().unwrap().len())
+    }
+}
+
+impl AudioCallback for SharedAudio {
+    type Channel = i16;
+
+    /// Si no hay muestras suficientes se rellena con silencio
+    fn callback(&mut self, out: &mut [i16]) {
+        let mut queue = self.0.lock().unwrap();
+        for sample in out {
+            *sample = queue.pop_front().unwrap_or(0);
+        }
+    }
+}
+
+/// Pasar un eje del stick a las dos direcciones, con los nombres que usa
+/// `InputMap` para el stick
+fn stick(input: &mut InputMap, value: f32, negative: &str, positive: &str) {
+    input.set_pad_button(negative, value < -STICK_DEADZONE);
+    input.set_pad_button(positive, value > STICK_DEADZONE);
+}
+
+/// Leer los eventos de los mandos, los botones se buscan en el mapa por su
+/// nombre en gilrs. Al desconectarse uno se sueltan todos los botones para
+/// que no se quede ninguno pulsado
+fn poll_gamepads(gilrs: &mut Gilrs, input: &mut InputMap) {
+    while let Some(event) = gilrs.next_event() {
+        match event.event {
+            EventType::ButtonPressed(button, _) => {
+                input.set_pad_button(&format!("{button:?}"), true);
+            },
+            EventType::ButtonReleased(button, _) => {
+                input.set_pad_button(&format!("{button:?}"), false);
+            },
+            EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                stick(input, value, "LeftStickLeft", "LeftStickRight");
+            },
+            // En gilrs el eje Y es positivo hacia arriba
+            EventType::AxisChanged(Axis::LeftStickY, value, _) => {
+                stick(input, value, "LeftStickDown", "LeftStickUp");
+            },
+            EventType::Connected => {
+                println!("Mando conectado: {}", gilrs.gamepad(event.id).name());
+            },
+            EventType::Disconnected => {
+                println!("Mando desconectado: {}", gilrs.gamepad(event.id).name());
+                input.release_pad();
+            },
+            _ => {},
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("uso: sdl <rom> [escala] [controles.toml]")?;
+    let scale = match args.next() {
+        Some(scale) => scale.parse().map_err(|_| format!("escala inválida {scale}"))?,
+        None => DEFAULT_SCALE,
+    };
+    let mut input = match args.next() {
+        Some(config) => std::fs::read_to_string(&config)
+            .map_err(|err| err.to_string())
+            .and_then(|text| InputMap::from_toml(&text).map_err(|err| err.to_string()))
+            .map_err(|err| format!("{config}: {err}"))?,
+        None => InputMap::default(),
+    };
+    let rom = std::fs::read(&path).map_err(|err| format!("{path}: {err}"))?;
+
+    let frame = SharedFrame(Arc::new(Mutex::new(Frame::new())));
+    let audio = SharedAudio::default();
+    let mut gb = GameBoy::builder()
+        .model(Model::preferred_for(&rom))
+        .rom(rom)
+        .video_sink(frame.clone())
+        .audio_sink(audio.clone())
+        .build()
+        .map_err(|err| format!("{path}: {err}"))?;
+
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let window = video
+        .window("gameboi", SCREEN_WIDTH as u32 * scale, SCREEN_HEIGHT as u32 * scale)
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|err| err.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|err| err.to_string())?;
+    // Al redimensionar se mantiene la proporción con bandas negras
+    canvas.set_logical_size(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|err| err.to_string())?;
+    let creator = canvas.texture_creator();
+    let mut texture = creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|err| err.to_string())?;
+
+    let desired = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(2),
+        samples: Some(1024),
+    };
+    let device = sdl.audio()?.open_playback(None, &desired, |_| audio.clone())?;
+    device.resume();
+
+    // Sin backend de mandos en la plataforma se sigue solo con teclado
+    let mut gilrs = Gilrs::new()
+        .inspect_err(|err| eprintln!("Sin soporte de mandos: {err}"))
+        .ok();
+    for (_, gamepad) in gilrs.iter().flat_map(Gilrs::gamepads) {
+        println!("Mando conectado: {}", gamepad.name());
+    }
+
+    let mut events = sdl.event_pump()?;
+    'running: loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    break 'running;
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    gb.set_fast_forward(true);
+                },
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => gb.set_fast_forward(false),
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
+                    input.set_key(&key.name(), true);
+                },
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    input.set_key(&key.name(), false);
+                },
+                _ => {},
+            }
+        }
+        if let Some(gilrs) = gilrs.as_mut() {
+            poll_gamepads(gilrs, &mut input);
+        }
+        input.apply(&mut gb);
+
+        if !gb.run_frame_realtime().map_err(|err| err.to_string())? {
+            continue;
+        }
+        texture.update(None, frame.0.lock().unwrap().pixels(), SCREEN_WIDTH * 4)
+            .map_err(|err| err.to_string())?;
+        canvas.clear();
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+//! Decodificar bytes arbitrarios nunca debe hacer panic y la longitud de una
+//! instrucción solo depende de sus bytes, no de dónde esté en memoria
+//!
+//! `cargo +nightly fuzz run decode`
+#![no_main]
+
+use gameboi::{Bus, Cpu};
+use libfuzzer_sys::fuzz_target;
+
+/// Longitud máxima de una instrucción del SM83 (opcode y dos inmediatos)
+const MAX_LEN: u16 = 3;
+
+/// Decodificar en `addr` con `bytes` a partir de ahí, el resto lee 0xFF
+fn decode_at(bytes: &[u8], addr: u16) -> Option<(gameboi::Instr, u16)> {
+    let mut memory = vec![0xFF; 0x10000];
+    for (i, byte) in bytes.iter().take(MAX_LEN as usize).enumerate() {
+        memory.as_mut_slice().write(addr.wrapping_add(i as u16), *byte);
+    }
+    let mut cpu = Cpu::new();
+    cpu.set_pc(addr);
+    let instr = cpu.decode(memory.as_slice()).ok()?;
+    Some((instr, cpu.pc().wrapping_sub(addr)))
+}
+
+fuzz_target!(|bytes: &[u8]| {
+    let Some((instr, len)) = decode_at(bytes, 0x0100) else {
+        return;
+    };
+    assert!((1..=MAX_LEN).contains(&len), "{instr:?} ocupa {len} bytes");
+
+    // Las instrucciones no dependen de la dirección salvo por los saltos
+    // relativos, que no cambian su codificación
+    let (moved, moved_len) = decode_at(bytes, 0xC123).expect("Decodifica en otra dirección");
+    assert_eq!(len, moved_len);
+    assert_eq!(instr, moved);
+});
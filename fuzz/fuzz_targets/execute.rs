@@ -0,0 +1,25 @@
+//! Ejecutar un programa arbitrario sobre 64 KiB de RAM plana durante unas
+//! pocas instrucciones nunca debe hacer panic
+//!
+//! `cargo +nightly fuzz run execute`
+#![no_main]
+
+use gameboi::Cpu;
+use libfuzzer_sys::fuzz_target;
+
+/// Instrucciones a ejecutar como mucho, suficientes para recorrer el
+/// programa sin que los bucles infinitos frenen el fuzzer
+const MAX_STEPS: usize = 256;
+
+fuzz_target!(|program: &[u8]| {
+    let mut memory = vec![0; 0x10000];
+    let len = program.len().min(memory.len());
+    memory[..len].copy_from_slice(&program[..len]);
+
+    let mut cpu = Cpu::new();
+    for _ in 0..MAX_STEPS {
+        if cpu.is_stopped() || cpu.execute(memory.as_mut_slice()).is_err() {
+            break;
+        }
+    }
+});
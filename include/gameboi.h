@@ -0,0 +1,103 @@
+/*
+ * API de C de gameboi, generada con cbindgen a partir de src/ffi.rs.
+ * Todas las funciones aceptan un puntero nulo a la GameBoy y entonces no
+ * hacen nada y devuelven false o 0.
+ */
+
+#ifndef GAMEBOI_H
+#define GAMEBOI_H
+
+/* No editar a mano, se genera con cbindgen */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Opaca, se crea con gameboi_create y se libera con gameboi_destroy
+typedef struct GameBoy GameBoy;
+
+// Ancho del framebuffer en píxeles
+#define GAMEBOI_SCREEN_WIDTH 160
+
+// Alto del framebuffer en píxeles
+#define GAMEBOI_SCREEN_HEIGHT 144
+
+// Botones para `gameboi_set_button`
+#define GAMEBOI_BUTTON_RIGHT 0
+
+#define GAMEBOI_BUTTON_LEFT 1
+
+#define GAMEBOI_BUTTON_UP 2
+
+#define GAMEBOI_BUTTON_DOWN 3
+
+#define GAMEBOI_BUTTON_A 4
+
+#define GAMEBOI_BUTTON_B 5
+
+#define GAMEBOI_BUTTON_SELECT 6
+
+#define GAMEBOI_BUTTON_START 7
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Crear una `GameBoy` sin ROM, se libera con `gameboi_destroy`
+GameBoy *gameboi_create(void);
+
+// Liberar una `GameBoy` de `gameboi_create`
+//
+// # Safety
+// `gb` debe venir de `gameboi_create` y no se puede volver a usar
+void gameboi_destroy(GameBoy *gb);
+
+// Cargar una ROM de `len` bytes, se copia. `false` si no es válida
+//
+// # Safety
+// `rom` debe apuntar a `len` bytes legibles
+bool gameboi_load_rom(GameBoy *gb, const uint8_t *rom, size_t len);
+
+// Emular un frame completo, `false` si la CPU encontró un opcode inválido
+//
+// # Safety
+// `gb` debe ser nulo o venir de `gameboi_create`
+bool gameboi_run_frame(GameBoy *gb);
+
+// Píxeles RGBA del último frame, `GAMEBOI_SCREEN_WIDTH` por
+// `GAMEBOI_SCREEN_HEIGHT` de 4 bytes por fila. El puntero es válido hasta
+// la siguiente llamada que reciba la misma `GameBoy`
+//
+// # Safety
+// `gb` debe ser nulo o venir de `gameboi_create`
+const uint8_t *gameboi_framebuffer(const GameBoy *gb);
+
+// Pulsar o soltar uno de los `GAMEBOI_BUTTON_*`, `false` si no existe
+//
+// # Safety
+// `gb` debe ser nulo o venir de `gameboi_create`
+bool gameboi_set_button(GameBoy *gb, uint8_t button, bool pressed);
+
+// Guardar el estado en `buffer` y devolver su tamaño. Si no cabe en
+// `capacity` bytes no se escribe nada, se llama otra vez con un buffer del
+// tamaño devuelto
+//
+// # Safety
+// `buffer` debe apuntar a `capacity` bytes escribibles o ser nulo con
+// `capacity` 0
+size_t gameboi_save_state(const GameBoy *gb, uint8_t *buffer, size_t capacity);
+
+// Cargar un estado de `gameboi_save_state`, `false` si no es válido o es
+// de otra ROM, y entonces el estado no cambia
+//
+// # Safety
+// `state` debe apuntar a `len` bytes legibles
+bool gameboi_load_state(GameBoy *gb, const uint8_t *state, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GAMEBOI_H */
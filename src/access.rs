@@ -0,0 +1,172 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::mmu::Bus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Acceso a memoria hecho por una instrucción
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemAccess {
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub value: u8,
+
+    /// Dirección de la instrucción que hizo el acceso
+    pub pc: u16,
+
+    /// Reloj de los periféricos en T-cycles al empezar la instrucción
+    pub cycle: u64,
+}
+
+/// Últimos accesos a memoria de la CPU, se conecta con
+/// `GameBoy::set_access_log`. Es mucho más barato que una traza completa y
+/// sirve para saber después de un breakpoint o un crash quién escribió un
+/// byte. Las lecturas del opcode y sus inmediatos no se apuntan
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    capacity: usize,
+    entries: VecDeque<MemAccess>,
+}
+
+impl AccessLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Accesos guardados del más antiguo al más reciente
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &MemAccess> {
+        self.entries.iter()
+    }
+
+    /// Escrituras en `addr` que siguen en el log, de la más reciente a la
+    /// más antigua
+    pub fn writes_to(&self, addr: u16) -> impl Iterator<Item = &MemAccess> {
+        self.entries.iter().rev()
+            .filter(move |access| access.kind == AccessKind::Write && access.addr == addr)
+    }
+
+    /// Última escritura en `addr`, si no ha salido ya del log
+    pub fn last_write(&self, addr: u16) -> Option<&MemAccess> {
+        self.writes_to(addr).next()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Volcar el log como texto, un acceso por línea del más antiguo al más
+    /// reciente, como `[123456] 0150: W C000 = 2A`
+    pub fn dump(&self, mut writer: impl Write) -> io::Result<()> {
+        for access in &self.entries {
+            let kind = match access.kind {
+                AccessKind::Read => 'R',
+                AccessKind::Write => 'W',
+            };
+            writeln!(writer, "[{}] {:04X}: {kind} {:04X} = {:02X}",
+                access.cycle, access.pc, access.addr, access.value)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record(&mut self, access: MemAccess) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(access);
+    }
+}
+
+/// Bus que apunta todo lo que pasa por él durante una instrucción, para
+/// el `AccessLog` y los hooks de escritura. `Bus::read` solo recibe `&self`,
+/// por eso los accesos van en un `RefCell`
+pub(crate) struct LoggedBus<'a, B: Bus + ?Sized> {
+    bus: &'a mut B,
+    accesses: RefCell<Vec<MemAccess>>,
+    pc: u16,
+    cycle: u64,
+}
+
+impl<'a, B: Bus + ?Sized> LoggedBus<'a, B> {
+    pub fn new(bus: &'a mut B, pc: u16, cycle: u64) -> Self {
+        Self { bus, accesses: RefCell::new(Vec::new()), pc, cycle }
+    }
+
+    /// Accesos hechos en el orden en el que ocurrieron
+    pub fn into_accesses(self) -> Vec<MemAccess> {
+        self.accesses.into_inner()
+    }
+
+    fn record(&self, kind: AccessKind, addr: u16, value: u8) {
+        self.accesses.borrow_mut()
+            .push(MemAccess { kind, addr, value, pc: self.pc, cycle: self.cycle });
+    }
+}
+
+impl<B: Bus + ?Sized> Bus for LoggedBus<'_, B> {
+    fn read(&self, addr: u16) -> u8 {
+        let value = self.bus.read(addr);
+        self.record(AccessKind::Read, addr, value);
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.bus.write(addr, value);
+        self.record(AccessKind::Write, addr, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_and_last_write() {
+        let mut memory = vec![0; 0x10000];
+        let mut log = AccessLog::new(3);
+        let mut bus = LoggedBus::new(memory.as_mut_slice(), 0x0150, 100);
+        bus.write(0xC000, 0x01);
+        bus.write(0xC001, 0x02);
+        bus.into_accesses().into_iter().for_each(|access| log.record(access));
+
+        let mut bus = LoggedBus::new(memory.as_mut_slice(), 0x0160, 120);
+        bus.write(0xC000, 0x2A);
+        assert_eq!(bus.read(0xC001), 0x02);
+        bus.into_accesses().into_iter().for_each(|access| log.record(access));
+
+        // El primer acceso ya salió del log
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.entries().next().unwrap().addr, 0xC001);
+        let last = log.last_write(0xC000).unwrap();
+        assert_eq!((last.pc, last.value, last.cycle), (0x0160, 0x2A, 120));
+        assert_eq!(log.writes_to(0xC000).count(), 1);
+        assert_eq!(log.last_write(0xC002), None);
+
+        let mut dump = Vec::new();
+        log.dump(&mut dump).unwrap();
+        assert_eq!(String::from_utf8(dump).unwrap().lines().last(), Some("[120] 0160: R C001 = 02"));
+    }
+}
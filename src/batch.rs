@@ -0,0 +1,58 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use crate::gameboy::GameBoy;
+
+/// Ejecutar `run` sobre cada `GameBoy` repartiéndolas entre `threads` hilos,
+/// 0 usa tantos hilos como núcleos tenga la máquina. Los resultados se
+/// devuelven en el mismo orden que `instances`, así se puede pasar un
+/// conjunto de ROMs por una suite de compatibilidad en paralelo
+pub fn run_batch<T, F>(instances: Vec<GameBoy>, threads: usize, run: F)
+    -> Vec<T>
+where
+    T: Send,
+    F: Fn(GameBoy) -> T + Sync,
+{
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    }.min(instances.len().max(1));
+
+    let count = instances.len();
+    let queue = Mutex::new(instances.into_iter().enumerate());
+    let results = Mutex::new((0..count).map(|_| None).collect::<Vec<_>>());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                // Se suelta el lock antes de ejecutar para no serializar
+                let Some((index, gb)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = run(gb);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results.into_inner().unwrap()
+        .into_iter()
+        .map(|result| result.expect("Todos los trabajos terminan"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_keeps_order() {
+        let instances = (0..8).map(GameBoy::with_seed).collect();
+        let seeds = run_batch(instances, 3, |mut gb| {
+            gb.run_cycles(1000);
+            gb.seed().unwrap()
+        });
+        assert_eq!(seeds, (0..8).collect::<Vec<_>>());
+        assert!(run_batch(Vec::new(), 0, |gb| gb.cycles()).is_empty());
+    }
+}
@@ -0,0 +1,6 @@
+//! Generador de los bindings de Kotlin y Swift de `src/gameboi.udl`, con la
+//! misma versión de UniFFI que el crate
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
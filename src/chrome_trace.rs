@@ -0,0 +1,142 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::gameboy::GameBoy;
+use crate::hooks::HookId;
+use crate::limiter::CPU_FREQUENCY;
+
+/// Nombres de los bits de IF en orden
+const INTERRUPT_NAMES: [&str; 5] = ["VBlank", "STAT", "Timer", "Serial", "Joypad"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    /// Frame entre dos instantes en T-cycles
+    Frame { frame: u64, start: u64, end: u64 },
+
+    /// Interrupción solicitada, por su bit en IF
+    Interrupt { bit: u8, pc: u16, at: u64 },
+}
+
+#[derive(Debug, Default)]
+struct Timeline {
+    events: Vec<Event>,
+
+    /// Final del último frame, donde empieza el siguiente
+    frame_start: u64,
+}
+
+/// Línea de tiempo en el formato de trace events de Chrome, se abre con
+/// Perfetto o `about://tracing`. Se conecta a una `GameBoy` con `attach` y
+/// apunta los frames y las interrupciones con la marca de tiempo exacta en
+/// T-cycles (convertida a microsegundos del hardware real, no del host).
+/// Como `RingTrace`, las copias comparten los eventos
+// TODO: Añadir los modos de la PPU y las transferencias DMA cuando existan
+#[derive(Debug, Clone, Default)]
+pub struct ChromeTrace {
+    timeline: Arc<Mutex<Timeline>>,
+}
+
+impl ChromeTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Empezar a apuntar los eventos de `gb`, devuelve los hooks registrados
+    /// para poder pararlo con `GameBoy::remove_hook`
+    pub fn attach(&self, gb: &mut GameBoy) -> [HookId; 2] {
+        self.timeline.lock().unwrap().frame_start = gb.mmu().now();
+
+        let timeline = self.timeline.clone();
+        let frames = gb.on_frame(move |event| {
+            let mut timeline = timeline.lock().unwrap();
+            let start = timeline.frame_start;
+            timeline.events.push(Event::Frame { frame: event.frame, start, end: event.cycle });
+            timeline.frame_start = event.cycle;
+        });
+        let timeline = self.timeline.clone();
+        let interrupts = gb.on_interrupt(move |event| {
+            let bit = event.mask.trailing_zeros() as u8;
+            timeline.lock().unwrap()
+                .events.push(Event::Interrupt { bit, pc: event.pc, at: event.cycle });
+        });
+        [frames, interrupts]
+    }
+
+    /// Número de eventos apuntados
+    pub fn len(&self) -> usize {
+        self.timeline.lock().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.timeline.lock().unwrap().events.clear();
+    }
+
+    /// Escribir la traza como JSON
+    pub fn write_json(&self, mut writer: impl Write) -> io::Result<()> {
+        let timeline = self.timeline.lock().unwrap();
+        writeln!(writer, "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[")?;
+        writeln!(writer, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\
+            \"args\":{{\"name\":\"Frames\"}}}},")?;
+        write!(writer, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":2,\
+            \"args\":{{\"name\":\"Interrupciones\"}}}}")?;
+        for event in &timeline.events {
+            writeln!(writer, ",")?;
+            match *event {
+                Event::Frame { frame, start, end } => write!(writer,
+                    "{{\"name\":\"Frame {frame}\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\
+                    \"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"cycles\":{}}}}}",
+                    micros(start), micros(end - start), end - start)?,
+                Event::Interrupt { bit, pc, at } => write!(writer,
+                    "{{\"name\":\"{}\",\"ph\":\"i\",\"s\":\"t\",\"pid\":1,\"tid\":2,\
+                    \"ts\":{:.3},\"args\":{{\"pc\":\"{pc:04X}\"}}}}",
+                    INTERRUPT_NAMES.get(bit as usize).unwrap_or(&"?"), micros(at))?,
+            }
+        }
+        writeln!(writer, "\n]}}")
+    }
+
+    /// Crear (o vaciar) el fichero en `path` y escribir en él la traza
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_json(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// Microsegundos de hardware real que duran `cycles` T-cycles
+#[inline]
+fn micros(cycles: u64) -> f64 {
+    cycles as f64 * 1_000_000.0 / CPU_FREQUENCY as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_and_interrupts() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&vec![0; 0x8000]).unwrap();
+        let trace = ChromeTrace::new();
+        trace.attach(&mut gb);
+
+        gb.step_frame().unwrap();
+        gb.mmu_mut().request_interrupt(crate::mmu::INT_SERIAL);
+        gb.step().unwrap();
+        assert_eq!(trace.len(), 2);
+
+        let mut json = Vec::new();
+        trace.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"name\":\"Frame 1\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":0.000,\
+            \"dur\":16742.706,\"args\":{\"cycles\":70224}"), "{json}");
+        assert!(json.contains("\"name\":\"Serial\",\"ph\":\"i\""), "{json}");
+        assert!(json.trim_end().ends_with("]}"));
+    }
+}
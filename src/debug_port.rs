@@ -0,0 +1,33 @@
+use std::io::{self, Write};
+
+/// Dirección del puerto de depuración que usan la mayoría de emuladores, un
+/// registro de IO que no existe en el hardware real
+pub const DEFAULT_DEBUG_PORT: u16 = 0xFF7F;
+
+/// Destino de los bytes que escribe el juego en el puerto de depuración, un
+/// `printf` para homebrew que no necesita ningún hardware. Se conecta con
+/// `Mmu::connect_debug_port`. Cualquier `FnMut(u8)` sirve como destino
+pub trait DebugSink: Send {
+    fn write_byte(&mut self, byte: u8);
+}
+
+impl<F: FnMut(u8) + Send> DebugSink for F {
+    fn write_byte(&mut self, byte: u8) {
+        self(byte)
+    }
+}
+
+/// Pasa los bytes del puerto de depuración a la salida estándar como texto,
+/// se vacía en cada salto de línea
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutDebug;
+
+impl DebugSink for StdoutDebug {
+    fn write_byte(&mut self, byte: u8) {
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(&[byte]);
+        if byte == b'\n' {
+            let _ = stdout.flush();
+        }
+    }
+}
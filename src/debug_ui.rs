@@ -0,0 +1,303 @@
+use egui::load::SizedTexture;
+use egui::{Color32, ColorImage, Context, Grid, RichText, ScrollArea, TextStyle, TextureHandle,
+    TextureOptions, Ui};
+
+use crate::debugger::Breakpoint;
+use crate::disasm::disassemble_range;
+use crate::gameboy::GameBoy;
+use crate::mmu::Bus;
+use crate::watch::wide_value;
+use crate::{Reg, FLAG_C, FLAG_H, FLAG_N, FLAG_Z};
+
+/// Tiles que caben en los 6 KiB de VRAM entre 0x8000 y 0x97FF
+const TILE_COUNT: usize = 384;
+
+/// Tiles por fila en el visor
+const TILES_PER_ROW: usize = 16;
+
+/// Tamaño en píxeles de la imagen del visor de tiles
+const TILES_WIDTH: usize = TILES_PER_ROW * 8;
+const TILES_HEIGHT: usize = TILE_COUNT / TILES_PER_ROW * 8;
+
+/// Instrucciones que se muestran a partir del PC
+const DISASM_LINES: usize = 20;
+
+/// Tonos de gris de los 4 colores de un tile, sin aplicar la paleta
+const SHADES: [Color32; 4] = [
+    Color32::from_gray(0xFF),
+    Color32::from_gray(0xA5),
+    Color32::from_gray(0x52),
+    Color32::from_gray(0x00),
+];
+
+/// Paneles de egui para depurar una `GameBoy`: estado de la CPU,
+/// desensamblado alrededor del PC, volcado de memoria, visor de tiles y
+/// lista de breakpoints. `show` los abre como ventanas y cada `*_panel`
+/// pinta uno en cualquier `Ui` para quien quiera colocarlos a su manera
+pub struct DebugUi {
+    /// Ventanas abiertas con `show`
+    pub cpu_open: bool,
+    pub disasm_open: bool,
+    pub memory_open: bool,
+    pub tiles_open: bool,
+    pub breakpoints_open: bool,
+
+    /// Dirección escrita en el volcado de memoria y si hay que saltar a ella
+    memory_input: String,
+    memory_jump: Option<u16>,
+
+    /// Dirección o símbolo escrito en la lista de breakpoints
+    breakpoint_input: String,
+
+    /// Textura del visor de tiles, se crea la primera vez que se pinta
+    tiles: Option<TextureHandle>,
+}
+
+impl DebugUi {
+    pub fn new() -> Self {
+        Self {
+            cpu_open: true,
+            disasm_open: true,
+            memory_open: true,
+            tiles_open: true,
+            breakpoints_open: true,
+            memory_input: String::new(),
+            memory_jump: None,
+            breakpoint_input: String::new(),
+            tiles: None,
+        }
+    }
+
+    /// Pintar todos los paneles abiertos como ventanas
+    pub fn show(&mut self, ctx: &Context, gb: &mut GameBoy) {
+        let mut open = self.cpu_open;
+        egui::Window::new("CPU").open(&mut open).show(ctx, |ui| self.cpu_panel(ui, gb));
+        self.cpu_open = open;
+
+        let mut open = self.disasm_open;
+        egui::Window::new("Desensamblado").open(&mut open).show(ctx, |ui| self.disasm_panel(ui, gb));
+        self.disasm_open = open;
+
+        let mut open = self.memory_open;
+        egui::Window::new("Memoria").open(&mut open).show(ctx, |ui| self.memory_panel(ui, gb));
+        self.memory_open = open;
+
+        let mut open = self.tiles_open;
+        egui::Window::new("Tiles").open(&mut open).show(ctx, |ui| self.tiles_panel(ui, gb));
+        self.tiles_open = open;
+
+        let mut open = self.breakpoints_open;
+        egui::Window::new("Breakpoints").open(&mut open)
+            .show(ctx, |ui| self.breakpoints_panel(ui, gb));
+        self.breakpoints_open = open;
+    }
+
+    /// Registros, flags y contador de ciclos
+    pub fn cpu_panel(&mut self, ui: &mut Ui, gb: &GameBoy) {
+        let cpu = gb.cpu();
+        Grid::new("gameboi_cpu").num_columns(2).striped(true).show(ui, |ui| {
+            for (name, reg) in [("AF", Reg::AF), ("BC", Reg::BC), ("DE", Reg::DE), ("HL", Reg::HL),
+                ("SP", Reg::SP)]
+            {
+                ui.label(name);
+                ui.monospace(format!("{:04X}", wide_value(cpu, reg)));
+                ui.end_row();
+            }
+            ui.label("PC");
+            ui.monospace(format!("{:04X}", cpu.pc()));
+            ui.end_row();
+        });
+
+        let f = cpu.read_reg(Reg::F);
+        ui.horizontal(|ui| {
+            for (name, flag) in [("Z", FLAG_Z), ("N", FLAG_N), ("H", FLAG_H), ("C", FLAG_C)] {
+                let text = RichText::new(name).monospace();
+                ui.label(if f & flag != 0 { text.strong() } else { text.weak() });
+            }
+        });
+        ui.label(format!("T-cycles: {}", cpu.cycles()));
+        if cpu.is_stopped() {
+            ui.label("Detenida por STOP");
+        }
+    }
+
+    /// Instrucciones a partir del PC, al pulsar en una se pone o quita un
+    /// breakpoint en ella
+    pub fn disasm_panel(&mut self, ui: &mut Ui, gb: &mut GameBoy) {
+        let pc = gb.cpu().pc();
+        let bank = gb.mmu().rom_bank(0x4000);
+        let lines = disassemble_range(gb.mmu(), pc, DISASM_LINES, bank, gb.debugger().symbols());
+
+        let mut toggle = None;
+        for line in &lines {
+            if let Some(label) = &line.label {
+                ui.monospace(format!("{label}:"));
+            }
+            let breakpoint = gb.debugger().breakpoints().any(|b| b.pc == line.addr);
+            let bytes = line.bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ");
+            let text = format!("{} {:04X}  {bytes:<8}  {}",
+                if breakpoint { "●" } else { " " },
+                line.addr,
+                line.text.as_deref().unwrap_or("db ?"));
+            let text = RichText::new(text).monospace();
+            let text = if line.addr == pc { text.strong().color(ui.visuals().warn_fg_color) } else { text };
+            if ui.selectable_label(false, text).clicked() {
+                toggle = Some(line.addr);
+            }
+        }
+
+        if let Some(addr) = toggle {
+            let debugger = gb.debugger_mut();
+            let existing = debugger.breakpoints().copied().filter(|b| b.pc == addr).collect::<Vec<_>>();
+            if existing.is_empty() {
+                debugger.add_breakpoint(Breakpoint::new(addr));
+            }
+            for breakpoint in existing {
+                debugger.remove_breakpoint(breakpoint);
+            }
+        }
+    }
+
+    /// Volcado hexadecimal de todo el espacio de direcciones
+    pub fn memory_panel(&mut self, ui: &mut Ui, gb: &GameBoy) {
+        ui.horizontal(|ui| {
+            ui.label("Ir a");
+            let response = ui.text_edit_singleline(&mut self.memory_input);
+            if response.lost_focus() || ui.button("Ir").clicked() {
+                self.memory_jump = parse_addr(&self.memory_input);
+            }
+        });
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut scroll = ScrollArea::vertical().auto_shrink(false);
+        if let Some(addr) = self.memory_jump.take() {
+            let spacing = ui.spacing().item_spacing.y;
+            scroll = scroll.vertical_scroll_offset((addr / 16) as f32 * (row_height + spacing));
+        }
+        scroll.show_rows(ui, row_height, 0x10000 / 16, |ui, rows| {
+            let mmu = gb.mmu();
+            for row in rows {
+                let start = (row * 16) as u16;
+                let bytes = (0..16).map(|i| mmu.read(start + i)).collect::<Vec<_>>();
+                let hex = bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ");
+                let ascii = bytes.iter()
+                    .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                    .collect::<String>();
+                ui.monospace(format!("{start:04X}  {hex}  {ascii}"));
+            }
+        });
+    }
+
+    /// Los 384 tiles de la VRAM en gris, sin aplicar la paleta
+    pub fn tiles_panel(&mut self, ui: &mut Ui, gb: &GameBoy) {
+        let pixels = decode_tiles(gb.mmu()).into_iter().map(|shade| SHADES[shade as usize]).collect();
+        let image = ColorImage::new([TILES_WIDTH, TILES_HEIGHT], pixels);
+        let texture = match self.tiles.as_mut() {
+            Some(texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            },
+            None => self.tiles
+                .insert(ui.ctx().load_texture("gameboi_tiles", image, TextureOptions::NEAREST)),
+        };
+        let size = egui::vec2(TILES_WIDTH as f32, TILES_HEIGHT as f32) * 2.0;
+        ui.image(SizedTexture::new(texture.id(), size));
+    }
+
+    /// Breakpoints puestos, se añaden por dirección (`$0150`, `0x150`) o por
+    /// el nombre de un símbolo si hay símbolos cargados
+    pub fn breakpoints_panel(&mut self, ui: &mut Ui, gb: &mut GameBoy) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.breakpoint_input);
+            if ui.button("Añadir").clicked() {
+                let input = self.breakpoint_input.trim();
+                let breakpoint = gb.debugger().symbols()
+                    .and_then(|symbols| Breakpoint::at_symbol(symbols, input))
+                    .or_else(|| parse_addr(input).map(Breakpoint::new));
+                if let Some(breakpoint) = breakpoint {
+                    gb.debugger_mut().add_breakpoint(breakpoint);
+                    self.breakpoint_input.clear();
+                }
+            }
+        });
+
+        let breakpoints = gb.debugger().breakpoints().copied().collect::<Vec<_>>();
+        let mut remove = None;
+        for breakpoint in breakpoints {
+            ui.horizontal(|ui| {
+                let name = gb.debugger().symbolize(breakpoint.bank.unwrap_or(1), breakpoint.pc);
+                let text = match breakpoint.bank {
+                    Some(bank) => format!("{bank:02X}:{:04X} {name}", breakpoint.pc),
+                    None => format!("{:04X} {name}", breakpoint.pc),
+                };
+                ui.monospace(text);
+                if ui.small_button("✖").clicked() {
+                    remove = Some(breakpoint);
+                }
+            });
+        }
+        if let Some(breakpoint) = remove {
+            gb.debugger_mut().remove_breakpoint(breakpoint);
+        }
+        if ui.button("Quitar todos").clicked() {
+            gb.debugger_mut().clear_breakpoints();
+        }
+    }
+}
+
+impl Default for DebugUi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Dirección en hexadecimal, con o sin `$` o `0x` delante
+fn parse_addr(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let hex = text.strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Colores (0-3) de los tiles de la VRAM colocados en filas de
+/// `TILES_PER_ROW`, cada tile son 8 filas de 2 bytes con el bit bajo del
+/// color en el primero
+fn decode_tiles<B: Bus + ?Sized>(bus: &B) -> Vec<u8> {
+    let mut pixels = vec![0; TILES_WIDTH * TILES_HEIGHT];
+    for tile in 0..TILE_COUNT {
+        let base = 0x8000 + tile as u16 * 16;
+        let (tile_x, tile_y) = (tile % TILES_PER_ROW * 8, tile / TILES_PER_ROW * 8);
+        for row in 0..8 {
+            let low = bus.read(base + row as u16 * 2);
+            let high = bus.read(base + row as u16 * 2 + 1);
+            for x in 0..8 {
+                let bit = 7 - x;
+                let color = (high >> bit & 1) << 1 | (low >> bit & 1);
+                pixels[(tile_y + row) * TILES_WIDTH + tile_x + x] = color;
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_and_addresses() {
+        let mut memory = vec![0; 0x10000];
+        // Segundo tile, primera fila: colores 3 0 1 2 0 0 0 0
+        memory[0x8010] = 0b1010_0000;
+        memory[0x8011] = 0b1001_0000;
+        let pixels = decode_tiles(memory.as_slice());
+        assert_eq!(pixels.len(), TILES_WIDTH * TILES_HEIGHT);
+        assert_eq!(pixels[8..13], [3, 0, 1, 2, 0]);
+
+        assert_eq!(parse_addr("$0150"), Some(0x150));
+        assert_eq!(parse_addr(" 0xFF40"), Some(0xFF40));
+        assert_eq!(parse_addr("Main"), None);
+    }
+}
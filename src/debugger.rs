@@ -0,0 +1,505 @@
+use std::collections::BTreeSet;
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::gameboy::StepInfo;
+use crate::mmu::Bus;
+use crate::symbols::{bank_of, SymbolTable};
+use crate::watch::WatchExpr;
+use crate::Cpu;
+
+/// Profundidad máxima de la pila de llamadas, hay juegos que salen de una
+/// subrutina saltando en vez de con RET y la pila crecería sin límite
+const MAX_CALL_DEPTH: usize = 256;
+
+/// Punto de parada en una dirección, opcionalmente solo cuando está mapeado
+/// un banco concreto de la ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Breakpoint {
+    pub pc: u16,
+
+    /// `None` para parar sea cual sea el banco
+    pub bank: Option<u16>,
+}
+
+impl Breakpoint {
+    pub fn new(pc: u16) -> Self {
+        Self { pc, bank: None }
+    }
+
+    pub fn with_bank(pc: u16, bank: u16) -> Self {
+        Self { pc, bank: Some(bank) }
+    }
+
+    /// Breakpoint en un símbolo, en la ROM conmutable solo para su banco
+    pub fn at_symbol(symbols: &SymbolTable, name: &str) -> Option<Self> {
+        let (bank, pc) = symbols.lookup(name)?;
+        Some(match pc {
+            0x4000..=0x7FFF => Self::with_bank(pc, bank),
+            _ => Self::new(pc),
+        })
+    }
+
+    #[inline]
+    fn matches(&self, pc: u16, bank: u16) -> bool {
+        self.pc == pc && self.bank.is_none_or(|b| b == bank)
+    }
+}
+
+/// Cómo se entró en una subrutina
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+/// Entrada de la pila de llamadas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallFrame {
+    pub kind: CallKind,
+
+    /// Dirección de la instrucción que hizo la llamada
+    pub call_site: u16,
+
+    /// Dirección de la subrutina
+    pub target: u16,
+
+    /// Dirección a la que debe volver el RET
+    pub return_addr: u16,
+}
+
+/// Palabra de 16 bits de la pila, ver `GameBoy::inspect_stack`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StackEntry {
+    /// Dirección de la palabra
+    pub addr: u16,
+
+    /// Distancia en bytes a SP, negativa para lo que ya se desapiló
+    pub offset: i32,
+
+    pub value: u16,
+
+    /// Si `value` apunta justo detrás de un CALL o un RST parece una
+    /// dirección de retorno, esta es la dirección de esa instrucción
+    pub call_site: Option<u16>,
+}
+
+/// Leer `before` palabras por debajo de `sp` y `after` a partir de él
+pub(crate) fn inspect_stack<B: Bus + ?Sized>(bus: &B, sp: u16, before: u16, after: u16)
+    -> Vec<StackEntry>
+{
+    let start = sp.wrapping_sub(before.wrapping_mul(2));
+    (0..before as u32 + after as u32)
+        .map(|i| {
+            let addr = start.wrapping_add(i as u16 * 2);
+            let value = u16::from_le_bytes([bus.read(addr), bus.read(addr.wrapping_add(1))]);
+            StackEntry {
+                addr,
+                offset: (i as i32 - before as i32) * 2,
+                value,
+                call_site: call_site(bus, value),
+            }
+        })
+        .collect()
+}
+
+/// Instrucción de llamada que tendría a `ret` como dirección de retorno
+fn call_site<B: Bus + ?Sized>(bus: &B, ret: u16) -> Option<u16> {
+    let call = ret.wrapping_sub(3);
+    let rst = ret.wrapping_sub(1);
+    if matches!(bus.read(call), 0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC) {
+        Some(call)
+    } else if bus.read(rst) & 0xC7 == 0xC7 {
+        Some(rst)
+    } else {
+        None
+    }
+}
+
+/// Cuándo se evalúa un watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WatchMode {
+    /// Después de cada instrucción
+    Instruction,
+
+    /// Al terminar cada frame
+    Frame,
+}
+
+/// Identificador de un watch para poder quitarlo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchId(u32);
+
+/// Cambio de valor de un watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchEvent {
+    pub id: WatchId,
+    pub old: u16,
+    pub new: u16,
+
+    /// Instrucción después de la que se vio el cambio
+    pub pc: u16,
+}
+
+struct Watch {
+    id: WatchId,
+    expr: WatchExpr,
+    mode: WatchMode,
+
+    /// Último valor visto, `None` hasta la primera evaluación
+    value: Option<u16>,
+    callback: Box<dyn FnMut(&WatchEvent) + Send>,
+}
+
+/// Depurador integrado en la `GameBoy`, las funciones `run_*` se detienen
+/// con `StepResult::HitBreakpoint` antes de ejecutar la instrucción de un
+/// breakpoint
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<Breakpoint>,
+
+    /// Breakpoint en el que se paró la última vez, volver a ejecutar desde
+    /// él no debe parar otra vez sin avanzar
+    stopped_at: Option<Breakpoint>,
+
+    /// Pila de llamadas paralela a la real, el SM83 no tiene frame pointer
+    /// así que es la única forma de sacar un backtrace. `None` si no se está
+    /// siguiendo
+    call_stack: Option<Vec<CallFrame>>,
+
+    watches: Vec<Watch>,
+    next_watch: u32,
+
+    /// Símbolos con los que se muestran las direcciones
+    symbols: Option<Arc<SymbolTable>>,
+}
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.breakpoints)
+            .field("call_stack", &self.call_stack)
+            .field("watches", &self.watches.iter()
+                .map(|watch| watch.expr.to_string())
+                .collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Añadir un breakpoint, devuelve `false` si ya existía
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.insert(breakpoint)
+    }
+
+    /// Quitar un breakpoint, devuelve `false` si no existía
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.remove(&breakpoint)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.stopped_at = None;
+    }
+
+    /// Breakpoints ordenados por dirección
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> + '_ {
+        self.breakpoints.iter()
+    }
+
+    /// Cargar (o quitar con `None`) los símbolos de la ROM
+    pub fn set_symbols(&mut self, symbols: Option<Arc<SymbolTable>>) {
+        self.symbols = symbols;
+    }
+
+    #[inline]
+    pub fn symbols(&self) -> Option<&SymbolTable> {
+        self.symbols.as_deref()
+    }
+
+    /// Texto de una dirección con los símbolos cargados, `bank` es el banco
+    /// mapeado en la ROM conmutable
+    pub fn symbolize(&self, bank: u16, addr: u16) -> String {
+        match self.symbols() {
+            Some(symbols) => symbols.symbolize(bank_of(addr, bank), addr),
+            None => format!("${addr:04X}"),
+        }
+    }
+
+    /// Backtrace de la pila de llamadas, de la subrutina más interna a la
+    /// más externa, como `UpdateOAM (desde Main+$3)`
+    // TODO: Guardar el banco en `CallFrame` cuando haya mappers, de momento
+    // se usa el único banco conmutable
+    pub fn backtrace(&self) -> Vec<String> {
+        self.call_stack().iter().rev()
+            .map(|frame| format!("{} (desde {})",
+                self.symbolize(1, frame.target), self.symbolize(1, frame.call_site)))
+            .collect()
+    }
+
+    /// Vigilar `expr` y llamar a `callback` cada vez que cambie su valor,
+    /// evaluándola según `mode`. El valor inicial se toma en la primera
+    /// evaluación, `GameBoy::add_watch` lo toma directamente al añadirlo
+    pub fn add_watch<F>(&mut self, expr: WatchExpr, mode: WatchMode, callback: F) -> WatchId
+    where
+        F: FnMut(&WatchEvent) + Send + 'static,
+    {
+        let id = WatchId(self.next_watch);
+        self.next_watch += 1;
+        self.watches.push(Watch { id, expr, mode, value: None, callback: Box::new(callback) });
+        id
+    }
+
+    /// Quitar un watch, devuelve `false` si no existía
+    pub fn remove_watch(&mut self, id: WatchId) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|watch| watch.id != id);
+        self.watches.len() != len
+    }
+
+    /// Expresiones vigiladas con su último valor
+    pub fn watches(&self) -> impl Iterator<Item = (WatchId, &WatchExpr, Option<u16>)> + '_ {
+        self.watches.iter().map(|watch| (watch.id, &watch.expr, watch.value))
+    }
+
+    #[inline]
+    pub(crate) fn has_watches(&self) -> bool {
+        !self.watches.is_empty()
+    }
+
+    /// Evaluar los watches de `mode` (todos con `None`) tras la instrucción
+    /// en `pc`, llamando a los callbacks de los que cambiaron
+    pub(crate) fn update_watches<B: Bus + ?Sized>(&mut self, mode: Option<WatchMode>,
+        pc: u16, cpu: &Cpu, bus: &B)
+    {
+        for watch in &mut self.watches {
+            if mode.is_some_and(|mode| mode != watch.mode) {
+                continue;
+            }
+            let new = watch.expr.eval(cpu, bus);
+            match watch.value.replace(new) {
+                Some(old) if old != new => {
+                    (watch.callback)(&WatchEvent { id: watch.id, old, new, pc });
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Seguir las llamadas y retornos para mantener `call_stack`, empieza
+    /// vacía cada vez que se activa
+    pub fn set_call_tracking(&mut self, enabled: bool) {
+        self.call_stack = enabled.then(Vec::new);
+    }
+
+    #[inline]
+    pub fn is_tracking_calls(&self) -> bool {
+        self.call_stack.is_some()
+    }
+
+    /// Vaciar la pila de llamadas sin dejar de seguirlas, para cuando la CPU
+    /// vuelve a empezar
+    pub fn clear_call_stack(&mut self) {
+        if let Some(stack) = self.call_stack.as_mut() {
+            stack.clear();
+        }
+    }
+
+    /// Pila de llamadas con la más interna al final, vacía si no se están
+    /// siguiendo las llamadas
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.as_deref().unwrap_or_default()
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn save_call_stack(&self) -> Option<Vec<CallFrame>> {
+        self.call_stack.clone()
+    }
+
+    /// Volver a una pila de llamadas guardada con `save_call_stack`, sin
+    /// cambiar si se están siguiendo o no
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_call_stack(&mut self, stack: Option<Vec<CallFrame>>) {
+        if self.call_stack.is_some() {
+            self.call_stack = Some(stack.unwrap_or_default());
+        }
+    }
+
+    /// Actualizar la pila de llamadas tras ejecutar la instrucción de `info`
+    /// cuyo opcode es `opcode`. Se mira el opcode y no `Instr` para que
+    /// funcione con cualquier instrucción, y el `pc` resultante para saber
+    /// si se tomaron las condicionales
+    pub(crate) fn track_call(&mut self, opcode: u8, info: &StepInfo) {
+        let Some(stack) = self.call_stack.as_mut() else {
+            return;
+        };
+
+        let kind = match opcode {
+            // CALL y CALL condicional
+            0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC
+                if info.new_pc != info.addr.wrapping_add(3) => CallKind::Call,
+            // RST
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => CallKind::Rst,
+            // RET, RETI y RET condicional
+            0xC9 | 0xD9 | 0xC0 | 0xC8 | 0xD0 | 0xD8
+                if info.new_pc != info.addr.wrapping_add(1) =>
+            {
+                // Si el juego manipuló la pila se vuelve al frame cuyo
+                // retorno coincide, si no hay ninguno se deja como está
+                if let Some(depth) = stack.iter()
+                    .rposition(|frame| frame.return_addr == info.new_pc)
+                {
+                    stack.truncate(depth);
+                }
+                return;
+            },
+            _ => return,
+        };
+
+        let len = match kind {
+            CallKind::Call => 3,
+            _ => 1,
+        };
+        Self::push_frame(stack, CallFrame {
+            kind,
+            call_site: info.addr,
+            target: info.new_pc,
+            return_addr: info.addr.wrapping_add(len),
+        });
+    }
+
+    /// Anotar la entrada a una interrupción que interrumpió la ejecución en
+    /// `return_addr`
+    pub(crate) fn track_interrupt(&mut self, vector: u16, return_addr: u16) {
+        if let Some(stack) = self.call_stack.as_mut() {
+            Self::push_frame(stack, CallFrame {
+                kind: CallKind::Interrupt,
+                call_site: return_addr,
+                target: vector,
+                return_addr,
+            });
+        }
+    }
+
+    fn push_frame(stack: &mut Vec<CallFrame>, frame: CallFrame) {
+        if stack.len() == MAX_CALL_DEPTH {
+            stack.remove(0);
+        }
+        stack.push(frame);
+    }
+
+    /// Comprobar antes de ejecutar la instrucción en `pc` si hay que parar
+    pub(crate) fn check(&mut self, pc: u16, bank: u16) -> Option<Breakpoint> {
+        if self.breakpoints.is_empty() {
+            return None;
+        }
+
+        let hit = self.breakpoints.iter()
+            .find(|breakpoint| breakpoint.matches(pc, bank))
+            .copied();
+
+        // Al continuar desde un breakpoint la primera instrucción se ejecuta
+        if hit.is_some() && self.stopped_at.take() == hit {
+            return None;
+        }
+        self.stopped_at = hit;
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_and_resume() {
+        let mut debugger = Debugger::new();
+        assert!(debugger.add_breakpoint(Breakpoint::new(0x0150)));
+        assert!(debugger.add_breakpoint(Breakpoint::with_bank(0x4000, 2)));
+        assert!(!debugger.add_breakpoint(Breakpoint::new(0x0150)));
+
+        assert_eq!(debugger.check(0x0100, 0), None);
+        assert_eq!(debugger.check(0x0150, 0), Some(Breakpoint::new(0x0150)));
+        assert_eq!(debugger.check(0x0150, 0), None);
+        assert_eq!(debugger.check(0x0151, 0), None);
+        assert_eq!(debugger.check(0x0150, 0), Some(Breakpoint::new(0x0150)));
+
+        assert_eq!(debugger.check(0x4000, 1), None);
+        assert_eq!(debugger.check(0x4000, 2), Some(Breakpoint::with_bank(0x4000, 2)));
+
+        assert!(debugger.remove_breakpoint(Breakpoint::new(0x0150)));
+        assert_eq!(debugger.breakpoints().count(), 1);
+    }
+
+    #[test]
+    fn stack_view() {
+        let mut memory = vec![0; 0x10000];
+        // CALL 0x2000 en 0x0150 y RST 0x28 en 0x0200
+        memory[0x0150..0x0153].copy_from_slice(&[0xCD, 0x00, 0x20]);
+        memory[0x0200] = 0xEF;
+        memory[0xDFF8..0xE000].copy_from_slice(&[0x34, 0x12, 0x53, 0x01, 0x01, 0x02, 0x00, 0x00]);
+
+        let entries = inspect_stack(memory.as_slice(), 0xDFFA, 1, 3);
+        let summary = entries.iter()
+            .map(|entry| (entry.addr, entry.offset, entry.value, entry.call_site))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, [
+            (0xDFF8, -2, 0x1234, None),
+            (0xDFFA, 0, 0x0153, Some(0x0150)),
+            (0xDFFC, 2, 0x0201, Some(0x0200)),
+            (0xDFFE, 4, 0x0000, None),
+        ]);
+    }
+
+    #[test]
+    fn call_stack() {
+        let step = |addr, new_pc| StepInfo {
+            addr,
+            bank: 0,
+            instr: crate::Instr::Nop,
+            cycles: 4,
+            new_pc,
+        };
+        let mut debugger = Debugger::new();
+        debugger.track_call(0xCD, &step(0x0150, 0x2000));
+        assert!(debugger.call_stack().is_empty());
+
+        debugger.set_call_tracking(true);
+        debugger.track_call(0xCD, &step(0x0150, 0x2000));
+        debugger.track_call(0xEF, &step(0x2005, 0x0028));
+        // CALL NZ no tomado
+        debugger.track_call(0xC4, &step(0x0028, 0x002B));
+        assert_eq!(debugger.call_stack(), [
+            CallFrame { kind: CallKind::Call, call_site: 0x0150, target: 0x2000, return_addr: 0x0153 },
+            CallFrame { kind: CallKind::Rst, call_site: 0x2005, target: 0x0028, return_addr: 0x2006 },
+        ]);
+
+        // RET Z no tomado y luego RETs
+        debugger.track_call(0xC8, &step(0x002B, 0x002C));
+        assert_eq!(debugger.call_stack().len(), 2);
+        debugger.set_symbols(Some(Arc::new(SymbolTable::parse("00:0150 Main\n00:2000 Sub"))));
+        assert_eq!(debugger.backtrace(), ["$0028 (desde Sub+$5)", "Sub (desde Main)"]);
+
+        debugger.track_call(0xC9, &step(0x002C, 0x2006));
+        debugger.track_call(0xC9, &step(0x2010, 0x0153));
+        assert!(debugger.call_stack().is_empty());
+
+        debugger.track_call(0xCD, &step(0x0150, 0x2000));
+        debugger.clear_call_stack();
+        assert!(debugger.call_stack().is_empty());
+        assert!(debugger.is_tracking_calls());
+    }
+}
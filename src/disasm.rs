@@ -0,0 +1,240 @@
+use crate::mmu::Bus;
+use crate::symbols::{bank_of, SymbolTable};
+use crate::{Cpu, Instr, Reg, RegAddr, C, NC, NZ, Z};
+
+/// Instrucción desensamblada
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisasmLine {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+
+    /// Nombre del símbolo que empieza en `addr`, si lo hay
+    pub label: Option<String>,
+
+    /// Instrucción en sintaxis de RGBDS, `None` si no se pudo decodificar
+    pub text: Option<String>,
+}
+
+/// Desensamblar la instrucción en `addr` con la ROM conmutable en `bank`. Si
+/// no se puede decodificar se devuelve el byte suelto sin texto
+pub fn disassemble<B: Bus + ?Sized>(bus: &B, addr: u16, bank: u16,
+    symbols: Option<&SymbolTable>) -> DisasmLine
+{
+    let mut cpu = Cpu::new();
+    cpu.set_pc(addr);
+    let instr = cpu.decode(bus);
+    let len = match instr {
+        Ok(_) => cpu.pc().wrapping_sub(addr).max(1),
+        Err(_) => 1,
+    };
+    let bytes = (0..len).map(|i| bus.read(addr.wrapping_add(i))).collect::<Vec<_>>();
+
+    DisasmLine {
+        addr,
+        label: symbols
+            .and_then(|symbols| symbols.name(bank_of(addr, bank), addr))
+            .map(str::to_string),
+        text: instr.ok().map(|instr| format_instr(&instr, addr, &bytes, bank, symbols)),
+        bytes,
+    }
+}
+
+/// Desensamblar `count` instrucciones seguidas desde `start`
+pub fn disassemble_range<B: Bus + ?Sized>(bus: &B, start: u16, count: usize, bank: u16,
+    symbols: Option<&SymbolTable>) -> Vec<DisasmLine>
+{
+    let mut addr = start;
+    (0..count)
+        .map(|_| {
+            let line = disassemble(bus, addr, bank, symbols);
+            addr = addr.wrapping_add(line.bytes.len() as u16);
+            line
+        })
+        .collect()
+}
+
+/// Texto de una instrucción en `addr`, `bytes` son los que ocupa en memoria
+/// y solo hacen falta para los inmediatos que no guarda `Instr` (si está
+/// vacío se muestran como `n8`)
+pub fn format_instr(instr: &Instr, addr: u16, bytes: &[u8], bank: u16,
+    symbols: Option<&SymbolTable>) -> String
+{
+    let target = |target: u16| match symbols {
+        Some(symbols) => symbols.symbolize(bank_of(target, bank), target),
+        None => format!("${target:04X}"),
+    };
+    let r = reg_name;
+    let w = wide_name;
+    let m = mem_name;
+
+    match *instr {
+        Instr::Nop => "nop".into(),
+        Instr::Halt => "halt".into(),
+        Instr::Stop => "stop".into(),
+        Instr::Di => "di".into(),
+        Instr::Ei => "ei".into(),
+
+        Instr::LdRegReg { src, dst } => format!("ld {}, {}", r(dst), r(src)),
+        Instr::LdRegImm { src, dst } => format!("ld {}, ${src:02X}", r(dst)),
+        Instr::LdRegMem { src, dst } => format!("ld [{}], {}", m(dst), r(src)),
+        Instr::LdMemReg { src, dst } => format!("ld {}, [{}]", r(dst), m(src)),
+        Instr::LdMemHLImm => match bytes.get(1) {
+            Some(imm) => format!("ld [hl], ${imm:02X}"),
+            None => "ld [hl], n8".into(),
+        },
+
+        Instr::AddRegReg { src, dst } => format!("add {}, {}", r(dst), r(src)),
+        Instr::AddRegImm { src, dst } => format!("add {}, ${src:02X}", r(dst)),
+        Instr::AddMemReg { src, dst } => format!("add {}, [{}]", r(dst), m(src)),
+        Instr::AddWRegWReg { src, dst } => format!("add {}, {}", w(dst), w(src)),
+        Instr::AddWRegImm { src, dst } => format!("add {}, {}", w(dst), src as i8),
+
+        Instr::AdcRegReg { src, dst } => format!("adc {}, {}", r(dst), r(src)),
+        Instr::AdcRegImm { src, dst } => format!("adc {}, ${src:02X}", r(dst)),
+        Instr::AdcMemReg { src, dst } => format!("adc {}, [{}]", r(dst), m(src)),
+
+        Instr::SubReg { src } => format!("sub a, {}", r(src)),
+        Instr::SubImm { src } => format!("sub a, ${src:02X}"),
+        Instr::SubMem { src } => format!("sub a, [{}]", m(src)),
+        Instr::SbcReg { src } => format!("sbc a, {}", r(src)),
+        Instr::SbcImm { src } => format!("sbc a, ${src:02X}"),
+        Instr::SbcMem { src } => format!("sbc a, [{}]", m(src)),
+        Instr::AndReg { src } => format!("and a, {}", r(src)),
+        Instr::AndImm { src } => format!("and a, ${src:02X}"),
+        Instr::AndMem { src } => format!("and a, [{}]", m(src)),
+        Instr::OrReg { src } => format!("or a, {}", r(src)),
+        Instr::OrImm { src } => format!("or a, ${src:02X}"),
+        Instr::OrMem { src } => format!("or a, [{}]", m(src)),
+        Instr::CpReg { src } => format!("cp a, {}", r(src)),
+        Instr::CpImm { src } => format!("cp a, ${src:02X}"),
+        Instr::CpMem { src } => format!("cp a, [{}]", m(src)),
+
+        Instr::IncReg { dst } => format!("inc {}", r(dst)),
+        Instr::IncWReg { dst } => format!("inc {}", w(dst)),
+        Instr::IncMem { dst } => format!("inc [{}]", m(dst)),
+        Instr::DecReg { dst } => format!("dec {}", r(dst)),
+        Instr::DecWReg { dst } => format!("dec {}", w(dst)),
+        Instr::DecMem { dst } => format!("dec [{}]", m(dst)),
+
+        Instr::LdWRegImm { src, dst } => format!("ld {}, {}", w(dst), target(src)),
+        Instr::LdMemImmReg { src, dst } => format!("ld [{}], {}", target(dst), r(src)),
+        Instr::Push { src } => format!("push {}", w(src)),
+        Instr::Pop { dst } => format!("pop {}", w(dst)),
+
+        Instr::JPImm { addr } => format!("jp {}", target(addr)),
+        Instr::JPCond { cond, addr } => format!("jp {}, {}", cond_name(cond), target(addr)),
+        Instr::JPReg { src } => format!("jp {}", w(src)),
+        Instr::JRelImm { offset } => format!("jr {}", target(relative(addr, offset))),
+        Instr::JRelCond { cond, offset } => {
+            format!("jr {}, {}", cond_name(cond), target(relative(addr, offset)))
+        },
+        Instr::Rst { addr } => format!("rst ${addr:02X}"),
+        Instr::Reti => "reti".into(),
+
+        Instr::RlcReg { reg } => format!("rlc {}", r(reg)),
+        Instr::RlcMem { reg } => format!("rlc [{}]", m(reg)),
+        Instr::RrcReg { reg } => format!("rrc {}", r(reg)),
+        Instr::RrcMem { reg } => format!("rrc [{}]", m(reg)),
+        Instr::RlReg { reg } => format!("rl {}", r(reg)),
+        Instr::RlMem { reg } => format!("rl [{}]", m(reg)),
+        Instr::RrReg { reg } => format!("rr {}", r(reg)),
+        Instr::RrMem { reg } => format!("rr [{}]", m(reg)),
+        Instr::SlaReg { reg } => format!("sla {}", r(reg)),
+        Instr::SlaMem { reg } => format!("sla [{}]", m(reg)),
+        Instr::SraReg { reg } => format!("sra {}", r(reg)),
+        Instr::SraMem { reg } => format!("sra [{}]", m(reg)),
+        Instr::SwapReg { reg } => format!("swap {}", r(reg)),
+        Instr::SwapMem { reg } => format!("swap [{}]", m(reg)),
+        Instr::SrlReg { reg } => format!("srl {}", r(reg)),
+        Instr::SrlMem { reg } => format!("srl [{}]", m(reg)),
+        Instr::BitReg { reg, bit } => format!("bit {bit}, {}", r(reg)),
+        Instr::BitMem { reg, bit } => format!("bit {bit}, [{}]", m(reg)),
+        Instr::ResReg { reg, bit } => format!("res {bit}, {}", r(reg)),
+        Instr::ResMem { reg, bit } => format!("res {bit}, [{}]", m(reg)),
+        Instr::SetReg { reg, bit } => format!("set {bit}, {}", r(reg)),
+        Instr::SetMem { reg, bit } => format!("set {bit}, [{}]", m(reg)),
+    }
+}
+
+/// Destino de un JR en `addr`, el offset se cuenta desde el final de la
+/// instrucción
+#[inline]
+fn relative(addr: u16, offset: u8) -> u16 {
+    addr.wrapping_add(2).wrapping_add_signed(offset as i8 as i16)
+}
+
+fn reg_name(reg: Reg) -> &'static str {
+    match reg {
+        Reg::A => "a",
+        Reg::F => "f",
+        Reg::B => "b",
+        Reg::C => "c",
+        Reg::D => "d",
+        Reg::E => "e",
+        Reg::H => "h",
+        Reg::L => "l",
+        Reg::SP => "sp",
+        Reg::Invalid => "?",
+    }
+}
+
+/// Los registros anchos se nombran por el primero de los dos
+fn wide_name(reg: Reg) -> &'static str {
+    match reg {
+        Reg::A => "af",
+        Reg::B => "bc",
+        Reg::D => "de",
+        Reg::H => "hl",
+        Reg::SP => "sp",
+        _ => "?",
+    }
+}
+
+fn mem_name(addr: RegAddr) -> &'static str {
+    match addr {
+        RegAddr::HL => "hl",
+        RegAddr::HLPlus => "hl+",
+        RegAddr::HLMinus => "hl-",
+        RegAddr::BC => "bc",
+        RegAddr::DE => "de",
+        RegAddr::Invalid => "?",
+    }
+}
+
+fn cond_name(cond: u8) -> &'static str {
+    match cond {
+        NZ => "nz",
+        NC => "nc",
+        Z => "z",
+        C => "c",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble_with_symbols() {
+        let mut memory = vec![0; 0x8000];
+        // LD B, $12; JR Main; LD A, B
+        memory[0x150..0x155].copy_from_slice(&[0x06, 0x12, 0x18, 0xFC, 0x78]);
+        let symbols = SymbolTable::parse("00:0150 Main");
+
+        let lines = disassemble_range(memory.as_slice(), 0x150, 3, 1, Some(&symbols));
+        let text = lines.iter()
+            .map(|line| (line.addr, line.label.as_deref(), line.text.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(text, [
+            (0x150, Some("Main"), "ld b, $12"),
+            (0x152, None, "jr Main"),
+            (0x154, None, "ld a, b"),
+        ]);
+        assert_eq!(lines[1].bytes, [0x18, 0xFC]);
+
+        let line = disassemble(memory.as_slice(), 0x152, 1, None);
+        assert_eq!(line.text.as_deref(), Some("jr $0150"));
+    }
+}
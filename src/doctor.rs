@@ -0,0 +1,47 @@
+use std::fmt::Write;
+
+use crate::mmu::Bus;
+use crate::{Cpu, Reg};
+
+/// Línea de log con el estado de la CPU antes de ejecutar la instrucción en
+/// `pc`, en el formato exacto que compara Gameboy Doctor:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+pub(crate) fn doctor_line<B: Bus + ?Sized>(cpu: &Cpu, bus: &B) -> String {
+    let mut line = String::with_capacity(80);
+    let regs = [
+        ("A", Reg::A), ("F", Reg::F), ("B", Reg::B), ("C", Reg::C),
+        ("D", Reg::D), ("E", Reg::E), ("H", Reg::H), ("L", Reg::L),
+    ];
+    for (name, reg) in regs {
+        let _ = write!(line, "{name}:{:02X} ", cpu.read_reg(reg));
+    }
+
+    let pc = cpu.pc();
+    let mem = |offset: u16| bus.read(pc.wrapping_add(offset));
+    let _ = write!(line, "SP:{:04X} PC:{pc:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        cpu.read_widereg(Reg::SP), mem(0), mem(1), mem(2), mem(3));
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doctor_format() {
+        let mut cpu = Cpu::new();
+        for (reg, value) in [(Reg::A, 0x01), (Reg::F, 0xB0), (Reg::C, 0x13),
+            (Reg::E, 0xD8), (Reg::H, 0x01), (Reg::L, 0x4D)]
+        {
+            cpu.write_reg(reg, value);
+        }
+        cpu.write_widereg(Reg::SP, 0xFFFE);
+        cpu.set_pc(0x0100);
+
+        let mut memory = vec![0; 0x8000];
+        memory[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x13, 0x02]);
+        assert_eq!(doctor_line(&cpu, memory.as_slice()),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02");
+    }
+}
@@ -0,0 +1,151 @@
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{Point, Size};
+use embedded_graphics_core::pixelcolor::Rgb888;
+use embedded_graphics_core::primitives::Rectangle;
+
+use crate::frame::{Frame, Rect, SCREEN_WIDTH};
+use crate::sink::VideoSink;
+
+/// Sink que dibuja los frames en cualquier `DrawTarget` de embedded-graphics,
+/// normalmente el driver de una pantalla SPI en un RP2040 o un ESP32. Por el
+/// bus solo cabe una fracción de los frames completos, así que se guarda lo
+/// que hay en la pantalla y en cada frame se envía solo el rectángulo que
+/// cubre los píxeles que han cambiado, fila a fila
+pub struct DisplaySink<D: DrawTarget> {
+    display: D,
+
+    /// Esquina de la pantalla donde va la de la Game Boy, para centrarla en
+    /// pantallas más grandes
+    origin: Point,
+
+    /// Lo que hay ahora en la pantalla, `None` si no se sabe y hay que
+    /// enviar el frame entero
+    shown: Option<Frame>,
+}
+
+impl<D> DisplaySink<D>
+where
+    D: DrawTarget + Send,
+    D::Color: From<Rgb888>,
+{
+    pub fn new(display: D) -> Self {
+        Self { display, origin: Point::zero(), shown: None }
+    }
+
+    pub fn origin(mut self, origin: Point) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Enviar el siguiente frame entero, para cuando algo más ha dibujado
+    /// encima
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    #[inline]
+    pub fn display(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn into_inner(self) -> D {
+        self.display
+    }
+}
+
+impl<D> VideoSink for DisplaySink<D>
+where
+    D: DrawTarget + Send,
+    D::Color: From<Rgb888>,
+{
+    fn present(&mut self, frame: &Frame) {
+        let dirty = match &self.shown {
+            Some(shown) => shown.diff(frame).bounds(),
+            None => Some(Rect::SCREEN),
+        };
+        let Some(dirty) = dirty else {
+            return;
+        };
+
+        let area = Rectangle::new(
+            self.origin + Point::new(dirty.x as i32, dirty.y as i32),
+            Size::new(dirty.width as u32, dirty.height as u32),
+        );
+        let pixels = (dirty.y..dirty.y + dirty.height).flat_map(|y| {
+            let row = &frame.pixels()[(y * SCREEN_WIDTH + dirty.x) * 4..][..dirty.width * 4];
+            row.chunks_exact(4).map(|pixel| Rgb888::new(pixel[0], pixel[1], pixel[2]).into())
+        });
+        // Si falla el envío no se sabe qué ha llegado a la pantalla, el
+        // siguiente frame va entero. El error no se propaga para no parar
+        // la emulación, igual que en `TerminalSink`
+        self.shown = match self.display.fill_contiguous(&area, pixels) {
+            Ok(()) => Some(frame.clone()),
+            Err(_) => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics_core::geometry::OriginDimensions;
+    use embedded_graphics_core::pixelcolor::Rgb565;
+    use embedded_graphics_core::Pixel;
+
+    use super::*;
+    use crate::frame::SCREEN_HEIGHT;
+
+    /// Pantalla falsa que apunta las áreas que le llegan
+    #[derive(Default)]
+    struct Recorder {
+        areas: Vec<(Rectangle, usize)>,
+    }
+
+    impl OriginDimensions for Recorder {
+        fn size(&self) -> Size {
+            Size::new(240, 240)
+        }
+    }
+
+    impl DrawTarget for Recorder {
+        type Color = Rgb565;
+        type Error = std::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, _: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            unreachable!()
+        }
+
+        fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Self::Color>,
+        {
+            self.areas.push((*area, colors.into_iter().count()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dirty_rect_updates() {
+        let mut sink = DisplaySink::new(Recorder::default()).origin(Point::new(40, 48));
+        let mut frame = Frame::new();
+        sink.present(&frame);
+        sink.present(&frame);
+
+        frame.set_pixel(10, 20, [0, 0, 0, 255]);
+        frame.set_pixel(3, 30, [0, 0, 0, 255]);
+        sink.present(&frame);
+
+        sink.invalidate();
+        sink.present(&frame);
+
+        let areas = sink.into_inner().areas;
+        let full = SCREEN_WIDTH * SCREEN_HEIGHT;
+        assert_eq!(areas, [
+            (Rectangle::new(Point::new(40, 48), Size::new(160, 144)), full),
+            (Rectangle::new(Point::new(43, 68), Size::new(8, 11)), 8 * 11),
+            (Rectangle::new(Point::new(40, 48), Size::new(160, 144)), full),
+        ]);
+    }
+}
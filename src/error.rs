@@ -0,0 +1,115 @@
+use std::fmt;
+
+use crate::model::Model;
+
+#[cfg(feature = "serde")]
+use crate::state::StateError;
+
+/// Tamaño máximo de una ROM sin mapper, lo que cabe en 0x0000-0x7FFF
+pub const MAX_ROM_SIZE: usize = 0x8000;
+
+/// Errores de la API pública. Los panics quedan para invariantes internas,
+/// todo lo que puede venir de una ROM, una boot ROM o un fichero del
+/// usuario llega como uno de estos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// La ROM no cabe en los 32 KiB que se mapean sin mapper
+    RomTooLarge { size: usize },
+
+    /// La ROM es más grande que 32 KiB y la cabecera pide un mapper (el byte
+    /// 0x0147) que todavía no se emula
+    UnsupportedMapper(u8),
+
+    /// La ROM es más corta que la cabecera del cartucho (0x0150 bytes)
+    TruncatedHeader { size: usize },
+
+    /// La ROM solo funciona en CGB y el modelo no lo es, ver
+    /// `GameBoy::set_allow_cgb_only`
+    RequiresCgb(Model),
+
+    /// La boot ROM no mide 256 bytes (DMG) ni 2304 (CGB)
+    InvalidBootRom { size: usize },
+
+    /// La RAM del cartucho no cabe en su región de 8 KiB
+    SramTooLarge { size: usize },
+
+    /// No se pudo decodificar la instrucción en `addr`
+    InvalidOpcode { addr: u16, opcode: u8 },
+
+    /// La instrucción en `addr` existe pero la CPU todavía no la emula
+    Unimplemented { addr: u16, opcode: u8 },
+
+    /// No se pudo cargar el save state
+    #[cfg(feature = "serde")]
+    State(StateError),
+
+    /// El mapa de controles de `InputMap::from_toml` no es válido
+    #[cfg(feature = "toml")]
+    InputConfig(String),
+
+    /// El DAT de `RomDatabase::from_dat` no es válido
+    #[cfg(feature = "romdb")]
+    RomDatabase(String),
+
+    /// El texto de `InputScript::parse` no es válido
+    InputScript(String),
+
+    /// `GameBoy::step_back` sin historial o sin un snapshot tan antiguo
+    NoHistory,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RomTooLarge { size } => {
+                write!(f, "la ROM ocupa {size} bytes, sin mapper caben {MAX_ROM_SIZE}")
+            },
+            Error::UnsupportedMapper(kind) => {
+                write!(f, "el tipo de cartucho {kind:#04X} usa un mapper no soportado")
+            },
+            Error::TruncatedHeader { size } => {
+                write!(f, "la ROM ocupa {size} bytes, no llega al final de la cabecera")
+            },
+            Error::RequiresCgb(model) => {
+                write!(f, "la ROM solo funciona en Game Boy Color, no en {model:?}")
+            },
+            Error::InvalidBootRom { size } => {
+                write!(f, "la boot ROM ocupa {size} bytes, deben ser 256 o 2304")
+            },
+            Error::SramTooLarge { size } => {
+                write!(f, "la RAM del cartucho ocupa {size} bytes, caben 8192")
+            },
+            Error::InvalidOpcode { addr, opcode } => {
+                write!(f, "opcode inválido {opcode:#04X} en {addr:#06X}")
+            },
+            Error::Unimplemented { addr, opcode } => {
+                write!(f, "opcode {opcode:#04X} en {addr:#06X} sin implementar")
+            },
+            #[cfg(feature = "serde")]
+            Error::State(err) => err.fmt(f),
+            #[cfg(feature = "toml")]
+            Error::InputConfig(err) => write!(f, "mapa de controles inválido: {err}"),
+            #[cfg(feature = "romdb")]
+            Error::RomDatabase(err) => write!(f, "base de datos de ROMs inválida: {err}"),
+            Error::InputScript(err) => write!(f, "guion de entrada inválido: {err}"),
+            Error::NoHistory => write!(f, "no hay historial para volver atrás"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "serde")]
+            Error::State(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<StateError> for Error {
+    fn from(err: StateError) -> Self {
+        Error::State(err)
+    }
+}
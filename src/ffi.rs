@@ -0,0 +1,159 @@
+use std::ptr;
+use std::slice;
+
+use crate::frame::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::GameBoy;
+use crate::joypad::Button;
+
+// Con el valor escrito para que cbindgen, que solo lee este fichero, los
+// pueda poner en la cabecera
+
+/// Ancho del framebuffer en píxeles
+pub const GAMEBOI_SCREEN_WIDTH: usize = 160;
+
+/// Alto del framebuffer en píxeles
+pub const GAMEBOI_SCREEN_HEIGHT: usize = 144;
+
+const _: () = assert!(GAMEBOI_SCREEN_WIDTH == SCREEN_WIDTH
+    && GAMEBOI_SCREEN_HEIGHT == SCREEN_HEIGHT);
+
+/// Botones para `gameboi_set_button`
+pub const GAMEBOI_BUTTON_RIGHT: u8 = 0;
+pub const GAMEBOI_BUTTON_LEFT: u8 = 1;
+pub const GAMEBOI_BUTTON_UP: u8 = 2;
+pub const GAMEBOI_BUTTON_DOWN: u8 = 3;
+pub const GAMEBOI_BUTTON_A: u8 = 4;
+pub const GAMEBOI_BUTTON_B: u8 = 5;
+pub const GAMEBOI_BUTTON_SELECT: u8 = 6;
+pub const GAMEBOI_BUTTON_START: u8 = 7;
+
+/// Crear una `GameBoy` sin ROM, se libera con `gameboi_destroy`
+#[no_mangle]
+pub extern "C" fn gameboi_create() -> *mut GameBoy {
+    Box::into_raw(Box::new(GameBoy::new()))
+}
+
+/// Liberar una `GameBoy` de `gameboi_create`
+///
+/// # Safety
+/// `gb` debe venir de `gameboi_create` y no se puede volver a usar
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_destroy(gb: *mut GameBoy) {
+    if !gb.is_null() {
+        drop(Box::from_raw(gb));
+    }
+}
+
+/// Cargar una ROM de `len` bytes, se copia. `false` si no es válida
+///
+/// # Safety
+/// `rom` debe apuntar a `len` bytes legibles
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_load_rom(gb: *mut GameBoy, rom: *const u8, len: usize) -> bool {
+    let (Some(gb), false) = (gb.as_mut(), rom.is_null()) else {
+        return false;
+    };
+    gb.load_rom(slice::from_raw_parts(rom, len)).is_ok()
+}
+
+/// Emular un frame completo, `false` si la CPU encontró un opcode inválido
+///
+/// # Safety
+/// `gb` debe ser nulo o venir de `gameboi_create`
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_run_frame(gb: *mut GameBoy) -> bool {
+    gb.as_mut().is_some_and(|gb| gb.step_frame().is_ok())
+}
+
+/// Píxeles RGBA del último frame, `GAMEBOI_SCREEN_WIDTH` por
+/// `GAMEBOI_SCREEN_HEIGHT` de 4 bytes por fila. El puntero es válido hasta
+/// la siguiente llamada que reciba la misma `GameBoy`
+///
+/// # Safety
+/// `gb` debe ser nulo o venir de `gameboi_create`
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_framebuffer(gb: *const GameBoy) -> *const u8 {
+    gb.as_ref().map_or(ptr::null(), |gb| gb.frame().pixels().as_ptr())
+}
+
+/// Pulsar o soltar uno de los `GAMEBOI_BUTTON_*`, `false` si no existe
+///
+/// # Safety
+/// `gb` debe ser nulo o venir de `gameboi_create`
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_set_button(gb: *mut GameBoy, button: u8, pressed: bool) -> bool {
+    let (Some(gb), Some(&button)) = (gb.as_mut(), Button::ALL.get(button as usize)) else {
+        return false;
+    };
+    gb.set_button(button, pressed);
+    true
+}
+
+/// Guardar el estado en `buffer` y devolver su tamaño. Si no cabe en
+/// `capacity` bytes no se escribe nada, se llama otra vez con un buffer del
+/// tamaño devuelto
+///
+/// # Safety
+/// `buffer` debe apuntar a `capacity` bytes escribibles o ser nulo con
+/// `capacity` 0
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_save_state(gb: *const GameBoy, buffer: *mut u8,
+    capacity: usize) -> usize
+{
+    let Some(gb) = gb.as_ref() else {
+        return 0;
+    };
+    let state = gb.save_state();
+    if state.len() <= capacity && !buffer.is_null() {
+        ptr::copy_nonoverlapping(state.as_ptr(), buffer, state.len());
+    }
+    state.len()
+}
+
+/// Cargar un estado de `gameboi_save_state`, `false` si no es válido o es
+/// de otra ROM, y entonces el estado no cambia
+///
+/// # Safety
+/// `state` debe apuntar a `len` bytes legibles
+#[no_mangle]
+pub unsafe extern "C" fn gameboi_load_state(gb: *mut GameBoy, state: *const u8,
+    len: usize) -> bool
+{
+    let (Some(gb), false) = (gb.as_mut(), state.is_null()) else {
+        return false;
+    };
+    gb.load_state(slice::from_raw_parts(state, len)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn c_api() {
+        unsafe {
+            let gb = gameboi_create();
+            // No cabe en los 32 KiB del banco fijo
+            let rom = vec![0; 0x8001];
+            assert!(!gameboi_load_rom(gb, rom.as_ptr(), rom.len()));
+            assert!(gameboi_load_rom(gb, rom.as_ptr(), 0x8000));
+            assert!(gameboi_run_frame(gb));
+            assert!(!gameboi_framebuffer(gb).is_null());
+            assert!(gameboi_set_button(gb, GAMEBOI_BUTTON_START, true));
+            assert!(!gameboi_set_button(gb, 8, true));
+
+            // Primero se pregunta el tamaño
+            let size = gameboi_save_state(gb, ptr::null_mut(), 0);
+            let mut state = vec![0; size];
+            assert_eq!(gameboi_save_state(gb, state.as_mut_ptr(), state.len()), size);
+            assert!(gameboi_run_frame(gb));
+            assert!(gameboi_load_state(gb, state.as_ptr(), state.len()));
+            assert!(!gameboi_load_state(gb, state.as_ptr(), 3));
+            assert_eq!((*gb).frame_count(), 1);
+            gameboi_destroy(gb);
+
+            assert!(!gameboi_run_frame(ptr::null_mut()));
+            assert_eq!(gameboi_save_state(ptr::null(), ptr::null_mut(), 0), 0);
+        }
+    }
+}
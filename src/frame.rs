@@ -0,0 +1,223 @@
+/// Ancho de la pantalla en píxeles
+pub const SCREEN_WIDTH: usize = 160;
+
+/// Alto de la pantalla en píxeles
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// Imagen de la pantalla en formato RGBA8, fila a fila desde la esquina
+/// superior izquierda
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frame {
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Crear un frame con todos los píxeles en blanco, que es lo que muestra
+    /// la pantalla con el LCD apagado
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+        }
+    }
+
+    /// Todos los píxeles como bytes RGBA
+    #[inline]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    #[inline]
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+
+    /// Color RGBA del píxel en `(x, y)`
+    #[inline]
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        debug_assert!(x < SCREEN_WIDTH && y < SCREEN_HEIGHT);
+        let i = (y * SCREEN_WIDTH + x) * 4;
+        self.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        debug_assert!(x < SCREEN_WIDTH && y < SCREEN_HEIGHT);
+        let i = (y * SCREEN_WIDTH + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    /// Píxeles que cambian de este frame a `other`, agrupados en bandas de
+    /// filas consecutivas con cambios
+    pub fn diff(&self, other: &Frame) -> FrameDiff {
+        let mut diff = FrameDiff::default();
+        let rows = self.pixels.chunks_exact(SCREEN_WIDTH * 4)
+            .zip(other.pixels.chunks_exact(SCREEN_WIDTH * 4));
+        for (y, (old, new)) in rows.enumerate() {
+            let mut changed = old.chunks_exact(4).zip(new.chunks_exact(4))
+                .enumerate()
+                .filter(|(_, (a, b))| a != b)
+                .map(|(x, _)| x);
+            let Some(first) = changed.next() else {
+                continue;
+            };
+            let (last, count) = changed.fold((first, 1), |(_, count), x| (x, count + 1));
+            diff.changed += count;
+
+            // La fila continúa la banda anterior si es la siguiente
+            match diff.rects.last_mut() {
+                Some(rect) if rect.y + rect.height == y => {
+                    let end = (rect.x + rect.width).max(last + 1);
+                    rect.x = rect.x.min(first);
+                    rect.width = end - rect.x;
+                    rect.height += 1;
+                },
+                _ => diff.rects.push(Rect { x: first, y, width: last + 1 - first, height: 1 }),
+            }
+        }
+        diff
+    }
+
+    /// Hash FNV-1a del tono de gris (0 blanco a 3 negro) de cada píxel, no
+    /// depende de la paleta con la que se coloree la imagen
+    pub fn shade_hash(&self) -> u64 {
+        self.pixels.chunks_exact(4)
+            .map(|pixel| shade(pixel[0], pixel[1], pixel[2]))
+            .fold(0xCBF2_9CE4_8422_2325, |hash, shade| {
+                (hash ^ shade as u64).wrapping_mul(0x0000_0100_0000_01B3)
+            })
+    }
+
+    /// Guardar el frame como PNG RGBA
+    #[cfg(feature = "image")]
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>)
+        -> std::io::Result<()>
+    {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+/// Tono de gris de un color según su luminancia
+#[inline]
+fn shade(r: u8, g: u8, b: u8) -> u8 {
+    let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+    ((255 - luma + 42) / 85) as u8
+}
+
+/// Rectángulo de la pantalla en píxeles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// La pantalla entera
+    pub const SCREEN: Rect = Rect { x: 0, y: 0, width: SCREEN_WIDTH, height: SCREEN_HEIGHT };
+
+    /// Menor rectángulo que cubre a los dos
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// Diferencias entre dos frames, ver `Frame::diff`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameDiff {
+    /// Píxeles distintos
+    pub changed: usize,
+
+    /// Un rectángulo por cada banda de filas consecutivas con cambios, con
+    /// las columnas que cubren todos los cambios de la banda. Ordenados de
+    /// arriba a abajo
+    pub rects: Vec<Rect>,
+}
+
+impl FrameDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changed == 0
+    }
+
+    /// Menor rectángulo que cubre todos los cambios, `None` si no hay
+    pub fn bounds(&self) -> Option<Rect> {
+        self.rects.iter().copied().reduce(|a, b| a.union(&b))
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_bands() {
+        let old = Frame::new();
+        let mut new = old.clone();
+        assert!(old.diff(&new).is_empty());
+        assert_eq!(old.diff(&new).bounds(), None);
+
+        let black = [0, 0, 0, 255];
+        new.set_pixel(10, 20, black);
+        new.set_pixel(3, 21, black);
+        new.set_pixel(12, 21, black);
+        new.set_pixel(100, 50, black);
+        let diff = old.diff(&new);
+        assert_eq!(diff.changed, 4);
+        assert_eq!(diff.rects, [
+            Rect { x: 3, y: 20, width: 10, height: 2 },
+            Rect { x: 100, y: 50, width: 1, height: 1 },
+        ]);
+        assert_eq!(diff.bounds(), Some(Rect { x: 3, y: 20, width: 98, height: 31 }));
+    }
+
+    #[test]
+    fn shade_hash() {
+        assert_eq!([0xFF, 0xA5, 0x52, 0x00].map(|gray| shade(gray, gray, gray)), [0, 1, 2, 3]);
+
+        // Cambiar de tono cambia el hash, cambiar de paleta no
+        let mut frame = Frame::new();
+        frame.set_pixel(3, 4, [0, 0, 0, 0xFF]);
+        assert_ne!(frame.shade_hash(), Frame::new().shade_hash());
+        let mut tinted = frame.clone();
+        tinted.set_pixel(3, 4, [0x08, 0x18, 0x20, 0xFF]);
+        assert_eq!(tinted.shade_hash(), frame.shade_hash());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn save_png() {
+        let path = std::env::temp_dir()
+            .join(format!("gameboi-frame-{}.png", std::process::id()));
+        let mut frame = Frame::new();
+        frame.set_pixel(5, 7, [0x12, 0x34, 0x56, 0xFF]);
+        frame.save_png(&path).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut reader = png::Decoder::new(&data[..]).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32));
+        assert_eq!(&pixels[..info.buffer_size()], frame.pixels());
+    }
+}
@@ -0,0 +1,2011 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "ppu")]
+use crate::frame::Frame;
+use crate::access::{AccessKind, AccessLog, LoggedBus, MemAccess};
+use crate::debugger::{inspect_stack, Debugger, StackEntry, WatchEvent, WatchId, WatchMode};
+use crate::doctor::doctor_line;
+use crate::error::Error;
+use crate::history::RegHistory;
+use crate::hooks::{FrameEvent, HookId, Hooks, InstructionEvent, InterruptEvent};
+use crate::joypad::{Button, Joypad};
+use crate::limiter::{FrameLimiter, CPU_FREQUENCY};
+use crate::mmu::{Bus, Mmu, IF, INT_JOYPAD};
+use crate::model::{CgbSupport, Model};
+#[cfg(feature = "ppu")]
+use crate::palette::{CompatPalette, PaletteHook};
+use crate::profiler::Profiler;
+#[cfg(feature = "serde")]
+use crate::rewind::{Rewind, Snapshot};
+use crate::rng::Rng;
+use crate::script::InputScript;
+use crate::sgb::Sgb;
+use crate::stats::{FrameStats, SubsystemStats};
+#[cfg(feature = "serde")]
+use crate::state::{rom_hash, StateReader, StateWriter};
+#[cfg(feature = "serde")]
+use crate::state::{CHUNK_CPU, CHUNK_FACADE, CHUNK_IME, CHUNK_MMU};
+#[cfg(all(feature = "serde", feature = "ppu"))]
+use crate::state::{CHUNK_FRAME, CHUNK_PALETTE};
+#[cfg(feature = "apu")]
+use crate::sink::AudioSink;
+use crate::sink::BatterySink;
+use crate::tracer::Tracer;
+use crate::watch::WatchExpr;
+#[cfg(feature = "ppu")]
+use crate::sink::VideoSink;
+use crate::{Cpu, Instr, Reg, INTERRUPT_DISPATCH_CYCLES};
+
+/// T-cycles que dura un frame completo de la pantalla (154 líneas de 456)
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+/// Callback de `GameBoy::set_rom_write_trap`
+pub type RomWriteTrap = Box<dyn FnMut(&MemAccess) + Send>;
+
+/// Por qué se detuvo una de las funciones `run_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// Se ejecutaron todos los ciclos pedidos
+    CyclesElapsed,
+
+    /// Se cumplió la condición de parada
+    ConditionMet,
+
+    /// El `pc` llegó a la dirección pedida
+    ReachedAddress,
+
+    /// No se pudo decodificar la instrucción en `pc`
+    InvalidOpcode,
+
+    /// La siguiente instrucción tiene un breakpoint del `Debugger`
+    HitBreakpoint,
+}
+
+/// Resultado de una de las funciones `run_*`, siempre se detienen entre dos
+/// instrucciones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    /// T-cycles que realmente transcurrieron
+    pub cycles: u64,
+
+    pub result: StepResult,
+}
+
+/// Instrucción ejecutada por `GameBoy::step_instruction`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StepInfo {
+    /// Dirección de la instrucción
+    pub addr: u16,
+
+    /// Banco de la ROM mapeado en `addr`
+    pub bank: u16,
+
+    pub instr: Instr,
+
+    /// T-cycles que tardó
+    pub cycles: u32,
+
+    /// `pc` después de ejecutarla, la siguiente instrucción
+    pub new_pc: u16,
+}
+
+/// La Game Boy completa, es dueña de la CPU, la MMU y los periféricos y se
+/// encarga de conectarlos, es el punto de entrada para quien quiera emular
+/// un juego sin montar las piezas a mano
+///
+/// Por defecto no tiene ningún sink conectado (modo headless), los frames se
+/// siguen renderizando en memoria y se pueden leer con `frame`, o se puede
+/// desactivar el renderizado del todo con `set_rendering` para ejecutar más
+/// rápido tests de CPU
+///
+/// Sin la feature `ppu` no hay framebuffer ni `VideoSink` y sin `apu` no hay
+/// `AudioSink`, queda solo la CPU, la MMU y los periféricos que no dependen
+/// de ellos
+pub struct GameBoy {
+    cpu: Cpu,
+    mmu: Mmu,
+
+    /// Último frame completo
+    // TODO: Lo debe rellenar la PPU, mientras tanto se queda en blanco
+    #[cfg(feature = "ppu")]
+    frame: Frame,
+
+    /// Frames completados desde el inicio
+    frame_count: u64,
+
+    /// T-cycles transcurridos dentro del frame actual
+    frame_cycles: u32,
+
+    /// Limitador usado por `run_frame_realtime`
+    limiter: FrameLimiter,
+
+    /// Modo turbo, se ejecuta sin esperar al limitador
+    fast_forward: bool,
+
+    /// En modo turbo solo se renderiza 1 de cada `frame_skip + 1` frames
+    frame_skip: u32,
+
+    /// Renderizar los frames, desactivarlo solo tiene sentido en headless
+    rendering: bool,
+
+    /// Cargar los juegos solo de CGB en los demás modelos
+    allow_cgb_only: bool,
+
+    #[cfg(feature = "ppu")]
+    video_sink: Option<Box<dyn VideoSink>>,
+    #[cfg(feature = "apu")]
+    audio_sink: Option<Box<dyn AudioSink>>,
+
+    /// Destino de la RAM del cartucho y cuántos T-cycles después de la
+    /// última escritura se guarda automáticamente, `None` para guardar solo
+    /// con `flush_sram`
+    battery_sink: Option<Box<dyn BatterySink>>,
+    sram_debounce: Option<u64>,
+
+    /// Paleta elegida para el modo compatibilidad de la CGB, `None` para la
+    /// que asigne la boot ROM
+    #[cfg(feature = "ppu")]
+    compat_palette: Option<CompatPalette>,
+    #[cfg(feature = "ppu")]
+    palette_hook: Option<PaletteHook>,
+
+    /// Semilla de la que se derivó el estado inicial, `None` si se creó con
+    /// `new` y la RAM empieza a 0
+    seed: Option<u64>,
+
+    debugger: Debugger,
+
+    /// Destino del log de Gameboy Doctor, `None` si está desactivado
+    doctor_log: Option<Box<dyn Write + Send>>,
+
+    tracer: Option<Tracer>,
+
+    profiler: Option<Profiler>,
+
+    access_log: Option<AccessLog>,
+
+    /// Aviso de las escrituras en la ROM que no van a un registro del mapper
+    rom_write_trap: Option<RomWriteTrap>,
+
+    reg_history: Option<RegHistory>,
+
+    hooks: Hooks,
+    stats: Option<SubsystemStats>,
+    frame_stats: Option<FrameStats>,
+
+    /// Instrucciones ejecutadas (o pasos con la CPU detenida) desde el
+    /// inicio, es la línea de tiempo de `step_back`
+    instructions: u64,
+
+    #[cfg(feature = "serde")]
+    rewind: Option<Rewind>,
+}
+
+impl GameBoy {
+    pub fn new() -> Self {
+        Self {
+            cpu: Cpu::new(),
+            mmu: Mmu::new(),
+            #[cfg(feature = "ppu")]
+            frame: Frame::new(),
+            frame_count: 0,
+            frame_cycles: 0,
+            limiter: FrameLimiter::new(),
+            fast_forward: false,
+            frame_skip: 0,
+            rendering: true,
+            allow_cgb_only: false,
+            #[cfg(feature = "ppu")]
+            video_sink: None,
+            #[cfg(feature = "apu")]
+            audio_sink: None,
+            battery_sink: None,
+            sram_debounce: None,
+            #[cfg(feature = "ppu")]
+            compat_palette: None,
+            #[cfg(feature = "ppu")]
+            palette_hook: None,
+            seed: None,
+            debugger: Debugger::new(),
+            doctor_log: None,
+            tracer: None,
+            profiler: None,
+            access_log: None,
+            rom_write_trap: None,
+            reg_history: None,
+            hooks: Hooks::default(),
+            stats: None,
+            frame_stats: None,
+            instructions: 0,
+            #[cfg(feature = "serde")]
+            rewind: None,
+        }
+    }
+
+    /// Crear una Game Boy en modo determinista: todo lo que en el hardware
+    /// real es impredecible (de momento el contenido inicial de la RAM; el
+    /// cable link sin conectar siempre lee 0xFF) se deriva de `seed`, así dos
+    /// ejecuciones con la misma semilla y la misma entrada dan exactamente el
+    /// mismo estado
+    pub fn with_seed(seed: u64) -> Self {
+        let mut gb = Self::new();
+        let mut rng = Rng::new(seed);
+        gb.mmu.fill_uninitialized_ram(&mut rng);
+        gb.seed = Some(seed);
+        gb
+    }
+
+    /// Semilla del estado inicial, es la que se debe guardar en un `Movie`
+    #[inline]
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Empezar a construir una Game Boy configurada
+    pub fn builder() -> GameBoyBuilder {
+        GameBoyBuilder::new()
+    }
+
+    /// Crear una Game Boy de un modelo concreto
+    pub fn with_model(model: Model) -> Self {
+        let mut gb = Self::new();
+        gb.set_model(model);
+        gb
+    }
+
+    /// Cambiar el modelo emulado, se debe hacer antes de cargar la ROM para
+    /// que los registros arranquen con los valores de ese modelo
+    pub fn set_model(&mut self, model: Model) {
+        self.mmu.set_model(model);
+    }
+
+    #[inline]
+    pub fn model(&self) -> Model {
+        self.mmu.model()
+    }
+
+    /// Ver `Mmu::is_cgb_mode`
+    #[inline]
+    pub fn is_cgb_mode(&self) -> bool {
+        self.mmu.is_cgb_mode()
+    }
+
+    /// Ver `Mmu::cgb_support`
+    #[inline]
+    pub fn cgb_support(&self) -> CgbSupport {
+        self.mmu.cgb_support()
+    }
+
+    /// Por defecto `load_rom` rechaza los juegos solo de CGB si el modelo no
+    /// es una CGB. Permitiéndolo se ejecutan como en el hardware real: el
+    /// juego ve que no está en una CGB y normalmente muestra su pantalla de
+    /// aviso o se cuelga
+    pub fn set_allow_cgb_only(&mut self, allow: bool) {
+        self.allow_cgb_only = allow;
+    }
+
+    /// Ver `Mmu::sgb`
+    #[inline]
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.mmu.sgb()
+    }
+
+    /// Imagen de 256x224 RGBA con el borde del Super Game Boy y el último
+    /// frame coloreado, `None` si no se está ejecutando en un SGB
+    #[cfg(feature = "ppu")]
+    pub fn sgb_frame(&mut self) -> Option<Vec<u8>> {
+        let frame = &self.frame;
+        self.mmu.sgb_mut().map(|sgb| sgb.render(frame))
+    }
+
+    /// Elegir la paleta con la que se colorea un juego de DMG en una CGB,
+    /// `None` deja la que asigne la boot ROM
+    #[cfg(feature = "ppu")]
+    pub fn set_compat_palette(&mut self, palette: Option<CompatPalette>) {
+        self.compat_palette = palette;
+    }
+
+    /// Elegir la paleta de los juegos de DMG al cargarlos, antes de mirar la
+    /// tabla de títulos de la boot ROM, para que el frontend tenga sus
+    /// propias paletas por juego. Si devuelve `None` se usa la de la tabla.
+    /// Los botones mantenidos al arrancar siguen teniendo prioridad
+    #[cfg(feature = "ppu")]
+    pub fn set_compat_palette_hook(
+        &mut self,
+        hook: impl Fn(&[u8]) -> Option<CompatPalette> + Send + 'static,
+    ) {
+        self.palette_hook = Some(Box::new(hook));
+    }
+
+    #[cfg(feature = "ppu")]
+    pub fn clear_compat_palette_hook(&mut self) {
+        self.palette_hook = None;
+    }
+
+    /// Paleta del modo compatibilidad, `None` si no se está ejecutando un
+    /// juego de DMG en una CGB
+    #[cfg(feature = "ppu")]
+    pub fn compat_palette(&self) -> Option<CompatPalette> {
+        if !self.model().is_cgb() || self.is_cgb_mode() {
+            return None;
+        }
+        Some(self.compat_palette.unwrap_or_default())
+    }
+
+    /// Cargar la ROM del cartucho, si no hay boot ROM se dejan la CPU y los
+    /// registros de IO como los dejaría la del modelo, falla si la ROM no
+    /// cabe en memoria
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.check_cgb_only(rom)?;
+        self.mmu.load_rom(rom)?;
+        if !self.mmu.is_boot_rom_mapped() {
+            self.skip_boot_cpu(rom);
+            self.mmu.apply_initial_io();
+
+            // La boot ROM deja elegir la paleta con los botones durante el
+            // logo y si no la busca por el título
+            #[cfg(feature = "ppu")]
+            if self.compat_palette.is_none() {
+                self.compat_palette = CompatPalette::from_buttons(self.mmu.joypad())
+                    .or_else(|| self.palette_hook.as_ref().and_then(|hook| hook(rom)))
+                    .or_else(|| CompatPalette::from_title(rom));
+            }
+        }
+        Ok(())
+    }
+
+    /// Cambiar la ROM por una nueva versión sin reiniciar, para probar los
+    /// cambios de un homebrew sin volver a jugar hasta el punto que se está
+    /// probando. Se conservan la WRAM, la VRAM, la RAM del cartucho y los
+    /// registros de IO. Con `keep_cpu` la CPU sigue por donde iba, lo que
+    /// solo tiene sentido si el código que ejecuta no se ha movido; si no,
+    /// vuelve al punto de entrada con los registros que deja la boot ROM
+    /// (o al principio de esta si sigue mapeada). El historial de
+    /// `step_back` se vacía porque sus snapshots son de la ROM anterior
+    pub fn reload_rom(&mut self, rom: &[u8], keep_cpu: bool) -> Result<(), Error> {
+        self.check_cgb_only(rom)?;
+        self.mmu.reload_rom(rom)?;
+        if !keep_cpu {
+            if self.mmu.is_boot_rom_mapped() {
+                self.cpu.set_pc(0x0000);
+            } else {
+                self.skip_boot_cpu(rom);
+            }
+            self.cpu.set_ime(false);
+            self.cpu.wake();
+            self.debugger.clear_call_stack();
+        }
+
+        #[cfg(feature = "serde")]
+        if let Some(rewind) = self.rewind.take() {
+            self.set_rewind(Some(Rewind::new(rewind.interval(), rewind.capacity())));
+        }
+        Ok(())
+    }
+
+    /// Los juegos solo de CGB no se cargan en otros modelos salvo con
+    /// `set_allow_cgb_only`
+    fn check_cgb_only(&self, rom: &[u8]) -> Result<(), Error> {
+        if !self.model().is_cgb() && !self.allow_cgb_only
+            && CgbSupport::from_header(rom) == CgbSupport::Only
+        {
+            return Err(Error::RequiresCgb(self.model()));
+        }
+        Ok(())
+    }
+
+    /// Dejar la CPU en el punto de entrada como la deja la boot ROM
+    fn skip_boot_cpu(&mut self, rom: &[u8]) {
+        let registers = self.model().initial_registers(CgbSupport::from_header(rom));
+        let regs = [Reg::A, Reg::F, Reg::B, Reg::C,
+            Reg::D, Reg::E, Reg::H, Reg::L];
+        for (reg, value) in regs.into_iter().zip(registers) {
+            self.cpu.write_reg(reg, value);
+        }
+        self.cpu.write_widereg(Reg::SP, 0xFFFE);
+        self.cpu.set_pc(0x0100);
+    }
+
+    /// Mapear una boot ROM y empezar a ejecutarla desde el principio
+    pub fn load_boot_rom(&mut self, boot_rom: &[u8]) -> Result<(), Error> {
+        self.mmu.load_boot_rom(boot_rom)?;
+        self.cpu.set_pc(0x0000);
+        Ok(())
+    }
+
+    /// Hash de la ROM cargada, los save states solo se pueden cargar sobre
+    /// la misma ROM con la que se guardaron
+    #[cfg(feature = "serde")]
+    pub fn rom_hash(&self) -> u64 {
+        rom_hash(&self.mmu.memory()[..0x8000])
+    }
+
+    /// Guardar todo el estado emulado en un snapshot autocontenido, cargarlo
+    /// con `load_state` continúa la ejecución exactamente igual. El formato
+    /// está descrito en `StateWriter`
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_hash());
+        writer.chunk(CHUNK_CPU, &self.cpu);
+        writer.chunk(CHUNK_MMU, &self.mmu);
+        writer.chunk(CHUNK_FACADE, &(self.frame_count, self.frame_cycles, self.seed));
+        writer.chunk(CHUNK_IME, &self.cpu.ime_state());
+        #[cfg(feature = "ppu")]
+        {
+            writer.chunk(CHUNK_FRAME, &self.frame);
+            writer.chunk(CHUNK_PALETTE, &self.compat_palette);
+        }
+        writer.finish()
+    }
+
+    /// Cargar un snapshot de `save_state`, si falla el estado no cambia. Los
+    /// sinks, el modo turbo y los periféricos conectados se mantienen
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let reader = StateReader::new(state, self.rom_hash())?;
+        let cpu = reader.chunk(CHUNK_CPU)?;
+        let mmu = reader.chunk(CHUNK_MMU)?;
+        let (frame_count, frame_cycles, seed) = reader.chunk(CHUNK_FACADE)?;
+        let ime = reader.optional(CHUNK_IME)?;
+        #[cfg(feature = "ppu")]
+        let frame = reader.optional(CHUNK_FRAME)?;
+        #[cfg(feature = "ppu")]
+        let compat_palette = reader.optional(CHUNK_PALETTE)?;
+
+        self.cpu = cpu;
+        self.cpu.set_ime_state(ime.unwrap_or_default());
+        self.mmu.restore(mmu);
+        self.frame_count = frame_count;
+        self.frame_cycles = frame_cycles;
+        self.seed = seed;
+        #[cfg(feature = "ppu")]
+        {
+            // Un state de un build sin PPU no tiene frame, se deja en blanco
+            self.frame = frame.unwrap_or_default();
+            self.compat_palette = compat_palette.flatten();
+        }
+        Ok(())
+    }
+
+    /// Conectar (o desconectar con `None`) el historial de `step_back`, se
+    /// guarda un primer snapshot del momento actual
+    #[cfg(feature = "serde")]
+    pub fn set_rewind(&mut self, rewind: Option<Rewind>) {
+        self.rewind = rewind;
+        let snapshot = self.snapshot();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(snapshot);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[inline]
+    pub fn rewind(&self) -> Option<&Rewind> {
+        self.rewind.as_ref()
+    }
+
+    #[cfg(feature = "serde")]
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            at: self.instructions,
+            state: self.save_state(),
+            call_stack: self.debugger.save_call_stack(),
+        }
+    }
+
+    /// Deshacer la última instrucción: se carga el snapshot anterior del
+    /// `Rewind` y se reejecuta hasta la instrucción previa con los sinks y
+    /// las trazas desconectados. Devuelve `Error::NoHistory` si no hay
+    /// historial o no llega tan atrás
+    #[cfg(feature = "serde")]
+    pub fn step_back(&mut self) -> Result<(), Error> {
+        let target = self.instructions.checked_sub(1).ok_or(Error::NoHistory)?;
+        let mut rewind = self.rewind.take().ok_or(Error::NoHistory)?;
+        let result = self.replay_to(&rewind, target);
+        if result.is_ok() {
+            rewind.truncate(target);
+        }
+        self.rewind = Some(rewind);
+        result
+    }
+
+    #[cfg(feature = "serde")]
+    fn replay_to(&mut self, rewind: &Rewind, target: u64) -> Result<(), Error> {
+        let snapshot = rewind.snapshot_before(target).ok_or(Error::NoHistory)?;
+        self.load_state(&snapshot.state)?;
+        self.instructions = snapshot.at;
+        self.debugger.restore_call_stack(snapshot.call_stack.clone());
+        let inputs = rewind.inputs_between(snapshot.at, target);
+
+        // Lo que ya se vio no se vuelve a entregar a los sinks ni a las trazas
+        #[cfg(feature = "ppu")]
+        let video_sink = self.video_sink.take();
+        #[cfg(feature = "apu")]
+        let audio_sink = self.audio_sink.take();
+        let battery_sink = self.battery_sink.take();
+        let doctor_log = self.doctor_log.take();
+        let tracer = self.tracer.take();
+        let profiler = self.profiler.take();
+        let access_log = self.access_log.take();
+        let rom_write_trap = self.rom_write_trap.take();
+        let reg_history = self.reg_history.take();
+        let hooks = std::mem::take(&mut self.hooks);
+        let stats = self.stats.take();
+        let frame_stats = self.frame_stats.take();
+
+        let mut inputs = inputs.into_iter().peekable();
+        let mut result = Ok(());
+        while self.instructions < target && result.is_ok() {
+            while let Some((_, button, pressed)) = inputs
+                .next_if(|(at, ..)| *at == self.instructions)
+            {
+                self.mmu.set_button(button, pressed);
+            }
+            result = self.step().map(|_| ());
+        }
+
+        #[cfg(feature = "ppu")]
+        {
+            self.video_sink = video_sink;
+        }
+        #[cfg(feature = "apu")]
+        {
+            self.audio_sink = audio_sink;
+        }
+        self.battery_sink = battery_sink;
+        self.doctor_log = doctor_log;
+        self.tracer = tracer;
+        self.profiler = profiler;
+        self.access_log = access_log;
+        self.rom_write_trap = rom_write_trap;
+        self.reg_history = reg_history;
+        self.hooks = hooks;
+        self.stats = stats;
+        self.frame_stats = frame_stats;
+        result
+    }
+
+    /// Exportar el estado como JSON: registros, registros de IO, mapper y las
+    /// regiones de memoria pedidas, pensado para adjuntarlo a un bug o
+    /// analizarlo desde scripts externos. No se puede volver a cargar, para
+    /// eso está `save_state`
+    #[cfg(feature = "json")]
+    pub fn export_json(&self, regions: &[std::ops::RangeInclusive<u16>]) -> String {
+        crate::json::export_json(self, regions)
+    }
+
+    /// Ejecutar una instrucción y avanzar los periféricos el mismo tiempo,
+    /// devuelve los T-cycles transcurridos, `Error::InvalidOpcode` si la
+    /// instrucción no se pudo decodificar o `Error::Unimplemented` si
+    /// todavía no se emula
+    #[inline]
+    pub fn step(&mut self) -> Result<u32, Error> {
+        self.step_instruction().map(|info| info.cycles)
+    }
+
+    /// Como `step` pero devolviendo qué instrucción se ejecutó, pensado para
+    /// los frontends de depuración. Con la CPU detenida por STOP no se
+    /// ejecuta nada y se repite `Instr::Stop` en la misma dirección
+    pub fn step_instruction(&mut self) -> Result<StepInfo, Error> {
+        // Pulsar un botón saca a la CPU de STOP
+        if self.cpu.is_stopped() && self.mmu.is_interrupt_requested(INT_JOYPAD) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(pc = self.cpu.pc(), "CPU despierta de STOP");
+            self.cpu.wake();
+        }
+
+        // La interrupción pendiente se atiende antes de la instrucción y
+        // cuenta como parte de su paso
+        let return_addr = self.cpu.pc();
+        let dispatch = if self.cpu.is_stopped() {
+            None
+        } else if !self.is_logging_accesses() {
+            self.cpu.service_interrupt(&mut self.mmu)
+        } else {
+            // El push del PC es la única escritura de la CPU que no hace una
+            // instrucción, se apunta como si la hubiera hecho la interrumpida
+            let cycle = self.mmu.now();
+            let mut bus = LoggedBus::new(&mut self.mmu, return_addr, cycle);
+            let vector = self.cpu.service_interrupt(&mut bus);
+            let accesses = bus.into_accesses();
+            self.record_accesses(accesses);
+            vector
+        };
+        if let Some(vector) = dispatch {
+            self.debugger.track_interrupt(vector, return_addr);
+
+            // Los T-cycles del dispatch pasan ya a la MMU, si la instrucción
+            // del vector falla no se pierden
+            self.mmu.tick(INTERRUPT_DISPATCH_CYCLES);
+            self.frame_cycles += INTERRUPT_DISPATCH_CYCLES;
+        }
+
+        let addr = self.cpu.pc();
+        let stopped = self.cpu.is_stopped();
+        let start = self.mmu.now();
+        if let Some(frame_stats) = self.frame_stats.as_mut() {
+            frame_stats.begin_frame(start);
+        }
+
+        // El opcode se lee antes de ejecutar, la instrucción podría cambiarlo
+        let opcode = (!stopped && (self.debugger.is_tracking_calls() || self.profiler.is_some()))
+            .then(|| (self.mmu.read(addr), self.mmu.read(addr.wrapping_add(1))));
+        let cpu_start = self.stats.is_some().then(Instant::now);
+        let (instr, cycles) = if stopped {
+            // Con la CPU detenida no avanza su contador pero el frame tiene
+            // que seguir avanzando para el frontend
+            (Instr::Stop, 4)
+        } else {
+            if let Some(log) = self.doctor_log.as_mut() {
+                if writeln!(log, "{}", doctor_line(&self.cpu, &self.mmu)).is_err() {
+                    self.doctor_log = None;
+                }
+            }
+            let instr = self.cpu.decode(&self.mmu)?;
+            if !self.is_logging_accesses() {
+                (instr, self.cpu.execute_instr(instr, &mut self.mmu)?)
+            } else {
+                let mut bus = LoggedBus::new(&mut self.mmu, addr, start);
+                let cycles = self.cpu.execute_instr(instr, &mut bus)?;
+                let accesses = bus.into_accesses();
+                self.record_accesses(accesses);
+                (instr, cycles)
+            }
+        };
+        if let (Some(stats), Some(cpu_start)) = (self.stats.as_mut(), cpu_start) {
+            let mmu_start = Instant::now();
+            stats.add_cpu(mmu_start - cpu_start);
+            self.mmu.tick(cycles);
+            stats.add_mmu(mmu_start.elapsed());
+        } else {
+            self.mmu.tick(cycles);
+        }
+        self.frame_cycles += cycles;
+
+        // El paso cuenta también el dispatch
+        let cycles = cycles + dispatch.map_or(0, |_| INTERRUPT_DISPATCH_CYCLES);
+
+        #[cfg(feature = "tracing")]
+        if !stopped && self.cpu.is_stopped() {
+            tracing::debug!(pc = addr, "CPU detenida por STOP");
+        }
+
+        let frame_done = self.frame_cycles >= CYCLES_PER_FRAME;
+        if frame_done {
+            self.frame_cycles -= CYCLES_PER_FRAME;
+            self.finish_frame();
+            if self.hooks.has_frame() {
+                self.hooks.frame(&FrameEvent { frame: self.frame_count, cycle: self.mmu.now() });
+            }
+        }
+        if self.hooks.has_interrupt() {
+            self.hooks.interrupts(self.mmu.read(IF), self.cpu.pc(), self.mmu.now());
+        }
+
+        if self.debugger.has_watches() {
+            self.debugger.update_watches(Some(WatchMode::Instruction), addr, &self.cpu, &self.mmu);
+            if frame_done {
+                self.debugger.update_watches(Some(WatchMode::Frame), addr, &self.cpu, &self.mmu);
+            }
+        }
+
+        if let Some(history) = self.reg_history.as_mut() {
+            history.sample(&self.cpu, self.mmu.now(), self.frame_count, frame_done);
+        }
+
+        self.instructions += 1;
+        #[cfg(feature = "serde")]
+        if self.rewind.as_ref().is_some_and(|rewind| rewind.is_due(self.instructions)) {
+            let snapshot = self.snapshot();
+            self.rewind.as_mut().unwrap().push(snapshot);
+        }
+
+        let info = StepInfo {
+            addr,
+            bank: self.mmu.rom_bank(addr),
+            instr,
+            cycles,
+            new_pc: self.cpu.pc(),
+        };
+        if let Some((opcode, next)) = opcode {
+            self.debugger.track_call(opcode, &info);
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.record(info.bank, addr, opcode, next, cycles);
+            }
+        }
+        // Con la CPU detenida no se ejecutó nada que trazar
+        if let (Some(tracer), false) = (self.tracer.as_mut(), stopped) {
+            tracer.trace(&info);
+        }
+        if !stopped && self.hooks.has_instruction() {
+            self.hooks.instruction(&InstructionEvent { info, cycle: start });
+        }
+        Ok(info)
+    }
+
+    /// Hay algo que necesita ver los accesos a memoria de la CPU
+    #[inline]
+    fn is_logging_accesses(&self) -> bool {
+        self.access_log.is_some() || self.hooks.has_mem_write() || self.rom_write_trap.is_some()
+    }
+
+    /// Pasar los accesos de la última instrucción al log y a los hooks
+    fn record_accesses(&mut self, accesses: Vec<MemAccess>) {
+        for access in accesses {
+            if access.kind == AccessKind::Write {
+                self.hooks.mem_write(&access);
+                if let Some(trap) = self.rom_write_trap.as_mut() {
+                    if access.addr < 0x8000 && !self.mmu.is_mapper_register(access.addr) {
+                        trap(&access);
+                    }
+                }
+            }
+            if let Some(log) = self.access_log.as_mut() {
+                log.record(access);
+            }
+        }
+    }
+
+    /// Entregar el frame terminado al sink, si lo hay, y pasar al siguiente
+    fn finish_frame(&mut self) {
+        #[cfg(feature = "ppu")]
+        if self.is_rendering_frame() {
+            if let Some(sink) = self.video_sink.as_mut() {
+                let start = self.stats.is_some().then(Instant::now);
+                sink.present(&self.frame);
+                if let (Some(stats), Some(start)) = (self.stats.as_mut(), start) {
+                    stats.add_ppu(start.elapsed());
+                }
+            }
+        }
+        if let Some(stats) = self.stats.as_mut() {
+            stats.end_frame();
+        }
+        self.frame_count += 1;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(frame = self.frame_count, cycles = self.cpu.cycles(), "fin de frame");
+        if let Some(frame_stats) = self.frame_stats.as_mut() {
+            #[cfg(feature = "apu")]
+            let audio_buffered = self.audio_sink.as_ref().and_then(|sink| sink.buffered());
+            #[cfg(not(feature = "apu"))]
+            let audio_buffered = None;
+            frame_stats.end_frame(self.frame_count, self.mmu.now(), audio_buffered);
+        }
+
+        if let Some(debounce) = self.sram_debounce {
+            if self.mmu.is_sram_dirty()
+                && self.mmu.now() - self.mmu.sram_written_at() >= debounce
+            {
+                // Si falla se reintenta en el siguiente frame, quien quiera
+                // ver el error debe llamar a `flush_sram` directamente
+                let _ = self.flush_sram();
+            }
+        }
+    }
+
+    /// Ejecutar hasta completar el frame actual
+    pub fn step_frame(&mut self) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("frame", number = self.frame_count).entered();
+
+        let frame = self.frame_count;
+        while self.frame_count == frame {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Ejecutar un frame por cada uno del guion con sus botones pulsados,
+    /// sin limitador. Los botones que el guion deje mantenidos siguen
+    /// pulsados al terminar
+    pub fn run_script(&mut self, script: &InputScript) -> Result<(), Error> {
+        for &state in script.frames() {
+            self.set_joypad_state(state);
+            self.step_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Pulsar y soltar los botones que cambian para dejarlos como `state`,
+    /// en el formato de `Joypad::state`
+    pub(crate) fn set_joypad_state(&mut self, state: u8) {
+        for button in Button::ALL {
+            let pressed = state & (1 << button as u8) != 0;
+            if self.joypad().is_pressed(button) != pressed {
+                self.set_button(button, pressed);
+            }
+        }
+    }
+
+    /// Ejecutar un frame y esperar lo necesario para ir a velocidad real,
+    /// es lo que debe llamar en bucle un frontend normal. Devuelve si el
+    /// frame se debe mostrar, en modo turbo con frame skip no todos se
+    /// muestran
+    pub fn run_frame_realtime(&mut self) -> Result<bool, Error> {
+        let render = self.is_rendering_frame();
+        self.step_frame()?;
+
+        if !self.fast_forward {
+            let start = self.frame_stats.is_some().then(Instant::now);
+            let slept = self.limiter.wait();
+            if let (Some(frame_stats), Some(start)) = (self.frame_stats.as_mut(), start) {
+                frame_stats.record_sleep(start.elapsed(), slept);
+            }
+        }
+        Ok(render)
+    }
+
+    /// Activar o desactivar el modo turbo, que ejecuta sin limitador y con el
+    /// audio silenciado
+    pub fn set_fast_forward(&mut self, enabled: bool) {
+        // Al volver a velocidad normal no se intenta recuperar el tiempo
+        if self.fast_forward && !enabled {
+            self.limiter.reset();
+        }
+        self.fast_forward = enabled;
+    }
+
+    #[inline]
+    pub fn is_fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    /// En modo turbo renderizar solo 1 de cada `skip + 1` frames
+    #[inline]
+    pub fn set_frame_skip(&mut self, skip: u32) {
+        self.frame_skip = skip;
+    }
+
+    #[inline]
+    pub fn frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    /// El frame en curso se debe renderizar o se puede saltar
+    #[inline]
+    pub fn is_rendering_frame(&self) -> bool {
+        self.rendering && (!self.fast_forward
+            || self.frame_count.is_multiple_of(self.frame_skip as u64 + 1))
+    }
+
+    /// Activar o desactivar el renderizado de frames
+    #[inline]
+    pub fn set_rendering(&mut self, enabled: bool) {
+        self.rendering = enabled;
+    }
+
+    /// Conectar (o desconectar con `None`) el destino de los frames
+    #[cfg(feature = "ppu")]
+    pub fn set_video_sink(&mut self, sink: Option<Box<dyn VideoSink>>) {
+        self.video_sink = sink;
+    }
+
+    /// Conectar (o desconectar con `None`) el destino del audio
+    #[cfg(feature = "apu")]
+    pub fn set_audio_sink(&mut self, sink: Option<Box<dyn AudioSink>>) {
+        self.audio_sink = sink;
+    }
+
+    /// Conectar (o desconectar con `None`) el destino de la RAM del cartucho
+    pub fn set_battery_sink(&mut self, sink: Option<Box<dyn BatterySink>>) {
+        self.battery_sink = sink;
+    }
+
+    /// Guardar automáticamente la RAM del cartucho cuando pase `delay` de
+    /// tiempo emulado sin escribir en ella, así una partida que escribe en
+    /// cada frame no desgasta la memoria flash. `None` lo desactiva
+    pub fn set_sram_debounce(&mut self, delay: Option<Duration>) {
+        self.sram_debounce = delay.map(|delay| {
+            (delay.as_secs_f64() * CPU_FREQUENCY as f64) as u64
+        });
+    }
+
+    /// Ver `Mmu::is_sram_dirty`
+    #[inline]
+    pub fn is_sram_dirty(&self) -> bool {
+        self.mmu.is_sram_dirty()
+    }
+
+    /// Enviar la RAM del cartucho al sink si hay cambios sin guardar,
+    /// devuelve si se guardó algo
+    pub fn flush_sram(&mut self) -> std::io::Result<bool> {
+        let Some(sink) = self.battery_sink.as_mut() else {
+            return Ok(false);
+        };
+        if !self.mmu.is_sram_dirty() {
+            return Ok(false);
+        }
+        sink.flush(self.mmu.sram())?;
+        self.mmu.clear_sram_dirty();
+        #[cfg(feature = "tracing")]
+        tracing::debug!("RAM del cartucho guardada");
+        Ok(true)
+    }
+
+    /// No hay ningún sink conectado
+    #[inline]
+    pub fn is_headless(&self) -> bool {
+        #[cfg(feature = "ppu")]
+        if self.video_sink.is_some() {
+            return false;
+        }
+        #[cfg(feature = "apu")]
+        if self.audio_sink.is_some() {
+            return false;
+        }
+        true
+    }
+
+    /// El audio se debe descartar en vez de reproducirse, para no oírlo
+    /// acelerado durante el modo turbo
+    #[inline]
+    pub fn is_audio_muted(&self) -> bool {
+        self.fast_forward
+    }
+
+    #[inline]
+    pub fn limiter_mut(&mut self) -> &mut FrameLimiter {
+        &mut self.limiter
+    }
+
+    /// Ejecutar al menos `cycles` T-cycles, como solo se para entre
+    /// instrucciones se puede pasar hasta en una instrucción. Las funciones
+    /// `run_*` paran antes en los breakpoints del `debugger`
+    pub fn run_cycles(&mut self, cycles: u64) -> RunSummary {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            if self.check_breakpoint() {
+                return RunSummary { cycles: elapsed, result: StepResult::HitBreakpoint };
+            }
+            match self.step() {
+                Ok(step) => elapsed += step as u64,
+                Err(_) => return RunSummary {
+                    cycles: elapsed,
+                    result: StepResult::InvalidOpcode,
+                },
+            }
+        }
+
+        RunSummary { cycles: elapsed, result: StepResult::CyclesElapsed }
+    }
+
+    /// Ejecutar instrucciones hasta que se cumpla `cond`, que se comprueba
+    /// después de cada instrucción por lo que siempre se ejecuta al menos una
+    pub fn run_until<F>(&mut self, mut cond: F) -> RunSummary
+    where
+        F: FnMut(&GameBoy) -> bool,
+    {
+        let mut elapsed = 0;
+        loop {
+            if self.check_breakpoint() {
+                return RunSummary { cycles: elapsed, result: StepResult::HitBreakpoint };
+            }
+            match self.step() {
+                Ok(step) => elapsed += step as u64,
+                Err(_) => return RunSummary {
+                    cycles: elapsed,
+                    result: StepResult::InvalidOpcode,
+                },
+            }
+
+            if cond(self) {
+                return RunSummary {
+                    cycles: elapsed,
+                    result: StepResult::ConditionMet,
+                };
+            }
+        }
+    }
+
+    /// Hay un breakpoint en la siguiente instrucción a ejecutar
+    fn check_breakpoint(&mut self) -> bool {
+        let pc = self.cpu.pc();
+        self.debugger.check(pc, self.mmu.rom_bank(pc)).is_some()
+    }
+
+    /// Escribir en `log` una línea por instrucción en el formato de Gameboy
+    /// Doctor para comparar con sus logs de referencia, `None` lo desactiva.
+    /// Si falla una escritura se desactiva solo. Para que los logs coincidan
+    /// Gameboy Doctor espera que LY se lea siempre como 0x90
+    pub fn set_doctor_log(&mut self, log: Option<Box<dyn Write + Send>>) {
+        self.doctor_log = log;
+    }
+
+    #[inline]
+    pub fn is_doctor_log_enabled(&self) -> bool {
+        self.doctor_log.is_some()
+    }
+
+    /// Conectar (o desconectar con `None`) una traza de ejecución
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    #[inline]
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Vista de la pila para un depurador: `before` palabras por debajo de SP
+    /// y `after` desde SP, marcando las que parecen direcciones de retorno
+    pub fn inspect_stack(&self, before: u16, after: u16) -> Vec<StackEntry> {
+        inspect_stack(&self.mmu, self.cpu.read_widereg(Reg::SP), before, after)
+    }
+
+    /// Conectar (o desconectar con `None`) el perfilador de instrucciones
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    #[inline]
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    #[inline]
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    /// Conectar (o desconectar con `None`) los contadores de tiempo por
+    /// subsistema
+    pub fn set_subsystem_stats(&mut self, stats: Option<SubsystemStats>) {
+        self.stats = stats;
+    }
+
+    #[inline]
+    pub fn subsystem_stats(&self) -> Option<&SubsystemStats> {
+        self.stats.as_ref()
+    }
+
+    #[inline]
+    pub fn subsystem_stats_mut(&mut self) -> Option<&mut SubsystemStats> {
+        self.stats.as_mut()
+    }
+
+    /// Conectar (o desconectar con `None`) las métricas por frame
+    pub fn set_frame_stats(&mut self, stats: Option<FrameStats>) {
+        self.frame_stats = stats;
+    }
+
+    #[inline]
+    pub fn frame_stats(&self) -> Option<&FrameStats> {
+        self.frame_stats.as_ref()
+    }
+
+    #[inline]
+    pub fn frame_stats_mut(&mut self) -> Option<&mut FrameStats> {
+        self.frame_stats.as_mut()
+    }
+
+    /// Conectar (o desconectar con `None`) el log de accesos a memoria
+    pub fn set_access_log(&mut self, log: Option<AccessLog>) {
+        self.access_log = log;
+    }
+
+    #[inline]
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.access_log.as_ref()
+    }
+
+    #[inline]
+    pub fn access_log_mut(&mut self) -> Option<&mut AccessLog> {
+        self.access_log.as_mut()
+    }
+
+    /// Llamar a `trap` con cada escritura de la CPU en la ROM que no vaya a
+    /// un registro del mapper del cartucho. El hardware las ignora, pero en
+    /// un juego en desarrollo casi siempre son un puntero mal calculado o
+    /// una pila desbordada, así se ve qué instrucción la hizo
+    pub fn set_rom_write_trap(&mut self, trap: impl FnMut(&MemAccess) + Send + 'static) {
+        self.rom_write_trap = Some(Box::new(trap));
+    }
+
+    pub fn clear_rom_write_trap(&mut self) {
+        self.rom_write_trap = None;
+    }
+
+    /// Conectar (o desconectar con `None`) el historial de registros
+    pub fn set_reg_history(&mut self, history: Option<RegHistory>) {
+        self.reg_history = history;
+    }
+
+    #[inline]
+    pub fn reg_history(&self) -> Option<&RegHistory> {
+        self.reg_history.as_ref()
+    }
+
+    #[inline]
+    pub fn reg_history_mut(&mut self) -> Option<&mut RegHistory> {
+        self.reg_history.as_mut()
+    }
+
+    /// Llamar a `callback` después de cada instrucción ejecutada
+    pub fn on_instruction<F>(&mut self, callback: F) -> HookId
+    where
+        F: FnMut(&InstructionEvent) + Send + 'static,
+    {
+        self.hooks.on_instruction(Box::new(callback))
+    }
+
+    /// Llamar a `callback` en cada escritura de la CPU en memoria
+    pub fn on_mem_write<F>(&mut self, callback: F) -> HookId
+    where
+        F: FnMut(&MemAccess) + Send + 'static,
+    {
+        self.hooks.on_mem_write(Box::new(callback))
+    }
+
+    /// Llamar a `callback` al terminar cada frame
+    pub fn on_frame<F>(&mut self, callback: F) -> HookId
+    where
+        F: FnMut(&FrameEvent) + Send + 'static,
+    {
+        self.hooks.on_frame(Box::new(callback))
+    }
+
+    /// Llamar a `callback` por cada interrupción nueva en IF, se comprueba
+    /// después de cada instrucción
+    pub fn on_interrupt<F>(&mut self, callback: F) -> HookId
+    where
+        F: FnMut(&InterruptEvent) + Send + 'static,
+    {
+        let flags = self.mmu.read(IF);
+        self.hooks.on_interrupt(Box::new(callback), flags)
+    }
+
+    /// Quitar un hook registrado con cualquiera de los `on_*`, devuelve
+    /// `false` si no existía
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        self.hooks.remove(id)
+    }
+
+    /// Ver `Debugger::add_watch`, el valor inicial es el actual
+    pub fn add_watch<F>(&mut self, expr: WatchExpr, mode: WatchMode, callback: F) -> WatchId
+    where
+        F: FnMut(&WatchEvent) + Send + 'static,
+    {
+        let id = self.debugger.add_watch(expr, mode, callback);
+        // Ninguno ha cambiado todavía, solo se rellenan los que no tienen valor
+        self.debugger.update_watches(None, self.cpu.pc(), &self.cpu, &self.mmu);
+        id
+    }
+
+    #[inline]
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    #[inline]
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Ejecutar hasta que la siguiente instrucción a ejecutar sea la de `pc`
+    pub fn run_to_address(&mut self, pc: u16) -> RunSummary {
+        let mut summary = self.run_until(|gb| gb.cpu.pc() == pc);
+        if summary.result == StepResult::ConditionMet {
+            summary.result = StepResult::ReachedAddress;
+        }
+        summary
+    }
+
+    /// Último frame completo
+    #[cfg(feature = "ppu")]
+    #[inline]
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Copia del último frame completo, el que se entregó en el último
+    /// VBlank, para guardarlo sin depender de un `VideoSink`. Con la feature
+    /// `image` se puede guardar con `Frame::save_png`
+    #[cfg(feature = "ppu")]
+    pub fn screenshot(&self) -> Frame {
+        self.frame.clone()
+    }
+
+    /// Número de frames completados
+    #[inline]
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// T-cycles ejecutados por la CPU desde el inicio
+    #[inline]
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    /// Pulsar o soltar un botón
+    #[inline]
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        #[cfg(feature = "serde")]
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.record_input(self.instructions, button, pressed);
+        }
+        self.mmu.set_button(button, pressed);
+    }
+
+    /// Instrucciones ejecutadas desde el inicio, contando como una cada
+    /// paso con la CPU detenida
+    #[inline]
+    pub fn instruction_count(&self) -> u64 {
+        self.instructions
+    }
+
+    #[inline]
+    pub fn joypad(&self) -> &Joypad {
+        self.mmu.joypad()
+    }
+
+    #[inline]
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    #[inline]
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    #[inline]
+    pub fn mmu(&self) -> &Mmu {
+        &self.mmu
+    }
+
+    #[inline]
+    pub fn mmu_mut(&mut self) -> &mut Mmu {
+        &mut self.mmu
+    }
+}
+
+/// Opciones de construcción de una `GameBoy`, se crea con
+/// `GameBoy::builder()`
+#[derive(Default)]
+pub struct GameBoyBuilder {
+    rom: Option<Vec<u8>>,
+    boot_rom: Option<Vec<u8>>,
+    seed: Option<u64>,
+    model: Model,
+    #[cfg(feature = "ppu")]
+    video_sink: Option<Box<dyn VideoSink>>,
+    #[cfg(feature = "apu")]
+    audio_sink: Option<Box<dyn AudioSink>>,
+    #[cfg(feature = "ppu")]
+    compat_palette: Option<CompatPalette>,
+    #[cfg(feature = "ppu")]
+    palette_hook: Option<PaletteHook>,
+    rendering: bool,
+    allow_cgb_only: bool,
+}
+
+impl GameBoyBuilder {
+    pub fn new() -> Self {
+        Self {
+            rendering: true,
+            ..Default::default()
+        }
+    }
+
+    /// ROM del cartucho
+    pub fn rom(mut self, rom: impl Into<Vec<u8>>) -> Self {
+        self.rom = Some(rom.into());
+        self
+    }
+
+    /// Boot ROM opcional, sin ella se empieza directamente en el cartucho
+    pub fn boot_rom(mut self, boot_rom: Option<impl Into<Vec<u8>>>) -> Self {
+        self.boot_rom = boot_rom.map(Into::into);
+        self
+    }
+
+    /// Modelo emulado, por defecto una DMG
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Semilla del modo determinista, ver `GameBoy::with_seed`
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    #[cfg(feature = "ppu")]
+    pub fn video_sink(mut self, sink: impl VideoSink + 'static) -> Self {
+        self.video_sink = Some(Box::new(sink));
+        self
+    }
+
+    #[cfg(feature = "apu")]
+    pub fn audio_sink(mut self, sink: impl AudioSink + 'static) -> Self {
+        self.audio_sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Ver `GameBoy::set_compat_palette`
+    #[cfg(feature = "ppu")]
+    pub fn compat_palette(mut self, palette: CompatPalette) -> Self {
+        self.compat_palette = Some(palette);
+        self
+    }
+
+    /// Ver `GameBoy::set_compat_palette_hook`
+    #[cfg(feature = "ppu")]
+    pub fn compat_palette_hook(
+        mut self,
+        hook: impl Fn(&[u8]) -> Option<CompatPalette> + Send + 'static,
+    ) -> Self {
+        self.palette_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Ver `GameBoy::set_allow_cgb_only`
+    pub fn allow_cgb_only(mut self, allow: bool) -> Self {
+        self.allow_cgb_only = allow;
+        self
+    }
+
+    /// Ver `GameBoy::set_rendering`
+    pub fn rendering(mut self, enabled: bool) -> Self {
+        self.rendering = enabled;
+        self
+    }
+
+    /// Construir la Game Boy, falla si la ROM o la boot ROM no son válidas
+    pub fn build(self) -> Result<GameBoy, Error> {
+        let mut gb = match self.seed {
+            Some(seed) => GameBoy::with_seed(seed),
+            None => GameBoy::new(),
+        };
+        gb.set_model(self.model);
+        gb.set_allow_cgb_only(self.allow_cgb_only);
+        #[cfg(feature = "ppu")]
+        {
+            gb.set_compat_palette(self.compat_palette);
+            gb.palette_hook = self.palette_hook;
+        }
+
+        if let Some(boot_rom) = self.boot_rom.as_deref() {
+            gb.load_boot_rom(boot_rom)?;
+        }
+        if let Some(rom) = self.rom.as_deref() {
+            gb.load_rom(rom)?;
+        }
+
+        #[cfg(feature = "ppu")]
+        {
+            gb.video_sink = self.video_sink;
+        }
+        #[cfg(feature = "apu")]
+        {
+            gb.audio_sink = self.audio_sink;
+        }
+        gb.rendering = self.rendering;
+        Ok(gb)
+    }
+}
+
+// La Game Boy se tiene que poder mover a otro hilo para `run_batch`, por eso
+// los sinks y periféricos conectados están acotados a `Send`
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<GameBoy>();
+};
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Addr;
+    use crate::debugger::Breakpoint;
+    use crate::mmu::{BOOT, IE, INT_TIMER, KEY0, KEY0_DMG_MODE};
+    #[cfg(feature = "serde")]
+    use crate::state::StateError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Sink que solo cuenta los frames recibidos
+    #[cfg(feature = "ppu")]
+    struct CountingSink(Arc<AtomicUsize>);
+
+    #[cfg(feature = "ppu")]
+    impl VideoSink for CountingSink {
+        fn present(&mut self, _frame: &Frame) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// ROM con un JR -2 en el punto de entrada, bucle infinito
+    fn spin_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        rom
+    }
+
+    #[test]
+    fn run_one_frame() {
+        // LD B, 0x12 seguido de NOPs
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x06, 0x12]);
+
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+        assert_eq!(gb.step(), Ok(8));
+        assert_eq!(gb.cpu().read_reg(Reg::B), 0x12);
+
+        gb.step_frame().unwrap();
+        assert_eq!(gb.frame_count(), 1);
+        assert!(gb.cycles() >= CYCLES_PER_FRAME as u64);
+
+        assert_eq!(gb.load_rom(&vec![0; 0x8001]), Err(Error::RomTooLarge { size: 0x8001 }));
+        let mut mbc1 = vec![0; 0x10000];
+        mbc1[0x0147] = 0x01;
+        assert_eq!(gb.load_rom(&mbc1), Err(Error::UnsupportedMapper(0x01)));
+
+        // HALT todavía no se emula, no avanza el reloj
+        rom[0x100] = 0x76;
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+        assert_eq!(gb.step(), Err(Error::Unimplemented { addr: 0x0100, opcode: 0x76 }));
+        assert_eq!(gb.cycles(), 0);
+    }
+
+    #[test]
+    fn run_functions() {
+        // LD B, 0x12 seguido de NOPs
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x06, 0x12]);
+
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+
+        let summary = gb.run_cycles(10);
+        assert_eq!(summary, RunSummary { cycles: 12, result: StepResult::CyclesElapsed });
+        assert_eq!(gb.cpu().pc(), 0x0103);
+
+        let summary = gb.run_to_address(0x0108);
+        assert_eq!(summary, RunSummary { cycles: 20, result: StepResult::ReachedAddress });
+
+        let summary = gb.run_until(|gb| gb.cycles() >= 40);
+        assert_eq!(summary.result, StepResult::ConditionMet);
+        assert_eq!(gb.cycles(), 40);
+    }
+
+    #[test]
+    fn interrupt_dispatch_cycles() {
+        // EI; NOP y en el vector del timer PUSH BC, que todavía no se emula
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0xFB, 0x00]);
+        rom[0x50] = 0xC5;
+
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+        gb.mmu_mut().write_word(Addr(IE), INT_TIMER);
+        gb.mmu_mut().write_word(Addr(IF), INT_TIMER);
+
+        // IME se activa después de la instrucción que sigue a EI
+        assert_eq!(gb.step_instruction().unwrap().new_pc, 0x0101);
+        assert_eq!(gb.step_instruction().unwrap().new_pc, 0x0102);
+
+        // El dispatch ya pasó por la MMU aunque la instrucción del vector falle
+        let now = gb.mmu().now();
+        assert_eq!(gb.step_instruction(), Err(Error::Unimplemented { addr: 0x0050, opcode: 0xC5 }));
+        assert_eq!(gb.mmu().now() - now, INTERRUPT_DISPATCH_CYCLES as u64);
+    }
+
+    #[test]
+    fn step_instruction() {
+        // LD B, 0x12; JR 0x0150
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0x06, 0x12, 0x18, 0x4C]);
+
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+        assert_eq!(gb.step_instruction(), Ok(StepInfo {
+            addr: 0x0100,
+            bank: 0,
+            instr: Instr::LdRegImm { src: 0x12, dst: Reg::B },
+            cycles: 8,
+            new_pc: 0x0102,
+        }));
+
+        let info = gb.step_instruction().unwrap();
+        assert_eq!((info.addr, info.instr, info.new_pc),
+            (0x0102, Instr::JRelImm { offset: 0x4C }, 0x0150));
+    }
+
+    #[test]
+    fn subsystem_stats() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&spin_rom()).unwrap();
+        gb.step_frame().unwrap();
+        gb.set_subsystem_stats(Some(SubsystemStats::new()));
+        gb.step_frame().unwrap();
+        gb.step_frame().unwrap();
+
+        let stats = gb.subsystem_stats().unwrap();
+        assert_eq!(stats.frames(), 2);
+        assert!(stats.total().cpu > Duration::ZERO);
+        assert_eq!(stats.total().apu, Duration::ZERO);
+    }
+
+    #[test]
+    fn frame_stats() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&spin_rom()).unwrap();
+        gb.set_frame_stats(Some(FrameStats::new(4)));
+        gb.step_frame().unwrap();
+        // A 10 frames por segundo siempre sobra tiempo para esperar
+        *gb.limiter_mut() = FrameLimiter::with_rate(10.0);
+        gb.run_frame_realtime().unwrap();
+        gb.run_frame_realtime().unwrap();
+
+        let stats = gb.frame_stats().unwrap();
+        let frames = stats.frames().collect::<Vec<_>>();
+        assert_eq!(frames.iter().map(|timing| timing.frame).collect::<Vec<_>>(), [1, 2, 3]);
+        assert!(frames.iter().all(|timing| timing.cycles.abs_diff(CYCLES_PER_FRAME) < 24));
+        assert!(frames[0].emulation > Duration::ZERO);
+        // El primer frame del limitador solo empieza a contar
+        assert!(!frames[1].slept);
+        assert!(frames[2].slept && frames[2].sleep > Duration::ZERO);
+    }
+
+    #[test]
+    fn doctor_log() {
+        /// Log que se puede leer después de dárselo a la Game Boy
+        struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for SharedLog {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut gb = GameBoy::new();
+        gb.load_rom(&spin_rom()).unwrap();
+        gb.set_doctor_log(Some(Box::new(SharedLog(log.clone()))));
+        gb.step().unwrap();
+        gb.step().unwrap();
+        gb.set_doctor_log(None);
+        gb.step().unwrap();
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        let line = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:18,FE,00,00\n";
+        assert_eq!(log, line.repeat(2));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn step_back() {
+        // Una serie de LD B, i para que cada instrucción cambie el estado
+        let mut rom = vec![0; 0x8000];
+        for i in 0..40 {
+            rom[0x100 + i * 2..0x102 + i * 2].copy_from_slice(&[0x06, i as u8]);
+        }
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+        assert_eq!(gb.step_back(), Err(Error::NoHistory));
+
+        gb.set_rewind(Some(Rewind::new(8, 4)));
+        let mut history = vec![(gb.cpu().clone(), *gb.joypad())];
+        for i in 0..40 {
+            if i == 13 {
+                gb.set_button(Button::A, true);
+            }
+            gb.step().unwrap();
+            history.push((gb.cpu().clone(), *gb.joypad()));
+        }
+        assert_eq!(gb.rewind().unwrap().len(), 4);
+
+        // Se puede volver hasta el snapshot más antiguo, en la instrucción 16
+        for expected in history[16..40].iter().rev() {
+            gb.step_back().unwrap();
+            assert_eq!(&(gb.cpu().clone(), *gb.joypad()), expected);
+        }
+        assert_eq!(gb.instruction_count(), 16);
+        assert_eq!(gb.step_back(), Err(Error::NoHistory));
+    }
+
+    #[test]
+    fn watches() {
+        // LD B, 1; LD B, 2; LD B, 2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x106].copy_from_slice(&[0x06, 0x01, 0x06, 0x02, 0x06, 0x02]);
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let expr = WatchExpr::parse("B").unwrap();
+        gb.add_watch(expr, WatchMode::Instruction, move |event| {
+            log.lock().unwrap().push((event.old, event.new, event.pc));
+        });
+        gb.run_cycles(8 * 3);
+        assert_eq!(*events.lock().unwrap(), [(0x00, 0x01, 0x0100), (0x01, 0x02, 0x0102)]);
+    }
+
+    #[test]
+    fn reload_rom() {
+        // JR -2 en el punto de entrada y un byte al final de la ROM
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        rom[0x7FFF] = 0x55;
+        let mut gb = GameBoy::builder().rom(rom.clone()).build().unwrap();
+        #[cfg(feature = "serde")]
+        gb.set_rewind(Some(Rewind::new(8, 4)));
+        gb.step_frame().unwrap();
+        gb.mmu_mut().write(0xC000, 0x12);
+        gb.mmu_mut().write(0x8000, 0x34);
+        gb.cpu_mut().write_reg(Reg::B, 0x77);
+
+        // Con la CPU conservada solo cambia la ROM, lo que sobra se borra
+        rom[0x0101] = 0xFD;
+        gb.reload_rom(&rom[..0x4000], true).unwrap();
+        assert_eq!(gb.mmu().read_word(Addr(0x0101)), 0xFD);
+        assert_eq!(gb.mmu().read_word(Addr(0x7FFF)), 0x00);
+        assert_eq!(gb.mmu().read_word(Addr(0xC000)), 0x12);
+        assert_eq!(gb.mmu().read_word(Addr(0x8000)), 0x34);
+        assert_eq!(gb.cpu().read_reg(Reg::B), 0x77);
+        #[cfg(feature = "serde")]
+        {
+            assert_eq!(gb.rewind().unwrap().len(), 1);
+            assert_eq!(gb.rewind().unwrap().capacity(), 4);
+        }
+
+        gb.step_frame().unwrap();
+        gb.debugger_mut().set_call_tracking(true);
+        gb.reload_rom(&rom, false).unwrap();
+        assert!(gb.debugger().is_tracking_calls());
+        assert_eq!(gb.cpu().pc(), 0x0100);
+        assert_eq!(gb.cpu().read_reg(Reg::B), 0x00);
+        assert_eq!(gb.mmu().read_word(Addr(0xC000)), 0x12);
+
+        rom[0x0143] = 0xC0;
+        assert_eq!(gb.reload_rom(&rom, false), Err(Error::RequiresCgb(Model::Dmg)));
+        assert_eq!(gb.reload_rom(&vec![0; 0x10000], false), Err(Error::RomTooLarge { size: 0x10000 }));
+    }
+
+    #[test]
+    fn rom_write_trap() {
+        use crate::mmu::{IE, INT_VBLANK};
+
+        // Pila desbordada hasta la ROM: el push del dispatch escribe en 0001
+        // y 0000
+        let overflow_stack = |cartridge_type: u8| {
+            let mut rom = vec![0; 0x8000];
+            rom[0x0147] = cartridge_type;
+            let mut gb = GameBoy::new();
+            gb.load_rom(&rom).unwrap();
+            gb.cpu_mut().write_widereg(Reg::SP, 0x0002);
+            gb.cpu_mut().set_ime(true);
+            gb.mmu_mut().write(IE, INT_VBLANK);
+            gb.mmu_mut().request_interrupt(INT_VBLANK);
+            gb
+        };
+
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let log = writes.clone();
+        let mut gb = overflow_stack(0x00);
+        gb.set_rom_write_trap(move |access| log.lock().unwrap().push((access.pc, access.addr, access.value)));
+        gb.step().unwrap();
+        assert_eq!(*writes.lock().unwrap(), [(0x0100, 0x0001, 0x01), (0x0100, 0x0000, 0x00)]);
+
+        // En un MBC1 toda la ROM son registros del mapper
+        let log = writes.clone();
+        let mut gb = overflow_stack(0x01);
+        gb.set_rom_write_trap(move |access| log.lock().unwrap().push((access.pc, access.addr, access.value)));
+        gb.step().unwrap();
+        assert_eq!(writes.lock().unwrap().len(), 2);
+        assert!(gb.mmu().is_mapper_register(0x6000));
+        gb.mmu_mut().load_rom(&[0x19; 0x150]).unwrap();
+        assert!(!gb.mmu().is_mapper_register(0x6000));
+
+        let mut gb = overflow_stack(0x00);
+        gb.set_rom_write_trap(|_| panic!("trap desactivado"));
+        gb.clear_rom_write_trap();
+        gb.step().unwrap();
+    }
+
+    #[test]
+    fn hooks() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&vec![0; 0x8000]).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        let id = gb.on_instruction(move |event| log.lock().unwrap().push(event.info.addr));
+        let frames = Arc::new(AtomicUsize::new(0));
+        let count = frames.clone();
+        gb.on_frame(move |_| { count.fetch_add(1, Ordering::Relaxed); });
+        let interrupts = Arc::new(Mutex::new(Vec::new()));
+        let log = interrupts.clone();
+        gb.on_interrupt(move |event| log.lock().unwrap().push(event.mask));
+
+        gb.run_cycles(12);
+        assert_eq!(*events.lock().unwrap(), [0x0100, 0x0101, 0x0102]);
+        assert!(gb.remove_hook(id));
+        assert!(!gb.remove_hook(id));
+        gb.run_cycles(4);
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        gb.step_frame().unwrap();
+        assert_eq!(frames.load(Ordering::Relaxed), 1);
+
+        // Con la fila de acción seleccionada pulsar A solicita la interrupción
+        gb.mmu_mut().write_word(Addr(0xFF00), 0x10);
+        gb.set_button(Button::A, true);
+        gb.step().unwrap();
+        assert_eq!(*interrupts.lock().unwrap(), [INT_JOYPAD]);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&vec![0; 0x8000]).unwrap();
+        gb.debugger_mut().add_breakpoint(Breakpoint::new(0x0104));
+
+        let summary = gb.run_cycles(100);
+        assert_eq!(summary, RunSummary { cycles: 16, result: StepResult::HitBreakpoint });
+        assert_eq!(gb.cpu().pc(), 0x0104);
+
+        // Al continuar se ejecuta la instrucción del breakpoint
+        let summary = gb.run_to_address(0x0106);
+        assert_eq!(summary, RunSummary { cycles: 8, result: StepResult::ReachedAddress });
+
+        gb.debugger_mut().add_breakpoint(Breakpoint::with_bank(0x0108, 1));
+        assert_eq!(gb.run_to_address(0x0109).result, StepResult::ReachedAddress);
+    }
+
+    #[test]
+    fn fast_forward_frame_skip() {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&spin_rom()).unwrap();
+        gb.set_fast_forward(true);
+        gb.set_frame_skip(2);
+        assert!(gb.is_audio_muted());
+
+        let rendered = (0..6)
+            .map(|_| gb.run_frame_realtime().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rendered, [true, false, false, true, false, false]);
+
+        gb.set_fast_forward(false);
+        assert!(gb.is_rendering_frame());
+    }
+
+    #[cfg(feature = "ppu")]
+    #[test]
+    fn video_sink_and_headless() {
+        let presented = Arc::new(AtomicUsize::new(0));
+        let mut gb = GameBoy::new();
+        gb.load_rom(&spin_rom()).unwrap();
+        assert!(gb.is_headless());
+
+        gb.set_video_sink(Some(Box::new(CountingSink(presented.clone()))));
+        assert!(!gb.is_headless());
+        for _ in 0..3 {
+            gb.step_frame().unwrap();
+        }
+        assert_eq!(presented.load(Ordering::Relaxed), 3);
+
+        gb.set_rendering(false);
+        gb.step_frame().unwrap();
+        assert_eq!(presented.load(Ordering::Relaxed), 3);
+        assert_eq!(gb.frame_count(), 4);
+    }
+
+    #[test]
+    fn seeded_initial_state() {
+        let wram = |gb: &GameBoy| (0xC000..=0xDFFF)
+            .map(|addr| gb.mmu().read_word(Addr(addr)))
+            .collect::<Vec<_>>();
+
+        let a = GameBoy::with_seed(1234);
+        let b = GameBoy::with_seed(1234);
+        let c = GameBoy::with_seed(4321);
+        assert_eq!(a.seed(), Some(1234));
+        assert_eq!(wram(&a), wram(&b));
+        assert_ne!(wram(&a), wram(&c));
+        assert!(wram(&GameBoy::new()).iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn builder_with_boot_rom() {
+        let mut gb = GameBoy::builder()
+            .rom(spin_rom())
+            .boot_rom(Some(vec![0xAA; 0x100]))
+            .seed(7)
+            .build()
+            .unwrap();
+
+        assert_eq!(gb.seed(), Some(7));
+        assert_eq!(gb.cpu().pc(), 0x0000);
+        assert_eq!(gb.mmu().read_word(Addr(0x0000)), 0xAA);
+        assert_eq!(gb.mmu().read_word(Addr(0x0100)), 0x18);
+
+        gb.mmu_mut().write_word(Addr(BOOT), 1);
+        assert_eq!(gb.mmu().read_word(Addr(0x0000)), 0x00);
+
+        assert_eq!(GameBoy::builder().boot_rom(Some([0; 3])).build().err(),
+            Some(Error::InvalidBootRom { size: 3 }));
+    }
+
+    #[test]
+    fn model_presets() {
+        let mut rom = spin_rom();
+        let gb = GameBoy::builder().rom(rom.clone()).build().unwrap();
+        assert_eq!(gb.model(), Model::Dmg);
+        assert_eq!(gb.cpu().read_reg(Reg::A), 0x01);
+        assert_eq!(gb.mmu().read_word(Addr(0xFF40)), 0x91);
+
+        // Un juego de DMG en una CGB se queda en modo compatibilidad
+        let gb = GameBoy::builder().model(Model::Cgb).rom(rom.clone())
+            .build().unwrap();
+        assert_eq!(gb.cpu().read_reg(Reg::A), 0x11);
+        assert!(!gb.is_cgb_mode());
+
+        rom[0x0143] = 0x80;
+        let gb = GameBoy::builder().model(Model::Cgb).rom(rom.clone())
+            .build().unwrap();
+        assert!(gb.is_cgb_mode());
+        assert_eq!(gb.cpu().read_reg(Reg::E), 0x56);
+
+        let gb = GameBoy::builder().model(Model::Mgb).rom(rom.clone()).build().unwrap();
+        assert!(!gb.is_cgb_mode());
+        assert_eq!(gb.cpu().read_reg(Reg::A), 0xFF);
+
+        // Los juegos solo de CGB no se cargan en una DMG salvo que se pida
+        rom[0x0143] = 0xC0;
+        assert_eq!(GameBoy::builder().rom(rom.clone()).build().err(),
+            Some(Error::RequiresCgb(Model::Dmg)));
+        let gb = GameBoy::builder().rom(rom.clone()).allow_cgb_only(true).build().unwrap();
+        assert_eq!(gb.cgb_support(), CgbSupport::Only);
+        assert!(!gb.is_cgb_mode());
+        let gb = GameBoy::builder().model(Model::Cgb).rom(rom.clone()).build().unwrap();
+        assert_eq!(gb.mmu().key0(), 0xC0);
+
+        // Con boot ROM el modo lo decide lo que ella escriba en KEY0, que
+        // queda fijo al desmapearla
+        rom[0x0143] = 0x80;
+        let mut gb = GameBoy::builder().model(Model::Cgb).boot_rom(Some(vec![0; 0x900]))
+            .rom(rom).build().unwrap();
+        assert!(gb.is_cgb_mode());
+        gb.mmu_mut().write(KEY0, KEY0_DMG_MODE);
+        assert!(!gb.is_cgb_mode());
+        gb.mmu_mut().write(BOOT, 0x11);
+        gb.mmu_mut().write(KEY0, 0x80);
+        assert!(!gb.is_cgb_mode());
+        assert_eq!(gb.cgb_support(), CgbSupport::Enhanced);
+    }
+
+    #[cfg(feature = "ppu")]
+    #[test]
+    fn compat_palette() {
+        let gb = GameBoy::builder().rom(spin_rom()).build().unwrap();
+        assert_eq!(gb.compat_palette(), None);
+
+        let gb = GameBoy::builder().model(Model::Cgb).rom(spin_rom())
+            .build().unwrap();
+        assert_eq!(gb.compat_palette(), Some(CompatPalette::DEFAULT));
+
+        // Mantener Left + B durante el arranque elige la escala de grises
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.set_button(Button::Left, true);
+        gb.set_button(Button::B, true);
+        gb.load_rom(&spin_rom()).unwrap();
+        assert_eq!(gb.compat_palette(), Some(CompatPalette::GRAYSCALE));
+
+        let gb = GameBoy::builder().model(Model::Agb).rom(spin_rom())
+            .compat_palette(CompatPalette::REVERSE).build().unwrap();
+        assert_eq!(gb.compat_palette(), Some(CompatPalette::REVERSE));
+
+        // La tabla de títulos de la boot ROM y el hook que va antes
+        let mut rom = spin_rom();
+        rom[0x0134..0x013A].copy_from_slice(b"TETRIS");
+        rom[0x014B] = 0x01;
+        let gb = GameBoy::builder().model(Model::Cgb).rom(rom.clone())
+            .build().unwrap();
+        assert_eq!(gb.compat_palette(), Some(CompatPalette::ORANGE));
+        let gb = GameBoy::builder().model(Model::Cgb).rom(rom)
+            .compat_palette_hook(|rom| (rom[0x0134] == b'T').then_some(CompatPalette::BLUE))
+            .build().unwrap();
+        assert_eq!(gb.compat_palette(), Some(CompatPalette::BLUE));
+    }
+
+    #[test]
+    fn run_script() {
+        let mut gb = GameBoy::builder().rom(spin_rom()).build().unwrap();
+        let script = InputScript::parse("press A 2; hold DOWN; wait 3").unwrap();
+        gb.run_script(&script).unwrap();
+        assert_eq!(gb.frame_count(), 6);
+        assert!(gb.joypad().is_pressed(Button::Down));
+        assert!(!gb.joypad().is_pressed(Button::A));
+    }
+
+    #[test]
+    fn sgb_detection() {
+        let mut rom = spin_rom();
+        rom[0x0146] = 0x03;
+        rom[0x014B] = 0x33;
+
+        let gb = GameBoy::builder().rom(rom.clone()).build().unwrap();
+        assert!(gb.sgb().is_none());
+
+        let gb = GameBoy::builder().model(Model::Sgb).rom(rom).build().unwrap();
+        assert_eq!(gb.sgb().map(Sgb::players), Some(1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_and_load_state() {
+        let mut gb = GameBoy::builder().rom(spin_rom()).seed(99).build().unwrap();
+        gb.run_cycles(10_000);
+        let state = gb.save_state();
+
+        gb.run_cycles(CYCLES_PER_FRAME as u64 * 2);
+        let expected = (gb.cpu().clone(), gb.frame_count(), gb.mmu().memory().to_vec());
+
+        let mut restored = GameBoy::new();
+        assert!(matches!(restored.load_state(&state),
+            Err(Error::State(StateError::RomMismatch { .. }))));
+        restored.load_rom(&spin_rom()).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.seed(), Some(99));
+        restored.run_cycles(CYCLES_PER_FRAME as u64 * 2);
+        assert_eq!((restored.cpu().clone(), restored.frame_count(),
+            restored.mmu().memory().to_vec()), expected);
+
+        assert_eq!(restored.load_state(&state[..10]),
+            Err(Error::State(StateError::Truncated)));
+    }
+
+    /// Sink que guarda en memoria cada volcado de la SRAM
+    #[derive(Clone, Default)]
+    struct MemoryBattery(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl BatterySink for MemoryBattery {
+        fn flush(&mut self, sram: &[u8]) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(sram.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sram_flush_and_debounce() {
+        let battery = MemoryBattery::default();
+        let mut gb = GameBoy::builder().rom(spin_rom()).build().unwrap();
+        gb.set_battery_sink(Some(Box::new(battery.clone())));
+        assert!(!gb.flush_sram().unwrap());
+
+        gb.mmu_mut().write_word(Addr(0xA000), 0x42);
+        assert!(gb.is_sram_dirty());
+        assert!(gb.flush_sram().unwrap());
+        assert!(!gb.is_sram_dirty());
+        assert_eq!(battery.0.lock().unwrap()[0][0], 0x42);
+
+        // Con debounce de 2 frames se guarda solo tras dejar de escribir
+        let frame = Duration::from_secs_f64(1.0 / crate::FRAME_RATE);
+        gb.set_sram_debounce(Some(frame * 2));
+        gb.mmu_mut().write_word(Addr(0xA001), 0x43);
+        gb.step_frame().unwrap();
+        assert!(gb.is_sram_dirty());
+        gb.step_frame().unwrap();
+        gb.step_frame().unwrap();
+        assert!(!gb.is_sram_dirty());
+        assert_eq!(battery.0.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::gameboy::GameBoy;
+use crate::watch::wide_value;
+use crate::Reg;
+
+/// Resumen del estado tras un paso: los registros, el reloj y un hash de
+/// las escrituras en memoria que hizo la instrucción
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StepDigest {
+    pub pc: u16,
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+
+    /// Reloj de los periféricos en T-cycles
+    pub cycle: u64,
+
+    /// Hash de las direcciones y valores escritos en orden, 0 si la
+    /// instrucción no escribió nada
+    pub writes: u64,
+}
+
+impl StepDigest {
+    fn capture(gb: &GameBoy, writes: u64) -> Self {
+        let cpu = gb.cpu();
+        Self {
+            pc: cpu.pc(),
+            af: wide_value(cpu, Reg::AF),
+            bc: wide_value(cpu, Reg::BC),
+            de: wide_value(cpu, Reg::DE),
+            hl: wide_value(cpu, Reg::HL),
+            sp: wide_value(cpu, Reg::SP),
+            cycle: gb.mmu().now(),
+            writes,
+        }
+    }
+
+    /// Leer una línea del fichero, `None` si no tiene el formato
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let mut word = || u16::from_str_radix(fields.next()?, 16).ok();
+        let (pc, af, bc, de, hl, sp) = (word()?, word()?, word()?, word()?, word()?, word()?);
+        let cycle = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let writes = u64::from_str_radix(fields.next()?, 16).ok()?;
+        fields.next().is_none().then_some(Self { pc, af, bc, de, hl, sp, cycle, writes })
+    }
+}
+
+/// Una línea del fichero: `PC AF BC DE HL SP ciclo escrituras` en hexadecimal
+impl fmt::Display for StepDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} {:04X} {:04X} {:04X} {:04X} {:04X} {:X} {:X}", self.pc, self.af,
+            self.bc, self.de, self.hl, self.sp, self.cycle, self.writes)
+    }
+}
+
+/// Primer paso en el que la ejecución no coincide con la traza grabada
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GoldenMismatch {
+    /// Paso, el 0 es el estado antes de la primera instrucción
+    pub step: usize,
+
+    /// `None` si la ejecución tiene más pasos que la traza
+    pub expected: Option<StepDigest>,
+
+    /// `None` si la CPU encontró un opcode inválido antes de terminar
+    pub actual: Option<StepDigest>,
+}
+
+impl fmt::Display for GoldenMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |digest: Option<StepDigest>| match digest {
+            Some(digest) => digest.to_string(),
+            None => "-".into(),
+        };
+        writeln!(f, "La traza diverge en el paso {}:", self.step)?;
+        writeln!(f, "esperado: {}", line(self.expected))?;
+        write!(f, "actual:   {}", line(self.actual))
+    }
+}
+
+/// Traza de referencia de una ejecución, para detectar cambios de
+/// comportamiento accidentales en cualquier parte del núcleo. Se graba con
+/// `record` una vez, se guarda como fixture y los tests la comparan con
+/// `replay`. Cuando un cambio es intencionado se vuelve a grabar
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoldenTrace {
+    steps: Vec<StepDigest>,
+}
+
+impl GoldenTrace {
+    /// Ejecutar `steps` instrucciones de `gb` apuntando el estado antes de
+    /// empezar y tras cada una. Termina antes si la CPU encuentra un opcode
+    /// inválido
+    pub fn record(gb: &mut GameBoy, steps: usize) -> Self {
+        let mut trace = Self::default();
+        run(gb, steps, |digest| {
+            trace.steps.push(digest);
+            true
+        });
+        trace
+    }
+
+    /// Ejecutar `gb` comparando cada paso con la traza, `None` si coincide
+    /// entera
+    pub fn replay(&self, gb: &mut GameBoy) -> Option<GoldenMismatch> {
+        let mut step = 0;
+        let mut mismatch = None;
+        run(gb, self.steps.len().saturating_sub(1), |digest| {
+            if self.steps[step] != digest {
+                mismatch = Some(GoldenMismatch {
+                    step,
+                    expected: Some(self.steps[step]),
+                    actual: Some(digest),
+                });
+                return false;
+            }
+            step += 1;
+            true
+        });
+        mismatch.or_else(|| (step < self.steps.len()).then(|| GoldenMismatch {
+            step,
+            expected: Some(self.steps[step]),
+            actual: None,
+        }))
+    }
+
+    /// Primer paso distinto entre dos trazas
+    pub fn diff(&self, actual: &GoldenTrace) -> Option<GoldenMismatch> {
+        let len = self.steps.len().max(actual.steps.len());
+        (0..len)
+            .map(|step| GoldenMismatch {
+                step,
+                expected: self.steps.get(step).copied(),
+                actual: actual.steps.get(step).copied(),
+            })
+            .find(|mismatch| mismatch.expected != mismatch.actual)
+    }
+
+    pub fn steps(&self) -> &[StepDigest] {
+        &self.steps
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Escribir la traza, una línea por paso. Las líneas que empiezan por
+    /// `#` son comentarios
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "# PC AF BC DE HL SP ciclo escrituras")?;
+        for digest in &self.steps {
+            writeln!(writer, "{digest}")?;
+        }
+        Ok(())
+    }
+
+    /// Leer una traza escrita con `write`
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut trace = Self::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let digest = StepDigest::parse(line).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData, format!("línea {} inválida: {line}", index + 1)))?;
+            trace.steps.push(digest);
+        }
+        Ok(trace)
+    }
+
+    /// Crear (o vaciar) el fichero en `path` y escribir en él la traza
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Pasar a `f` el estado inicial y el de tras cada una de las `steps`
+/// instrucciones, hasta que devuelve `false` o la CPU encuentra un opcode
+/// inválido
+fn run(gb: &mut GameBoy, steps: usize, mut f: impl FnMut(StepDigest) -> bool) {
+    let writes = Arc::new(AtomicU64::new(0));
+    let hash = writes.clone();
+    let hook = gb.on_mem_write(move |access| {
+        let word = (access.addr as u64) << 8 | access.value as u64;
+        let value = hash.load(Ordering::Relaxed);
+        hash.store((value ^ word).wrapping_mul(0x0000_0100_0000_01B3), Ordering::Relaxed);
+    });
+
+    let mut proceed = f(StepDigest::capture(gb, 0));
+    for _ in 0..steps {
+        if !proceed || gb.step_instruction().is_err() {
+            break;
+        }
+        proceed = f(StepDigest::capture(gb, writes.swap(0, Ordering::Relaxed)));
+    }
+    gb.remove_hook(hook);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_replay() {
+        // LD B, $12 y NOPs
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x06, 0x12]);
+        let boot = || {
+            let mut gb = GameBoy::new();
+            gb.load_rom(&rom).unwrap();
+            gb
+        };
+        let trace = GoldenTrace::record(&mut boot(), 4);
+        assert_eq!(trace.len(), 5);
+        assert_eq!(trace.steps()[1].bc & 0xFF00, 0x1200);
+        assert_eq!(trace.steps()[1].writes, 0);
+        assert_eq!(trace.steps()[4].cycle - trace.steps()[0].cycle, 20);
+        assert_eq!(trace.replay(&mut boot()), None);
+
+        let mut text = Vec::new();
+        trace.write(&mut text).unwrap();
+        assert_eq!(GoldenTrace::read(text.as_slice()).unwrap(), trace);
+        assert!(GoldenTrace::read("0100 0000".as_bytes()).is_err());
+
+        // Otro valor en B se ve desde el primer paso
+        let mut changed = boot();
+        changed.cpu_mut().write_reg(Reg::B, 1);
+        let mismatch = trace.replay(&mut changed).unwrap();
+        assert_eq!((mismatch.step, mismatch.expected, mismatch.actual.is_some()),
+            (0, Some(trace.steps()[0]), true));
+
+        let longer = GoldenTrace::record(&mut boot(), 5);
+        assert_eq!(trace.diff(&longer).map(|mismatch| (mismatch.step, mismatch.expected)),
+            Some((5, None)));
+    }
+}
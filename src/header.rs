@@ -0,0 +1,192 @@
+use crate::error::Error;
+use crate::model::{header_title, supports_sgb, CgbSupport, HEADER_CARTRIDGE_TYPE, HEADER_TITLE};
+
+/// Rango del logo de Nintendo en la cabecera
+const HEADER_LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+
+/// Dirección del código de tamaño de la ROM
+const HEADER_ROM_SIZE: usize = 0x0148;
+
+/// Dirección del código de tamaño de la RAM del cartucho
+const HEADER_RAM_SIZE: usize = 0x0149;
+
+/// Dirección del checksum de la cabecera, cubre de 0x0134 a 0x014C
+const HEADER_CHECKSUM: usize = 0x014D;
+
+/// Dirección del checksum global en big endian, la suma de todos los bytes
+/// de la ROM menos los dos suyos
+const HEADER_GLOBAL_CHECKSUM: usize = 0x014E;
+
+/// Fin de la cabecera, una ROM más corta no tiene una completa
+const HEADER_END: usize = 0x0150;
+
+/// En los multicarts MBC1M cada juego ocupa 256 KiB y tiene su cabecera
+const MBC1M_GAME_SIZE: usize = 0x40000;
+
+/// Cabecera del cartucho ya interpretada, pensada para identificar una ROM
+/// antes de ejecutarla
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomHeader {
+    pub title: String,
+
+    /// Byte de tipo de cartucho, dice el mapper y si lleva RAM, batería o RTC
+    pub cartridge_type: u8,
+
+    /// Tamaño de la ROM que declara la cabecera, `None` si el código no es
+    /// válido
+    pub rom_size: Option<usize>,
+
+    /// Tamaño de la RAM del cartucho, `None` si el código no es válido
+    pub ram_size: Option<usize>,
+
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+
+    /// Checksum de la cabecera guardado y si coincide con el calculado, la
+    /// boot ROM no arranca si no coincide
+    pub header_checksum: u8,
+    pub header_checksum_valid: bool,
+
+    /// Checksum global guardado y si coincide, ningún modelo lo comprueba
+    pub global_checksum: u16,
+    pub global_checksum_valid: bool,
+
+    /// Es un multicart MBC1M, con varios juegos de 256 KiB
+    pub multicart: bool,
+}
+
+impl RomHeader {
+    /// Interpretar la cabecera de `rom`, falla si la ROM no llega a tener
+    /// una completa
+    pub fn parse(rom: &[u8]) -> Result<Self, Error> {
+        if rom.len() < HEADER_END {
+            return Err(Error::TruncatedHeader { size: rom.len() });
+        }
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let global_checksum = u16::from_be_bytes([
+            rom[HEADER_GLOBAL_CHECKSUM],
+            rom[HEADER_GLOBAL_CHECKSUM + 1],
+        ]);
+        let global_sum = rom.iter()
+            .enumerate()
+            .filter(|(addr, _)| !matches!(*addr, HEADER_GLOBAL_CHECKSUM | 0x014F))
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+
+        Ok(Self {
+            title: header_title(rom),
+            cartridge_type: rom[HEADER_CARTRIDGE_TYPE],
+            rom_size: match rom[HEADER_ROM_SIZE] {
+                code @ 0x00..=0x08 => Some(0x8000 << code),
+                _ => None,
+            },
+            ram_size: match rom[HEADER_RAM_SIZE] {
+                0x00 => Some(0),
+                0x01 => Some(0x800),
+                0x02 => Some(0x2000),
+                0x03 => Some(0x8000),
+                0x04 => Some(0x20000),
+                0x05 => Some(0x10000),
+                _ => None,
+            },
+            cgb: CgbSupport::from_header(rom),
+            sgb: supports_sgb(rom),
+            header_checksum,
+            header_checksum_valid: header_checksum_of(rom) == header_checksum,
+            global_checksum,
+            global_checksum_valid: global_sum == global_checksum,
+            multicart: is_mbc1m(rom),
+        })
+    }
+
+    /// Nombre del mapper y de los extras del cartucho, `None` si el tipo no
+    /// es ninguno de los conocidos
+    pub fn cartridge_name(&self) -> Option<&'static str> {
+        Some(match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => return None,
+        })
+    }
+}
+
+/// El checksum que calcula la boot ROM sobre el título y el resto de la
+/// cabecera
+fn header_checksum_of(rom: &[u8]) -> u8 {
+    rom[HEADER_TITLE.start..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+/// Un MBC1M es un MBC1 de 1 MiB con el logo de Nintendo repetido al
+/// principio del segundo juego, en el banco 0x10
+fn is_mbc1m(rom: &[u8]) -> bool {
+    matches!(rom[HEADER_CARTRIDGE_TYPE], 0x01..=0x03)
+        && rom.len() == 4 * MBC1M_GAME_SIZE
+        && rom[HEADER_LOGO] == rom[MBC1M_GAME_SIZE + HEADER_LOGO.start..MBC1M_GAME_SIZE + HEADER_LOGO.end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header() {
+        assert_eq!(RomHeader::parse(&[0; 0x14F]), Err(Error::TruncatedHeader { size: 0x14F }));
+
+        let mut rom = vec![0; 0x8000];
+        rom[HEADER_TITLE][..4].copy_from_slice(b"TEST");
+        rom[HEADER_RAM_SIZE] = 0x02;
+        rom[HEADER_CARTRIDGE_TYPE] = 0x09;
+        let header = RomHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "TEST");
+        assert_eq!(header.cartridge_name(), Some("ROM+RAM+BATTERY"));
+        assert_eq!((header.rom_size, header.ram_size), (Some(0x8000), Some(0x2000)));
+        assert!(!header.header_checksum_valid && !header.global_checksum_valid);
+        assert!(!header.multicart);
+
+        rom[HEADER_CHECKSUM] = header_checksum_of(&rom);
+        let sum = rom.iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
+        rom[HEADER_GLOBAL_CHECKSUM..HEADER_END].copy_from_slice(&sum.to_be_bytes());
+        let header = RomHeader::parse(&rom).unwrap();
+        assert!(header.header_checksum_valid && header.global_checksum_valid);
+    }
+
+    #[test]
+    fn mbc1m_multicart() {
+        let mut rom = vec![0; 4 * MBC1M_GAME_SIZE];
+        rom[HEADER_CARTRIDGE_TYPE] = 0x01;
+        rom[HEADER_LOGO].fill(0xCE);
+        assert!(!RomHeader::parse(&rom).unwrap().multicart);
+
+        rom[MBC1M_GAME_SIZE..][HEADER_LOGO].fill(0xCE);
+        assert!(RomHeader::parse(&rom).unwrap().multicart);
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use crate::watch::wide_value;
+use crate::{Cpu, Reg};
+
+/// Registros de la CPU en un momento dado, los pares con el primer registro
+/// como byte alto (`af >> 8` es A)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegSnapshot {
+    /// Reloj de los periféricos en T-cycles
+    pub cycle: u64,
+
+    /// Frame en curso
+    pub frame: u64,
+
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl RegSnapshot {
+    fn new(cpu: &Cpu, cycle: u64, frame: u64) -> Self {
+        Self {
+            cycle,
+            frame,
+            af: wide_value(cpu, Reg::AF),
+            bc: wide_value(cpu, Reg::BC),
+            de: wide_value(cpu, Reg::DE),
+            hl: wide_value(cpu, Reg::HL),
+            sp: wide_value(cpu, Reg::SP),
+            pc: cpu.pc(),
+        }
+    }
+
+    #[inline]
+    pub fn a(&self) -> u8 {
+        (self.af >> 8) as u8
+    }
+}
+
+/// Cada cuánto se toma una muestra
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryInterval {
+    /// Al terminar cada frame
+    Frame,
+
+    /// Cada N T-cycles, como solo se para entre instrucciones la muestra se
+    /// toma en la primera que pase del plazo
+    Cycles(u64),
+}
+
+/// Historial de los registros de la CPU para dibujar cómo evolucionaron
+/// en los frontends de depuración, se conecta con `GameBoy::set_reg_history`.
+/// Guarda las últimas `capacity` muestras
+#[derive(Debug, Clone)]
+pub struct RegHistory {
+    interval: HistoryInterval,
+    capacity: usize,
+    samples: VecDeque<RegSnapshot>,
+
+    /// Instante de la siguiente muestra con `HistoryInterval::Cycles`
+    next_at: u64,
+}
+
+impl RegHistory {
+    pub fn new(interval: HistoryInterval, capacity: usize) -> Self {
+        let interval = match interval {
+            HistoryInterval::Cycles(n) => HistoryInterval::Cycles(n.max(1)),
+            frame => frame,
+        };
+        Self {
+            interval,
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            next_at: 0,
+        }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> HistoryInterval {
+        self.interval
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Muestras de la más antigua a la más reciente
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = &RegSnapshot> + ExactSizeIterator {
+        self.samples.iter()
+    }
+
+    #[inline]
+    pub fn latest(&self) -> Option<&RegSnapshot> {
+        self.samples.back()
+    }
+
+    /// Muestras tomadas en los T-cycles `from..=to`
+    pub fn between(&self, from: u64, to: u64) -> impl Iterator<Item = &RegSnapshot> {
+        self.samples.iter().filter(move |sample| (from..=to).contains(&sample.cycle))
+    }
+
+    /// Última muestra tomada en o antes de `cycle`
+    pub fn at(&self, cycle: u64) -> Option<&RegSnapshot> {
+        self.samples.iter().rev().find(|sample| sample.cycle <= cycle)
+    }
+
+    /// Serie `(cycle, valor)` de un registro para dibujarla, como
+    /// `history.series(|s| s.hl)`
+    pub fn series<F>(&self, value: F) -> impl Iterator<Item = (u64, u16)> + '_
+    where
+        F: Fn(&RegSnapshot) -> u16 + 'static,
+    {
+        self.samples.iter().map(move |sample| (sample.cycle, value(sample)))
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Tomar una muestra si toca después de una instrucción
+    pub(crate) fn sample(&mut self, cpu: &Cpu, cycle: u64, frame: u64, frame_done: bool) {
+        let due = match self.interval {
+            HistoryInterval::Frame => frame_done,
+            HistoryInterval::Cycles(n) => {
+                let due = cycle >= self.next_at;
+                if due {
+                    self.next_at = cycle + n;
+                }
+                due
+            },
+        };
+        if !due || self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(RegSnapshot::new(cpu, cycle, frame));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_and_query() {
+        let mut cpu = Cpu::new();
+        let mut history = RegHistory::new(HistoryInterval::Cycles(10), 3);
+        for cycle in [0, 4, 12, 16, 24, 40] {
+            cpu.write_reg(Reg::H, (cycle >> 2) as u8);
+            history.sample(&cpu, cycle, 0, false);
+        }
+
+        // Se muestrean 0, 12, 24 y 40, la primera ya salió del historial
+        let cycles = history.samples().map(|sample| sample.cycle).collect::<Vec<_>>();
+        assert_eq!(cycles, [12, 24, 40]);
+        assert_eq!(history.series(|s| s.hl).collect::<Vec<_>>(),
+            [(12, 0x0300), (24, 0x0600), (40, 0x0A00)]);
+        assert_eq!(history.at(30).map(|sample| sample.cycle), Some(24));
+        assert_eq!(history.between(13, 40).count(), 2);
+
+        let mut frames = RegHistory::new(HistoryInterval::Frame, 8);
+        frames.sample(&cpu, 100, 0, false);
+        frames.sample(&cpu, 200, 1, true);
+        assert_eq!(frames.latest().map(|sample| sample.frame), Some(1));
+        assert_eq!(frames.len(), 1);
+    }
+}
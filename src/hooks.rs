@@ -0,0 +1,159 @@
+use std::fmt;
+
+use crate::access::MemAccess;
+use crate::gameboy::StepInfo;
+
+/// Identificador de un hook para poder quitarlo con `GameBoy::remove_hook`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HookId(u32);
+
+/// Instrucción ejecutada, `info.instr` incluye sus operandos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionEvent {
+    pub info: StepInfo,
+
+    /// Reloj de los periféricos en T-cycles al empezar la instrucción
+    pub cycle: u64,
+}
+
+/// Frame terminado
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameEvent {
+    /// Frames completados contando este
+    pub frame: u64,
+    pub cycle: u64,
+}
+
+/// Interrupción solicitada en IF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptEvent {
+    /// Bit de IF, como `INT_SERIAL`
+    pub mask: u8,
+
+    /// PC cuando se vio la solicitud
+    pub pc: u16,
+    pub cycle: u64,
+}
+
+type Callback<E> = Box<dyn FnMut(&E) + Send>;
+
+/// Hooks registrados en la `GameBoy`, la base sobre la que montar trucos,
+/// scripting o logros sin tocar el núcleo. Los callbacks solo reciben el
+/// evento, no pueden modificar la `GameBoy` mientras se ejecuta
+#[derive(Default)]
+pub(crate) struct Hooks {
+    next_id: u32,
+    instruction: Vec<(HookId, Callback<InstructionEvent>)>,
+    mem_write: Vec<(HookId, Callback<MemAccess>)>,
+    frame: Vec<(HookId, Callback<FrameEvent>)>,
+    interrupt: Vec<(HookId, Callback<InterruptEvent>)>,
+
+    /// IF la última vez que se miró, para avisar solo de los bits nuevos
+    last_if: u8,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("instruction", &self.instruction.len())
+            .field("mem_write", &self.mem_write.len())
+            .field("frame", &self.frame.len())
+            .field("interrupt", &self.interrupt.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    fn next_id(&mut self) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub fn on_instruction(&mut self, callback: Callback<InstructionEvent>) -> HookId {
+        let id = self.next_id();
+        self.instruction.push((id, callback));
+        id
+    }
+
+    pub fn on_mem_write(&mut self, callback: Callback<MemAccess>) -> HookId {
+        let id = self.next_id();
+        self.mem_write.push((id, callback));
+        id
+    }
+
+    pub fn on_frame(&mut self, callback: Callback<FrameEvent>) -> HookId {
+        let id = self.next_id();
+        self.frame.push((id, callback));
+        id
+    }
+
+    /// `flags` es IF en este momento, lo que ya esté solicitado no se avisa
+    pub fn on_interrupt(&mut self, callback: Callback<InterruptEvent>, flags: u8) -> HookId {
+        if self.interrupt.is_empty() {
+            self.last_if = flags;
+        }
+        let id = self.next_id();
+        self.interrupt.push((id, callback));
+        id
+    }
+
+    /// Quitar un hook de cualquier tipo, `false` si no existía
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let before = self.len();
+        self.instruction.retain(|(hook, _)| *hook != id);
+        self.mem_write.retain(|(hook, _)| *hook != id);
+        self.frame.retain(|(hook, _)| *hook != id);
+        self.interrupt.retain(|(hook, _)| *hook != id);
+        self.len() != before
+    }
+
+    fn len(&self) -> usize {
+        self.instruction.len() + self.mem_write.len() + self.frame.len() + self.interrupt.len()
+    }
+
+    #[inline]
+    pub fn has_instruction(&self) -> bool {
+        !self.instruction.is_empty()
+    }
+
+    #[inline]
+    pub fn has_mem_write(&self) -> bool {
+        !self.mem_write.is_empty()
+    }
+
+    #[inline]
+    pub fn has_frame(&self) -> bool {
+        !self.frame.is_empty()
+    }
+
+    #[inline]
+    pub fn has_interrupt(&self) -> bool {
+        !self.interrupt.is_empty()
+    }
+
+    pub fn instruction(&mut self, event: &InstructionEvent) {
+        self.instruction.iter_mut().for_each(|(_, callback)| callback(event));
+    }
+
+    pub fn mem_write(&mut self, access: &MemAccess) {
+        self.mem_write.iter_mut().for_each(|(_, callback)| callback(access));
+    }
+
+    pub fn frame(&mut self, event: &FrameEvent) {
+        self.frame.iter_mut().for_each(|(_, callback)| callback(event));
+    }
+
+    /// Avisar de los bits de IF que se han activado desde la última vez,
+    /// de menor a mayor (el orden de prioridad)
+    // TODO: Avisar cuando se atienden y no solo cuando se solicitan, cuando
+    // la CPU haga el dispatch
+    pub fn interrupts(&mut self, flags: u8, pc: u16, cycle: u64) {
+        let new = flags & !self.last_if;
+        self.last_if = flags;
+        for bit in (0..5).filter(|bit| new & 1 << bit != 0) {
+            let event = InterruptEvent { mask: 1 << bit, pc, cycle };
+            self.interrupt.iter_mut().for_each(|(_, callback)| callback(&event));
+        }
+    }
+}
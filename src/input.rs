@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "toml")]
+use crate::error::Error;
+use crate::gameboy::GameBoy;
+use crate::joypad::Button;
+
+/// Frames que dura cada mitad del ciclo pulsado/soltado de los botones con
+/// turbo si el mapa no dice otra cosa, unas 15 pulsaciones por segundo
+pub const DEFAULT_TURBO_RATE: u32 = 2;
+
+/// A qué botón de la Game Boy va una tecla o un botón del mando
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub button: Button,
+
+    /// Mientras se mantiene se alterna entre pulsado y soltado cada
+    /// `turbo_rate` frames, como el `Autofire`
+    pub turbo: bool,
+}
+
+/// Mapa de las teclas y botones del mando del host a los botones de la Game
+/// Boy. Los nombres son los que use el frontend (`Keycode::name()` de SDL o
+/// los `Button` de gilrs, por ejemplo) y no distinguen mayúsculas. El
+/// frontend le pasa los eventos con `set_key` y `set_pad_button` y al inicio
+/// de cada frame llama a `apply`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputMap {
+    keyboard: HashMap<String, Binding>,
+    gamepad: HashMap<String, Binding>,
+    turbo_rate: u32,
+
+    /// Nombres de las entradas mapeadas que se mantienen pulsadas
+    held_keys: HashSet<String>,
+    held_pad_buttons: HashSet<String>,
+}
+
+impl InputMap {
+    /// Mapa vacío, sin ninguna entrada asignada
+    pub fn new() -> Self {
+        Self {
+            keyboard: HashMap::new(),
+            gamepad: HashMap::new(),
+            turbo_rate: DEFAULT_TURBO_RATE,
+            held_keys: HashSet::new(),
+            held_pad_buttons: HashSet::new(),
+        }
+    }
+
+    /// Leer un mapa de TOML:
+    ///
+    /// ```toml
+    /// turbo_rate = 2
+    ///
+    /// [keyboard]
+    /// Z = "A"
+    /// X = "B"
+    /// A = { button = "A", turbo = true }
+    ///
+    /// [gamepad]
+    /// East = "A"
+    /// South = "B"
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        let config = toml::from_str::<config::InputConfig>(text)
+            .map_err(|err| Error::InputConfig(err.message().to_string()))?;
+
+        let mut map = Self::new();
+        map.turbo_rate = config.turbo_rate;
+        for (name, binding) in config.keyboard {
+            map.bind_key(&name, binding.into());
+        }
+        for (name, binding) in config.gamepad {
+            map.bind_pad_button(&name, binding.into());
+        }
+        Ok(map)
+    }
+
+    pub fn bind_key(&mut self, name: &str, binding: Binding) {
+        self.keyboard.insert(name.to_lowercase(), binding);
+    }
+
+    pub fn bind_pad_button(&mut self, name: &str, binding: Binding) {
+        self.gamepad.insert(name.to_lowercase(), binding);
+    }
+
+    #[inline]
+    pub fn key(&self, name: &str) -> Option<Binding> {
+        self.keyboard.get(&name.to_lowercase()).copied()
+    }
+
+    #[inline]
+    pub fn pad_button(&self, name: &str) -> Option<Binding> {
+        self.gamepad.get(&name.to_lowercase()).copied()
+    }
+
+    /// Cambiar cada cuántos frames alternan los botones con turbo, 0 los deja
+    /// pulsados sin alternar
+    pub fn set_turbo_rate(&mut self, rate: u32) {
+        self.turbo_rate = rate;
+    }
+
+    #[inline]
+    pub fn turbo_rate(&self) -> u32 {
+        self.turbo_rate
+    }
+
+    /// Pulsar o soltar una tecla, devuelve si está mapeada para que el
+    /// frontend pueda usar las demás para sus atajos
+    pub fn set_key(&mut self, name: &str, pressed: bool) -> bool {
+        let name = name.to_lowercase();
+        if !self.keyboard.contains_key(&name) {
+            return false;
+        }
+        set_held(&mut self.held_keys, name, pressed);
+        true
+    }
+
+    /// Pulsar o soltar un botón del mando, devuelve si está mapeado
+    pub fn set_pad_button(&mut self, name: &str, pressed: bool) -> bool {
+        let name = name.to_lowercase();
+        if !self.gamepad.contains_key(&name) {
+            return false;
+        }
+        set_held(&mut self.held_pad_buttons, name, pressed);
+        true
+    }
+
+    /// Soltar todo lo del mando, para cuando se desconecta
+    pub fn release_pad(&mut self) {
+        self.held_pad_buttons.clear();
+    }
+
+    /// Estado que ve el juego para el botón en el frame `frame`: pulsado si
+    /// se mantiene alguna entrada sin turbo que vaya a él o, con turbo, en
+    /// la mitad pulsada del ciclo
+    pub fn is_pressed(&self, button: Button, frame: u64) -> bool {
+        let turbo_on = match self.turbo_rate as u64 {
+            0 => true,
+            rate => (frame / rate).is_multiple_of(2),
+        };
+        let held = self.held_keys.iter().filter_map(|name| self.keyboard.get(name))
+            .chain(self.held_pad_buttons.iter().filter_map(|name| self.gamepad.get(name)));
+        held.filter(|binding| binding.button == button)
+            .any(|binding| !binding.turbo || turbo_on)
+    }
+
+    /// Pasar el estado de los botones en el frame actual a la Game Boy, solo
+    /// se tocan los que cambian para que el `Rewind` no grabe entradas de más
+    pub fn apply(&self, gb: &mut GameBoy) {
+        let frame = gb.frame_count();
+        for button in Button::ALL {
+            let pressed = self.is_pressed(button, frame);
+            if gb.joypad().is_pressed(button) != pressed {
+                gb.set_button(button, pressed);
+            }
+        }
+    }
+}
+
+/// Flechas, Z (A), X (B), Enter (Start) y Retroceso (Select) con los nombres
+/// de SDL, y en el mando la cruceta, el stick izquierdo y los botones con la
+/// disposición de la Game Boy: el derecho es A y el de abajo B
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = Self::new();
+        let keyboard = [
+            ("Right", Button::Right), ("Left", Button::Left),
+            ("Up", Button::Up), ("Down", Button::Down),
+            ("Z", Button::A), ("X", Button::B),
+            ("Return", Button::Start), ("Backspace", Button::Select),
+        ];
+        let gamepad = [
+            ("DPadRight", Button::Right), ("DPadLeft", Button::Left),
+            ("DPadUp", Button::Up), ("DPadDown", Button::Down),
+            ("LeftStickRight", Button::Right), ("LeftStickLeft", Button::Left),
+            ("LeftStickUp", Button::Up), ("LeftStickDown", Button::Down),
+            ("East", Button::A), ("South", Button::B),
+            ("Start", Button::Start), ("Select", Button::Select),
+        ];
+        for (name, button) in keyboard {
+            map.bind_key(name, Binding { button, turbo: false });
+        }
+        for (name, button) in gamepad {
+            map.bind_pad_button(name, Binding { button, turbo: false });
+        }
+        map
+    }
+}
+
+fn set_held(held: &mut HashSet<String>, name: String, pressed: bool) {
+    if pressed {
+        held.insert(name);
+    } else {
+        held.remove(&name);
+    }
+}
+
+/// Formato del fichero de configuración
+#[cfg(feature = "toml")]
+mod config {
+    use std::collections::HashMap;
+
+    use super::{Binding, DEFAULT_TURBO_RATE};
+    use crate::joypad::Button;
+
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct InputConfig {
+        #[serde(default = "default_turbo_rate")]
+        pub turbo_rate: u32,
+        #[serde(default)]
+        pub keyboard: HashMap<String, BindingConfig>,
+        #[serde(default)]
+        pub gamepad: HashMap<String, BindingConfig>,
+    }
+
+    fn default_turbo_rate() -> u32 {
+        DEFAULT_TURBO_RATE
+    }
+
+    /// Un botón suelto (`Z = "A"`) o una tabla con turbo
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    pub(super) enum BindingConfig {
+        Button(Button),
+        Full {
+            button: Button,
+            #[serde(default)]
+            turbo: bool,
+        },
+    }
+
+    impl From<BindingConfig> for Binding {
+        fn from(config: BindingConfig) -> Self {
+            match config {
+                BindingConfig::Button(button) => Binding { button, turbo: false },
+                BindingConfig::Full { button, turbo } => Binding { button, turbo },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_and_turbo() {
+        let mut map = InputMap::default();
+        map.bind_key("A", Binding { button: Button::A, turbo: true });
+        assert!(!map.set_key("Q", true));
+
+        assert!(map.set_key("z", true));
+        assert!(map.is_pressed(Button::A, 0));
+        map.set_key("Z", false);
+        assert!(!map.is_pressed(Button::A, 0));
+
+        map.set_key("A", true);
+        let pattern = (0..6).map(|frame| map.is_pressed(Button::A, frame)).collect::<Vec<_>>();
+        assert_eq!(pattern, [true, true, false, false, true, true]);
+
+        // Otra entrada sin turbo al mismo botón lo deja pulsado
+        map.set_pad_button("East", true);
+        assert!(map.is_pressed(Button::A, 2));
+        map.release_pad();
+        assert!(!map.is_pressed(Button::A, 2));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn parse_toml() {
+        let map = InputMap::from_toml(r#"
+            turbo_rate = 3
+
+            [keyboard]
+            K = "B"
+            L = { button = "A", turbo = true }
+
+            [gamepad]
+            North = "Start"
+        "#).unwrap();
+        assert_eq!(map.turbo_rate(), 3);
+        assert_eq!(map.key("k"), Some(Binding { button: Button::B, turbo: false }));
+        assert_eq!(map.key("L"), Some(Binding { button: Button::A, turbo: true }));
+        assert_eq!(map.pad_button("north"), Some(Binding { button: Button::Start, turbo: false }));
+        assert_eq!(map.key("Z"), None);
+
+        assert!(matches!(InputMap::from_toml("[keyboard]\nZ = \"C\""), Err(Error::InputConfig(_))));
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+
+/// Dirección del registro RP (puerto de infrarrojos, solo CGB)
+pub const RP: u16 = 0xFF56;
+
+/// Bit de RP que enciende el LED emisor
+pub const RP_LED: u8 = 1 << 0;
+
+/// Bit de RP que se lee a 0 cuando llega luz al receptor
+pub const RP_RECEIVING: u8 = 1 << 1;
+
+/// Bits de RP que deben estar a 1 para que se pueda leer el receptor
+pub const RP_READ_ENABLE: u8 = 0b1100_0000;
+
+/// Abstracción del emisor/receptor de infrarrojos, permite que el frontend
+/// simule el intercambio entre varias instancias
+pub trait IrTransceiver: Send {
+    /// El juego encendió o apagó el LED
+    fn set_led(&mut self, on: bool);
+
+    /// Llega luz de algún otro emisor al receptor
+    fn is_receiving(&self) -> bool;
+}
+
+/// Dos puertos de infrarrojos apuntándose entre sí dentro del mismo proceso,
+/// se crean siempre por pares con `PairedIr::pair`
+#[derive(Debug, Clone)]
+pub struct PairedIr {
+    /// Estado del LED de cada extremo
+    leds: Arc<Mutex<[bool; 2]>>,
+    side: usize,
+}
+
+impl PairedIr {
+    pub fn pair() -> (Self, Self) {
+        let leds = Arc::new(Mutex::new([false; 2]));
+        let a = Self { leds: leds.clone(), side: 0 };
+        let b = Self { leds, side: 1 };
+        (a, b)
+    }
+}
+
+impl IrTransceiver for PairedIr {
+    fn set_led(&mut self, on: bool) {
+        self.leds.lock().unwrap()[self.side] = on;
+    }
+
+    fn is_receiving(&self) -> bool {
+        self.leds.lock().unwrap()[1 - self.side]
+    }
+}
+
+/// Valor que lee la CPU de RP a partir de lo escrito por el juego, los bits
+/// 2-5 no existen y se leen a 1
+pub fn read_rp(written: u8, receiving: bool) -> u8 {
+    let mut value = (written & (RP_LED | RP_READ_ENABLE)) | 0b0011_1100;
+    if !(receiving && written & RP_READ_ENABLE == RP_READ_ENABLE) {
+        value |= RP_RECEIVING;
+    }
+    value
+}
@@ -0,0 +1,197 @@
+use crate::mmu::Mmu;
+
+/// Los botones físicos de la Game Boy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Button {
+    Right = 0,
+    Left = 1,
+    Up = 2,
+    Down = 3,
+    A = 4,
+    B = 5,
+    Select = 6,
+    Start = 7,
+}
+
+impl Button {
+    /// Todos los botones, en el orden de sus bits dentro de la matriz
+    pub const ALL: [Button; 8] = [
+        Button::Right, Button::Left, Button::Up, Button::Down,
+        Button::A, Button::B, Button::Select, Button::Start,
+    ];
+
+    /// Máscara del botón dentro del byte de estado del `Joypad`, los 4 bits
+    /// bajos son las direcciones y los 4 altos los botones de acción
+    #[inline]
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Bit de JOYP que a 0 selecciona la fila de direcciones
+pub const JOYP_SELECT_DPAD: u8 = 1 << 4;
+
+/// Bit de JOYP que a 0 selecciona la fila de botones de acción
+pub const JOYP_SELECT_BUTTONS: u8 = 1 << 5;
+
+/// Estado de la matriz de botones conectada al registro JOYP (0xFF00)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Joypad {
+    /// Un bit por cada `Button`, a 1 si está pulsado
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self { pressed: 0 }
+    }
+
+    /// Pulsar o soltar un botón
+    #[inline]
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+    }
+
+    #[inline]
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() != 0
+    }
+
+    /// Estado de todos los botones como un byte, un bit por `Button`
+    #[inline]
+    pub fn state(&self) -> u8 {
+        self.pressed
+    }
+
+    /// Calcula el valor que lee la CPU de JOYP a partir de las líneas de
+    /// selección escritas por el juego, tanto la selección como los botones
+    /// son activos a nivel bajo, es decir un 0 significa pulsado. Los bits 6 y
+    /// 7 no están conectados y siempre se leen como 1
+    pub fn read(&self, select: u8) -> u8 {
+        let select = select & (JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS);
+
+        // Si las dos filas están seleccionadas las líneas se unen, por lo que
+        // un botón pulsado en cualquiera de ellas tira la línea a 0
+        let mut lines = 0;
+        if select & JOYP_SELECT_DPAD == 0 {
+            lines |= self.pressed & 0x0F;
+        }
+        if select & JOYP_SELECT_BUTTONS == 0 {
+            lines |= self.pressed >> 4;
+        }
+
+        0b1100_0000 | select | (!lines & 0x0F)
+    }
+}
+
+/// Autofire (turbo) implementado por encima del registro JOYP: el frontend
+/// indica qué botones mantiene pulsados y en los que tengan autofire se alterna
+/// entre pulsado y soltado cada `rate` frames, por lo que el juego solo ve
+/// pulsaciones rápidas normales
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Autofire {
+    /// Número de frames que dura cada mitad del ciclo pulsado/soltado de cada
+    /// botón, 0 si el botón no tiene autofire
+    rates: [u32; 8],
+
+    /// Botones que mantiene pulsados el usuario, mismo formato que `Joypad`
+    held: u8,
+}
+
+impl Autofire {
+    pub fn new() -> Self {
+        Self { rates: [0; 8], held: 0 }
+    }
+
+    /// Activar el autofire de un botón alternando cada `rate` frames, un
+    /// `rate` de 0 lo desactiva
+    pub fn set_rate(&mut self, button: Button, rate: u32) {
+        self.rates[button as usize] = rate;
+    }
+
+    #[inline]
+    pub fn rate(&self, button: Button) -> u32 {
+        self.rates[button as usize]
+    }
+
+    /// Indicar si el usuario mantiene pulsado el botón
+    #[inline]
+    pub fn set_held(&mut self, button: Button, held: bool) {
+        if held {
+            self.held |= button.mask();
+        } else {
+            self.held &= !button.mask();
+        }
+    }
+
+    /// Estado que ve el juego para el botón en el frame `frame`
+    pub fn is_pressed(&self, button: Button, frame: u64) -> bool {
+        if self.held & button.mask() == 0 {
+            return false;
+        }
+
+        match self.rates[button as usize] as u64 {
+            0 => true,
+            rate => (frame / rate).is_multiple_of(2),
+        }
+    }
+
+    /// Aplicar el estado de todos los botones en el frame `frame` a la MMU,
+    /// se debe llamar una vez al inicio de cada frame
+    pub fn apply(&self, frame: u64, mmu: &mut Mmu) {
+        for button in Button::ALL {
+            mmu.set_button(button, self.is_pressed(button, frame));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn button_matrix() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Start, true);
+        joypad.set_button(Button::Left, true);
+
+        // Ninguna fila seleccionada
+        assert_eq!(joypad.read(0x30), 0xFF);
+
+        // Fila de direcciones, solo Left (bit 1) a 0
+        assert_eq!(joypad.read(0x20), 0xED);
+
+        // Fila de acción, solo Start (bit 3) a 0
+        assert_eq!(joypad.read(0x10), 0xD7);
+
+        joypad.set_button(Button::Start, false);
+        assert_eq!(joypad.read(0x10), 0xDF);
+        assert!(!joypad.is_pressed(Button::Start));
+    }
+
+    #[test]
+    fn autofire_toggles() {
+        let mut autofire = Autofire::new();
+        autofire.set_rate(Button::A, 2);
+        autofire.set_held(Button::A, true);
+        autofire.set_held(Button::B, true);
+
+        let presses = (0..8)
+            .map(|frame| autofire.is_pressed(Button::A, frame))
+            .collect::<Vec<_>>();
+        assert_eq!(presses, [true, true, false, false, true, true, false, false]);
+
+        // Sin autofire el botón se mantiene pulsado
+        assert!((0..8).all(|frame| autofire.is_pressed(Button::B, frame)));
+
+        autofire.set_held(Button::A, false);
+        assert!(!autofire.is_pressed(Button::A, 0));
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use serde::Serialize;
+
+use crate::gameboy::GameBoy;
+use crate::{Reg, FLAG_C, FLAG_H, FLAG_N, FLAG_Z};
+
+/// Volcado legible del estado, todos los valores van en hexadecimal como
+/// texto para que los diffs sean fáciles de leer
+#[derive(Serialize)]
+struct JsonState {
+    model: String,
+    cgb_mode: bool,
+    cycles: u64,
+    frame_count: u64,
+    registers: BTreeMap<&'static str, String>,
+    flags: BTreeMap<&'static str, bool>,
+    io: BTreeMap<String, String>,
+    mapper: JsonMapper,
+    regions: Vec<JsonRegion>,
+}
+
+#[derive(Serialize)]
+struct JsonMapper {
+    // TODO: Cuando haya mappers incluir el banco seleccionado y la RAM
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct JsonRegion {
+    start: String,
+    end: String,
+
+    /// Bytes de la región en hexadecimal, sin separadores
+    data: String,
+}
+
+/// Ver `GameBoy::export_json`
+pub(crate) fn export_json(gb: &GameBoy, regions: &[RangeInclusive<u16>]) -> String {
+    let cpu = gb.cpu();
+    let hex = |value: u8| format!("{value:02X}");
+
+    let mut registers = [
+        ("a", Reg::A), ("f", Reg::F), ("b", Reg::B), ("c", Reg::C),
+        ("d", Reg::D), ("e", Reg::E), ("h", Reg::H), ("l", Reg::L),
+    ].into_iter()
+        .map(|(name, reg)| (name, hex(cpu.read_reg(reg))))
+        .collect::<BTreeMap<_, _>>();
+    registers.insert("pc", format!("{:04X}", cpu.pc()));
+
+    let f = cpu.read_reg(Reg::F);
+    let flags = [("z", FLAG_Z), ("n", FLAG_N), ("h", FLAG_H), ("c", FLAG_C)]
+        .into_iter()
+        .map(|(name, flag)| (name, f & flag != 0))
+        .collect();
+
+    // Se lee la memoria cruda para no provocar efectos de los handlers
+    let memory = gb.mmu().memory();
+    let io = (0xFF00..=0xFF7F).chain([0xFFFF])
+        .map(|addr: usize| (format!("{addr:04X}"), hex(memory[addr])))
+        .collect();
+
+    let regions = regions.iter()
+        .map(|range| JsonRegion {
+            start: format!("{:04X}", range.start()),
+            end: format!("{:04X}", range.end()),
+            data: memory[*range.start() as usize..=*range.end() as usize]
+                .iter()
+                .map(|byte| hex(*byte))
+                .collect(),
+        })
+        .collect();
+
+    let state = JsonState {
+        model: format!("{:?}", gb.model()),
+        cgb_mode: gb.is_cgb_mode(),
+        cycles: gb.cycles(),
+        frame_count: gb.frame_count(),
+        registers,
+        flags,
+        io,
+        mapper: JsonMapper { kind: "rom-only" },
+        regions,
+    };
+    serde_json::to_string_pretty(&state).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_registers_and_regions() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0153].copy_from_slice(&[1, 2, 3]);
+        let gb = GameBoy::builder().rom(rom).build().unwrap();
+
+        let json = gb.export_json(&[0x0150..=0x0152]);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["registers"]["a"], "01");
+        assert_eq!(value["registers"]["pc"], "0100");
+        assert_eq!(value["flags"]["z"], true);
+        assert_eq!(value["io"]["FF40"], "91");
+        assert_eq!(value["regions"][0]["data"], "010203");
+        assert_eq!(value["model"], "Dmg");
+    }
+}
@@ -1,9 +1,168 @@
+mod error;
 mod mmu;
-
-use crate::mmu::Mmu;
+#[cfg(feature = "ppu")]
+mod frame;
+mod gameboy;
+mod scheduler;
+mod limiter;
+mod sink;
+mod rng;
+mod model;
+mod header;
+mod sgb;
+#[cfg(feature = "ppu")]
+mod palette;
+#[cfg(feature = "ppu")]
+mod terminal;
+#[cfg(feature = "embedded")]
+mod embedded;
+#[cfg(all(feature = "ppu", feature = "apu"))]
+mod vectors;
+mod batch;
+mod regression;
+mod debugger;
+mod doctor;
+mod tracer;
+mod profiler;
+mod stats;
+mod access;
+mod history;
+mod hooks;
+mod chrome_trace;
+mod watch;
+mod symbols;
+mod disasm;
+mod scanner;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "json")]
+mod sm83;
+#[cfg(feature = "debug-ui")]
+mod debug_ui;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "mobile")]
+mod mobile;
+#[cfg(feature = "serde")]
+mod rewind;
+#[cfg(feature = "serde")]
+mod slots;
+mod saves;
+mod rtc;
+#[cfg(feature = "romdb")]
+mod romdb;
+#[cfg(feature = "serde")]
+mod state;
+mod lockstep;
+mod reftrace;
+mod golden;
+mod testrom;
+mod joypad;
+mod input;
+mod movie;
+mod script;
+mod serial;
+mod printer;
+mod ir;
+mod peripheral;
+mod debug_port;
+#[cfg(feature = "net")]
+mod net;
+#[cfg(all(feature = "net", feature = "serde"))]
+mod rollback;
+
+pub use crate::error::{Error, MAX_ROM_SIZE};
+pub use crate::mmu::{Addr, Bus, MemRegion, Mmu};
+pub use crate::mmu::{INT_JOYPAD, INT_SERIAL, INT_STAT, INT_TIMER, INT_VBLANK};
+use crate::mmu::{IE, IF};
+pub use crate::peripheral::{InterruptFlags, Peripheral};
+pub use crate::debug_port::{DebugSink, StdoutDebug, DEFAULT_DEBUG_PORT};
+#[cfg(feature = "ppu")]
+pub use crate::frame::{Frame, FrameDiff, Rect, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use crate::batch::run_batch;
+pub use crate::regression::{run_regression, RegressionReport, RomResult, RomStatus};
+pub use crate::debugger::{Breakpoint, CallFrame, CallKind, Debugger, StackEntry};
+pub use crate::debugger::{WatchEvent, WatchId, WatchMode};
+pub use crate::watch::WatchExpr;
+pub use crate::symbols::SymbolTable;
+pub use crate::disasm::{disassemble, disassemble_range, format_instr, DisasmLine};
+pub use crate::profiler::{Hotspot, Profiler};
+pub use crate::stats::{FrameStats, FrameTiming, SubsystemStats, SubsystemTimes};
+pub use crate::access::{AccessKind, AccessLog, MemAccess};
+pub use crate::history::{HistoryInterval, RegHistory, RegSnapshot};
+pub use crate::hooks::{FrameEvent, HookId, InstructionEvent, InterruptEvent};
+pub use crate::chrome_trace::ChromeTrace;
+pub use crate::tracer::{RingTrace, TraceFilter, TraceSink, Tracer, WriteTrace};
+pub use crate::scanner::{MemoryScanner, ScanFilter, WRAM};
+#[cfg(feature = "serde")]
+pub use crate::rewind::Rewind;
+#[cfg(feature = "debug-ui")]
+pub use crate::debug_ui::DebugUi;
+#[cfg(feature = "wasm")]
+pub use crate::wasm::WebGameBoy;
+#[cfg(feature = "python")]
+pub use crate::python::PyGameBoi;
+#[cfg(feature = "mobile")]
+pub use crate::mobile::{MobileError, MobileGameBoy};
+#[cfg(feature = "json")]
+pub use crate::sm83::{run_sm83_json, Sm83Case, Sm83State, Sm83Summary};
+#[cfg(feature = "serde")]
+pub use crate::slots::{SlotInfo, SlotManager};
+pub use crate::saves::{BatteryFile, SaveManager};
+#[cfg(feature = "mmap")]
+pub use crate::saves::MappedSram;
+pub use crate::rtc::{RtcCatchUp, RtcRegisters, RtcSave};
+#[cfg(feature = "romdb")]
+pub use crate::romdb::{identify_rom, rom_crc32, RomDatabase, RomEntry};
+#[cfg(feature = "serde")]
+pub use crate::state::{StateError, STATE_VERSION};
+pub use crate::lockstep::{run_lockstep, Divergence, DivergenceKind, Granularity};
+pub use crate::reftrace::{compare_trace, TraceDivergence, TraceField, TraceFormat};
+pub use crate::golden::{GoldenMismatch, GoldenTrace, StepDigest};
+pub use crate::testrom::{run_blargg, BlarggReport, BLARGG_MAX_FRAMES};
+pub use crate::testrom::{run_mooneye, MooneyeReport, MOONEYE_MAX_FRAMES};
+pub use crate::gameboy::{GameBoy, GameBoyBuilder, RomWriteTrap, RunSummary, StepInfo, StepResult};
+#[cfg(feature = "ppu")]
+pub use crate::palette::{CompatPalette, Layer};
+#[cfg(feature = "ppu")]
+pub use crate::terminal::{TerminalMode, TerminalSink};
+#[cfg(feature = "embedded")]
+pub use crate::embedded::DisplaySink;
+#[cfg(all(feature = "ppu", feature = "apu"))]
+pub use crate::vectors::{AvManifest, AvMismatch, AvVector};
+pub use crate::sgb::{Sgb, SgbMask, SGB_HEIGHT, SGB_WIDTH};
+pub use crate::model::{header_title, CgbSupport, Model};
+pub use crate::header::RomHeader;
+pub use crate::limiter::{FrameLimiter, CPU_FREQUENCY, FRAME_RATE};
+pub use crate::sink::BatterySink;
+#[cfg(feature = "apu")]
+pub use crate::sink::AudioSink;
+#[cfg(feature = "ppu")]
+pub use crate::sink::VideoSink;
+pub use crate::joypad::{Autofire, Button, Joypad};
+pub use crate::input::{Binding, InputMap, DEFAULT_TURBO_RATE};
+pub use crate::movie::Movie;
+pub use crate::script::InputScript;
+pub use crate::serial::{PairedLink, SerialCapture, SerialLink, TestOutcome};
+pub use crate::printer::{PrintedImage, Printer};
+pub use crate::ir::{IrTransceiver, PairedIr};
+#[cfg(feature = "net")]
+pub use crate::net::{LinkRole, TcpLink};
+#[cfg(all(feature = "net", feature = "serde"))]
+pub use crate::rollback::{RollbackSession, MAX_ROLLBACK_FRAMES};
+
+// El scaffolding de UniFFI tiene que estar en la raíz, usa los tipos de
+// `src/gameboi.udl` re-exportados arriba
+#[cfg(feature = "mobile")]
+uniffi::include_scaffolding!("gameboi");
 
 /// Los registros de 8bits la CPU
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Reg {
     Invalid = 0,
@@ -35,6 +194,7 @@ impl Reg {
 /// Los posibles conjuntos de registros usados como contenedor de una dirección
 /// Los casos `HLPlus` y `HLMinus` son especiales ya que añaden 1 a la dirección
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum RegAddr {
     Invalid = 0,
@@ -47,12 +207,13 @@ pub enum RegAddr {
 
 impl RegAddr {
     pub fn from_u8(value: u8) -> Self {
-        debug_assert!((value >= 10 && value <= 15) || value == 0);
+        debug_assert!((10..=15).contains(&value) || value == 0);
         unsafe { std::mem::transmute::<u8, Self>(value) }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum InstrKind {
     /// Nop
@@ -60,6 +221,7 @@ pub enum InstrKind {
 
     /// Control
     Halt = 1,
+    Stop = 39,
 
     /// Basic Loads
     LdRegReg = 2,
@@ -156,6 +318,10 @@ pub enum InstrKind {
     SetReg = 75,
     SetMem = 76,
 
+    /// Interrupts
+    Di,
+    Ei,
+
     /// Calls and returns
     Ret,
     RetCond,
@@ -163,13 +329,14 @@ pub enum InstrKind {
 }
 
 impl InstrKind {
-    pub fn from_u8(value: u8) -> Self {
-        debug_assert!(value <= 10);
+    pub const fn from_u8(value: u8) -> Self {
+        debug_assert!(value <= InstrKind::Reti as u8);
         unsafe { std::mem::transmute::<u8, Self>(value) }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instr {
     /// Nop :d
     Nop,                                     
@@ -177,6 +344,15 @@ pub enum Instr {
     /// Finalizar la ejecución de la máquina
     Halt,
 
+    /// Detener la CPU hasta que se pulse un botón
+    Stop,
+
+    /// Desactivar las interrupciones (IME a 0)
+    Di,
+
+    /// Activar las interrupciones a partir de la siguiente instrucción
+    Ei,
+
     /// LD (loads)
     LdRegReg { src: Reg,     dst: Reg },
     LdRegImm { src: u8,      dst: Reg },
@@ -237,6 +413,9 @@ pub enum Instr {
     JRelCond { cond: u8, offset: u8 },
     Rst { addr: u8 },
 
+    /// Volver de una interrupción activando IME
+    Reti,
+
     RlcReg { reg: Reg },
     RlcMem { reg: RegAddr },
     RrcReg { reg: Reg },
@@ -262,7 +441,75 @@ pub enum Instr {
 
 }
 
-#[derive(Debug)]
+/// Grupos de instrucciones, sirven para filtrar trazas y perfiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InstrClass {
+    /// NOP, HALT, STOP, DI y EI
+    Control,
+    Load,
+    Arithmetic,
+    Stack,
+    Jump,
+
+    /// Rotaciones y desplazamientos del prefijo CB
+    Shift,
+
+    /// BIT, RES y SET
+    Bit,
+}
+
+impl Instr {
+    pub fn class(&self) -> InstrClass {
+        use Instr::*;
+        match self {
+            Nop | Halt | Stop | Di | Ei => InstrClass::Control,
+            LdRegReg { .. } | LdRegImm { .. } | LdRegMem { .. } | LdMemReg { .. }
+                | LdMemHLImm | LdWRegImm { .. } | LdMemImmReg { .. } => InstrClass::Load,
+            Push { .. } | Pop { .. } => InstrClass::Stack,
+            JPImm { .. } | JPCond { .. } | JPReg { .. } | JRelImm { .. }
+                | JRelCond { .. } | Rst { .. } | Reti => InstrClass::Jump,
+            RlcReg { .. } | RlcMem { .. } | RrcReg { .. } | RrcMem { .. }
+                | RlReg { .. } | RlMem { .. } | RrReg { .. } | RrMem { .. }
+                | SlaReg { .. } | SlaMem { .. } | SraReg { .. } | SraMem { .. }
+                | SwapReg { .. } | SwapMem { .. } | SrlReg { .. }
+                | SrlMem { .. } => InstrClass::Shift,
+            BitReg { .. } | BitMem { .. } | ResReg { .. } | ResMem { .. }
+                | SetReg { .. } | SetMem { .. } => InstrClass::Bit,
+            _ => InstrClass::Arithmetic,
+        }
+    }
+
+    /// La instrucción escribe en memoria, incluidas las que apilan
+    pub fn writes_memory(&self) -> bool {
+        use Instr::*;
+        matches!(self,
+            LdRegMem { .. } | LdMemHLImm | LdMemImmReg { .. } | IncMem { .. }
+            | DecMem { .. } | Push { .. } | Rst { .. } | RlcMem { .. }
+            | RrcMem { .. } | RlMem { .. } | RrMem { .. } | SlaMem { .. }
+            | SraMem { .. } | SwapMem { .. } | SrlMem { .. } | ResMem { .. }
+            | SetMem { .. })
+    }
+
+    /// Bytes que ocupa la instrucción en memoria, opcode incluido
+    pub fn size(&self) -> u16 {
+        use Instr::*;
+        match self {
+            LdWRegImm { .. } | LdMemImmReg { .. } | JPImm { .. } | JPCond { .. } => 3,
+            Stop | LdRegImm { .. } | LdMemHLImm | AddRegImm { .. } | AddWRegImm { .. }
+                | AdcRegImm { .. } | SubImm { .. } | SbcImm { .. } | AndImm { .. }
+                | OrImm { .. } | CpImm { .. } | JRelImm { .. } | JRelCond { .. } => 2,
+            _ if self.class() == InstrClass::Shift || self.class() == InstrClass::Bit => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// T-cycles que tarda `Cpu::service_interrupt`
+pub const INTERRUPT_DISPATCH_CYCLES: u32 = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cpu {
     /// Hay 8, registros de 8-bits, 3 registros de 16-bits que son las unión de
     /// 2 registros de 8-bits BC, DE y HL, además del Stack Pointer (SP) que es
@@ -272,7 +519,25 @@ pub struct Cpu {
     registers: [u8; 10],
 
     /// Program counter
-    pc: u16
+    pc: u16,
+
+    /// La CPU está detenida por STOP y no ejecuta hasta que se despierte
+    stopped: bool,
+
+    /// Interrupt Master Enable, a 0 las interrupciones se quedan pendientes
+    /// en IF. Se guarda en su propio chunk de los save states para que los
+    /// antiguos, sin él, se sigan pudiendo cargar
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ime: bool,
+
+    /// EI se ejecutó y IME se activa al terminar la siguiente instrucción,
+    /// va en el mismo chunk que `ime`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ei_delay: bool,
+
+    /// Contador monótono de T-cycles ejecutados desde que se creó la CPU,
+    /// nunca se reinicia por lo que sirve como marca de tiempo global
+    cycles: u64,
 }
 
 /// Zero Flag: Se activa cuando el resultado de la última operación matemática
@@ -291,25 +556,34 @@ const FLAG_H: u8 = 1 << 5;
 /// el registro A es el menor valor al ejecutar la instrucción CP
 const FLAG_C: u8 = 1 << 4;
 
+/// Opcode válido que todavía no se decodifica, `decode` devuelve
+/// `Error::Unimplemented`
+const NI: u8 = 0xFF;
+
+/// Opcode que no existe en la SM83 (0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC,
+/// 0xED, 0xF4, 0xFC y 0xFD), `decode` devuelve `Error::InvalidOpcode`
+const IL: u8 = 0xFE;
+
 /// Esta tabla se usa para discernir el tipo de instrucción `InstrKind` que 
-/// luego se convierte a `Instr` accediendo a las otras tablas
-const INST_KIND_TABLE: &[u8] = &[
-    0,40, 4, 0, 0, 0, 3, 0, 0,10, 5, 0, 0, 0, 3, 0,
-    0,40, 4, 0, 0, 0, 3, 0,48,10, 5, 0, 0, 0, 3, 0,
-   49,40, 4, 0, 0, 0, 3, 0,49,10, 5, 0, 0, 0, 3, 0,
-   49,40, 4, 0, 0, 0, 3, 0,49,10, 5, 0, 0, 0, 3, 0,
+/// luego se convierte a `Instr` accediendo a las otras tablas. Las entradas
+/// `NI` e `IL` no son un `InstrKind` y se descartan antes de convertirlas
+const INST_KIND_TABLE: [u8; 0x100] = [
+    0,40, 4,NI,NI,NI, 3,NI,NI,10, 5,34,NI,NI, 3,NI,
+   39,40, 4,NI,NI,NI, 3,NI,48,10, 5,34,NI,NI, 3,NI,
+   49,40, 4,NI,NI,NI, 3,NI,49,10, 5,34,NI,NI, 3,NI,
+   49,40, 4,NI,NI,NI, 6,NI,49,10, 5,34,NI,NI, 3,NI,
     2, 2, 2, 2, 2, 2, 5, 2, 2, 2, 2, 2, 2, 2, 5, 2,
     2, 2, 2, 2, 2, 2, 5, 2, 2, 2, 2, 2, 2, 2, 5, 2,
     2, 2, 2, 2, 2, 2, 5, 2, 2, 2, 2, 2, 2, 2, 5, 2,
     4, 4, 4, 4, 4, 4, 1, 4, 2, 2, 2, 2, 2, 2, 5, 2,
     7, 7, 7, 7, 7, 7, 9, 7,12,12,12,12,12,12,14,12,
-   15,15,15,15,15,15,17,15,18,18,18,18,18,18,18,18,
-   21,21,21,21,21,21,23,21,24,24,24,24,24,24,24,24,
-   27,27,27,27,27,27,27,28, 0, 0, 0, 0, 0, 0, 0, 0,
-    0,43, 0,46,45,42, 9, 0, 0, 0, 0,46, 0, 0,13, 0,
-    0,43, 0,46, 0,42,16, 0, 0, 0, 0,46, 0, 0,19, 0,
-    0,43, 0, 0, 0,42,22, 0,11, 0,47, 0, 0, 0,25, 0,
-    0,43, 0, 0, 0,42,28, 0, 0, 0,10, 0, 0, 0, 0, 0,
+   15,15,15,15,15,15,17,15,18,18,18,18,18,18,20,18,
+   21,21,21,21,21,21,23,21,24,24,24,24,24,24,26,24,
+   27,27,27,27,27,27,29,27,NI,NI,NI,NI,NI,NI,NI,NI,
+   NI,43,NI,46,45,42, 9,NI,NI,NI,NI,46,NI,NI,13,NI,
+   NI,43,NI,IL,NI,42,16,NI,NI,81,NI,IL,NI,IL,19,NI,
+   NI,43,NI,IL,IL,42,22,NI,11,NI,47,IL,IL,IL,25,NI,
+   NI,43,NI,77,IL,42,28,NI,NI,NI,NI,78,IL,IL,NI,NI,
 ];
 
 const NZ: u8 = FLAG_N | FLAG_Z;
@@ -320,12 +594,12 @@ const C:  u8 = FLAG_C;
 /// Tabla usada para discernir el operando de entrada de la instrucción, sus
 /// valores son convertibles directamente a los enums `Reg` y `RegMem`, en 
 /// release la conversión se hace sin comprobaciones
-const SRC_TABLE: &[u8] = &[
+const SRC_TABLE: [u8; 0x100] = [
     0, 0, 1, 3, 3, 3, 0, 0, 9, 3,13, 3, 4, 3, 0, 0,
     0, 0, 1, 5, 5, 5, 0, 0, 0, 5,14, 5, 6, 5, 0, 0,
    NZ, 0, 1, 7, 7, 7, 0, 0, Z, 7,11, 7, 8, 8, 0, 0,
    NC, 0, 1, 9,10,10, 0, 0, C, 9,12, 9, 1, 1, 0, 0,
-    3, 3, 4, 6, 7, 8,10, 1, 3, 4, 5, 6, 7, 8,10, 1,
+    3, 4, 5, 6, 7, 8,10, 1, 3, 4, 5, 6, 7, 8,10, 1,
     3, 4, 5, 6, 7, 8,10, 1, 3, 4, 5, 6, 7, 8,10, 1,
     3, 4, 5, 6, 7, 8,10, 1, 3, 4, 5, 6, 7, 8,10, 1,
     3, 4, 5, 6, 7, 8, 0, 1, 3, 4, 5, 6, 7, 8,10, 1,
@@ -336,15 +610,15 @@ const SRC_TABLE: &[u8] = &[
    NZ, 3,NZ, 0, 0, 3, 0, 0, Z, 0, Z, 0, Z, 0, 0, 0,
    NC, 5,NC, 0, 0, 5, 0, 0, C, 0, C, 0, C, 0, 0, 0,
     0, 7, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0,
+    0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
 /// Tabla usada para discernir el operando destino
-const DST_TABLE: &[u8] = &[
+const DST_TABLE: [u8; 0x100] = [
     0, 3,12, 0, 0, 0, 3, 0, 0, 7, 1, 0, 0, 4, 0, 0,
     0, 5,13, 0, 0, 0, 5, 0, 0, 7, 1, 0, 0, 6, 0, 0,
     0, 7,10, 0, 0, 0, 7, 0, 0, 7, 1, 0, 0, 8, 0, 0,
-    0, 9,11, 0, 0, 0, 9, 0, 0, 7, 1, 0, 0, 1, 0, 0,
+    0, 9,11, 0, 0, 0, 0, 0, 0, 7, 1, 0, 0, 1, 0, 0,
     3, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4,
     5, 5, 5, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 6,
     7, 7, 7, 7, 7, 7, 7, 7, 8, 8, 8, 8, 8, 8, 8, 8,
@@ -356,31 +630,32 @@ const DST_TABLE: &[u8] = &[
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 9, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0,
 ];
 
-/// Tabla usada para discernir el operando destino
-const PREFIX_TABLE: &[u8] = &[
+/// Como `INST_KIND_TABLE` para las instrucciones con prefijo 0xCB, indexada
+/// por el segundo byte
+const PREFIX_TABLE: [u8; 0x100] = [
    55,55,55,55,55,55,56,55,57,57,57,57,57,57,58,57,
    59,59,59,59,59,59,60,59,61,61,61,61,61,61,62,61,
    63,63,63,63,63,63,64,63,65,65,65,65,65,65,66,65,
    67,67,67,67,67,67,68,67,69,69,69,69,69,69,70,69,
-   71,71,71,71,71,71,71,71,73,73,73,73,73,73,73,73,
-   75,75,75,75,75,75,75,75,77,77,77,77,77,77,77,77,
-   79,79,79,79,79,79,79,79,81,81,81,81,81,81,81,81,
-   83,83,83,83,83,83,83,83,85,85,85,85,85,85,85,85,
-   87,87,87,87,87,87,87,87,89,89,89,89,89,89,89,89,
-   91,91,91,91,91,91,91,91,93,93,93,93,93,93,93,93,
-   95,95,95,95,95,95,95,95,97,97,97,97,97,97,97,97,    
-    0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 9, 0, 0, 0, 0, 0,
+   71,71,71,71,71,71,72,71,71,71,71,71,71,71,72,71,
+   71,71,71,71,71,71,72,71,71,71,71,71,71,71,72,71,
+   71,71,71,71,71,71,72,71,71,71,71,71,71,71,72,71,
+   71,71,71,71,71,71,72,71,71,71,71,71,71,71,72,71,
+   73,73,73,73,73,73,74,73,73,73,73,73,73,73,74,73,
+   73,73,73,73,73,73,74,73,73,73,73,73,73,73,74,73,
+   73,73,73,73,73,73,74,73,73,73,73,73,73,73,74,73,
+   73,73,73,73,73,73,74,73,73,73,73,73,73,73,74,73,
+   75,75,75,75,75,75,76,75,75,75,75,75,75,75,76,75,
+   75,75,75,75,75,75,76,75,75,75,75,75,75,75,76,75,
+   75,75,75,75,75,75,76,75,75,75,75,75,75,75,76,75,
+   75,75,75,75,75,75,76,75,75,75,75,75,75,75,76,75,
 ];
 
-/// Tabla usada para discernir el operando destino
-const PREFIX_SRC_TABLE: &[u8] = &[
+/// Bit sobre el que operan BIT, RES y SET
+const PREFIX_SRC_TABLE: [u8; 0x100] = [
    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -399,8 +674,9 @@ const PREFIX_SRC_TABLE: &[u8] = &[
    6, 6, 6, 6, 6, 6, 6, 6, 7, 7, 7, 7, 7, 7, 7, 7,
 ];
 
-/// Tabla usada para discernir el operando destino
-const PREFIX_DST_TABLE: &[u8] = &[
+/// Registro o dirección en registro sobre el que operan las instrucciones
+/// con prefijo
+const PREFIX_DST_TABLE: [u8; 0x100] = [
    3, 4, 5, 6, 7, 8, 10, 1, 3, 4, 5, 6, 7, 8, 10, 1,
    3, 4, 5, 6, 7, 8, 10, 1, 3, 4, 5, 6, 7, 8, 10, 1,
    3, 4, 5, 6, 7, 8, 10, 1, 3, 4, 5, 6, 7, 8, 10, 1,
@@ -419,11 +695,125 @@ const PREFIX_DST_TABLE: &[u8] = &[
    3, 4, 5, 6, 7, 8, 10, 1, 3, 4, 5, 6, 7, 8, 10, 1,
 ];
 
-/// Detiene la ejecución del programa (sleep) durante una cantidad de tiempo
-/// dependiente de qué tenga la cpu configurado como un tick
+/// Qué espera encontrar cada tipo de instrucción en `SRC_TABLE` y
+/// `DST_TABLE`
+#[derive(Clone, Copy)]
+enum Operand {
+    /// La tabla no se usa
+    Unused,
+
+    /// Un registro de 8 bits, de `Reg::A` a `Reg::SP`
+    Reg,
+
+    /// Un par de registros de 16 bits: BC, DE, HL o SP
+    WideReg,
+
+    /// Los pares de PUSH y POP, con AF en vez de SP
+    StackReg,
+
+    /// Una dirección en registro de `RegAddr`
+    Addr,
+
+    /// Los flags que tienen que estar activos para saltar
+    Cond,
+}
+
+impl Operand {
+    /// En las tablas un 0 marca un opcode inválido para `decode`, el resto de
+    /// valores tienen que encajar con el operando
+    const fn accepts(self, value: u8) -> bool {
+        value == 0 || match self {
+            Operand::Unused => true,
+            Operand::Reg => value <= Reg::SP as u8,
+            Operand::WideReg => matches!(value, 3 | 5 | 7 | 9),
+            Operand::StackReg => matches!(value, 1 | 3 | 5 | 7),
+            Operand::Addr => value >= RegAddr::HL as u8 && value <= RegAddr::DE as u8,
+            Operand::Cond => matches!(value, NZ | Z | NC | C),
+        }
+    }
+}
+
+/// Operandos de origen y destino de cada tipo de instrucción sin prefijo,
+/// como los lee `decode`. `None` si no puede estar en `INST_KIND_TABLE`
+const fn operands(kind: InstrKind) -> Option<(Operand, Operand)> {
+    use InstrKind::*;
+    use Operand::*;
+    Some(match kind {
+        LdRegReg | AddRegReg | AdcRegReg => (Reg, Reg),
+        AddWRegWReg => (WideReg, WideReg),
+        LdRegImm | AddRegImm | AdcRegImm => (Unused, Reg),
+        LdWRegImm | AddWRegImm => (Unused, WideReg),
+        LdRegMem => (Reg, Addr),
+        LdMemReg | AddMemReg | AdcMemReg => (Addr, Reg),
+        SubReg | SbcReg | AndReg | XorReg | OrReg | CpReg | IncReg | DecReg | JPReg
+            | LdMemImmReg => (Reg, Unused),
+        IncWReg | DecWReg => (WideReg, Unused),
+        Push | Pop => (StackReg, Unused),
+        SubMem | SbcMem | AndMem | XorMem | OrMem | CpMem | IncMem | DecMem => (Addr, Unused),
+        JPCond | JRelCond | RetCond => (Cond, Unused),
+        RlcReg | RlcMem | RrcReg | RrcMem | RlReg | RlMem | RrReg | RrMem | SlaReg | SlaMem
+            | SraReg | SraMem | SwapReg | SwapMem | SrlReg | SrlMem | BitReg | BitMem
+            | ResReg | ResMem | SetReg | SetMem => return None,
+        _ => (Unused, Unused),
+    })
+}
+
+/// Las instrucciones con prefijo que operan sobre una dirección en registro,
+/// el resto lo hacen sobre un registro
+const fn is_prefixed_mem(kind: InstrKind) -> bool {
+    use InstrKind::*;
+    matches!(kind, RlcMem | RrcMem | RlMem | RrMem | SlaMem | SraMem | SwapMem | SrlMem
+        | BitMem | ResMem | SetMem)
+}
+
+/// Comprobar en tiempo de compilación que las tablas son coherentes: los
+/// tipos de instrucción fuera de `NI` e `IL` existen (si no
+/// `InstrKind::from_u8` sería UB), los operandos son del tipo que espera
+/// cada instrucción y todas las instrucciones con prefijo están definidas.
+/// Un error en una tabla falla al compilar con el mensaje del `assert!`
+const fn check_tables() {
+    let mut opcode = 0;
+    while opcode < 0x100 {
+        let kind = INST_KIND_TABLE[opcode];
+        if kind != NI && kind != IL {
+            assert!(kind <= InstrKind::Reti as u8,
+                "INST_KIND_TABLE tiene un InstrKind inexistente");
+            let Some((src, dst)) = operands(InstrKind::from_u8(kind)) else {
+                panic!("INST_KIND_TABLE tiene una instrucción con prefijo");
+            };
+            assert!(src.accepts(SRC_TABLE[opcode]), "SRC_TABLE no encaja con INST_KIND_TABLE");
+            assert!(dst.accepts(DST_TABLE[opcode]), "DST_TABLE no encaja con INST_KIND_TABLE");
+        }
+
+        let kind = PREFIX_TABLE[opcode];
+        assert!(kind >= InstrKind::RlcReg as u8 && kind <= InstrKind::SetMem as u8,
+            "PREFIX_TABLE tiene una instrucción sin prefijo");
+        let target = if is_prefixed_mem(InstrKind::from_u8(kind)) {
+            Operand::Addr
+        } else {
+            Operand::Reg
+        };
+        assert!(PREFIX_DST_TABLE[opcode] != 0 && target.accepts(PREFIX_DST_TABLE[opcode]),
+            "PREFIX_DST_TABLE no encaja con PREFIX_TABLE");
+        assert!(PREFIX_SRC_TABLE[opcode] < 8, "PREFIX_SRC_TABLE tiene un bit fuera de rango");
+        opcode += 1;
+    }
+}
+
+const _: () = check_tables();
+
+/// El valor de las tablas es un registro de 8 bits y no una dirección en
+/// registro, las tablas tienen alguna de estas en instrucciones de registro
+#[inline]
+const fn is_reg(value: u8) -> bool {
+    value != 0 && value <= Reg::SP as u8
+}
+
+/// Avanza el contador de ciclos de la CPU `$n` T-cycles, la espera para ir a
+/// velocidad real no se hace aquí sino una vez por frame en `FrameLimiter`
 macro_rules! tick {
     ($self:expr, $n:expr) => {
-        // TODO
+        $self.cycles += $n;
     }
 }
 
@@ -431,20 +821,119 @@ impl Cpu {
     pub fn new() -> Self {
         Self {
             registers: [0; 10],
-            pc: 0
+            pc: 0,
+            stopped: false,
+            ime: false,
+            ei_delay: false,
+            cycles: 0,
         }
     }
 
-    // TODO: Las instrucciones se deberán leer de la MMU y no pasarlas como un
-    // slice como si se supiera exactamente cuales valores en memoria son o no
-    // realmente instrucciones
-    pub fn decode(&mut self, instructions: &[u8]) -> Option<Instr> {
+    #[inline]
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    /// Cambiar IME desde fuera, como si se hubiera ejecutado DI o un EI
+    /// que ya hizo efecto
+    #[inline]
+    pub fn set_ime(&mut self, enabled: bool) {
+        self.ime = enabled;
+        self.ei_delay = false;
+    }
+
+    /// IME y el EI pendiente, para los save states
+    #[cfg(feature = "serde")]
+    pub(crate) fn ime_state(&self) -> (bool, bool) {
+        (self.ime, self.ei_delay)
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn set_ime_state(&mut self, (ime, ei_delay): (bool, bool)) {
+        self.ime = ime;
+        self.ei_delay = ei_delay;
+    }
+
+    /// Atender la interrupción pendiente de más prioridad si IME está
+    /// activo, devuelve el vector al que se saltó. Como en el hardware tarda
+    /// 5 M-cycles: 2 de espera, 2 apilando PC y 1 saltando, y la interrupción
+    /// se elige después de apilar el byte alto. Si esa escritura cae en IE
+    /// (con SP a 0x0000) y deja de haber alguna pendiente, el dispatch se
+    /// cancela y salta a 0x0000 sin limpiar IF, es lo que prueba `ie_push`
+    /// de mooneye
+    pub fn service_interrupt<B: Bus + ?Sized>(&mut self, bus: &mut B) -> Option<u16> {
+        if !self.ime || bus.read(IE) & bus.read(IF) & 0x1F == 0 {
+            return None;
+        }
+        self.ime = false;
+        tick!(self, 8);
+
+        let [low, high] = self.pc.to_le_bytes();
+        let sp = self.read_widereg(Reg::SP).wrapping_sub(1);
+        bus.write(sp, high);
+        tick!(self, 4);
+        let pending = bus.read(IE) & bus.read(IF) & 0x1F;
+        let sp = sp.wrapping_sub(1);
+        bus.write(sp, low);
+        tick!(self, 4);
+        self.write_widereg(Reg::SP, sp);
+
+        let vector = match pending {
+            0 => 0x0000,
+            _ => {
+                let bit = pending.trailing_zeros();
+                bus.write(IF, bus.read(IF) & !(1 << bit));
+                0x0040 + bit as u16 * 8
+            },
+        };
+        tick!(self, 4);
+        self.pc = vector;
+        Some(vector)
+    }
+
+    /// La CPU está detenida por STOP, el dueño de la MMU debe llamar a
+    /// `wake` cuando se solicite la interrupción de joypad
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Salir del estado STOP
+    #[inline]
+    pub fn wake(&mut self) {
+        self.stopped = false;
+    }
+
+    /// Número total de T-cycles ejecutados por la CPU
+    #[inline]
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Cambiar el program counter
+    #[inline]
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    #[inline]
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Leer la instrucción que hay en `pc` a través del bus y avanzar el `pc`
+    /// hasta la siguiente, `Error::InvalidOpcode` si el opcode no existe y
+    /// `Error::Unimplemented` si todavía no se decodifica
+    pub fn decode<B: Bus + ?Sized>(&mut self, bus: &B) -> Result<Instr, Error> {
         // Extraer el opcode y extraer por separado los primeros y últimos 4 bits
         // que representan la fila y la columna en la matriz de instrucciones
-        let opcode = instructions[self.pc as usize];
+        let addr = self.pc;
+        let opcode = bus.read(addr);
+        let invalid = || Error::InvalidOpcode { addr, opcode };
+        let unimplemented = || Error::Unimplemented { addr, opcode };
 
         // Avanzar el PC
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
 
         // Macros útiles para no repetir código en el decode
         macro_rules! decode_reg {
@@ -452,21 +941,23 @@ impl Cpu {
                 // Extraer registro
                 let $loc = SRC_TABLE[opcode as usize];
 
-                assert!($loc != 0);
+                if $loc == 0 {
+                    return Err(invalid());
+                }
 
                 let $loc = Reg::from_u8($loc);
 
-                Some(Instr::$variant { $loc })
+                Ok(Instr::$variant { $loc })
             }};
         }
 
         macro_rules! decode_imm {
             ($loc:ident, $variant:ident) => {{
                 // Extraer immediate
-                let imm = instructions[self.pc as usize];
-                self.pc += 1;
+                let imm = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
 
-                Some(Instr::$variant { $loc: imm })
+                Ok(Instr::$variant { $loc: imm })
             }};
         }
 
@@ -475,11 +966,13 @@ impl Cpu {
                 // Extraer registro
                 let $loc = SRC_TABLE[opcode as usize];
 
-                assert!($loc != 0);
+                if $loc == 0 {
+                    return Err(invalid());
+                }
 
                 let $loc = RegAddr::from_u8($loc);
 
-                Some(Instr::$variant { $loc })            
+                Ok(Instr::$variant { $loc })            
             }};
         }
 
@@ -489,30 +982,36 @@ impl Cpu {
                 let src = SRC_TABLE[opcode as usize];
                 let dst = DST_TABLE[opcode as usize];
 
-                assert!(src != 0);
-                assert!(dst != 0);
+                if src == 0 {
+                    return Err(invalid());
+                }
+                if dst == 0 {
+                    return Err(invalid());
+                }
 
                 let src = Reg::from_u8(src);
                 let dst = Reg::from_u8(dst);
 
-                Some(Instr::$variant { src, dst })
+                Ok(Instr::$variant { src, dst })
             }}
         }
 
         macro_rules! decode_reg_imm {
             ($variant:ident) => {{
                 // Extraer immediate
-                let imm = instructions[self.pc as usize];
-                self.pc += 1;
+                let imm = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 
                 // Extraer registro destino
                 let dst = DST_TABLE[opcode as usize];
 
-                assert!(dst != 0);
+                if dst == 0 {
+                    return Err(invalid());
+                }
 
                 let dst = Reg::from_u8(dst);
 
-                Some(Instr::$variant { src: imm, dst })        
+                Ok(Instr::$variant { src: imm, dst })        
             }}
         }
 
@@ -522,13 +1021,17 @@ impl Cpu {
                 let src = SRC_TABLE[opcode as usize];
                 let dst = DST_TABLE[opcode as usize];
 
-                assert!(src != 0);
-                assert!(dst != 0);
+                if src == 0 {
+                    return Err(invalid());
+                }
+                if dst == 0 {
+                    return Err(invalid());
+                }
 
                 let src = Reg::from_u8(src);
                 let dst = RegAddr::from_u8(dst);
 
-                Some(Instr::$variant { src, dst })
+                Ok(Instr::$variant { src, dst })
             }}
         }
 
@@ -539,81 +1042,108 @@ impl Cpu {
                 let src = SRC_TABLE[opcode as usize];
                 let dst = DST_TABLE[opcode as usize];
 
-                assert!(src != 0);
-                assert!(dst != 0);
+                if src == 0 {
+                    return Err(invalid());
+                }
+                if dst == 0 {
+                    return Err(invalid());
+                }
 
                 let src = RegAddr::from_u8(src);
                 let dst = Reg::from_u8(dst);
 
-                Some(Instr::$variant { src, dst })
+                Ok(Instr::$variant { src, dst })
             }}
         }
 
+        // Las de prefijo reciben el segundo byte, que es el que indexa sus
+        // tablas. Todas sus entradas son válidas, lo comprueba `check_tables`
         macro_rules! prefix_decode_reg {
-            ($loc:ident, $variant:ident) => {{
-                // Extraer registro
-                let $loc = PREFIX_DST_TABLE[opcode as usize];
-
-                assert!($loc != 0);
+            ($cb:expr, $variant:ident) => {{
+                let reg = Reg::from_u8(PREFIX_DST_TABLE[$cb]);
 
-                let $loc = Reg::from_u8($loc);
-
-                Some(Instr::$variant { $loc })
+                Ok(Instr::$variant { reg })
             }};
         }
 
         macro_rules! prefix_decode_mem {
-            ($loc:ident, $variant:ident) => {{
-                // Extraer registro
-                let $loc = PREFIX_DST_TABLE[opcode as usize];
-
-                assert!($loc != 0);
-
-                let $loc = RegAddr::from_u8($loc);
+            ($cb:expr, $variant:ident) => {{
+                let reg = RegAddr::from_u8(PREFIX_DST_TABLE[$cb]);
 
-                Some(Instr::$variant { $loc })            
+                Ok(Instr::$variant { reg })
             }};
         }
 
-        macro_rules! prefix_decode_reg_imm {
-            ($reg_loc:ident, $imm_loc:ident, $variant:ident) => {{
-                // Extraer immediate
-                let imm = instructions[self.pc as usize];
-                self.pc += 1;
-                
-                // Extraer registro destino
-                let dst = PREFIX_DST_TABLE[opcode as usize];
-
-                assert!(dst != 0);
-
-                let dst = Reg::from_u8(dst);
+        macro_rules! prefix_decode_reg_bit {
+            ($cb:expr, $variant:ident) => {{
+                let reg = Reg::from_u8(PREFIX_DST_TABLE[$cb]);
+                let bit = PREFIX_SRC_TABLE[$cb];
 
-                Some(Instr::$variant { $imm_loc: imm, $reg_loc: dst })
-            }}
+                Ok(Instr::$variant { reg, bit })
+            }};
         }
 
-        macro_rules! prefix_decode_mem_imm {
-            ($mem_loc:ident, $imm_loc:ident, $variant:ident) => {{
-                // Extraer immediate
-                let imm = instructions[self.pc as usize];
-                self.pc += 1;
-                
-                // Extraer registro como mem destino
-                let dst = PREFIX_DST_TABLE[opcode as usize];
+        macro_rules! prefix_decode_mem_bit {
+            ($cb:expr, $variant:ident) => {{
+                let reg = RegAddr::from_u8(PREFIX_DST_TABLE[$cb]);
+                let bit = PREFIX_SRC_TABLE[$cb];
 
-                assert!(dst != 0);
+                Ok(Instr::$variant { reg, bit })
+            }};
+        }
 
-                let dst = RegAddr::from_u8(dst);
+        // Prefixed instructions
+        if opcode == 0xCB {
+            let cb = bus.read(self.pc) as usize;
+            self.pc = self.pc.wrapping_add(1);
+
+            return match InstrKind::from_u8(PREFIX_TABLE[cb]) {
+                InstrKind::RlcReg => prefix_decode_reg!(cb, RlcReg),
+                InstrKind::RlcMem => prefix_decode_mem!(cb, RlcMem),
+                InstrKind::RrcReg => prefix_decode_reg!(cb, RrcReg),
+                InstrKind::RrcMem => prefix_decode_mem!(cb, RrcMem),
+                InstrKind::RlReg => prefix_decode_reg!(cb, RlReg),
+                InstrKind::RlMem => prefix_decode_mem!(cb, RlMem),
+                InstrKind::RrReg => prefix_decode_reg!(cb, RrReg),
+                InstrKind::RrMem => prefix_decode_mem!(cb, RrMem),
+                InstrKind::SlaReg => prefix_decode_reg!(cb, SlaReg),
+                InstrKind::SlaMem => prefix_decode_mem!(cb, SlaMem),
+                InstrKind::SraReg => prefix_decode_reg!(cb, SraReg),
+                InstrKind::SraMem => prefix_decode_mem!(cb, SraMem),
+                InstrKind::SwapReg => prefix_decode_reg!(cb, SwapReg),
+                InstrKind::SwapMem => prefix_decode_mem!(cb, SwapMem),
+                InstrKind::SrlReg => prefix_decode_reg!(cb, SrlReg),
+                InstrKind::SrlMem => prefix_decode_mem!(cb, SrlMem),
+                InstrKind::BitReg => prefix_decode_reg_bit!(cb, BitReg),
+                InstrKind::BitMem => prefix_decode_mem_bit!(cb, BitMem),
+                InstrKind::ResReg => prefix_decode_reg_bit!(cb, ResReg),
+                InstrKind::ResMem => prefix_decode_mem_bit!(cb, ResMem),
+                InstrKind::SetReg => prefix_decode_reg_bit!(cb, SetReg),
+                InstrKind::SetMem => prefix_decode_mem_bit!(cb, SetMem),
+                _ => Err(invalid()),
+            };
+        }
 
-                Some(Instr::$variant { $imm_loc: imm, $mem_loc: dst })
-            }}
+        // Common (unprefixed) instructions
+        let kind = INST_KIND_TABLE[opcode as usize];
+        if kind == IL {
+            return Err(invalid());
+        }
+        if kind == NI {
+            return Err(unimplemented());
         }
 
-        let mut res;
+        match InstrKind::from_u8(kind) {
+            InstrKind::Nop => Ok(Instr::Nop),
+            InstrKind::Halt => Ok(Instr::Halt),
+            InstrKind::Stop => {
+                // STOP ocupa 2 bytes aunque el segundo se ignora
+                self.pc = self.pc.wrapping_add(1);
 
-        // Common (unprefixed) instructions
-        res = match InstrKind::from_u8(INST_KIND_TABLE[opcode as usize]) {
-            InstrKind::Halt => Some(Instr::Halt),
+                Ok(Instr::Stop)
+            },
+            InstrKind::Di => Ok(Instr::Di),
+            InstrKind::Ei => Ok(Instr::Ei),
             InstrKind::LdRegReg => decode_reg_reg!(LdRegReg),
             InstrKind::LdRegImm => decode_reg_imm!(LdRegImm),
             InstrKind::LdRegMem => decode_reg_mem!(LdRegMem),
@@ -648,111 +1178,92 @@ impl Cpu {
             InstrKind::CpMem => decode_mem!(src, CpMem),
             InstrKind::LdWRegImm => {
                 // Extraer immediate
-                let immh = instructions[self.pc as usize];
-                self.pc += 1;
-                let imml = instructions[self.pc as usize];
-                self.pc += 1;
+                let immh = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let imml = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let imm = u16::from_le_bytes([immh, imml]);
                 
                 // Extraer registro destino
                 let dst = DST_TABLE[opcode as usize];
 
-                assert!(dst != 0);
+                if dst == 0 {
+                    return Err(invalid());
+                }
 
                 let dst = Reg::from_u8(dst);
 
-                Some(Instr::LdWRegImm { src: imm, dst })
+                Ok(Instr::LdWRegImm { src: imm, dst })
             },
             InstrKind::LdMemImmReg => {
                 // Extraer immediate
-                let immh = instructions[self.pc as usize];
-                self.pc += 1;
-                let imml = instructions[self.pc as usize];
-                self.pc += 1;
+                let immh = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let imml = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let imm = u16::from_le_bytes([immh, imml]);
 
                 // Extraer registro origen
                 let src = SRC_TABLE[opcode as usize];
 
-                assert!(src != 0);
+                if src == 0 {
+                    return Err(invalid());
+                }
 
                 let src = Reg::from_u8(src);
 
-                Some(Instr::LdMemImmReg { src, dst: imm })
+                Ok(Instr::LdMemImmReg { src, dst: imm })
             }
             InstrKind::Push => decode_reg!(src, Push),
             InstrKind::Pop  => decode_reg!(dst, Pop),
             InstrKind::JPImm => {
                 // Extraer immediate
-                let immh = instructions[self.pc as usize];
-                self.pc += 1;
-                let imml = instructions[self.pc as usize];
-                self.pc += 1;
+                let immh = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let imml = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let imm = u16::from_le_bytes([immh, imml]);
 
-                Some(Instr::JPImm { addr: imm })
+                Ok(Instr::JPImm { addr: imm })
             },
             InstrKind::JPCond => {
                 // Extraer immediate
-                let immh = instructions[self.pc as usize];
-                self.pc += 1;
-                let imml = instructions[self.pc as usize];
-                self.pc += 1;
+                let immh = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                let imml = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
                 let imm = u16::from_le_bytes([immh, imml]);
                 
                 // Extraer condition
                 let cond = SRC_TABLE[opcode as usize];
 
-                assert!(cond != 0);
+                if cond == 0 {
+                    return Err(invalid());
+                }
 
-                Some(Instr::JPCond { cond, addr: imm })
+                Ok(Instr::JPCond { cond, addr: imm })
             },
             InstrKind::JPReg => decode_reg!(src, JPReg),
             InstrKind::JRelImm => decode_imm!(offset, JRelImm),
             InstrKind::JRelCond => {
                 // Extraer immediate
-                let imm = instructions[self.pc as usize];
-                self.pc += 1;
+                let imm = bus.read(self.pc);
+                self.pc = self.pc.wrapping_add(1);
 
                 // Extraer condition
                 let cond = SRC_TABLE[opcode as usize];
 
-                assert!(cond != 0);
+                if cond == 0 {
+                    return Err(invalid());
+                }
 
-                Some(Instr::JRelCond { cond, offset: imm })
+                Ok(Instr::JRelCond { cond, offset: imm })
             },
+            InstrKind::Reti => Ok(Instr::Reti),
 
-            _ => { None }
-        };
-
-        // Prefixed instructions
-        res = if res.is_none() && opcode == 0xCB {
-            match InstrKind::from_u8(PREFIX_TABLE[opcode as usize]) {
-                InstrKind::RlcReg => prefix_decode_reg!(reg, RlcReg),
-                InstrKind::RlcMem => prefix_decode_mem!(reg, RlcMem),
-                InstrKind::RrcReg => prefix_decode_reg!(reg, RrcReg),
-                InstrKind::RrcMem => prefix_decode_mem!(reg, RrcMem),
-                InstrKind::RlReg => prefix_decode_reg!(reg, RlReg),
-                InstrKind::RlMem => prefix_decode_mem!(reg, RlMem),
-                InstrKind::RrReg => prefix_decode_reg!(reg, RrReg),
-                InstrKind::RrMem => prefix_decode_mem!(reg, RrMem),
-                InstrKind::SlaReg => prefix_decode_reg!(reg, SlaReg),
-                InstrKind::SlaMem => prefix_decode_mem!(reg, SlaMem),
-                InstrKind::SraReg => prefix_decode_reg!(reg, SraReg),
-                InstrKind::SraMem => prefix_decode_mem!(reg, SraMem),
-                InstrKind::BitReg => prefix_decode_reg_imm!(reg, bit, BitReg),
-                InstrKind::BitMem => prefix_decode_mem_imm!(reg, bit, BitMem),
-                InstrKind::ResReg => prefix_decode_reg_imm!(reg, bit, ResReg),
-                InstrKind::ResMem => prefix_decode_mem_imm!(reg, bit, ResMem),
-                InstrKind::SetReg => prefix_decode_reg_imm!(reg, bit, SetReg),
-                InstrKind::SetMem => prefix_decode_mem_imm!(reg, bit, SetMem),
-                _ => None,
-            }
-        } else {
-           unreachable!()
-        };
-        
-        res
+            // Están en la tabla pero todavía no tienen su `Instr`
+            _ => Err(unimplemented()),
+        }
     }
 
     /// Escribir en un registro de 8-bits
@@ -786,8 +1297,9 @@ impl Cpu {
             panic!("Cannot wide read into this register {:?}", reg);
         }
         
+        // El primer registro del par es el byte alto: B en BC, H en HL
         let reg_range = (reg as usize - 1)..=reg as usize;
-        u16::from_le_bytes(self.registers[reg_range].try_into().unwrap())
+        u16::from_be_bytes(self.registers[reg_range].try_into().unwrap())
     }
 
     /// Escribir en dos registros que forman un valor de 16-bits, solo se puede
@@ -801,9 +1313,9 @@ impl Cpu {
             panic!("Cannot wide write into this register {:?}", reg);
         }
 
-        let [l, h] = u16::to_le_bytes(value);
-        self.registers[reg as usize - 1] = l;
-        self.registers[reg as usize] = h;
+        let [h, l] = u16::to_be_bytes(value);
+        self.registers[reg as usize - 1] = h;
+        self.registers[reg as usize] = l;
     }
 
     /// Sumar dos valores de 8-bits de la alu    
@@ -813,7 +1325,7 @@ impl Cpu {
     fn alu_add(&mut self, a: u8, b: u8) -> u8 {
         // Realizar la operación y decidir que flags se activan
         let (res, carry) = a.overflowing_add(b);
-        let half_carry = res >> 4 != 0;
+        let half_carry = (a & 0xF) + (b & 0xF) > 0xF;
         let zero = res == 0;
 
         // Crear el u8 de flags de la operación
@@ -832,25 +1344,22 @@ impl Cpu {
         res
     }
 
-    /// Sumar dos valores de 16-bits en la alu
+    /// Sumar dos valores de 16-bits en la alu, como `ADD HL, rr` el half
+    /// carry sale del bit 11 y no se toca el flag Z
     #[inline]
     fn alu_wideadd(&mut self, a: u16, b: u16) -> u16 {
         // Realizar la operación y decidir que flags se activan
         let (res, carry) = a.overflowing_add(b);
-        let half_carry = res >> 12 != 0;
-        let zero = res == 0;
+        let half_carry = (a & 0xFFF) + (b & 0xFFF) > 0xFFF;
 
         // Crear el u8 de flags de la operación
-        let mut flags = 0;
+        let mut flags = self.read_reg(Reg::F) & FLAG_Z;
         if carry {
             flags |= FLAG_C;
         }
         if half_carry {
             flags |= FLAG_H;
         }
-        if zero {
-            flags |= FLAG_Z;
-        }
         self.write_reg(Reg::F, flags);
 
         res
@@ -861,21 +1370,18 @@ impl Cpu {
     // NOTE: Esto produce un ADC en x64? espero, sino emos sido engañados
     #[inline]
     fn alu_adc(&mut self, a: u8, b: u8) -> u8 {
+        // El carry de la operación anterior entra también en el half carry
+        let carry_in = (self.read_reg(Reg::F) & FLAG_C != 0) as u8;
+
         // Realizar la operación y decidir que flags se activan
-        let (mut res, mut carry) = a.overflowing_add(b);
-        let half_carry = res >> 4 != 0;
+        let (res, carry) = a.overflowing_add(b);
+        let (res, carry_out) = res.overflowing_add(carry_in);
+        let carry = carry | carry_out;
+        let half_carry = (a & 0xF) + (b & 0xF) + carry_in > 0xF;
         let zero = res == 0;
 
-        // Sumar el carry si la flag está activada
-        let mut flags = self.read_reg(Reg::F); 
-        if flags & FLAG_C != 0 {
-            let (new_res, new_carry) = res.overflowing_add(1);
-            res = new_res;
-            carry |= new_carry;
-        }
-
         // Crear el u8 de flags de la operación
-        flags = 0;
+        let mut flags = 0;
         if carry {
             flags |= FLAG_C;
         }
@@ -896,17 +1402,17 @@ impl Cpu {
         // Realizar la operación y decidir que flags se activan
         let (res, carry) = a.overflowing_sub(b);
 
-        // FIXME: No se si esto realmente calcula el borrow a partir de 4-bit
-        // tal como dice la spec, simplemente me pareció la solución naive
-        let half_carry = b >> 4 != 0;
+        // El half carry es el borrow del bit 4, C y H se activan cuando hay
+        // borrow
+        let half_carry = a & 0xF < b & 0xF;
         let zero = res == 0;
 
         // Crear el u8 de flags de la operación
         let mut flags = FLAG_N;
-        if !carry {
+        if carry {
             flags |= FLAG_C;
         }
-        if !half_carry {
+        if half_carry {
             flags |= FLAG_H;
         }
         if zero {
@@ -921,29 +1427,22 @@ impl Cpu {
     /// alguna operción anterior
     #[inline]
     fn alu_sbc(&mut self, a: u8, b: u8) -> u8 {
-        // Realizar la operación y decidir que flags se activan
-        let (mut res, mut carry) = a.overflowing_sub(b);
+        // El carry de la operación anterior se resta también
+        let carry_in = (self.read_reg(Reg::F) & FLAG_C != 0) as u8;
 
-        // FIXME: No se si esto realmente calcula el borrow a partir de 4-bit
-        // tal como dice la spec, simplemente me pareció la solución naive
-        let half_carry = b >> 4 != 0;
+        // Realizar la operación y decidir que flags se activan
+        let (res, carry) = a.overflowing_sub(b);
+        let (res, carry_out) = res.overflowing_sub(carry_in);
+        let carry = carry | carry_out;
+        let half_carry = a & 0xF < (b & 0xF) + carry_in;
         let zero = res == 0;
 
-        // Sumar el carry si la flag está activada
-        // FIXME: Según la spec es sumar el carry a la solución
-        let mut flags = self.read_reg(Reg::F); 
-        if flags & FLAG_C != 0 {
-            let (new_res, new_carry) = res.overflowing_add(1);
-            res = new_res;
-            carry |= new_carry;
-        }
-
         // Crear el u8 de flags de la operación
-        flags = FLAG_N;
-        if !carry {
+        let mut flags = FLAG_N;
+        if carry {
             flags |= FLAG_C;
         }
-        if !half_carry {
+        if half_carry {
             flags |= FLAG_H;
         }
         if zero {
@@ -1012,7 +1511,7 @@ impl Cpu {
         let res = a.rotate_right(1);
 
         // Extraer y aplicar los flags
-        let carry = a & 0b11111110 == 1;
+        let carry = a & 1 == 1;
         let zero = res == 0;
         let mut flags = 0;
         if carry {
@@ -1031,8 +1530,8 @@ impl Cpu {
         // Extraer la carry flag
         let carry = (self.read_reg(Reg::F) & FLAG_C != 0) as u8;
 
-        // Hacer la operación rotate por 1 a izquierda
-        let res = a.rotate_left(carry as u32);
+        // Hacer la operación rotate por 1 a izquierda a través del carry
+        let res = (a << 1) | carry;
 
         // Extraer y aplicar los flags
         let carry = a >> 7 == 1;
@@ -1052,13 +1551,13 @@ impl Cpu {
     #[inline]
     fn alu_rr(&mut self, a: u8) -> u8 {
         // Extraer la carry flag
-        let carry = (self.read_reg(Reg::F) & FLAG_C != 0) as u8 ;
+        let carry = (self.read_reg(Reg::F) & FLAG_C != 0) as u8;
 
-        // Hacer la operación rotate por 1 a izquierda
-        let res = a.rotate_right(carry as u32);
+        // Hacer la operación rotate por 1 a derecha a través del carry
+        let res = (a >> 1) | (carry << 7);
 
         // Extraer y aplicar los flags
-        let carry = a & 0b11111110 == 1;
+        let carry = a & 1 == 1;
         let zero = res == 0;
         let mut flags = 0;
         if carry {
@@ -1075,7 +1574,8 @@ impl Cpu {
     #[inline]
     fn alu_sla(&mut self, a: u8) -> u8 {
         // Hacer la operación shift por 1 a izquierda
-        let (res, carry) = a.overflowing_shl(1);
+        let res = a << 1;
+        let carry = a >> 7 == 1;
 
         // Extraer y aplicar los flags
         let zero = res == 0;
@@ -1094,8 +1594,8 @@ impl Cpu {
     #[inline]
     fn alu_sra(&mut self, a: u8) -> u8 {
         // Hacer la operación shift por 1 a derecha
-        let (mut res, carry) = a.overflowing_shr(1);
-        res |= a & 0b10000000;
+        let res = (a >> 1) | (a & 0b10000000);
+        let carry = a & 1 == 1;
 
         // Extraer y aplicar los flags
         let zero = res == 0;
@@ -1115,7 +1615,7 @@ impl Cpu {
     fn alu_swap(&mut self, a: u8) -> u8 {
         // Intercambiar los nimbles
         let hnimble = a >> 4;
-        let lnimble = a & 0b11110000;
+        let lnimble = a & 0b00001111;
         let res = (lnimble << 4) | hnimble;
      
         // Extraer y aplicar los flags
@@ -1132,7 +1632,8 @@ impl Cpu {
     #[inline]
     fn alu_srl(&mut self, a: u8) -> u8 {
         // Hacer la operación shift por 1 a derecha
-        let (res, carry) = a.overflowing_shr(1);
+        let res = a >> 1;
+        let carry = a & 1 == 1;
 
         // Extraer y aplicar los flags
         let zero = res == 0;
@@ -1154,7 +1655,7 @@ impl Cpu {
         let is_zero = a & (1 << bit) == 0;
 
         // Aplicar los flags necesarios
-        let old_carry = self.read_reg(Reg::F);
+        let old_carry = self.read_reg(Reg::F) & FLAG_C;
         let mut flags = old_carry | FLAG_H;
         if is_zero {
             flags |= FLAG_Z;
@@ -1172,15 +1673,238 @@ impl Cpu {
         a | (1 << bit)
     }
 
-    // TODO: A esta función habrá que pasarle la MMU
-    pub fn execute(&mut self, instructions: &[u8]) -> Option<()> {
-        // Hacer decode de la instrucción a ejecutar
-        let instr = self.decode(instructions)?;
+    /// Hacer decode y ejecutar la siguiente instrucción sobre el bus
+    pub fn execute<B: Bus + ?Sized>(&mut self, bus: &mut B) -> Result<(), Error> {
+        // Con la CPU detenida no corre el reloj
+        if self.stopped {
+            return Ok(());
+        }
+
+        // Las instrucciones más comunes se ejecutan directamente desde el
+        // opcode, el resto pasan por `decode`
+        if !self.execute_fast(bus) {
+            let instr = self.decode(bus)?;
+            self.execute_instr(instr, bus)?;
+        }
+        Ok(())
+    }
+
+    /// Camino rápido de `execute`: ejecutar la instrucción en `pc` leyendo
+    /// los operandos de las tablas sin construir una `Instr`, que solo hace
+    /// falta para depurar. Devuelve `false` sin tocar nada si la instrucción
+    /// no está en el camino rápido (usa memoria, no está implementada o el
+    /// opcode no es válido) y hay que ir por `decode` y `execute_instr`, que
+    /// definen el comportamiento que se copia aquí
+    #[inline]
+    fn execute_fast<B: Bus + ?Sized>(&mut self, bus: &B) -> bool {
+        // Tras un EI la siguiente instrucción tiene que activar IME, eso solo
+        // lo hace `execute_instr`
+        if self.ei_delay {
+            return false;
+        }
+
+        let pc = self.pc;
+        let opcode = bus.read(pc) as usize;
+        let src = SRC_TABLE[opcode];
+        let dst = DST_TABLE[opcode];
+        // Los inmediatos solo se leen en las instrucciones que los llevan
+        let imm = || bus.read(pc.wrapping_add(1));
+        let wide_imm = || u16::from_le_bytes([imm(), bus.read(pc.wrapping_add(2))]);
+
+        let kind = INST_KIND_TABLE[opcode];
+        if kind == NI || kind == IL {
+            return false;
+        }
+
+        let kind = InstrKind::from_u8(kind);
+        match kind {
+            InstrKind::Nop => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 4);
+            },
+            InstrKind::Stop => {
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 4);
+                self.stopped = true;
+            },
+            InstrKind::LdRegReg if is_reg(src) && is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 4);
+                self.write_reg(Reg::from_u8(dst), self.read_reg(Reg::from_u8(src)));
+            },
+            InstrKind::LdRegImm if is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 8);
+                self.write_reg(Reg::from_u8(dst), imm());
+            },
+            InstrKind::AddRegReg if is_reg(src) && is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 4);
+                let dst = Reg::from_u8(dst);
+                let res = self.alu_add(self.read_reg(Reg::from_u8(src)), self.read_reg(dst));
+                self.write_reg(dst, res);
+            },
+            InstrKind::AddRegImm if is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 8);
+                let dst = Reg::from_u8(dst);
+                let res = self.alu_add(imm(), self.read_reg(dst));
+                self.write_reg(dst, res);
+            },
+            InstrKind::AddWRegWReg if is_reg(src) && is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 8);
+                let dst = Reg::from_u8(dst);
+                let res = self.alu_wideadd(self.read_widereg(Reg::from_u8(src)),
+                    self.read_widereg(dst));
+                self.write_widereg(dst, res);
+            },
+            InstrKind::AdcRegReg if is_reg(src) && is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 4);
+                let dst = Reg::from_u8(dst);
+                let res = self.alu_adc(self.read_reg(Reg::from_u8(src)), self.read_reg(dst));
+                self.write_reg(dst, res);
+            },
+            InstrKind::AdcRegImm if is_reg(dst) => {
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 8);
+                let dst = Reg::from_u8(dst);
+                let res = self.alu_adc(imm(), self.read_reg(dst));
+                self.write_reg(dst, res);
+            },
+            InstrKind::SubReg | InstrKind::SbcReg | InstrKind::AndReg | InstrKind::OrReg
+                | InstrKind::CpReg if is_reg(src) =>
+            {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 4);
+                self.alu_a(kind, self.read_reg(Reg::from_u8(src)));
+            },
+            InstrKind::SubImm | InstrKind::SbcImm | InstrKind::AndImm | InstrKind::OrImm
+                | InstrKind::CpImm =>
+            {
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 8);
+                self.alu_a(kind, imm());
+            },
+            // INC y DEC llevan el registro en la tabla de origen, como en
+            // `decode`
+            InstrKind::IncReg | InstrKind::DecReg if is_reg(src) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 4);
+                let reg = Reg::from_u8(src);
+                let carry = self.read_reg(Reg::F) & FLAG_C;
+                let res = if kind == InstrKind::IncReg {
+                    self.alu_add(self.read_reg(reg), 1)
+                } else {
+                    self.alu_sub(self.read_reg(reg), 1)
+                };
+                self.write_reg(reg, res);
+                let flags = self.read_reg(Reg::F) & !FLAG_C | carry;
+                self.write_reg(Reg::F, flags);
+            },
+            InstrKind::IncWReg if is_reg(src) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 8);
+                let reg = Reg::from_u8(src);
+                self.write_widereg(reg, self.read_widereg(reg).wrapping_add(1));
+            },
+            InstrKind::DecWReg if is_reg(src) => {
+                self.pc = self.pc.wrapping_add(1);
+                tick!(self, 8);
+                let reg = Reg::from_u8(src);
+                self.write_widereg(reg, self.read_widereg(reg).wrapping_sub(1));
+            },
+            InstrKind::LdWRegImm if is_reg(dst) => {
+                let value = wide_imm();
+                self.pc = self.pc.wrapping_add(3);
+                tick!(self, 12);
+                self.write_widereg(Reg::from_u8(dst), value);
+            },
+            InstrKind::JPImm => {
+                tick!(self, 16);
+                self.pc = wide_imm();
+            },
+            InstrKind::JPCond if src != 0 => {
+                let addr = wide_imm();
+                self.pc = self.pc.wrapping_add(3);
+                tick!(self, 12);
+                if self.read_reg(Reg::F) & src == src {
+                    tick!(self, 4);
+                    self.pc = addr;
+                }
+            },
+            InstrKind::JRelImm => {
+                let offset = imm();
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 8);
+                self.pc = self.pc.wrapping_add_signed(offset as i8 as i16);
+            },
+            InstrKind::JRelCond if src != 0 => {
+                let offset = imm();
+                self.pc = self.pc.wrapping_add(2);
+                tick!(self, 8);
+                if self.read_reg(Reg::F) & src == src {
+                    tick!(self, 4);
+                    self.pc = self.pc.wrapping_add_signed(offset as i8 as i16);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Operaciones de la alu sobre A del camino rápido, CP no guarda el
+    /// resultado
+    #[inline]
+    fn alu_a(&mut self, kind: InstrKind, value: u8) {
+        let a = self.read_reg(Reg::A);
+        let res = match kind {
+            InstrKind::SubReg | InstrKind::SubImm => self.alu_sub(a, value),
+            InstrKind::SbcReg | InstrKind::SbcImm => self.alu_sbc(a, value),
+            InstrKind::AndReg | InstrKind::AndImm => self.alu_and(a, value),
+            InstrKind::OrReg | InstrKind::OrImm => self.alu_or(a, value),
+            _ => {
+                self.alu_sub(a, value);
+                return;
+            },
+        };
+        self.write_reg(Reg::A, res);
+    }
+
+    /// Ejecutar una instrucción ya leída con `decode`, por lo que el `pc` ya
+    /// apunta a la siguiente, devuelve los T-cycles que tardó o
+    /// `Error::Unimplemented` sin tocar la CPU si todavía no se emula
+    // TODO: El bus se usará para las instrucciones con memoria, por ahora
+    // solo lo usa RETI y para leer el opcode del error
+    pub fn execute_instr<B: Bus + ?Sized>(&mut self, instr: Instr, bus: &mut B)
+        -> Result<u32, Error>
+    {
+        let start = self.cycles;
+
+        // El EI de la instrucción anterior activa IME al terminar esta
+        let enable_ime = self.ei_delay;
 
         // Realizar la ejecución según instrucción
         match instr {
-            Instr::Nop => {},
-            Instr::Halt => { todo!() },
+            Instr::Nop => {
+                tick!(self, 4);
+            },
+            Instr::Stop => {
+                tick!(self, 4);
+                self.stopped = true;
+            },
+            Instr::Di => {
+                tick!(self, 4);
+
+                // También cancela un EI justo anterior
+                self.ime = false;
+                self.ei_delay = false;
+            },
+            Instr::Ei => {
+                tick!(self, 4);
+                self.ei_delay = true;
+            },
             Instr::LdRegReg { src, dst } => {
                 tick!(self, 4);
                 self.write_reg(dst, self.read_reg(src));
@@ -1189,9 +1913,6 @@ impl Cpu {
                 tick!(self, 8);
                 self.write_reg(dst, src);
             },
-            Instr::LdRegMem { .. } => todo!(),
-            Instr::LdMemReg { .. } => todo!(),
-            Instr::LdMemHLImm => todo!(),
             Instr::AddRegReg { src, dst } => {
                 tick!(self, 4);
                 let res = self.alu_add(self.read_reg(src), self.read_reg(dst));
@@ -1202,7 +1923,6 @@ impl Cpu {
                 let res = self.alu_add(src, self.read_reg(dst));
                 self.write_reg(dst, res);
             },
-            Instr::AddMemReg { .. } => todo!(),
             Instr::AddWRegWReg { src, dst } => {
                 tick!(self, 8);
                 let res = self.alu_wideadd(self.read_widereg(src), 
@@ -1212,8 +1932,15 @@ impl Cpu {
             },
             Instr::AddWRegImm { src, dst } => {
                 tick!(self, 16);
-                let res = self.alu_wideadd(src as u16, self.read_widereg(dst));
-                self.write_widereg(dst, res);
+
+                // El inmediato tiene signo y los flags salen de sumar el
+                // byte bajo sin signo, Z siempre queda a 0
+                let sp = self.read_widereg(dst);
+                let [low, _] = sp.to_le_bytes();
+                self.alu_add(low, src);
+                let flags = self.read_reg(Reg::F) & (FLAG_H | FLAG_C);
+                self.write_reg(Reg::F, flags);
+                self.write_widereg(dst, sp.wrapping_add_signed(src as i8 as i16));
             }
             Instr::AdcRegReg { src, dst } => {
                 tick!(self, 4);
@@ -1225,7 +1952,6 @@ impl Cpu {
                 let res = self.alu_adc(src, self.read_reg(dst));
                 self.write_reg(dst, res);
             },
-            Instr::AdcMemReg { .. } => todo!(),
             Instr::SubReg { src } => {
                 tick!(self, 4);
                 let res = self.alu_sub(self.read_reg(Reg::A), self.read_reg(src));
@@ -1236,7 +1962,6 @@ impl Cpu {
                 let res = self.alu_sub(self.read_reg(Reg::A), src);
                 self.write_reg(Reg::A, res);
             },
-            Instr::SubMem { .. } => todo!(),
             Instr::SbcReg { src } => {
                 tick!(self, 4);
                 let res = self.alu_sbc(self.read_reg(Reg::A), self.read_reg(src));
@@ -1247,7 +1972,6 @@ impl Cpu {
                 let res = self.alu_sbc(self.read_reg(Reg::A), src);
                 self.write_reg(Reg::A, res);
             },
-            Instr::SbcMem { .. } => todo!(),
             Instr::AndReg { src } => {
                 tick!(self, 4);
                 let res = self.alu_and(self.read_reg(Reg::A), self.read_reg(src));
@@ -1258,7 +1982,6 @@ impl Cpu {
                 let res = self.alu_and(self.read_reg(Reg::A), src);
                 self.write_reg(Reg::A, res);
             },
-            Instr::AndMem { .. } => todo!(),
             Instr::OrReg { src } => {
                 tick!(self, 4);
                 let res = self.alu_or(self.read_reg(Reg::A), self.read_reg(src));
@@ -1269,43 +1992,40 @@ impl Cpu {
                 let res = self.alu_or(self.read_reg(Reg::A), src);
                 self.write_reg(Reg::A, res);
             },
-            Instr::OrMem { .. } => todo!(),
             Instr::IncReg { dst } => {
                 tick!(self, 4);
+                let carry = self.read_reg(Reg::F) & FLAG_C;
                 let res = self.alu_add(self.read_reg(dst), 1);
                 self.write_reg(dst, res);
 
                 // Los incrementos no modifican el flag de carry
-                let flags = self.read_reg(Reg::F) ^ FLAG_C;
+                let flags = self.read_reg(Reg::F) & !FLAG_C | carry;
                 self.write_reg(Reg::F, flags);
             },
             Instr::IncWReg { dst } => {
                 tick!(self, 8);
-                let res = self.alu_wideadd(self.read_widereg(dst), 1);
+                let res = self.read_widereg(dst).wrapping_add(1);
                 self.write_widereg(dst, res);
 
-                // Los incrementos no modifican el flag de carry
-                let flags = self.read_reg(Reg::F) ^ FLAG_C;
-                self.write_reg(Reg::F, flags);
+                // Los incrementos de 16-bits no modifican los flags
             },
-            Instr::IncMem { .. } => todo!(),
             Instr::DecReg { dst } => {
                 tick!(self, 4);
+                let carry = self.read_reg(Reg::F) & FLAG_C;
                 let res = self.alu_sub(self.read_reg(dst), 1);
                 self.write_reg(dst, res);
 
-                // Los incrementos no modifican el flag de carry
-                let flags = self.read_reg(Reg::F) ^ FLAG_C;
+                // Los decrementos no modifican el flag de carry
+                let flags = self.read_reg(Reg::F) & !FLAG_C | carry;
                 self.write_reg(Reg::F, flags);
             },
             Instr::DecWReg { dst } => {
                 tick!(self, 8);
-                let res = self.read_widereg(dst).checked_sub(1).unwrap_or(0);
+                let res = self.read_widereg(dst).wrapping_sub(1);
                 self.write_widereg(dst, res);
 
                 // Los decrementos no modifican los flags
             },
-            Instr::DecMem { .. } => todo!(),
             Instr::CpReg { src } => {
                 tick!(self, 4);
                 self.alu_sub(self.read_reg(Reg::A), self.read_reg(src));
@@ -1314,19 +2034,10 @@ impl Cpu {
                 tick!(self, 8);
                 self.alu_sub(self.read_reg(Reg::A), src);
             },
-            Instr::CpMem { .. } => todo!(),
             Instr::LdWRegImm { src, dst } => {
                 tick!(self, 12);
                 self.write_widereg(dst, src);
             },
-            Instr::LdMemImmReg { .. } => todo!(),
-            Instr::Push { src } => {
-                tick!(self, 16);
-                let _value = self.read_widereg(src);
-
-                todo!();
-            },
-            Instr::Pop { .. } => todo!(),
             Instr::JPImm { addr } => {
                 tick!(self, 16);
                 self.pc = addr;
@@ -1338,21 +2049,19 @@ impl Cpu {
                 // a 1
                 let flags = self.read_reg(Reg::F);
                 if flags & cond != cond {
-                    return Some(());
+                    return Ok((self.cycles - start) as u32);
                 }
 
                 tick!(self, 4);
 
                 self.pc = addr;
             },
-            Instr::JPReg { .. } => todo!(),
             Instr::JRelImm { offset } => {
                 tick!(self, 8);
 
                 // Añadir el offset a pc
-                let offset = offset as i16;
-                self.pc = (self.pc as i16 + offset).try_into()
-                    .expect("After a relative jump `pc` is negative");
+                // El offset es un entero de 8-bits con signo
+                self.pc = self.pc.wrapping_add_signed(offset as i8 as i16);
             },
             Instr::JRelCond { cond, offset } => {
                 tick!(self, 8);
@@ -1361,53 +2070,53 @@ impl Cpu {
                 // a 1
                 let flags = self.read_reg(Reg::F);
                 if flags & cond != cond {
-                    return Some(());
+                    return Ok((self.cycles - start) as u32);
                 }
                 
                 tick!(self, 4);
 
                 // Añadir el offset a pc
-                let offset = offset as i16;
-                self.pc = (self.pc as i16 + offset).try_into()
-                    .expect("After a relative jump `pc` is negative");
+                // El offset es un entero de 8-bits con signo
+                self.pc = self.pc.wrapping_add_signed(offset as i8 as i16);
             },
-            Instr::Rst { addr } => {
-                tick!(self, 8);
+            Instr::Reti => {
+                tick!(self, 16);
+
+                // Desapilar PC, el byte bajo está en SP
+                let sp = self.read_widereg(Reg::SP);
+                let low = bus.read(sp);
+                let high = bus.read(sp.wrapping_add(1));
+                self.write_widereg(Reg::SP, sp.wrapping_add(2));
+                self.pc = u16::from_le_bytes([low, high]);
 
-                // Mover la dirección actual al stack
-                let [curr_addr_h, curr_addr_l] = self.pc.to_le_bytes();
-                todo!();
+                // A diferencia de EI el IME se activa sin esperar
+                self.ime = true;
             },
             Instr::RlcReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_rlc(self.read_reg(reg));
                 self.write_reg(reg, res);
             },
-            Instr::RlcMem { .. } => todo!(),
             Instr::RrcReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_rrc(self.read_reg(reg));
                 self.write_reg(reg, res);
             },
-            Instr::RrcMem { .. } => todo!(),
             Instr::RlReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_rl(self.read_reg(reg));
                 self.write_reg(reg, res);
             },
-            Instr::RlMem { .. } => todo!(),
             Instr::RrReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_rr(self.read_reg(reg));
                 self.write_reg(reg, res);
             },
-            Instr::RrMem { .. } => todo!(),
             Instr::SlaReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_sla(self.read_reg(reg));
                 self.write_reg(reg, res);
             },
-            Instr::SlaMem { .. } => todo!(),
             Instr::SraReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_sra(self.read_reg(reg));
@@ -1418,7 +2127,6 @@ impl Cpu {
                 let res = self.alu_swap(self.read_reg(reg));
                 self.write_reg(reg, res);
             },
-            Instr::SwapMem { .. } => todo!(),
             Instr::SrlReg { reg } => {
                 tick!(self, 8);
                 let res = self.alu_srl(self.read_reg(reg));
@@ -1428,21 +2136,46 @@ impl Cpu {
                 tick!(self, 8);
                 self.alu_bit(self.read_reg(reg), bit);
             },
-            Instr::BitMem { .. } => todo!(),
             Instr::ResReg { reg, bit } => {
                 tick!(self, 8);
-                self.alu_res(self.read_reg(reg), bit);
+                let res = self.alu_res(self.read_reg(reg), bit);
+                self.write_reg(reg, res);
             },
-            Instr::ResMem { .. } => todo!(),
             Instr::SetReg { reg, bit } => {
                 tick!(self, 8);
-                self.alu_set(self.read_reg(reg), bit);
+                let res = self.alu_set(self.read_reg(reg), bit);
+                self.write_reg(reg, res);
+            },
+            // Todavía no se emulan HALT, JP HL ni las que usan la memoria o
+            // la pila
+            Instr::Halt | Instr::JPReg { .. } | Instr::LdRegMem { .. } | Instr::LdMemReg { .. }
+                | Instr::LdMemHLImm | Instr::LdMemImmReg { .. } | Instr::AddMemReg { .. }
+                | Instr::AdcMemReg { .. } | Instr::SubMem { .. } | Instr::SbcMem { .. }
+                | Instr::AndMem { .. } | Instr::OrMem { .. } | Instr::IncMem { .. }
+                | Instr::DecMem { .. } | Instr::CpMem { .. } | Instr::Push { .. }
+                | Instr::Pop { .. } | Instr::Rst { .. } | Instr::RlcMem { .. }
+                | Instr::RrcMem { .. } | Instr::RlMem { .. } | Instr::RrMem { .. }
+                | Instr::SlaMem { .. } | Instr::SraMem { .. } | Instr::SwapMem { .. }
+                | Instr::SrlMem { .. } | Instr::BitMem { .. } | Instr::ResMem { .. }
+                | Instr::SetMem { .. } =>
+            {
+                let addr = self.pc.wrapping_sub(instr.size());
+                return Err(Error::Unimplemented { addr, opcode: bus.read(addr) });
             },
-            Instr::SetMem { .. } => todo!(), 
-            _ => todo!()
         }
 
-        Some(())
+        if enable_ime && self.ei_delay {
+            self.ime = true;
+            self.ei_delay = false;
+        }
+
+        Ok((self.cycles - start) as u32)
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -1459,24 +2192,482 @@ mod tests {
         let mut cpu = Cpu::new();
         assert_eq!(
             cpu.decode(example_program.as_slice()), 
-            Some(Instr::LdRegReg {
+            Ok(Instr::LdRegReg {
                 src: Reg::B,
                 dst: Reg::B
             })
         );
         assert_eq!(
             cpu.decode(example_program.as_slice()), 
-            Some(Instr::LdRegReg {
+            Ok(Instr::LdRegReg {
                 src: Reg::B,
                 dst: Reg::D
             })
         );
         assert_eq!(
             cpu.decode(example_program.as_slice()), 
-            Some(Instr::LdMemReg {
+            Ok(Instr::LdMemReg {
                 src: RegAddr::HL,
                 dst: Reg::B
             })
         );
     }
+
+    #[test]
+    fn interrupt_dispatch() {
+        let mut bus = vec![0; 0x10000];
+        let mut cpu = Cpu::new();
+        cpu.set_pc(0x1234);
+        cpu.write_widereg(Reg::SP, 0xD000);
+        bus[IE as usize] = INT_TIMER | INT_SERIAL;
+        bus[IF as usize] = INT_TIMER | INT_SERIAL;
+
+        // Sin IME se quedan pendientes
+        assert_eq!(cpu.service_interrupt(bus.as_mut_slice()), None);
+        cpu.set_ime(true);
+        assert_eq!(cpu.service_interrupt(bus.as_mut_slice()), Some(0x0050));
+        assert_eq!(cpu.cycles(), INTERRUPT_DISPATCH_CYCLES as u64);
+        assert_eq!((cpu.pc(), cpu.read_widereg(Reg::SP)), (0x0050, 0xCFFE));
+        assert_eq!(bus[0xCFFE..0xD000], [0x34, 0x12]);
+        assert_eq!(bus[IF as usize], INT_SERIAL);
+        assert!(!cpu.ime());
+
+        // Con SP a 0x0000 el byte alto de PC acaba en IE: 0x02 quita la
+        // única pendiente y se cancela, 0x03 mantiene VBLANK
+        for (pc, vector, flags) in [(0x0200, 0x0000, INT_VBLANK), (0x0300, 0x0040, 0)] {
+            let mut cpu = Cpu::new();
+            cpu.set_pc(pc);
+            cpu.set_ime(true);
+            bus[IE as usize] = INT_VBLANK;
+            bus[IF as usize] = INT_VBLANK;
+            assert_eq!(cpu.service_interrupt(bus.as_mut_slice()), Some(vector));
+            assert_eq!(cpu.read_widereg(Reg::SP), 0xFFFE);
+            assert_eq!(bus[IF as usize], flags);
+        }
+    }
+
+    #[test]
+    fn ei_di_reti() {
+        // EI; NOP; EI; DI y RETI con la dirección de vuelta en la pila
+        let mut bus = vec![0; 0x10000];
+        bus[..5].copy_from_slice(&[0xFB, 0x00, 0xFB, 0xF3, 0xD9]);
+        bus[0xCFFE..0xD000].copy_from_slice(&[0x34, 0x12]);
+        let mut cpu = Cpu::new();
+        cpu.write_widereg(Reg::SP, 0xCFFE);
+
+        // IME se activa al terminar la instrucción siguiente a EI
+        cpu.execute(bus.as_mut_slice()).unwrap();
+        assert!(!cpu.ime());
+        cpu.execute(bus.as_mut_slice()).unwrap();
+        assert!(cpu.ime());
+
+        // DI justo después de EI lo cancela
+        cpu.set_ime(false);
+        cpu.execute(bus.as_mut_slice()).unwrap();
+        cpu.execute(bus.as_mut_slice()).unwrap();
+        assert!(!cpu.ime());
+
+        // RETI activa IME sin esperar
+        let start = cpu.cycles();
+        cpu.execute(bus.as_mut_slice()).unwrap();
+        assert_eq!(cpu.cycles() - start, 16);
+        assert_eq!((cpu.pc(), cpu.read_widereg(Reg::SP)), (0x1234, 0xD000));
+        assert!(cpu.ime());
+    }
+
+    #[test]
+    fn prefixed_decode() {
+        let program = [0xCB, 0x11, 0xCB, 0x7E, 0xCB, 0xFF, 0xCB, 0x36];
+        let mut cpu = Cpu::new();
+        assert_eq!(cpu.decode(program.as_slice()), Ok(Instr::RlReg { reg: Reg::C }));
+        assert_eq!(cpu.pc(), 2);
+        assert_eq!(cpu.decode(program.as_slice()),
+            Ok(Instr::BitMem { reg: RegAddr::HL, bit: 7 }));
+        assert_eq!(cpu.decode(program.as_slice()), Ok(Instr::SetReg { reg: Reg::A, bit: 7 }));
+        assert_eq!(cpu.decode(program.as_slice()), Ok(Instr::SwapMem { reg: RegAddr::HL }));
+        assert_eq!(cpu.pc(), 8);
+    }
+
+    #[test]
+    fn res_set_write_back() {
+        // RES 0, B y SET 7, B guardan el resultado en el registro
+        let mut program = [0xCB, 0x80, 0xCB, 0xF8];
+        let mut cpu = Cpu::new();
+        cpu.write_reg(Reg::B, 0x01);
+        cpu.execute(program.as_mut_slice()).unwrap();
+        assert_eq!(cpu.read_reg(Reg::B), 0x00);
+        cpu.execute(program.as_mut_slice()).unwrap();
+        assert_eq!(cpu.read_reg(Reg::B), 0x80);
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let mut cpu = Cpu::new();
+        cpu.set_pc(0x0001);
+        assert_eq!(cpu.decode([0x00, 0xD3].as_slice()),
+            Err(Error::InvalidOpcode { addr: 0x0001, opcode: 0xD3 }));
+
+        // Los que existen pero no se decodifican todavía no pasan por NOP
+        for opcode in [0xCD, 0xC9, 0xC7, 0xE9] {
+            let mut cpu = Cpu::new();
+            assert_eq!(cpu.decode([opcode].as_slice()),
+                Err(Error::Unimplemented { addr: 0x0000, opcode }));
+        }
+    }
+
+    #[test]
+    fn unimplemented_instr() {
+        // PUSH BC y HALT se decodifican pero la CPU no los emula, tienen que
+        // devolver el error en vez de hacer panic
+        for (program, addr, opcode) in [([0x00, 0xC5], 0x0001, 0xC5), ([0x76, 0x00], 0x0000, 0x76)] {
+            let mut memory = program;
+            let mut cpu = Cpu::new();
+            let result = (0..2).try_for_each(|_| cpu.execute(memory.as_mut_slice()));
+            assert_eq!(result, Err(Error::Unimplemented { addr, opcode }));
+        }
+    }
+
+    #[test]
+    fn cycle_counter() {
+        let mut example_program = [
+            0x40, 0x06, 0x12
+        ];
+
+        let mut cpu = Cpu::new();
+        assert_eq!(cpu.cycles(), 0);
+        cpu.execute(example_program.as_mut_slice()).unwrap();
+        assert_eq!(cpu.cycles(), 4);
+        cpu.execute(example_program.as_mut_slice()).unwrap();
+        assert_eq!(cpu.cycles(), 12);
+        assert_eq!(cpu.read_reg(Reg::B), 0x12);
+    }
+
+    #[test]
+    fn pc_wraps() {
+        // Tanto el camino rápido como `decode` pasan de 0xFFFF a 0x0000
+        let mut memory = vec![0; 0x10000];
+        let mut cpu = Cpu::new();
+        cpu.set_pc(0xFFFF);
+        cpu.execute(memory.as_mut_slice()).unwrap();
+        assert_eq!(cpu.pc(), 0x0000);
+        cpu.set_pc(0xFFFF);
+        assert_eq!(cpu.decode(memory.as_slice()), Ok(Instr::Nop));
+        assert_eq!(cpu.pc(), 0x0000);
+
+        // DEC rr sobre 0x0000 también da la vuelta
+        memory[0] = 0x0B;
+        cpu.execute(memory.as_mut_slice()).unwrap();
+        assert_eq!(cpu.read_widereg(Reg::BC), 0xFFFF);
+    }
+
+    #[test]
+    fn rrc_carry() {
+        // El bit que sale por la derecha va al carry
+        let mut cpu = Cpu::new();
+        assert_eq!(cpu.alu_rrc(0x01), 0x80);
+        assert_eq!(cpu.read_reg(Reg::F) & FLAG_C, FLAG_C);
+        cpu.alu_rrc(0x02);
+        assert_eq!(cpu.read_reg(Reg::F) & FLAG_C, 0);
+    }
+
+    #[test]
+    fn rr_carry() {
+        // Como en RRC el bit 0 del operando pasa al carry
+        let mut cpu = Cpu::new();
+        cpu.alu_rr(0x01);
+        assert_eq!(cpu.read_reg(Reg::F) & FLAG_C, FLAG_C);
+        cpu.alu_rr(0xFE);
+        assert_eq!(cpu.read_reg(Reg::F) & FLAG_C, 0);
+    }
+
+    #[test]
+    fn wide_register_pairs() {
+        // B, D y H son el byte alto de su par
+        let mut cpu = Cpu::new();
+        cpu.write_widereg(Reg::B, 0x1234);
+        assert_eq!((cpu.read_reg(Reg::B), cpu.read_reg(Reg::C)), (0x12, 0x34));
+        cpu.write_reg(Reg::L, 0x01);
+        cpu.write_reg(Reg::H, 0xC0);
+        assert_eq!(cpu.read_widereg(Reg::H), 0xC001);
+        cpu.write_widereg(Reg::SP, 0xFFFE);
+        assert_eq!(cpu.read_widereg(Reg::SP), 0xFFFE);
+    }
+
+    #[test]
+    fn fast_path_matches_decode() {
+        // Cada opcode con dos juegos de registros y de inmediatos, con los
+        // flags de las condiciones activos y sin activar
+        let mut covered = 0;
+        for opcode in 0..=0xFF {
+            // Con 0x5A todos los registros salen a 0 y DEC rr da la vuelta
+            for (regs, imm) in [(0x00, [0x00, 0x00]), (0xF0, [0x81, 0x7F]), (0x0F, [0xFE, 0x12]),
+                (0x5A, [0xFF, 0xFF])]
+            {
+                let program = [0x00, 0x00, opcode, imm[0], imm[1]];
+                let mut fast = Cpu::new();
+                fast.registers = [regs ^ 0x5A; 10];
+                fast.registers[Reg::F as usize - 1] = regs;
+                fast.set_pc(2);
+                let mut slow = fast.clone();
+
+                if !fast.execute_fast(program.as_slice()) {
+                    continue;
+                }
+                let instr = slow.decode(program.as_slice()).unwrap();
+                slow.execute_instr(instr, &mut [0; 0][..]).unwrap();
+                assert_eq!(fast, slow, "{opcode:#04X} ({instr:?}) con F = {regs:#04X}");
+                covered += 1;
+            }
+        }
+        // Que el camino rápido no se haya quedado sin usar por un cambio en
+        // las tablas
+        assert!(covered > 3 * 0x70, "solo {covered} casos por el camino rápido");
+    }
+
+    /// Flags a partir del resultado en 16 o 32 bits, el half carry sale de
+    /// comparar el bit por encima del nibble (o de los 12 bits) con el de los
+    /// operandos, distinto de como lo calcula la alu
+    fn reference_flags(zero: bool, sub: bool, half: bool, carry: bool) -> u8 {
+        (zero as u8) << 7 | (sub as u8) << 6 | (half as u8) << 5 | (carry as u8) << 4
+    }
+
+    /// Operación de 8 bits de la alu con el carry de entrada, contra la
+    /// referencia con el mismo carry, para todos los valores de a y b
+    fn check_alu(name: &str, op: fn(&mut Cpu, u8, u8) -> u8,
+        reference: fn(u8, u8, bool) -> (u8, u8))
+    {
+        let mut cpu = Cpu::new();
+        for carry in [false, true] {
+            for a in 0..=0xFF {
+                for b in 0..=0xFF {
+                    cpu.write_reg(Reg::F, if carry { FLAG_C } else { 0 });
+                    let res = op(&mut cpu, a, b);
+                    assert_eq!((res, cpu.read_reg(Reg::F)), reference(a, b, carry),
+                        "{name} {a:#04X}, {b:#04X} con carry {carry}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alu_arithmetic() {
+        fn add(a: u8, b: u8, carry: u32) -> (u8, u8) {
+            let res = a as u32 + b as u32 + carry;
+            let half = (a as u32 ^ b as u32 ^ res) & 0x10 != 0;
+            (res as u8, reference_flags(res as u8 == 0, false, half, res > 0xFF))
+        }
+        fn sub(a: u8, b: u8, carry: u32) -> (u8, u8) {
+            let res = (a as u32).wrapping_sub(b as u32).wrapping_sub(carry);
+            let half = (a as u32 ^ b as u32 ^ res) & 0x10 != 0;
+            (res as u8, reference_flags(res as u8 == 0, true, half, res > 0xFF))
+        }
+
+        check_alu("add", Cpu::alu_add, |a, b, _| add(a, b, 0));
+        check_alu("adc", Cpu::alu_adc, |a, b, carry| add(a, b, carry as u32));
+        check_alu("sub", Cpu::alu_sub, |a, b, _| sub(a, b, 0));
+        check_alu("sbc", Cpu::alu_sbc, |a, b, carry| sub(a, b, carry as u32));
+        check_alu("and", Cpu::alu_and, |a, b, _| (a & b, reference_flags(a & b == 0, false,
+            true, false)));
+        check_alu("or", Cpu::alu_or, |a, b, _| (a | b, reference_flags(a | b == 0, false,
+            false, false)));
+    }
+
+    #[test]
+    fn alu_wide_add() {
+        // Todos los valores de HL contra un muestreo de rr que recorre todos
+        // los nibbles, el flag Z no cambia
+        let mut cpu = Cpu::new();
+        for zero in [false, true] {
+            for a in 0..=0xFFFF {
+                for b in (0..=0xFFFFu32).step_by(0x0111) {
+                    let b = b as u16;
+                    cpu.write_reg(Reg::F, if zero { FLAG_Z } else { FLAG_N | FLAG_C });
+                    let res = cpu.alu_wideadd(a, b);
+
+                    let expected = a as u32 + b as u32;
+                    let half = (a as u32 ^ b as u32 ^ expected) & 0x1000 != 0;
+                    assert_eq!((res, cpu.read_reg(Reg::F)), (expected as u16,
+                        reference_flags(zero, false, half, expected > 0xFFFF)),
+                        "add hl {a:#06X}, {b:#06X}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alu_rotate_shift() {
+        // Resultado y bit que sale hacia el carry, a partir de los bits de a
+        fn bits(a: u8) -> [bool; 8] {
+            std::array::from_fn(|i| a & (1 << i) != 0)
+        }
+        fn from_bits(bits: [bool; 8]) -> u8 {
+            bits.iter().enumerate().fold(0, |res, (i, &bit)| res | (bit as u8) << i)
+        }
+        fn shifted(a: u8, left: bool, fill: bool) -> (u8, u8) {
+            let bits = bits(a);
+            let (res, out) = if left {
+                (from_bits(std::array::from_fn(|i| if i == 0 { fill } else { bits[i - 1] })),
+                    bits[7])
+            } else {
+                (from_bits(std::array::from_fn(|i| if i == 7 { fill } else { bits[i + 1] })),
+                    bits[0])
+            };
+            (res, reference_flags(res == 0, false, false, out))
+        }
+
+        type Unary = (&'static str, fn(&mut Cpu, u8) -> u8, fn(u8, bool) -> (u8, u8));
+        let cases: [Unary; 7] = [
+            ("rlc", Cpu::alu_rlc, |a, _| shifted(a, true, bits(a)[7])),
+            ("rrc", Cpu::alu_rrc, |a, _| shifted(a, false, bits(a)[0])),
+            ("rl", Cpu::alu_rl, |a, carry| shifted(a, true, carry)),
+            ("rr", Cpu::alu_rr, |a, carry| shifted(a, false, carry)),
+            ("sla", Cpu::alu_sla, |a, _| shifted(a, true, false)),
+            ("sra", Cpu::alu_sra, |a, _| shifted(a, false, bits(a)[7])),
+            ("srl", Cpu::alu_srl, |a, _| shifted(a, false, false)),
+        ];
+        let mut cpu = Cpu::new();
+        for (name, op, reference) in cases {
+            for carry in [false, true] {
+                for a in 0..=0xFF {
+                    cpu.write_reg(Reg::F, if carry { FLAG_C } else { 0 });
+                    let res = op(&mut cpu, a);
+                    assert_eq!((res, cpu.read_reg(Reg::F)), reference(a, carry),
+                        "{name} {a:#04X} con carry {carry}");
+                }
+            }
+        }
+    }
+
+    /// Instrucción a partir del operando de la muestra, para las que llevan
+    /// inmediato
+    type MakeInstr = fn(u8) -> Instr;
+
+    /// Efecto de cada instrucción sobre Z, N, H y C como en la tabla de
+    /// opcodes de Pan Docs: `0` y `1` los ponen a ese valor, `-` no los toca
+    /// y la letra del flag significa que depende del resultado. Se prueban
+    /// las `Instr` directamente para que los fallos del decode no tapen los
+    /// del executor
+    // TODO: Añadir XOR, DAA, CPL, SCF, CCF y RLCA/RRCA/RLA/RRA cuando tengan
+    // variante en `Instr`, y las de memoria cuando el executor use el bus
+    const FLAG_EFFECTS: &[(&str, MakeInstr, &str)] = &[
+        ("NOP", |_| Instr::Nop, "----"),
+        ("LD B, C", |_| Instr::LdRegReg { src: Reg::C, dst: Reg::B }, "----"),
+        ("LD B, n", |n| Instr::LdRegImm { src: n, dst: Reg::B }, "----"),
+        ("LD BC, nn", |n| Instr::LdWRegImm { src: n as u16 * 0x101, dst: Reg::BC }, "----"),
+        ("INC B", |_| Instr::IncReg { dst: Reg::B }, "Z0H-"),
+        ("INC A", |_| Instr::IncReg { dst: Reg::A }, "Z0H-"),
+        ("DEC B", |_| Instr::DecReg { dst: Reg::B }, "Z1H-"),
+        ("DEC A", |_| Instr::DecReg { dst: Reg::A }, "Z1H-"),
+        ("INC BC", |_| Instr::IncWReg { dst: Reg::BC }, "----"),
+        ("DEC BC", |_| Instr::DecWReg { dst: Reg::BC }, "----"),
+        ("ADD HL, BC", |_| Instr::AddWRegWReg { src: Reg::BC, dst: Reg::HL }, "-0HC"),
+        ("ADD SP, e", |n| Instr::AddWRegImm { src: n, dst: Reg::SP }, "00HC"),
+        ("ADD A, B", |_| Instr::AddRegReg { src: Reg::B, dst: Reg::A }, "Z0HC"),
+        ("ADD A, n", |n| Instr::AddRegImm { src: n, dst: Reg::A }, "Z0HC"),
+        ("ADC A, B", |_| Instr::AdcRegReg { src: Reg::B, dst: Reg::A }, "Z0HC"),
+        ("ADC A, n", |n| Instr::AdcRegImm { src: n, dst: Reg::A }, "Z0HC"),
+        ("SUB B", |_| Instr::SubReg { src: Reg::B }, "Z1HC"),
+        ("SUB n", |n| Instr::SubImm { src: n }, "Z1HC"),
+        ("SBC A, B", |_| Instr::SbcReg { src: Reg::B }, "Z1HC"),
+        ("SBC A, n", |n| Instr::SbcImm { src: n }, "Z1HC"),
+        ("AND B", |_| Instr::AndReg { src: Reg::B }, "Z010"),
+        ("AND n", |n| Instr::AndImm { src: n }, "Z010"),
+        ("OR B", |_| Instr::OrReg { src: Reg::B }, "Z000"),
+        ("OR n", |n| Instr::OrImm { src: n }, "Z000"),
+        ("CP B", |_| Instr::CpReg { src: Reg::B }, "Z1HC"),
+        ("CP n", |n| Instr::CpImm { src: n }, "Z1HC"),
+        ("RLC B", |_| Instr::RlcReg { reg: Reg::B }, "Z00C"),
+        ("RRC B", |_| Instr::RrcReg { reg: Reg::B }, "Z00C"),
+        ("RL B", |_| Instr::RlReg { reg: Reg::B }, "Z00C"),
+        ("RR B", |_| Instr::RrReg { reg: Reg::B }, "Z00C"),
+        ("SLA B", |_| Instr::SlaReg { reg: Reg::B }, "Z00C"),
+        ("SRA B", |_| Instr::SraReg { reg: Reg::B }, "Z00C"),
+        ("SWAP B", |_| Instr::SwapReg { reg: Reg::B }, "Z000"),
+        ("SRL B", |_| Instr::SrlReg { reg: Reg::B }, "Z00C"),
+        ("BIT 0, B", |_| Instr::BitReg { reg: Reg::B, bit: 0 }, "Z01-"),
+        ("BIT 7, B", |_| Instr::BitReg { reg: Reg::B, bit: 7 }, "Z01-"),
+        ("RES 0, B", |_| Instr::ResReg { reg: Reg::B, bit: 0 }, "----"),
+        ("SET 7, B", |_| Instr::SetReg { reg: Reg::B, bit: 7 }, "----"),
+    ];
+
+    #[test]
+    fn flag_effects() {
+        // Operandos que cruzan los límites de nibble y de byte
+        const SAMPLES: [u8; 8] = [0x00, 0x01, 0x0F, 0x10, 0x7F, 0x80, 0xFE, 0xFF];
+        const FLAGS: [u8; 4] = [FLAG_Z, FLAG_N, FLAG_H, FLAG_C];
+
+        let mut failures = Vec::new();
+        for &(name, make, effects) in FLAG_EFFECTS {
+            for (value, other, initial) in SAMPLES.iter()
+                .flat_map(|&value| [(value, value), (value, value ^ 0x5A)])
+                .flat_map(|(value, other)| [(value, other, 0x00), (value, other, 0xF0)])
+            {
+                // A con un valor y el resto de registros e inmediatos con otro
+                let mut cpu = Cpu::new();
+                for reg in [Reg::B, Reg::C, Reg::D, Reg::E, Reg::H, Reg::L] {
+                    cpu.write_reg(reg, other);
+                }
+                cpu.write_reg(Reg::A, value);
+                cpu.write_reg(Reg::F, initial);
+                cpu.write_widereg(Reg::SP, value as u16 * 0x101);
+                cpu.execute_instr(make(other), &mut [0; 0][..]).unwrap();
+
+                let flags = cpu.read_reg(Reg::F);
+                let broken = FLAGS.into_iter().zip(effects.chars()).any(|(flag, effect)| {
+                    let expected = match effect {
+                        '0' => 0,
+                        '1' => flag,
+                        '-' => initial & flag,
+                        _ => return false,
+                    };
+                    flags & flag != expected
+                });
+                if broken {
+                    failures.push(format!("{name} con A = {value:02X}, r = {other:02X} y \
+                        F = {initial:02X}: F = {flags:02X}, no cumple {effects}"));
+                }
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn alu_bits() {
+        let mut cpu = Cpu::new();
+        for carry in [false, true] {
+            for a in 0..=0xFF {
+                cpu.write_reg(Reg::F, if carry { FLAG_C } else { 0 });
+                let res = cpu.alu_swap(a);
+                assert_eq!(res, (a % 16) * 16 + a / 16, "swap {a:#04X}");
+                assert_eq!(cpu.read_reg(Reg::F), reference_flags(a == 0, false, false, false));
+
+                for bit in 0..8 {
+                    // BIT no toca el carry
+                    cpu.write_reg(Reg::F, if carry { FLAG_C | FLAG_N } else { FLAG_Z });
+                    cpu.alu_bit(a, bit);
+                    let set = (a >> bit) % 2 == 1;
+                    assert_eq!(cpu.read_reg(Reg::F), reference_flags(!set, false, true, carry),
+                        "bit {bit}, {a:#04X}");
+
+                    let mask = 2u8.pow(bit as u32);
+                    assert_eq!(cpu.alu_res(a, bit), if set { a - mask } else { a });
+                    assert_eq!(cpu.alu_set(a, bit), if set { a } else { a + mask });
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_instr() {
+        let info = gameboy::StepInfo {
+            addr: 0x0150,
+            bank: 1,
+            instr: Instr::LdMemReg { src: RegAddr::HL, dst: Reg::A },
+            cycles: 8,
+            new_pc: 0x0151,
+        };
+        let data = bincode::serialize(&info).unwrap();
+        assert_eq!(bincode::deserialize::<gameboy::StepInfo>(&data).unwrap(), info);
+    }
 }
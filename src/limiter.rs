@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+/// Frecuencia del reloj de la CPU en Hz
+pub const CPU_FREQUENCY: u32 = 4194304;
+
+/// Frames por segundo de la Game Boy, el reloj va a 4194304Hz y cada frame
+/// dura 70224 T-cycles
+pub const FRAME_RATE: f64 = CPU_FREQUENCY as f64 / 70224.0;
+
+/// Margen antes de cada deadline que se espera con spin en vez de con sleep,
+/// ya que el sleep del sistema puede despertar bastante tarde
+const SPIN_THRESHOLD: Duration = Duration::from_micros(1500);
+
+/// Si se va con tanto retraso no se intenta recuperar, simplemente se
+/// reinicia la planificación a partir de ahora
+const MAX_LAG_FRAMES: u32 = 3;
+
+/// Limitador que mantiene la emulación a la velocidad real
+///
+/// Los deadlines se calculan sumando la duración de un frame al deadline
+/// anterior en vez de al instante actual, así el error de cada espera no se
+/// acumula (deriva) y la media se mantiene exactamente en `FRAME_RATE`
+#[derive(Debug, Clone)]
+pub struct FrameLimiter {
+    frame_duration: Duration,
+
+    /// Instante en el que debe terminar el frame actual
+    deadline: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        Self::with_rate(FRAME_RATE)
+    }
+
+    /// Crear un limitador a `fps` frames por segundo
+    pub fn with_rate(fps: f64) -> Self {
+        Self {
+            frame_duration: Duration::from_secs_f64(1.0 / fps),
+            deadline: None,
+        }
+    }
+
+    #[inline]
+    pub fn frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+
+    /// Olvidar la planificación, el siguiente `wait` empieza de nuevo a
+    /// contar, útil tras pausar la emulación
+    pub fn reset(&mut self) {
+        self.deadline = None;
+    }
+
+    /// Esperar hasta el final del frame actual, devuelve si hizo falta
+    /// esperar o si por el contrario ya se iba con retraso
+    pub fn wait(&mut self) -> bool {
+        let now = Instant::now();
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => {
+                // El primer frame empieza ahora
+                self.deadline = Some(now + self.frame_duration);
+                return false;
+            },
+        };
+
+        if now >= deadline {
+            // Con demasiado retraso se reinicia la planificación en vez de
+            // ejecutar una ráfaga de frames sin esperar para recuperar
+            let lag = now - deadline;
+            self.deadline = if lag > self.frame_duration * MAX_LAG_FRAMES {
+                Some(now + self.frame_duration)
+            } else {
+                Some(deadline + self.frame_duration)
+            };
+            return false;
+        }
+
+        // Dormir la mayor parte y hacer spin el final para ser preciso
+        let remaining = deadline - now;
+        if remaining > SPIN_THRESHOLD {
+            std::thread::sleep(remaining - SPIN_THRESHOLD);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+
+        self.deadline = Some(deadline + self.frame_duration);
+        true
+    }
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paces_frames() {
+        let mut limiter = FrameLimiter::with_rate(500.0);
+        let start = Instant::now();
+
+        assert!(!limiter.wait());
+        for _ in 0..5 {
+            assert!(limiter.wait());
+        }
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+}
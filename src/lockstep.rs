@@ -0,0 +1,143 @@
+use std::fmt;
+
+use crate::gameboy::GameBoy;
+use crate::Cpu;
+
+/// Cada cuánto se comparan las dos instancias de `run_lockstep`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    /// Tras cada instrucción, encuentra el punto exacto de la divergencia
+    Instruction,
+
+    /// Tras cada frame, mucho más rápido pero solo acota la divergencia
+    Frame,
+}
+
+/// Qué es lo primero que se encontró distinto entre las dos instancias
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// Algún registro de la CPU, el `pc` o el contador de ciclos
+    Registers,
+
+    /// El primer byte de memoria que difiere
+    Memory { addr: u16, a: u8, b: u8 },
+
+    /// Solo una de las dos encontró un opcode inválido
+    InvalidOpcode { a: bool, b: bool },
+}
+
+/// Primera divergencia entre dos instancias, con el estado de las dos CPUs
+/// antes y después del paso en el que se produjo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Número de pasos (instrucciones o frames) completados sin divergir
+    pub step: u64,
+
+    pub kind: DivergenceKind,
+
+    /// Estado de las dos CPUs antes del paso, todavía iguales
+    pub before: Cpu,
+
+    pub cpu_a: Cpu,
+    pub cpu_b: Cpu,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Divergencia tras {} pasos desde pc={:#06X}",
+            self.step, self.before.pc())?;
+        match self.kind {
+            DivergenceKind::Registers => writeln!(f, "Registros distintos")?,
+            DivergenceKind::Memory { addr, a, b } => {
+                writeln!(f, "Memoria distinta en {addr:#06X}: {a:#04X} != {b:#04X}")?
+            },
+            DivergenceKind::InvalidOpcode { a, b } => {
+                writeln!(f, "Opcode inválido: a={a} b={b}")?
+            },
+        }
+        writeln!(f, "a: {:?}", self.cpu_a)?;
+        write!(f, "b: {:?}", self.cpu_b)
+    }
+}
+
+/// Ejecutar dos configuraciones del emulador a la vez durante `steps` pasos
+/// comparando tras cada uno los registros y la memoria, devuelve la primera
+/// divergencia o `None` si se comportaron igual. Si las dos encuentran el
+/// mismo opcode inválido se para sin divergencia
+pub fn run_lockstep(a: &mut GameBoy, b: &mut GameBoy, steps: u64,
+    granularity: Granularity) -> Option<Divergence>
+{
+    for step in 0..steps {
+        let before = a.cpu().clone();
+        let (ok_a, ok_b) = match granularity {
+            Granularity::Instruction => (a.step().is_ok(), b.step().is_ok()),
+            Granularity::Frame => (a.step_frame().is_ok(), b.step_frame().is_ok()),
+        };
+
+        let kind = if ok_a != ok_b {
+            Some(DivergenceKind::InvalidOpcode { a: !ok_a, b: !ok_b })
+        } else if a.cpu() != b.cpu() {
+            Some(DivergenceKind::Registers)
+        } else {
+            first_difference(a.mmu().memory(), b.mmu().memory())
+        };
+
+        if let Some(kind) = kind {
+            return Some(Divergence {
+                step,
+                kind,
+                before,
+                cpu_a: a.cpu().clone(),
+                cpu_b: b.cpu().clone(),
+            });
+        }
+        if !ok_a {
+            break;
+        }
+    }
+    None
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<DivergenceKind> {
+    a.iter().zip(b)
+        .position(|(a, b)| a != b)
+        .map(|addr| DivergenceKind::Memory {
+            addr: addr as u16,
+            a: a[addr],
+            b: b[addr],
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Model, Reg};
+
+    #[test]
+    fn finds_first_divergence() {
+        let rom = vec![0; 0x8000];
+        let build = |model, seed| GameBoy::builder()
+            .rom(rom.clone())
+            .model(model)
+            .seed(seed)
+            .build()
+            .unwrap();
+
+        // La Pocket arranca con otro valor en A
+        let divergence = run_lockstep(&mut build(Model::Dmg, 0),
+            &mut build(Model::Mgb, 0), 10, Granularity::Instruction).unwrap();
+        assert_eq!(divergence.kind, DivergenceKind::Registers);
+        assert_eq!(divergence.step, 0);
+        assert_eq!(divergence.cpu_a.read_reg(Reg::A), 0x01);
+        assert_eq!(divergence.cpu_b.read_reg(Reg::A), 0xFF);
+
+        // Con otra semilla la WRAM empieza distinta
+        let divergence = run_lockstep(&mut build(Model::Dmg, 1),
+            &mut build(Model::Dmg, 2), 10, Granularity::Instruction).unwrap();
+        assert!(matches!(divergence.kind,
+            DivergenceKind::Memory { addr: 0xC000..=0xDFFF, .. }));
+
+        assert!(run_lockstep(&mut build(Model::Dmg, 3),
+            &mut build(Model::Dmg, 3), 3, Granularity::Frame).is_none());
+    }
+}
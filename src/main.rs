@@ -0,0 +1,561 @@
+//! Ejecutable `gameboi` para usar el emulador sin escribir un programa:
+//!
+//! ```text
+//! gameboi [run] <rom> [--model dmg|mgb|sgb|cgb|agb] [--boot-rom fichero]
+//!     [--trace fichero|-] [--frames N] [--load-state fichero]
+//!     [--save-state fichero] [--terminal half|braille] [--debug-port ADDR]
+//!     [--trap-rom-writes]
+//! gameboi disasm <rom> [--bank N] [--start ADDR] [--sym fichero]
+//! gameboi info <rom>
+//! gameboi bench <rom> [--frames N] [--model dmg|mgb|sgb|cgb|agb]
+//! gameboi regress <directorio> [--frames N] [--threads N] [--format csv|json]
+//! gameboi vectors <rom> [--script fichero] [--frames N] [--interval N]
+//!     [--model dmg|mgb|sgb|cgb|agb] [--verify manifiesto]
+//! ```
+//!
+//! Sin un frontend con ventana compilado se ejecuta sin pantalla y sin
+//! limitador, así que conviene pasar `--frames`. Con `--terminal` se dibuja
+//! la pantalla en el terminal a velocidad real. Con `--debug-port` lo que
+//! escriba la ROM en esa dirección (normalmente FF7F) sale por stdout y con
+//! `--trap-rom-writes` las escrituras en la ROM que no van al mapper se
+//! avisan por stderr con la instrucción que las hizo.
+//! `regress` ejecuta todas las ROMs de un directorio y escribe el informe por
+//! stdout, para comparar con `diff` el de dos versiones. `vectors` escribe
+//! por stdout un `AvManifest` con los hashes de imagen y audio de la ROM, o
+//! con `--verify` comprueba que la ejecución coincide con uno guardado
+
+use std::fmt::Display;
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use gameboi::{disassemble, run_regression, CgbSupport, GameBoy, Model, RomHeader, StdoutDebug, SymbolTable,
+    TraceFilter, Tracer, WriteTrace, FRAME_RATE};
+#[cfg(feature = "ppu")]
+use gameboi::{TerminalMode, TerminalSink};
+#[cfg(all(feature = "ppu", feature = "apu"))]
+use gameboi::{AvManifest, InputScript};
+
+const USAGE: &str = "uso: gameboi [run] <rom> [--model dmg|mgb|sgb|cgb|agb] \
+    [--boot-rom fichero] [--trace fichero|-] [--frames N] [--load-state fichero] \
+    [--save-state fichero] [--terminal half|braille] [--debug-port ADDR] \
+    [--trap-rom-writes]
+     gameboi disasm <rom> [--bank N] [--start ADDR] [--sym fichero]
+     gameboi info <rom>
+     gameboi bench <rom> [--frames N] [--model dmg|mgb|sgb|cgb|agb]
+     gameboi regress <directorio> [--frames N] [--threads N] [--format csv|json]
+     gameboi vectors <rom> [--script fichero] [--frames N] [--interval N] \
+    [--model dmg|mgb|sgb|cgb|agb] [--verify manifiesto]";
+
+/// Frames de `gameboi bench` si no se pasa `--frames`, un minuto emulado
+const BENCH_FRAMES: u64 = 3600;
+
+/// Frames por ROM de `gameboi regress` si no se pasa `--frames`, diez
+/// segundos emulados
+const REGRESS_FRAMES: u32 = 600;
+
+/// Frames y cada cuántos se apunta un vector en `gameboi vectors` si no se
+/// pasan `--frames` o `--interval`
+#[cfg(all(feature = "ppu", feature = "apu"))]
+const VECTOR_FRAMES: u64 = 600;
+#[cfg(all(feature = "ppu", feature = "apu"))]
+const VECTOR_INTERVAL: u64 = 60;
+
+/// Tamaño de un banco de ROM
+const BANK_SIZE: usize = 0x4000;
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.first().map(String::as_str) {
+        None | Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        },
+        Some("run") => RunOptions::parse(&args[1..]).and_then(run),
+        Some("disasm") => DisasmOptions::parse(&args[1..]).and_then(disasm),
+        Some("info") => match &args[1..] {
+            [rom] => info(rom),
+            _ => Err(USAGE.to_string()),
+        },
+        Some("bench") => RunOptions::parse(&args[1..]).and_then(bench),
+        Some("regress") => RegressOptions::parse(&args[1..]).and_then(regress),
+        #[cfg(all(feature = "ppu", feature = "apu"))]
+        Some("vectors") => VectorOptions::parse(&args[1..]).and_then(vectors),
+        Some(_) => RunOptions::parse(&args).and_then(run),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("gameboi: {err}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Opciones de `gameboi run`
+#[derive(Debug, Default)]
+struct RunOptions {
+    rom: String,
+    model: Option<Model>,
+    boot_rom: Option<String>,
+    trace: Option<String>,
+    frames: Option<u64>,
+    load_state: Option<String>,
+    save_state: Option<String>,
+    #[cfg(feature = "ppu")]
+    terminal: Option<TerminalMode>,
+    debug_port: Option<u16>,
+    trap_rom_writes: bool,
+}
+
+impl RunOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = RunOptions::default();
+        let mut rom = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned()
+                .ok_or_else(|| format!("falta el valor de {arg}"));
+            match arg.as_str() {
+                "--model" => options.model = Some(parse_model(&value()?)?),
+                "--boot-rom" => options.boot_rom = Some(value()?),
+                "--trace" => options.trace = Some(value()?),
+                "--frames" => options.frames = Some(parse_number(arg, &value()?)?),
+                "--load-state" => options.load_state = Some(value()?),
+                "--save-state" => options.save_state = Some(value()?),
+                #[cfg(feature = "ppu")]
+                "--terminal" => options.terminal = Some(parse_terminal(&value()?)?),
+                "--debug-port" => options.debug_port = Some(parse_addr(arg, &value()?)?),
+                "--trap-rom-writes" => options.trap_rom_writes = true,
+                flag if flag.starts_with("--") => return Err(format!("opción desconocida {flag}")),
+                path if rom.is_none() => rom = Some(path.to_string()),
+                extra => return Err(format!("argumento de más {extra}")),
+            }
+        }
+        options.rom = rom.ok_or_else(|| USAGE.to_string())?;
+        Ok(options)
+    }
+}
+
+/// Opciones de `gameboi disasm`
+#[derive(Debug, Default)]
+struct DisasmOptions {
+    rom: String,
+    bank: u16,
+    start: Option<u16>,
+    sym: Option<String>,
+}
+
+impl DisasmOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = DisasmOptions::default();
+        let mut rom = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned()
+                .ok_or_else(|| format!("falta el valor de {arg}"));
+            match arg.as_str() {
+                "--bank" => {
+                    let value = value()?;
+                    options.bank = value.parse()
+                        .map_err(|_| format!("{arg} espera un número, no {value}"))?;
+                },
+                "--start" => options.start = Some(parse_addr(arg, &value()?)?),
+                "--sym" => options.sym = Some(value()?),
+                flag if flag.starts_with("--") => return Err(format!("opción desconocida {flag}")),
+                path if rom.is_none() => rom = Some(path.to_string()),
+                extra => return Err(format!("argumento de más {extra}")),
+            }
+        }
+        options.rom = rom.ok_or_else(|| USAGE.to_string())?;
+        Ok(options)
+    }
+}
+
+/// Opciones de `gameboi regress`
+#[derive(Debug)]
+struct RegressOptions {
+    dir: String,
+    frames: u32,
+    threads: usize,
+    #[cfg(feature = "json")]
+    json: bool,
+}
+
+impl RegressOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = RegressOptions {
+            dir: String::new(),
+            frames: REGRESS_FRAMES,
+            threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            #[cfg(feature = "json")]
+            json: false,
+        };
+        let mut dir = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned()
+                .ok_or_else(|| format!("falta el valor de {arg}"));
+            match arg.as_str() {
+                "--frames" => {
+                    let value = value()?;
+                    options.frames = parse_number(arg, &value)?.try_into()
+                        .map_err(|_| format!("{arg} demasiado grande: {value}"))?;
+                },
+                "--threads" => {
+                    let value = value()?;
+                    options.threads = parse_number(arg, &value)?.try_into()
+                        .map_err(|_| format!("{arg} demasiado grande: {value}"))?;
+                },
+                "--format" => match value()?.as_str() {
+                    #[cfg(feature = "json")]
+                    format @ ("csv" | "json") => options.json = format == "json",
+                    #[cfg(not(feature = "json"))]
+                    "csv" => (),
+                    #[cfg(not(feature = "json"))]
+                    "json" => return Err("--format json necesita la feature `json`".into()),
+                    format => return Err(format!("formato desconocido {format}")),
+                },
+                flag if flag.starts_with("--") => return Err(format!("opción desconocida {flag}")),
+                path if dir.is_none() => dir = Some(path.to_string()),
+                extra => return Err(format!("argumento de más {extra}")),
+            }
+        }
+        options.dir = dir.ok_or_else(|| USAGE.to_string())?;
+        Ok(options)
+    }
+}
+
+/// Opciones de `gameboi vectors`
+#[cfg(all(feature = "ppu", feature = "apu"))]
+#[derive(Debug, Default)]
+struct VectorOptions {
+    rom: String,
+    script: Option<String>,
+    frames: Option<u64>,
+    interval: Option<u64>,
+    model: Option<Model>,
+    verify: Option<String>,
+}
+
+#[cfg(all(feature = "ppu", feature = "apu"))]
+impl VectorOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = VectorOptions::default();
+        let mut rom = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned()
+                .ok_or_else(|| format!("falta el valor de {arg}"));
+            match arg.as_str() {
+                "--script" => options.script = Some(value()?),
+                "--frames" => options.frames = Some(parse_number(arg, &value()?)?),
+                "--interval" => match parse_number(arg, &value()?)? {
+                    0 => return Err(format!("{arg} tiene que ser al menos 1")),
+                    interval => options.interval = Some(interval),
+                },
+                "--model" => options.model = Some(parse_model(&value()?)?),
+                "--verify" => options.verify = Some(value()?),
+                flag if flag.starts_with("--") => return Err(format!("opción desconocida {flag}")),
+                path if rom.is_none() => rom = Some(path.to_string()),
+                extra => return Err(format!("argumento de más {extra}")),
+            }
+        }
+        options.rom = rom.ok_or_else(|| USAGE.to_string())?;
+        Ok(options)
+    }
+}
+
+fn parse_model(name: &str) -> Result<Model, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "dmg" => Ok(Model::Dmg),
+        "mgb" => Ok(Model::Mgb),
+        "sgb" => Ok(Model::Sgb),
+        "cgb" => Ok(Model::Cgb),
+        "agb" => Ok(Model::Agb),
+        _ => Err(format!("modelo desconocido {name}")),
+    }
+}
+
+#[cfg(feature = "ppu")]
+fn parse_terminal(name: &str) -> Result<TerminalMode, String> {
+    match name {
+        "half" => Ok(TerminalMode::HalfBlock),
+        "braille" => Ok(TerminalMode::Braille),
+        _ => Err(format!("modo de terminal desconocido {name}")),
+    }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("{flag} espera un número, no {value}"))
+}
+
+/// Dirección en hexadecimal, con o sin `$` o `0x` delante
+fn parse_addr(flag: &str, value: &str) -> Result<u16, String> {
+    let digits = value.strip_prefix('$')
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value);
+    u16::from_str_radix(digits, 16)
+        .map_err(|_| format!("{flag} espera una dirección en hexadecimal, no {value}"))
+}
+
+/// Leer un fichero añadiendo la ruta al error
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|err| context(path, err))
+}
+
+/// Error al escribir un listado, si se cortó la tubería (`| head`) no se
+/// considera un error
+fn output_error(err: io::Error) -> String {
+    match err.kind() {
+        io::ErrorKind::BrokenPipe => std::process::exit(0),
+        _ => err.to_string(),
+    }
+}
+
+fn context(path: &str, err: impl Display) -> String {
+    format!("{path}: {err}")
+}
+
+fn run(options: RunOptions) -> Result<(), String> {
+    let rom = read(&options.rom)?;
+    let boot_rom = options.boot_rom.as_deref().map(read).transpose()?;
+    #[cfg(not(feature = "serde"))]
+    if options.load_state.is_some() || options.save_state.is_some() {
+        return Err("los save states necesitan la feature serde".into());
+    }
+
+    let model = options.model.unwrap_or_else(|| Model::preferred_for(&rom));
+    let mut gb = GameBoy::builder()
+        .rom(rom)
+        .boot_rom(boot_rom)
+        .model(model)
+        .build()
+        .map_err(|err| context(&options.rom, err))?;
+
+    #[cfg(feature = "serde")]
+    if let Some(path) = options.load_state.as_deref() {
+        gb.load_state(&read(path)?).map_err(|err| context(path, err))?;
+    }
+    match options.trace.as_deref() {
+        Some("-") => gb.set_tracer(Some(Tracer::new(TraceFilter::new(), WriteTrace::stderr()))),
+        Some(path) => {
+            let sink = WriteTrace::file(path).map_err(|err| context(path, err))?;
+            gb.set_tracer(Some(Tracer::new(TraceFilter::new(), sink)));
+        },
+        None => {},
+    }
+    if let Some(addr) = options.debug_port {
+        gb.mmu_mut().connect_debug_port(addr, Box::new(StdoutDebug));
+    }
+    if options.trap_rom_writes {
+        gb.set_rom_write_trap(|access| eprintln!("escritura en la ROM en {:04X}: {:04X} = {:02X}",
+            access.pc, access.addr, access.value));
+    }
+
+    // Sin nada que mostrar se ejecuta lo más rápido posible
+    #[cfg(feature = "ppu")]
+    let realtime = options.terminal.is_some();
+    #[cfg(not(feature = "ppu"))]
+    let realtime = false;
+    #[cfg(feature = "ppu")]
+    if let Some(mode) = options.terminal {
+        // Limpiar la pantalla, cada frame se dibuja desde la esquina
+        print!("\x1b[2J");
+        gb.set_video_sink(Some(Box::new(TerminalSink::stdout().mode(mode))));
+    }
+
+    while options.frames.is_none_or(|frames| gb.frame_count() < frames) {
+        if realtime {
+            gb.run_frame_realtime().map_err(|err| err.to_string())?;
+        } else {
+            gb.step_frame().map_err(|err| err.to_string())?;
+        }
+    }
+    // Vaciar el `BufWriter` de la traza antes de salir
+    gb.set_tracer(None);
+
+    #[cfg(feature = "serde")]
+    if let Some(path) = options.save_state.as_deref() {
+        std::fs::write(path, gb.save_state()).map_err(|err| context(path, err))?;
+    }
+    println!("{} frames, {} ciclos, {} instrucciones",
+        gb.frame_count(), gb.cycles(), gb.instruction_count());
+    Ok(())
+}
+
+/// Listado de la ROM desde `start` hasta el final de su región: el banco 0 en
+/// 0x0000-0x3FFF o el banco `bank` en 0x4000-0x7FFF. Por defecto empieza en
+/// el punto de entrada, o al principio del banco si no es el 0
+fn disasm(options: DisasmOptions) -> Result<(), String> {
+    let rom = read(&options.rom)?;
+    let symbols = options.sym.as_deref()
+        .map(|path| SymbolTable::load(path).map_err(|err| context(path, err)))
+        .transpose()?;
+
+    // El bus ve el banco 0 y el conmutable como sin mapper, el resto flota.
+    // Con `--bank 0` en 0x4000-0x7FFF está el banco 1
+    let bank = options.bank.max(1);
+    let switchable = rom.get(bank as usize * BANK_SIZE..).unwrap_or_default();
+    if options.bank > 0 && switchable.is_empty() {
+        return Err(format!("{}: no hay banco {bank}", options.rom));
+    }
+    let mut bus = rom[..BANK_SIZE.min(rom.len())].to_vec();
+    bus.resize(BANK_SIZE, 0xFF);
+    bus.extend_from_slice(&switchable[..BANK_SIZE.min(switchable.len())]);
+
+    let start = options.start.unwrap_or(if options.bank == 0 { 0x0100 } else { 0x4000 });
+    let end: usize = if start < 0x4000 { 0x3FFF } else { 0x7FFF };
+    if start as usize > end {
+        return Err(format!("--start {start:#06X} está fuera de la ROM"));
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut addr = start as usize;
+    while addr <= end {
+        let line = disassemble(bus.as_slice(), addr as u16, bank, symbols.as_ref());
+        if let Some(label) = &line.label {
+            writeln!(out, "{label}:").map_err(output_error)?;
+        }
+        let bytes = line.bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ");
+        let text = match &line.text {
+            Some(text) => text.clone(),
+            None => format!("db ${:02X}", line.bytes[0]),
+        };
+        writeln!(out, "{:02X}:{:04X}  {bytes:<8}  {text}", bank_of(line.addr, bank), line.addr)
+            .map_err(output_error)?;
+        addr += line.bytes.len();
+    }
+    out.flush().map_err(output_error)
+}
+
+/// Banco en el que está `addr`, como en los ficheros `.sym`
+fn bank_of(addr: u16, bank: u16) -> u16 {
+    if addr < 0x4000 { 0 } else { bank }
+}
+
+/// Imprimir la cabecera de la ROM, lo primero que se pide en un informe de
+/// compatibilidad
+fn info(path: &str) -> Result<(), String> {
+    let rom = read(path)?;
+    let header = RomHeader::parse(&rom).map_err(|err| context(path, err))?;
+    let size = |size: Option<usize>| match size {
+        Some(0) => "ninguna".to_string(),
+        Some(size) => format!("{} KiB", size / 1024),
+        None => "código inválido".to_string(),
+    };
+    let valid = |valid: bool| if valid { "válido" } else { "inválido" };
+
+    println!("Título:          {}", header.title);
+    println!("Cartucho:        {:#04X} ({})", header.cartridge_type,
+        header.cartridge_name().unwrap_or("desconocido"));
+    println!("ROM:             {} (el fichero ocupa {} KiB)", size(header.rom_size), rom.len() / 1024);
+    println!("RAM:             {}", size(header.ram_size));
+    println!("CGB:             {}", match header.cgb {
+        CgbSupport::None => "no",
+        CgbSupport::Enhanced => "compatible",
+        CgbSupport::Only => "exclusivo",
+    });
+    println!("SGB:             {}", if header.sgb { "sí" } else { "no" });
+    println!("Checksum:        {:#04X} ({})", header.header_checksum,
+        valid(header.header_checksum_valid));
+    println!("Checksum global: {:#06X} ({})", header.global_checksum,
+        valid(header.global_checksum_valid));
+    if header.multicart {
+        println!("Multicart:       MBC1M");
+    }
+    Ok(())
+}
+
+/// Ejecutar `--frames` frames (`BENCH_FRAMES` por defecto) sin limitador ni
+/// ventana y medir la velocidad, para comparar rendimiento entre versiones
+/// con las ROMs de cada uno
+fn bench(options: RunOptions) -> Result<(), String> {
+    #[cfg(feature = "ppu")]
+    if options.terminal.is_some() {
+        return Err("bench solo acepta --frames y --model".into());
+    }
+    if options.boot_rom.is_some() || options.trace.is_some()
+        || options.load_state.is_some() || options.save_state.is_some()
+        || options.debug_port.is_some() || options.trap_rom_writes
+    {
+        return Err("bench solo acepta --frames y --model".into());
+    }
+
+    let rom = read(&options.rom)?;
+    let model = options.model.unwrap_or_else(|| Model::preferred_for(&rom));
+    let mut gb = GameBoy::builder()
+        .rom(rom)
+        .model(model)
+        .build()
+        .map_err(|err| context(&options.rom, err))?;
+
+    let frames = options.frames.unwrap_or(BENCH_FRAMES);
+    let start = Instant::now();
+    while gb.frame_count() < frames {
+        gb.step_frame().map_err(|err| err.to_string())?;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let fps = gb.frame_count() as f64 / elapsed;
+    println!("{} frames en {elapsed:.3} s", gb.frame_count());
+    println!("{fps:.1} frames/s, {:.2}x tiempo real", fps / FRAME_RATE);
+    println!("{:.0} instrucciones/s", gb.instruction_count() as f64 / elapsed);
+    Ok(())
+}
+
+/// Ejecutar cada ROM del directorio y escribir el informe por stdout, el
+/// resumen va por stderr para no mezclarlo al redirigir el informe
+fn regress(options: RegressOptions) -> Result<(), String> {
+    let report = run_regression(&options.dir, options.frames, options.threads)
+        .map_err(|err| context(&options.dir, err))?;
+
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    #[cfg(feature = "json")]
+    let written = match options.json {
+        true => writeln!(stdout, "{}", report.to_json()).and_then(|()| stdout.flush()),
+        false => report.write_csv(&mut stdout),
+    };
+    #[cfg(not(feature = "json"))]
+    let written = report.write_csv(&mut stdout);
+    written.map_err(output_error)?;
+    eprintln!("{} de {} ROMs completadas", report.completed(), report.results.len());
+    Ok(())
+}
+
+/// Generar los vectores de la ROM, o comprobarlos con `--verify`. Con
+/// `--verify` el modelo, los frames y el intervalo son los del manifiesto
+#[cfg(all(feature = "ppu", feature = "apu"))]
+fn vectors(options: VectorOptions) -> Result<(), String> {
+    let rom = read(&options.rom)?;
+    let script = match options.script.as_deref() {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|err| context(path, err))?;
+            InputScript::parse(&text).map_err(|err| context(path, err))?
+        },
+        None => InputScript::default(),
+    };
+
+    if let Some(path) = options.verify.as_deref() {
+        if options.frames.is_some() || options.interval.is_some() || options.model.is_some() {
+            return Err("--verify usa los --frames, --interval y --model del manifiesto".into());
+        }
+        let manifest = AvManifest::load(path).map_err(|err| context(path, err))?;
+        return match manifest.verify(&rom, &script).map_err(|err| context(&options.rom, err))? {
+            None => {
+                println!("{} vectores correctos", manifest.vectors().len());
+                Ok(())
+            },
+            Some(mismatch) => Err(mismatch.to_string()),
+        };
+    }
+
+    let model = options.model.unwrap_or_else(|| Model::preferred_for(&rom));
+    let frames = options.frames.unwrap_or(VECTOR_FRAMES);
+    let interval = options.interval.unwrap_or(VECTOR_INTERVAL);
+    let manifest = AvManifest::generate(&rom, model, &script, frames, interval)
+        .map_err(|err| context(&options.rom, err))?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    manifest.write(&mut stdout).and_then(|()| stdout.flush()).map_err(output_error)
+}
@@ -1,95 +1,1241 @@
-use std::ops::Range;
-
-/// Variantes que controlan el acceso de lectura a memoria desde CPU
-pub enum MemRead {
-    /// Se reemplaza el valor que quiere leer la CPU por otro
-    Replace(u8),
-
-    /// Muestra el valor que hay realmente en memoria a la CPU
-    PassThrough,
-}
-
-/// Variantes que controlan el acceso de escritura a memoria desde CPU
-pub enum MemWrite {
-    /// Se reemplaza el valor que quiere escribir la CPU por otro
-    Replace(u8),
-
-    /// Permite la escritura
-    PassThrough,
-
-    /// No permite la escritura y falla silencionamente
-    Block,
-}
-
-pub struct MemHandler {
-    /// La función es llamada cuando al CPU intenta leer desde memoria y hay
-    /// un handler a esa región
-    on_read: fn(mmu: &Mmu, addr: Addr) -> MemRead,
-
-    /// La función es llamada cuando al CPU intenta escribir a memoria y hay
-    /// un handler a esa región
-    on_write: fn(mmu: &Mmu, addr: Addr, value: u8) -> MemWrite,
-}
-
-const IO_HANDLE: MemHandler = MemHandler {
-    on_read: |mmu: &Mmu, addr: Addr| -> MemRead {
-        MemRead::PassThrough
-    },
-    on_write: |mmu: &Mmu, addr: Addr, value: u8| -> MemWrite {
-        MemWrite::PassThrough
-    },
-};
-
-pub struct Addr(u16);
-
-impl Addr {
-    pub fn get_handler(mmu: &Mmu) -> MemHandler {
-        todo!()
-    }
-}
-
-/*
-struct MemHandlers {
-    mem_handlers_ranges: Vec<Range<usize>>,
-    mem_handlers: Vec<MemHandler>,
-}
-
-impl MemHandlers {
-    fn new() -> Self {
-        Self {
-            mem_handler_ranges: Vec::new(),
-            mem_handlers: Vec::new()
-        }
-    }
-}
-*/
-
-pub struct Mmu {
-    memory: [u8; u16::MAX as usize],
-}
-
-impl Mmu {
-    pub fn new() -> Self {
-        Self {
-            memory: [0; u16::MAX as usize],
-        }
-    }
-
-    pub fn read_word(&self, addr: Addr) -> Option<u8> {
-        self.memory.get(addr.0 as usize)
-    }
-
-    pub fn write_word(&mut self, addr: Addr, value: u8) -> Option<()> c{
-        *self.memory.get_mut(addr.0 as usize) = value;
-    }
-
-    pub fn read_dword(&self, addr: Addr) -> Option<u16> {
-        let h = self.memory.get(addr.0 as usize)?;
-        let l = self.memory.get(addr.0.checked_add(1)? as usize)?;
-        Some(u16::from_le_bytes([h, l]))
-    }
-
-    pub fn write_dword(&mut self, addr: Addr, value: u16) {
-
-    }
-}
+use crate::joypad::{Button, Joypad, JOYP_SELECT_BUTTONS, JOYP_SELECT_DPAD};
+use crate::ir::{read_rp, IrTransceiver, RP, RP_LED};
+use crate::error::{Error, MAX_ROM_SIZE};
+use crate::debug_port::DebugSink;
+use crate::peripheral::{InterruptFlags, Peripheral};
+use crate::model::{supports_sgb, CgbSupport, Model, HEADER_CARTRIDGE_TYPE, HEADER_CGB_FLAG};
+use crate::rng::Rng;
+#[cfg(feature = "mmap")]
+use crate::saves::MappedSram;
+use crate::scheduler::{Event, Scheduler};
+use crate::sgb::Sgb;
+use crate::serial::{Serial, SerialLink, CYCLES_PER_BIT, SB, SC};
+
+/// Dirección del registro JOYP
+pub const JOYP: u16 = 0xFF00;
+
+/// Dirección del registro que desmapea la boot ROM al escribir en él
+pub const BOOT: u16 = 0xFF50;
+
+/// Dirección de KEY0, el modo de la CPU de la CGB. Solo se puede escribir
+/// mientras está mapeada la boot ROM, que copia ahí el byte de compatibilidad
+/// CGB de la cabecera
+pub const KEY0: u16 = 0xFF4C;
+
+/// Bit de KEY0 que deja la CGB en modo compatibilidad con DMG
+pub const KEY0_DMG_MODE: u8 = 1 << 2;
+
+/// RAM del cartucho, en los cartuchos con batería se conserva al apagar
+pub const SRAM: std::ops::RangeInclusive<u16> = 0xA000..=0xBFFF;
+
+/// Dirección del registro IF (interrupciones solicitadas)
+pub const IF: u16 = 0xFF0F;
+
+/// Dirección del registro IE (interrupciones habilitadas)
+pub const IE: u16 = 0xFFFF;
+
+/// Bits de IF, el orden también es el de prioridad de las interrupciones
+pub const INT_VBLANK: u8 = 1 << 0;
+pub const INT_STAT: u8 = 1 << 1;
+pub const INT_TIMER: u8 = 1 << 2;
+pub const INT_SERIAL: u8 = 1 << 3;
+pub const INT_JOYPAD: u8 = 1 << 4;
+
+/// Variantes que controlan el acceso de lectura a memoria desde CPU
+pub enum MemRead {
+    /// Se reemplaza el valor que quiere leer la CPU por otro
+    Replace(u8),
+
+    /// Muestra el valor que hay realmente en memoria a la CPU
+    PassThrough,
+}
+
+/// Variantes que controlan el acceso de escritura a memoria desde CPU
+pub enum MemWrite {
+    /// Se reemplaza el valor que quiere escribir la CPU por otro
+    Replace(u8),
+
+    /// Permite la escritura
+    PassThrough,
+
+    /// No permite la escritura y falla silencionamente
+    Block,
+}
+
+pub struct MemHandler {
+    /// La función es llamada cuando al CPU intenta leer desde memoria y hay
+    /// un handler a esa región
+    on_read: fn(mmu: &Mmu, addr: Addr) -> MemRead,
+
+    /// La función es llamada cuando al CPU intenta escribir a memoria y hay
+    /// un handler a esa región
+    on_write: fn(mmu: &Mmu, addr: Addr, value: u8) -> MemWrite,
+}
+
+const IO_HANDLE: MemHandler = MemHandler {
+    on_read: |_mmu: &Mmu, _addr: Addr| -> MemRead {
+        MemRead::PassThrough
+    },
+    on_write: |_mmu: &Mmu, _addr: Addr, _value: u8| -> MemWrite {
+        MemWrite::PassThrough
+    },
+};
+
+// TODO: Cuando existan los mappers las escrituras a la ROM se tendrán que
+// redirigir a sus registros
+const ROM_HANDLE: MemHandler = MemHandler {
+    on_read: |mmu: &Mmu, addr: Addr| -> MemRead {
+        // Mientras está mapeada la boot ROM tapa el inicio de la ROM, en CGB
+        // salvo la cabecera del cartucho (0x0100-0x01FF)
+        match mmu.boot_rom.as_deref() {
+            Some(boot) if !(0x0100..0x0200).contains(&addr.0) => {
+                match boot.get(addr.0 as usize) {
+                    Some(value) => MemRead::Replace(*value),
+                    None => MemRead::PassThrough,
+                }
+            },
+            _ => MemRead::PassThrough,
+        }
+    },
+    on_write: |_mmu: &Mmu, _addr: Addr, _value: u8| -> MemWrite {
+        MemWrite::Block
+    },
+};
+
+const JOYP_HANDLE: MemHandler = MemHandler {
+    on_read: |mmu: &Mmu, addr: Addr| -> MemRead {
+        // En memoria solo se guardan las líneas de selección
+        let select = mmu.memory[addr.0 as usize];
+
+        // Sin ninguna fila seleccionada el SGB indica qué mando se lee
+        if let Some(sgb) = mmu.sgb.as_ref() {
+            if sgb.players() > 1 && select == JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS {
+                return MemRead::Replace(sgb.joypad_id());
+            }
+        }
+        MemRead::Replace(mmu.joypad.read(select))
+    },
+    on_write: |_mmu: &Mmu, _addr: Addr, value: u8| -> MemWrite {
+        // Solo los bits de selección son escribibles
+        MemWrite::Replace(value & (JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS))
+    },
+};
+
+const SC_HANDLE: MemHandler = MemHandler {
+    on_read: |mmu: &Mmu, addr: Addr| -> MemRead {
+        // Los bits sin usar de SC siempre se leen a 1
+        MemRead::Replace(mmu.memory[addr.0 as usize] | 0b0111_1110)
+    },
+    on_write: |_mmu: &Mmu, _addr: Addr, _value: u8| -> MemWrite {
+        MemWrite::PassThrough
+    },
+};
+
+const RP_HANDLE: MemHandler = MemHandler {
+    on_read: |mmu: &Mmu, addr: Addr| -> MemRead {
+        // El puerto de infrarrojos solo existe en modo CGB
+        if !mmu.is_cgb_mode() {
+            return MemRead::Replace(0xFF);
+        }
+        let receiving = mmu.ir.as_ref().is_some_and(|ir| ir.is_receiving());
+        MemRead::Replace(read_rp(mmu.memory[addr.0 as usize], receiving))
+    },
+    on_write: |mmu: &Mmu, _addr: Addr, _value: u8| -> MemWrite {
+        if mmu.is_cgb_mode() {
+            MemWrite::PassThrough
+        } else {
+            MemWrite::Block
+        }
+    },
+};
+
+const KEY0_HANDLE: MemHandler = MemHandler {
+    on_read: |mmu: &Mmu, _addr: Addr| -> MemRead {
+        if mmu.model.is_cgb() {
+            MemRead::PassThrough
+        } else {
+            MemRead::Replace(0xFF)
+        }
+    },
+    on_write: |mmu: &Mmu, _addr: Addr, _value: u8| -> MemWrite {
+        // Al desmapear la boot ROM el modo queda fijo hasta apagar
+        if mmu.model.is_cgb() && mmu.boot_rom.is_some() {
+            MemWrite::PassThrough
+        } else {
+            MemWrite::Block
+        }
+    },
+};
+
+/// Lo que escribe la boot ROM de la CGB en KEY0: el byte de compatibilidad
+/// de la cabecera si es un juego de CGB y si no el modo compatibilidad
+fn boot_key0(rom: &[u8]) -> u8 {
+    match rom.get(HEADER_CGB_FLAG) {
+        Some(&flag) if flag & 0x80 != 0 => flag,
+        _ => KEY0_DMG_MODE,
+    }
+}
+
+/// Comprobar que la ROM se puede mapear sin mapper, la usan tanto
+/// `Mmu::load_rom` como `Mmu::reload_rom`
+fn validate_rom(rom: &[u8]) -> Result<(), Error> {
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(match rom[HEADER_CARTRIDGE_TYPE] {
+            0x00 | 0x08 | 0x09 => Error::RomTooLarge { size: rom.len() },
+            kind => Error::UnsupportedMapper(kind),
+        });
+    }
+    Ok(())
+}
+
+/// Interfaz con la que la CPU accede a memoria, la implementa la `Mmu` y
+/// también un slice de bytes plano para poder probar la CPU sin el resto del
+/// hardware
+pub trait Bus {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+}
+
+impl Bus for [u8] {
+    /// Fuera del slice el bus está flotando y se lee 0xFF
+    #[inline]
+    fn read(&self, addr: u16) -> u8 {
+        self.get(addr as usize).copied().unwrap_or(0xFF)
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, value: u8) {
+        if let Some(byte) = self.get_mut(addr as usize) {
+            *byte = value;
+        }
+    }
+}
+
+impl Bus for Mmu {
+    #[inline]
+    fn read(&self, addr: u16) -> u8 {
+        self.read_word(Addr(addr))
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, value: u8) {
+        self.write_word(Addr(addr), value);
+    }
+}
+
+/// Regiones del mapa de memoria
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemRegion {
+    /// Banco 0 de la ROM, fijo salvo en los multicart
+    Rom0,
+
+    /// Banco de la ROM que tenga seleccionado el mapper
+    RomX,
+    Vram,
+
+    /// RAM del cartucho, ver `SRAM`
+    Sram,
+    Wram,
+
+    /// Espejo de 0xC000-0xDDFF
+    Echo,
+    Oam,
+
+    /// Hueco entre la OAM y los registros de IO
+    Unusable,
+    Io,
+    Hram,
+
+    /// Registro IE, el último byte del mapa
+    Ie,
+}
+
+impl MemRegion {
+    /// Todas las regiones en orden de dirección
+    pub const ALL: [MemRegion; 11] = [
+        MemRegion::Rom0, MemRegion::RomX, MemRegion::Vram, MemRegion::Sram,
+        MemRegion::Wram, MemRegion::Echo, MemRegion::Oam, MemRegion::Unusable,
+        MemRegion::Io, MemRegion::Hram, MemRegion::Ie,
+    ];
+
+    /// Direcciones que ocupa la región
+    pub const fn range(self) -> std::ops::RangeInclusive<u16> {
+        match self {
+            MemRegion::Rom0 => 0x0000..=0x3FFF,
+            MemRegion::RomX => 0x4000..=0x7FFF,
+            MemRegion::Vram => 0x8000..=0x9FFF,
+            MemRegion::Sram => 0xA000..=0xBFFF,
+            MemRegion::Wram => 0xC000..=0xDFFF,
+            MemRegion::Echo => 0xE000..=0xFDFF,
+            MemRegion::Oam => 0xFE00..=0xFE9F,
+            MemRegion::Unusable => 0xFEA0..=0xFEFF,
+            MemRegion::Io => 0xFF00..=0xFF7F,
+            MemRegion::Hram => HRAM_START..=0xFFFE,
+            MemRegion::Ie => 0xFFFF..=0xFFFF,
+        }
+    }
+
+    /// Primera dirección de la región
+    #[inline]
+    pub const fn start(self) -> Addr {
+        Addr(*self.range().start())
+    }
+}
+
+/// Dirección del bus de 16 bits. Las operaciones dan la vuelta al llegar al
+/// final del mapa, igual que el contador de programa y los punteros de la
+/// CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Addr(pub u16);
+
+impl Addr {
+    #[inline]
+    pub const fn wrapping_add(self, offset: u16) -> Addr {
+        Addr(self.0.wrapping_add(offset))
+    }
+
+    #[inline]
+    pub const fn wrapping_sub(self, offset: u16) -> Addr {
+        Addr(self.0.wrapping_sub(offset))
+    }
+
+    /// Sumar un desplazamiento con signo, como los saltos relativos
+    #[inline]
+    pub const fn wrapping_add_signed(self, offset: i16) -> Addr {
+        Addr(self.0.wrapping_add_signed(offset))
+    }
+
+    /// Región del mapa de memoria a la que pertenece
+    pub const fn region(self) -> MemRegion {
+        match self.0 {
+            0x0000..=0x3FFF => MemRegion::Rom0,
+            0x4000..=0x7FFF => MemRegion::RomX,
+            0x8000..=0x9FFF => MemRegion::Vram,
+            0xA000..=0xBFFF => MemRegion::Sram,
+            0xC000..=0xDFFF => MemRegion::Wram,
+            0xE000..=0xFDFF => MemRegion::Echo,
+            0xFE00..=0xFE9F => MemRegion::Oam,
+            0xFEA0..=0xFEFF => MemRegion::Unusable,
+            0xFF00..=0xFF7F => MemRegion::Io,
+            0xFF80..=0xFFFE => MemRegion::Hram,
+            0xFFFF => MemRegion::Ie,
+        }
+    }
+
+    /// Desplazamiento desde el inicio de su región
+    #[inline]
+    pub const fn offset(self) -> u16 {
+        self.0 - self.region().start().0
+    }
+
+    /// Obtener el handler de la región a la que pertenece la dirección, si es
+    /// que tiene alguno
+    pub fn get_handler(&self) -> Option<&'static MemHandler> {
+        match self.region() {
+            MemRegion::Rom0 | MemRegion::RomX => Some(&ROM_HANDLE),
+            MemRegion::Io => match self.0 {
+                JOYP => Some(&JOYP_HANDLE),
+                SC => Some(&SC_HANDLE),
+                RP => Some(&RP_HANDLE),
+                KEY0 => Some(&KEY0_HANDLE),
+                _ => Some(&IO_HANDLE),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<u16> for Addr {
+    #[inline]
+    fn from(addr: u16) -> Self {
+        Addr(addr)
+    }
+}
+
+impl From<Addr> for u16 {
+    #[inline]
+    fn from(addr: Addr) -> Self {
+        addr.0
+    }
+}
+
+impl std::fmt::Display for Addr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "${:04X}", self.0)
+    }
+}
+
+/// Cómo se accede a cada página de 256 bytes del mapa de memoria, la mayoría
+/// de accesos van a ROM y WRAM y con esto se leen y escriben directamente en
+/// `memory` sin buscar el `MemHandler`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PageClass {
+    /// Sin handler ni efectos al escribir: VRAM, WRAM, echo y OAM
+    Plain,
+
+    /// ROM del cartucho, se lee directamente salvo con la boot ROM mapeada
+    /// y las escrituras se ignoran
+    Rom,
+
+    /// RAM del cartucho, se lee directamente pero las escrituras la marcan
+    /// como modificada
+    Sram,
+
+    /// Registros de IO por el camino lento, la HRAM y IE del final de la
+    /// página directamente
+    Io,
+}
+
+/// Inicio de la HRAM, después de los registros de IO
+const HRAM_START: u16 = 0xFF80;
+
+/// Clase de cada página, indexada por el byte alto de la dirección. Tiene
+/// que coincidir con `Addr::get_handler` y con los efectos de `write_word`
+const PAGE_CLASSES: [PageClass; 0x100] = {
+    let mut classes = [PageClass::Plain; 0x100];
+    let mut page = 0;
+    while page < 0x100 {
+        classes[page] = match Addr((page as u16) << 8).region() {
+            MemRegion::Rom0 | MemRegion::RomX => PageClass::Rom,
+            MemRegion::Sram => PageClass::Sram,
+            MemRegion::Io => PageClass::Io,
+            _ => PageClass::Plain,
+        };
+        page += 1;
+    }
+    classes
+};
+
+/*
+struct MemHandlers {
+    mem_handlers_ranges: Vec<Range<usize>>,
+    mem_handlers: Vec<MemHandler>,
+}
+
+impl MemHandlers {
+    fn new() -> Self {
+        Self {
+            mem_handler_ranges: Vec::new(),
+            mem_handlers: Vec::new()
+        }
+    }
+}
+*/
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmu {
+    /// En el heap para no llenar la pila al mover la MMU o la `GameBoy`
+    #[cfg_attr(feature = "serde", serde(with = "crate::state::boxed_array"))]
+    memory: Box<[u8; u16::MAX as usize + 1]>,
+
+    /// Estado de los botones que se expone a través de JOYP
+    joypad: Joypad,
+
+    /// Boot ROM mapeada sobre el inicio de la ROM, se descarta al escribir en
+    /// `BOOT`
+    boot_rom: Option<Box<[u8]>>,
+
+    /// Modelo emulado y soporte de CGB que declara la ROM cargada, juntos
+    /// deciden si están disponibles las funciones de CGB
+    model: Model,
+    cgb_support: CgbSupport,
+
+    /// Super Game Boy, solo si el modelo es SGB y la ROM lo soporta
+    sgb: Option<Sgb>,
+
+    /// Se escribió en la RAM del cartucho desde el último `clear_sram_dirty`
+    /// y en qué instante fue la última escritura
+    sram_dirty: bool,
+    sram_written_at: u64,
+
+    /// `.sav` mapeado en memoria que recibe cada escritura en la RAM del
+    /// cartucho
+    #[cfg(feature = "mmap")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sram_map: Option<MappedSram>,
+
+    /// Eventos pendientes de los periféricos
+    scheduler: Scheduler,
+
+    /// Transferencia en curso del puerto serie
+    serial: Serial,
+
+    /// Cable link conectado al puerto serie, si lo hay, no forma parte de
+    /// los save states
+    #[cfg_attr(feature = "serde", serde(skip))]
+    link: Option<Box<dyn SerialLink>>,
+
+    /// Transceptor conectado al puerto de infrarrojos, si lo hay
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ir: Option<Box<dyn IrTransceiver>>,
+
+    /// Periféricos externos que se avanzan en cada `tick`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    peripherals: Vec<Box<dyn Peripheral>>,
+
+    /// Dirección del puerto de depuración y a dónde van sus bytes
+    #[cfg_attr(feature = "serde", serde(skip))]
+    debug_port: Option<(u16, Box<dyn DebugSink>)>,
+}
+
+impl Mmu {
+    pub fn new() -> Self {
+        let mut memory: Box<[u8; u16::MAX as usize + 1]> =
+            vec![0; u16::MAX as usize + 1].try_into().unwrap();
+
+        // Ninguna fila de botones seleccionada
+        memory[JOYP as usize] = JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS;
+
+        Self {
+            memory,
+            joypad: Joypad::new(),
+            boot_rom: None,
+            model: Model::Dmg,
+            cgb_support: CgbSupport::None,
+            sgb: None,
+            sram_dirty: false,
+            sram_written_at: 0,
+            #[cfg(feature = "mmap")]
+            sram_map: None,
+            scheduler: Scheduler::new(),
+            serial: Serial::new(),
+            link: None,
+            ir: None,
+            peripherals: Vec::new(),
+            debug_port: None,
+        }
+    }
+
+    /// Rellenar la WRAM y la HRAM con basura como la que tiene la RAM real al
+    /// encender, sacada de `rng` para que sea reproducible
+    pub fn fill_uninitialized_ram(&mut self, rng: &mut Rng) {
+        rng.fill(&mut self.memory[0xC000..=0xDFFF]);
+        rng.fill(&mut self.memory[0xFF80..=0xFFFE]);
+    }
+
+    /// Copiar la ROM del cartucho a su región, sin mappers solo se soportan
+    /// ROMs de hasta 32KB
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        validate_rom(rom)?;
+        self.memory[..rom.len()].copy_from_slice(rom);
+        self.cgb_support = CgbSupport::from_header(rom);
+        // Sin boot ROM se deja KEY0 como lo dejaría ella
+        if self.boot_rom.is_none() {
+            self.memory[KEY0 as usize] = boot_key0(rom);
+        }
+        self.sgb = (self.model.is_sgb() && supports_sgb(rom)).then(Sgb::new);
+        Ok(())
+    }
+
+    /// Sustituir la ROM del cartucho sin tocar el resto de la memoria, para
+    /// `GameBoy::reload_rom`. KEY0 no cambia porque el modo ya lo decidió el
+    /// arranque, lo que sobre de la ROM anterior se borra
+    pub fn reload_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        validate_rom(rom)?;
+        self.memory[..rom.len()].copy_from_slice(rom);
+        self.memory[rom.len()..MAX_ROM_SIZE].fill(0);
+        self.cgb_support = CgbSupport::from_header(rom);
+        // El estado del SGB se conserva mientras la ROM lo siga soportando
+        let sgb = self.model.is_sgb() && supports_sgb(rom);
+        if sgb != self.sgb.is_some() {
+            self.sgb = sgb.then(Sgb::new);
+        }
+        Ok(())
+    }
+
+    /// Cambiar el modelo emulado, se debe hacer antes de empezar a ejecutar
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    #[inline]
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Estado del Super Game Boy, `None` si no se ejecuta en uno o el juego
+    /// no lo soporta
+    #[inline]
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    #[inline]
+    pub fn sgb_mut(&mut self) -> Option<&mut Sgb> {
+        self.sgb.as_mut()
+    }
+
+    /// Están disponibles las funciones de CGB, es decir el modelo es una CGB
+    /// y KEY0 no la ha dejado en modo compatibilidad. La boot ROM lo decide
+    /// con el byte de compatibilidad de la cabecera, ver `cgb_support`
+    #[inline]
+    pub fn is_cgb_mode(&self) -> bool {
+        self.model.is_cgb() && self.memory[KEY0 as usize] & KEY0_DMG_MODE == 0
+    }
+
+    /// Soporte de CGB que declara la cabecera de la ROM cargada
+    #[inline]
+    pub fn cgb_support(&self) -> CgbSupport {
+        self.cgb_support
+    }
+
+    /// Valor de KEY0, aunque el modelo no sea una CGB
+    #[inline]
+    pub fn key0(&self) -> u8 {
+        self.memory[KEY0 as usize]
+    }
+
+    /// Dejar los registros de IO como los deja la boot ROM del modelo, para
+    /// cuando se arranca sin ella
+    pub fn apply_initial_io(&mut self) {
+        for &(addr, value) in self.model.initial_io() {
+            self.memory[addr as usize] = value;
+        }
+    }
+
+    /// Mapear una boot ROM, de 256 bytes (DMG) o de 2304 bytes (CGB)
+    pub fn load_boot_rom(&mut self, boot_rom: &[u8]) -> Result<(), Error> {
+        if !matches!(boot_rom.len(), 0x100 | 0x900) {
+            return Err(Error::InvalidBootRom { size: boot_rom.len() });
+        }
+        self.boot_rom = Some(boot_rom.into());
+        Ok(())
+    }
+
+    /// La boot ROM sigue mapeada
+    #[inline]
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    /// Banco de la ROM mapeado en `addr`, 0 fuera de la ROM
+    // TODO: Cuando haya mappers devolver el banco seleccionado en 0x4000
+    #[inline]
+    pub fn rom_bank(&self, addr: u16) -> u16 {
+        match addr {
+            0x4000..=0x7FFF => 1,
+            _ => 0,
+        }
+    }
+
+    /// `addr` es un registro del mapper del cartucho cargado, según el tipo
+    /// de la cabecera. Las escrituras en el resto de la ROM no hacen nada
+    pub fn is_mapper_register(&self, addr: u16) -> bool {
+        match self.memory[HEADER_CARTRIDGE_TYPE] {
+            // Sin mapper
+            0x00 | 0x08 | 0x09 => false,
+            // MBC2 solo decodifica la mitad baja
+            0x05 | 0x06 => addr < 0x4000,
+            // MBC5 no tiene nada en 6000-7FFF
+            0x19..=0x1E => addr < 0x6000,
+            _ => addr < 0x8000,
+        }
+    }
+
+    /// Leer un byte como la CPU, las páginas sin handler se leen
+    /// directamente y el resto pasan por `read_word_slow`
+    #[inline]
+    pub fn read_word(&self, addr: Addr) -> u8 {
+        match PAGE_CLASSES[addr.0 as usize >> 8] {
+            PageClass::Plain | PageClass::Sram => self.memory[addr.0 as usize],
+            PageClass::Rom if self.boot_rom.is_none() => self.memory[addr.0 as usize],
+            PageClass::Io if addr.0 >= HRAM_START => self.memory[addr.0 as usize],
+            _ => self.read_word_slow(addr),
+        }
+    }
+
+    /// Escribir un byte como la CPU, igual que `read_word` con un camino
+    /// rápido para las páginas sin handler
+    #[inline]
+    pub fn write_word(&mut self, addr: Addr, value: u8) {
+        // El byte sigue escribiéndose en memoria como cualquier otro
+        if let Some((_, sink)) = self.debug_port.as_mut().filter(|(port, _)| *port == addr.0) {
+            sink.write_byte(value);
+        }
+        match PAGE_CLASSES[addr.0 as usize >> 8] {
+            PageClass::Plain => self.memory[addr.0 as usize] = value,
+            // `ROM_HANDLE` bloquea todas las escrituras
+            PageClass::Rom => {},
+            PageClass::Io if addr.0 >= HRAM_START => self.memory[addr.0 as usize] = value,
+            _ => self.write_word_slow(addr, value),
+        }
+    }
+
+    fn read_word_slow(&self, addr: Addr) -> u8 {
+        if let Some(handler) = addr.get_handler() {
+            if let MemRead::Replace(value) = (handler.on_read)(self, addr) {
+                return value;
+            }
+        }
+
+        self.memory[addr.0 as usize]
+    }
+
+    fn write_word_slow(&mut self, addr: Addr, mut value: u8) {
+        if let Some(handler) = addr.get_handler() {
+            match (handler.on_write)(self, addr, value) {
+                MemWrite::Replace(new_value) => value = new_value,
+                MemWrite::PassThrough => {},
+                MemWrite::Block => return,
+            }
+        }
+
+        // Cambiar la selección de filas puede bajar líneas de JOYP
+        let old_lines = self.joypad_lines();
+        self.memory[addr.0 as usize] = value;
+        match addr.0 {
+            JOYP => {
+                self.check_joypad_irq(old_lines);
+                if let Some(sgb) = self.sgb.as_mut() {
+                    sgb.write_joyp(value, &self.memory[0x8000..0x9000]);
+                }
+            },
+            SC => self.write_serial_control(value),
+            BOOT if value != 0 => {
+                #[cfg(feature = "tracing")]
+                if self.boot_rom.is_some() {
+                    tracing::debug!("boot ROM desmapeada");
+                }
+                self.boot_rom = None;
+            },
+            addr if SRAM.contains(&addr) => {
+                self.sram_dirty = true;
+                self.sram_written_at = self.scheduler.now();
+                #[cfg(feature = "mmap")]
+                if let Some(map) = self.sram_map.as_mut() {
+                    map.write((addr - SRAM.start()) as usize, value);
+                }
+            },
+            RP if self.is_cgb_mode() => {
+                if let Some(ir) = self.ir.as_mut() {
+                    ir.set_led(value & RP_LED != 0);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Avanzar `cycles` T-cycles los periféricos que dependen del reloj, solo
+    /// se hace trabajo si vence alguno de sus eventos
+    pub fn tick(&mut self, cycles: u32) {
+        self.scheduler.advance(cycles);
+        while let Some((at, event)) = self.scheduler.pop_due() {
+            self.handle_event(at, event);
+        }
+
+        if !self.peripherals.is_empty() {
+            let mut irq = InterruptFlags::new();
+            for peripheral in &mut self.peripherals {
+                peripheral.tick(cycles, &mut irq);
+            }
+            if irq.bits() != 0 {
+                self.request_interrupt(irq.bits());
+            }
+        }
+    }
+
+    /// Instante actual del reloj de los periféricos en T-cycles
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.scheduler.now()
+    }
+
+    /// Atender un evento que estaba programado en el instante `at`, los
+    /// siguientes eventos se programan a partir de `at` para no acumular
+    /// deriva aunque se atiendan tarde
+    fn handle_event(&mut self, at: u64, event: Event) {
+        match event {
+            Event::SerialBit => {
+                let mut sb = self.memory[SB as usize];
+                let mut sc = self.memory[SC as usize];
+                if self.serial.shift_bit(&mut sb, &mut sc) {
+                    self.request_interrupt(INT_SERIAL);
+                } else {
+                    self.scheduler.schedule_at(at + CYCLES_PER_BIT as u64,
+                        Event::SerialBit);
+                }
+                self.memory[SB as usize] = sb;
+                self.memory[SC as usize] = sc;
+            },
+            Event::SerialPoll if self.serial.is_waiting_external() => {
+                // El otro extremo puede haber iniciado la transferencia
+                let sb = self.memory[SB as usize];
+                let next = at + CYCLES_PER_BIT as u64;
+                match self.link.as_mut().and_then(|link| link.offer(sb)) {
+                    Some(received) => {
+                        self.serial.start(received);
+                        self.scheduler.schedule_at(next, Event::SerialBit);
+                    },
+                    None => self.scheduler.schedule_at(next, Event::SerialPoll),
+                }
+            },
+            Event::SerialPoll => {},
+        }
+    }
+
+    /// Al escribir SC con reloj interno se intercambia el byte con el otro
+    /// extremo del cable y empieza la transferencia, con reloj externo se
+    /// empieza a comprobar periódicamente si el otro extremo la inicia
+    fn write_serial_control(&mut self, sc: u8) {
+        self.scheduler.cancel(Event::SerialBit);
+        self.scheduler.cancel(Event::SerialPoll);
+
+        if self.serial.write_control(sc) {
+            let sb = self.memory[SB as usize];
+            let incoming = self.link.as_mut()
+                .and_then(|link| link.exchange(sb))
+                .unwrap_or(0xFF);
+            self.serial.start(incoming);
+            self.scheduler.schedule_in(CYCLES_PER_BIT, Event::SerialBit);
+        } else if self.serial.is_waiting_external() && self.link.is_some() {
+            self.scheduler.schedule_in(0, Event::SerialPoll);
+        }
+    }
+
+    /// Conectar un cable link al puerto serie, reemplazando el anterior
+    pub fn connect_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = Some(link);
+    }
+
+    /// Desconectar el cable link, devolviéndolo si había uno
+    pub fn disconnect_link(&mut self) -> Option<Box<dyn SerialLink>> {
+        self.link.take()
+    }
+
+    /// Conectar un transceptor al puerto de infrarrojos
+    pub fn connect_ir(&mut self, ir: Box<dyn IrTransceiver>) {
+        self.ir = Some(ir);
+    }
+
+    /// Desconectar el transceptor de infrarrojos
+    pub fn disconnect_ir(&mut self) -> Option<Box<dyn IrTransceiver>> {
+        self.ir.take()
+    }
+
+    /// Conectar un periférico externo, se avanza después de los que ya hay
+    pub fn connect_peripheral(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.push(peripheral);
+    }
+
+    /// Desconectar todos los periféricos externos, devolviéndolos en el
+    /// orden en el que se conectaron
+    pub fn disconnect_peripherals(&mut self) -> Vec<Box<dyn Peripheral>> {
+        std::mem::take(&mut self.peripherals)
+    }
+
+    /// Mandar a `sink` lo que se escriba en `addr`, normalmente
+    /// `DEFAULT_DEBUG_PORT`. Reemplaza el puerto anterior
+    pub fn connect_debug_port(&mut self, addr: u16, sink: Box<dyn DebugSink>) {
+        self.debug_port = Some((addr, sink));
+    }
+
+    pub fn disconnect_debug_port(&mut self) -> Option<Box<dyn DebugSink>> {
+        self.debug_port.take().map(|(_, sink)| sink)
+    }
+
+    /// Solicitar una interrupción activando su bit en IF
+    #[inline]
+    pub fn request_interrupt(&mut self, mask: u8) {
+        // TODO: Instrumentar también el dispatch cuando la CPU lo implemente
+        #[cfg(feature = "tracing")]
+        tracing::trace!(mask, "interrupción solicitada");
+        self.memory[IF as usize] |= mask;
+    }
+
+    /// Comprobar si una interrupción está solicitada en IF
+    #[inline]
+    pub fn is_interrupt_requested(&self, mask: u8) -> bool {
+        self.memory[IF as usize] & mask != 0
+    }
+
+    /// Las 4 líneas de entrada de JOYP según la selección actual
+    #[inline]
+    fn joypad_lines(&self) -> u8 {
+        self.joypad.read(self.memory[JOYP as usize]) & 0x0F
+    }
+
+    /// La interrupción de joypad se produce cuando cualquiera de las líneas
+    /// seleccionadas pasa de alto a bajo
+    #[inline]
+    fn check_joypad_irq(&mut self, old_lines: u8) {
+        if old_lines & !self.joypad_lines() != 0 {
+            self.request_interrupt(INT_JOYPAD);
+        }
+    }
+
+    /// Pulsar o soltar un botón del joypad
+    #[inline]
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let old_lines = self.joypad_lines();
+        self.joypad.set_button(button, pressed);
+        self.check_joypad_irq(old_lines);
+    }
+
+    /// Aplicar de golpe el estado de todos los botones, en el formato de
+    /// `Joypad::state`
+    pub fn set_joypad_state(&mut self, state: u8) {
+        for button in Button::ALL {
+            self.set_button(button, state & (1 << button as u8) != 0);
+        }
+    }
+
+    #[inline]
+    pub fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    /// RAM del cartucho
+    // TODO: Sin mappers es simplemente la región 0xA000-0xBFFF, sin bancos ni
+    // registro de habilitación
+    #[inline]
+    pub fn sram(&self) -> &[u8] {
+        &self.memory[*SRAM.start() as usize..=*SRAM.end() as usize]
+    }
+
+    /// Restaurar la RAM del cartucho de un fichero de guardado, falla si es
+    /// más grande que la región
+    pub fn load_sram(&mut self, sram: &[u8]) -> Result<(), Error> {
+        self.memory[*SRAM.start() as usize..=*SRAM.end() as usize]
+            .get_mut(..sram.len())
+            .ok_or(Error::SramTooLarge { size: sram.len() })?
+            .copy_from_slice(sram);
+        #[cfg(feature = "mmap")]
+        if let Some(map) = self.sram_map.as_mut() {
+            map.copy_from(sram);
+        }
+        Ok(())
+    }
+
+    /// Respaldar la RAM del cartucho con un `.sav` mapeado en memoria,
+    /// reemplazando el anterior. La RAM pasa a ser lo que tenga el fichero
+    #[cfg(feature = "mmap")]
+    pub fn map_sram(&mut self, map: MappedSram) {
+        let sram = &mut self.memory[*SRAM.start() as usize..=*SRAM.end() as usize];
+        let len = map.contents().len().min(sram.len());
+        sram[..len].copy_from_slice(&map.contents()[..len]);
+        self.sram_map = Some(map);
+    }
+
+    /// Dejar de escribir en el fichero mapeado, devolviéndolo si había uno
+    #[cfg(feature = "mmap")]
+    pub fn unmap_sram(&mut self) -> Option<MappedSram> {
+        self.sram_map.take()
+    }
+
+    /// Hay escrituras en la RAM del cartucho sin guardar
+    #[inline]
+    pub fn is_sram_dirty(&self) -> bool {
+        self.sram_dirty
+    }
+
+    /// Instante (en T-cycles) de la última escritura en la RAM del cartucho
+    #[inline]
+    pub fn sram_written_at(&self) -> u64 {
+        self.sram_written_at
+    }
+
+    #[inline]
+    pub fn clear_sram_dirty(&mut self) {
+        self.sram_dirty = false;
+    }
+
+    /// Memoria tal cual, sin pasar por los handlers de IO ni la boot ROM
+    #[inline]
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..]
+    }
+
+    /// Sustituir el estado por el de un save state manteniendo conectados
+    /// los periféricos actuales
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, mut state: Mmu) {
+        state.link = self.link.take();
+        state.ir = self.ir.take();
+        state.peripherals = std::mem::take(&mut self.peripherals);
+        state.debug_port = self.debug_port.take();
+        // Los save states anteriores a KEY0 lo tienen a 0 aunque el juego
+        // esté en modo compatibilidad, y tras la boot ROM nunca vale 0 con
+        // un juego de DMG
+        if state.boot_rom.is_none() && state.cgb_support == CgbSupport::None
+            && state.memory[KEY0 as usize] == 0
+        {
+            state.memory[KEY0 as usize] = KEY0_DMG_MODE;
+        }
+        // El fichero tiene que seguir siendo la RAM que se está emulando
+        #[cfg(feature = "mmap")]
+        {
+            state.sram_map = self.sram_map.take();
+            if let Some(map) = state.sram_map.as_mut() {
+                map.copy_from(&state.memory[*SRAM.start() as usize..=*SRAM.end() as usize]);
+            }
+        }
+        *self = state;
+    }
+
+    /// Leer 16 bits en little endian con `read_word`, en 0xFFFF el segundo
+    /// byte es el de 0x0000 como en el bus real
+    pub fn read_dword(&self, addr: Addr) -> u16 {
+        let l = self.read_word(addr);
+        let h = self.read_word(Addr(addr.0.wrapping_add(1)));
+        u16::from_le_bytes([l, h])
+    }
+
+    /// Escribir 16 bits en little endian con `write_word`, primero el byte
+    /// bajo
+    pub fn write_dword(&mut self, addr: Addr, value: u16) {
+        let [l, h] = value.to_le_bytes();
+        self.write_word(addr, l);
+        self.write_word(Addr(addr.0.wrapping_add(1)), h);
+    }
+}
+
+impl Default for Mmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use crate::debug_port::DEFAULT_DEBUG_PORT;
+    use crate::ir::PairedIr;
+    use crate::serial::{PairedLink, SerialCapture, TestOutcome};
+
+    #[test]
+    fn dword_access() {
+        // Pasan por el bus: en la ROM no se escribe y en 0xFFFF se da la vuelta
+        let mut mmu = Mmu::new();
+        mmu.write_dword(Addr(0xC000), 0x1234);
+        assert_eq!(mmu.memory[0xC000..0xC002], [0x34, 0x12]);
+        assert_eq!(mmu.read_dword(Addr(0xC000)), 0x1234);
+        mmu.write_dword(Addr(0x0100), 0x1234);
+        assert_eq!(mmu.read_dword(Addr(0x0100)), 0x0000);
+        mmu.write_word(Addr(0xFFFF), 0xAB);
+        assert_eq!(mmu.read_dword(Addr(0xFFFF)), 0x00AB);
+    }
+
+    #[test]
+    fn custom_peripheral() {
+        /// Timer que pide su interrupción cada 1024 T-cycles
+        struct Divider(u32);
+
+        impl Peripheral for Divider {
+            fn tick(&mut self, cycles: u32, irq: &mut InterruptFlags) {
+                self.0 += cycles;
+                if self.0 >= 1024 {
+                    self.0 -= 1024;
+                    irq.request(INT_TIMER);
+                }
+            }
+        }
+
+        let mut mmu = Mmu::new();
+        mmu.connect_peripheral(Box::new(Divider(0)));
+        mmu.tick(1020);
+        assert_eq!(mmu.read_word(Addr(IF)) & INT_TIMER, 0);
+        mmu.tick(4);
+        assert_eq!(mmu.read_word(Addr(IF)) & INT_TIMER, INT_TIMER);
+
+        assert_eq!(mmu.disconnect_peripherals().len(), 1);
+        mmu.write_word(Addr(IF), 0);
+        mmu.tick(2048);
+        assert_eq!(mmu.read_word(Addr(IF)) & INT_TIMER, 0);
+    }
+
+    #[test]
+    fn debug_port() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let captured = output.clone();
+        let mut mmu = Mmu::new();
+        mmu.connect_debug_port(DEFAULT_DEBUG_PORT, Box::new(move |byte| {
+            captured.lock().unwrap().push(byte);
+        }));
+        for byte in b"hola\n" {
+            mmu.write_word(Addr(DEFAULT_DEBUG_PORT), *byte);
+        }
+        mmu.write_word(Addr(0xFF7E), b'x');
+
+        // En cualquier otra dirección, aunque tenga camino rápido
+        let captured = output.clone();
+        mmu.connect_debug_port(0xC000, Box::new(move |byte| {
+            captured.lock().unwrap().push(byte);
+        }));
+        mmu.write_word(Addr(0xC000), b'!');
+        mmu.write_word(Addr(DEFAULT_DEBUG_PORT), b'y');
+        assert_eq!(mmu.read_word(Addr(0xC000)), b'!');
+        assert!(mmu.disconnect_debug_port().is_some());
+        assert_eq!(*output.lock().unwrap(), b"hola\n!");
+    }
+
+    #[test]
+    fn addr_regions() {
+        // Las regiones cubren el mapa entero, en orden y sin solaparse
+        let mut next = 0u32;
+        for region in MemRegion::ALL {
+            assert_eq!(*region.range().start() as u32, next);
+            assert!(region.range().all(|addr| Addr(addr).region() == region));
+            next = *region.range().end() as u32 + 1;
+        }
+        assert_eq!(next, 0x10000);
+
+        assert_eq!(Addr(0xFE10).region(), MemRegion::Oam);
+        assert_eq!(Addr(0xFE10).offset(), 0x10);
+        assert_eq!(Addr(0xFFFF).wrapping_add(2), Addr(0x0001));
+        assert_eq!(Addr(0x0000).wrapping_sub(1).region(), MemRegion::Ie);
+        assert_eq!(Addr(0x4000).wrapping_add_signed(-1).region(), MemRegion::Rom0);
+        assert_eq!(u16::from(Addr::from(0xC000)), 0xC000);
+        assert_eq!(Addr(0xFF80).to_string(), "$FF80");
+    }
+
+    #[test]
+    fn fast_paths_match_handlers() {
+        // Con y sin boot ROM, cada dirección leída y escrita por el camino
+        // rápido y por los handlers
+        for boot_rom in [false, true] {
+            let mut mmu = Mmu::new();
+            let mut rng = Rng::new(1);
+            rng.fill(&mut mmu.memory[..]);
+            if boot_rom {
+                mmu.load_boot_rom(&[0xAA; 0x100]).unwrap();
+            }
+            for addr in 0..=0xFFFF {
+                assert_eq!(mmu.read_word(Addr(addr)), mmu.read_word_slow(Addr(addr)),
+                    "leer {addr:#06X}");
+            }
+
+            // Las dos MMU reciben las mismas escrituras, la de BOOT desmapea
+            // la boot ROM y se deja fuera
+            let mut slow = Mmu::new();
+            slow.memory.copy_from_slice(&mmu.memory[..]);
+            slow.boot_rom = mmu.boot_rom.clone();
+            for addr in (0..=0xFFFF).filter(|addr| *addr != BOOT) {
+                let value = addr as u8 ^ 0xA5;
+                mmu.write_word(Addr(addr), value);
+                slow.write_word_slow(Addr(addr), value);
+                assert_eq!(mmu.memory[addr as usize], slow.memory[addr as usize],
+                    "escribir {addr:#06X}");
+                assert_eq!(mmu.is_sram_dirty(), slow.is_sram_dirty(), "escribir {addr:#06X}");
+                mmu.clear_sram_dirty();
+                slow.clear_sram_dirty();
+            }
+            assert!(mmu.memory[..] == slow.memory[..]);
+        }
+    }
+
+    #[test]
+    fn joypad_interrupt() {
+        let mut mmu = Mmu::new();
+
+        // Sin ninguna fila seleccionada pulsar no produce interrupción
+        mmu.set_button(Button::A, true);
+        assert!(!mmu.is_interrupt_requested(INT_JOYPAD));
+
+        // Seleccionar la fila de acción con A pulsado baja la línea 0
+        mmu.write_word(Addr(JOYP), 0x10);
+        assert!(mmu.is_interrupt_requested(INT_JOYPAD));
+
+        // Soltar nunca produce interrupción
+        mmu.write_word(Addr(IF), 0);
+        mmu.set_button(Button::A, false);
+        assert!(!mmu.is_interrupt_requested(INT_JOYPAD));
+
+        mmu.set_button(Button::B, true);
+        assert!(mmu.is_interrupt_requested(INT_JOYPAD));
+    }
+
+    #[test]
+    fn serial_transfer_without_peer() {
+        let mut mmu = Mmu::new();
+        mmu.write_word(Addr(SB), 0x42);
+        mmu.write_word(Addr(SC), 0x81);
+
+        // La transferencia tarda 8 bits * 512 ciclos
+        mmu.tick(8 * 512 - 1);
+        assert!(!mmu.is_interrupt_requested(INT_SERIAL));
+        assert_eq!(mmu.read_word(Addr(SC)), 0xFF);
+
+        mmu.tick(1);
+        assert!(mmu.is_interrupt_requested(INT_SERIAL));
+        assert_eq!(mmu.read_word(Addr(SB)), 0xFF);
+        assert_eq!(mmu.read_word(Addr(SC)), 0x7F);
+    }
+
+    #[test]
+    fn serial_paired_link() {
+        let (a, b) = PairedLink::pair();
+        let mut master = Mmu::new();
+        let mut slave = Mmu::new();
+        master.connect_link(Box::new(a));
+        slave.connect_link(Box::new(b));
+
+        // El esclavo se prepara con reloj externo antes de que empiece
+        slave.write_word(Addr(SB), 0x12);
+        slave.write_word(Addr(SC), 0x80);
+        slave.tick(4);
+
+        master.write_word(Addr(SB), 0x34);
+        master.write_word(Addr(SC), 0x81);
+        master.tick(8 * 512);
+
+        // El esclavo se entera de la transferencia con hasta un bit de retraso
+        slave.tick(9 * 512);
+
+        assert_eq!(master.read_word(Addr(SB)), 0x12);
+        assert_eq!(slave.read_word(Addr(SB)), 0x34);
+        assert!(master.is_interrupt_requested(INT_SERIAL));
+        assert!(slave.is_interrupt_requested(INT_SERIAL));
+    }
+
+    #[test]
+    fn serial_capture() {
+        let capture = SerialCapture::new();
+        let mut mmu = Mmu::new();
+        mmu.connect_link(Box::new(capture.clone()));
+
+        for byte in b"cpu_instrs\n\nPassed" {
+            mmu.write_word(Addr(SB), *byte);
+            mmu.write_word(Addr(SC), 0x81);
+            mmu.tick(8 * 512);
+        }
+
+        // Con reloj externo no se envía nada
+        mmu.write_word(Addr(SB), b'!');
+        mmu.write_word(Addr(SC), 0x80);
+
+        assert_eq!(capture.output(), "cpu_instrs\n\nPassed");
+        assert_eq!(capture.outcome(), Some(TestOutcome::Passed));
+    }
+
+    #[test]
+    fn infrared_pair() {
+        let (a, b) = PairedIr::pair();
+        let mut sender = Mmu::new();
+        let mut receiver = Mmu::new();
+        let mut rom = [0; 0x150];
+        rom[0x0143] = 0x80;
+        for mmu in [&mut sender, &mut receiver] {
+            mmu.set_model(Model::Cgb);
+            mmu.load_rom(&rom).unwrap();
+        }
+        sender.connect_ir(Box::new(a));
+        receiver.connect_ir(Box::new(b));
+
+        // Sin habilitar la lectura el receptor siempre da 1
+        sender.write_word(Addr(RP), 0x01);
+        assert_eq!(receiver.read_word(Addr(RP)), 0x3E);
+
+        receiver.write_word(Addr(RP), 0xC0);
+        assert_eq!(receiver.read_word(Addr(RP)), 0xFC);
+
+        sender.write_word(Addr(RP), 0x00);
+        assert_eq!(receiver.read_word(Addr(RP)), 0xFE);
+
+        // En una DMG el registro no existe
+        receiver.set_model(Model::Dmg);
+        assert_eq!(receiver.read_word(Addr(RP)), 0xFF);
+    }
+}
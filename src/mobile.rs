@@ -0,0 +1,106 @@
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::frame::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::GameBoy;
+use crate::joypad::Button;
+
+/// Errores que ven los frontends como excepciones de Kotlin y Swift
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MobileError {
+    InvalidRom,
+    InvalidOpcode,
+
+    /// El save state no se pudo cargar, con el `StateError` como texto
+    InvalidState { reason: String },
+}
+
+impl fmt::Display for MobileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MobileError::InvalidRom => write!(f, "la ROM no es válida"),
+            MobileError::InvalidOpcode => write!(f, "opcode inválido"),
+            MobileError::InvalidState { reason } => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MobileError {}
+
+/// La `GameBoy` para Android e iOS. UniFFI la comparte entre hilos detrás de
+/// un `Arc`, así que va en un `Mutex` aunque el frontend la use desde uno
+pub struct MobileGameBoy {
+    gb: Mutex<GameBoy>,
+}
+
+impl MobileGameBoy {
+    pub fn new(rom: Vec<u8>) -> Result<Self, MobileError> {
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).map_err(|_| MobileError::InvalidRom)?;
+        Ok(Self { gb: Mutex::new(gb) })
+    }
+
+    #[inline]
+    fn gb(&self) -> MutexGuard<'_, GameBoy> {
+        self.gb.lock().unwrap()
+    }
+
+    /// Emular un frame completo
+    pub fn step_frame(&self) -> Result<(), MobileError> {
+        self.gb().step_frame().map_err(|_| MobileError::InvalidOpcode)
+    }
+
+    /// Copia de los píxeles RGBA del último frame, para un `Bitmap` de
+    /// Android o un `CGImage`
+    pub fn frame(&self) -> Vec<u8> {
+        self.gb().frame().pixels().to_vec()
+    }
+
+    pub fn width(&self) -> u32 {
+        SCREEN_WIDTH as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        SCREEN_HEIGHT as u32
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.gb().frame_count()
+    }
+
+    pub fn set_button(&self, button: Button, pressed: bool) {
+        self.gb().set_button(button, pressed);
+    }
+
+    /// Guardar el estado, por ejemplo al pasar la app a segundo plano
+    pub fn save_state(&self) -> Vec<u8> {
+        self.gb().save_state()
+    }
+
+    pub fn load_state(&self, state: Vec<u8>) -> Result<(), MobileError> {
+        self.gb().load_state(&state)
+            .map_err(|err| MobileError::InvalidState { reason: err.to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mobile_api() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        let gb = MobileGameBoy::new(rom).unwrap();
+        gb.step_frame().unwrap();
+        assert_eq!(gb.frame().len(), (gb.width() * gb.height() * 4) as usize);
+        gb.set_button(Button::Start, true);
+
+        let state = gb.save_state();
+        gb.step_frame().unwrap();
+        gb.load_state(state).unwrap();
+        assert_eq!(gb.frame_count(), 1);
+        assert!(matches!(gb.load_state(vec![0; 3]), Err(MobileError::InvalidState { .. })));
+        assert_eq!(MobileGameBoy::new(vec![0; 0x8001]).err(), Some(MobileError::InvalidRom));
+    }
+}
@@ -0,0 +1,204 @@
+/// Rango del título en la cabecera del cartucho
+pub const HEADER_TITLE: std::ops::Range<usize> = 0x0134..0x0144;
+
+/// Dirección del byte de compatibilidad CGB en la cabecera del cartucho
+pub const HEADER_CGB_FLAG: usize = 0x0143;
+
+/// Dirección del byte de compatibilidad SGB en la cabecera del cartucho
+pub const HEADER_SGB_FLAG: usize = 0x0146;
+
+/// Dirección del tipo de cartucho en la cabecera, dice qué mapper lleva
+pub const HEADER_CARTRIDGE_TYPE: usize = 0x0147;
+
+/// Dirección del código de licencia antiguo, debe ser 0x33 para que el SGB
+/// haga caso al byte de compatibilidad SGB
+pub const HEADER_OLD_LICENSEE: usize = 0x014B;
+
+/// La cabecera declara funciones de Super Game Boy
+pub fn supports_sgb(rom: &[u8]) -> bool {
+    rom.get(HEADER_SGB_FLAG) == Some(&0x03)
+        && rom.get(HEADER_OLD_LICENSEE) == Some(&0x33)
+}
+
+/// Título del juego según la cabecera, en los juegos de CGB el último byte es
+/// el de compatibilidad por lo que el título es más corto
+pub fn header_title(rom: &[u8]) -> String {
+    let end = match CgbSupport::from_header(rom) {
+        CgbSupport::None => HEADER_TITLE.end,
+        _ => HEADER_CGB_FLAG,
+    };
+    rom.get(HEADER_TITLE.start..end.min(rom.len()))
+        .unwrap_or_default()
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .filter(|byte| byte.is_ascii_graphic() || **byte == b' ')
+        .map(|byte| *byte as char)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Modelo de hardware emulado, cambia los valores con los que la boot ROM
+/// deja los registros y la disponibilidad de las funciones de CGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Model {
+    /// Game Boy original
+    #[default]
+    Dmg,
+
+    /// Game Boy Pocket
+    Mgb,
+
+    /// Super Game Boy
+    Sgb,
+
+    /// Game Boy Color
+    Cgb,
+
+    /// Game Boy Advance en modo compatibilidad
+    Agb,
+}
+
+/// Qué soporte de CGB declara la cabecera del cartucho
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CgbSupport {
+    /// Juego de DMG, en una CGB se ejecuta en modo compatibilidad
+    None,
+
+    /// Funciona en DMG pero aprovecha la CGB si la hay
+    Enhanced,
+
+    /// Solo funciona en CGB
+    Only,
+}
+
+impl CgbSupport {
+    /// Leer el soporte de CGB de la cabecera, una ROM sin cabecera se trata
+    /// como un juego de DMG
+    pub fn from_header(rom: &[u8]) -> Self {
+        match rom.get(HEADER_CGB_FLAG) {
+            Some(0xC0) => CgbSupport::Only,
+            Some(flag) if flag & 0x80 != 0 => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        }
+    }
+}
+
+impl Model {
+    /// El modelo tiene el hardware de la CGB, aunque puede estar en modo
+    /// compatibilidad según la cabecera
+    #[inline]
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    /// El modelo es un Super Game Boy
+    #[inline]
+    pub fn is_sgb(self) -> bool {
+        self == Model::Sgb
+    }
+
+    /// Modelo en el que mejor se ejecuta la ROM según su cabecera
+    pub fn preferred_for(rom: &[u8]) -> Self {
+        match CgbSupport::from_header(rom) {
+            CgbSupport::Enhanced | CgbSupport::Only => Model::Cgb,
+            CgbSupport::None if supports_sgb(rom) => Model::Sgb,
+            CgbSupport::None => Model::Dmg,
+        }
+    }
+
+    /// Con la ROM dada el modelo activa las funciones de CGB, las CGB en
+    /// modo compatibilidad se comportan como una DMG
+    #[inline]
+    pub fn is_cgb_mode(self, support: CgbSupport) -> bool {
+        self.is_cgb() && support != CgbSupport::None
+    }
+
+    /// Registros A, F, B, C, D, E, H y L tal como los deja la boot ROM
+    pub fn initial_registers(self, support: CgbSupport) -> [u8; 8] {
+        match self {
+            Model::Dmg => [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Mgb => [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            // En modo compatibilidad la boot ROM deja en DE y HL la paleta
+            // elegida, se usan los valores de un juego sin paleta asignada
+            Model::Cgb | Model::Agb if !self.is_cgb_mode(support) => {
+                let f = if self == Model::Agb { 0x00 } else { 0x80 };
+                let b = if self == Model::Agb { 0x01 } else { 0x00 };
+                [0x11, f, b, 0x00, 0x00, 0x08, 0x00, 0x7C]
+            },
+            Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+            Model::Agb => [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        }
+    }
+
+    /// Valores de los registros de IO tras la boot ROM que difieren de 0
+    pub fn initial_io(self) -> &'static [(u16, u8)] {
+        const DMG_IO: &[(u16, u8)] = &[
+            (0xFF04, 0xAB), (0xFF07, 0xF8), (0xFF0F, 0xE1),
+            (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+            (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF18, 0xFF), (0xFF19, 0xBF),
+            (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF),
+            (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF23, 0xBF), (0xFF24, 0x77),
+            (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91), (0xFF41, 0x85),
+            (0xFF46, 0xFF), (0xFF47, 0xFC),
+        ];
+        const SGB_IO: &[(u16, u8)] = &[
+            (0xFF07, 0xF8), (0xFF0F, 0xE1),
+            (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+            (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF18, 0xFF), (0xFF19, 0xBF),
+            (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF),
+            (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF23, 0xBF), (0xFF24, 0x77),
+            (0xFF25, 0xF3), (0xFF26, 0xF0), (0xFF40, 0x91), (0xFF41, 0x85),
+            (0xFF46, 0xFF), (0xFF47, 0xFC),
+        ];
+        const CGB_IO: &[(u16, u8)] = &[
+            (0xFF07, 0xF8), (0xFF0F, 0xE1),
+            (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+            (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF18, 0xFF), (0xFF19, 0xBF),
+            (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF),
+            (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF23, 0xBF), (0xFF24, 0x77),
+            (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91), (0xFF41, 0x85),
+            (0xFF47, 0xFC), (0xFF4F, 0xFE), (0xFF70, 0xF8),
+        ];
+
+        match self {
+            Model::Dmg | Model::Mgb => DMG_IO,
+            Model::Sgb => SGB_IO,
+            Model::Cgb | Model::Agb => CGB_IO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_compatibility() {
+        let mut rom = vec![0; 0x150];
+        assert_eq!(Model::preferred_for(&rom), Model::Dmg);
+        assert!(!Model::Cgb.is_cgb_mode(CgbSupport::from_header(&rom)));
+
+        rom[HEADER_SGB_FLAG] = 0x03;
+        assert_eq!(Model::preferred_for(&rom), Model::Dmg);
+        rom[HEADER_OLD_LICENSEE] = 0x33;
+        assert_eq!(Model::preferred_for(&rom), Model::Sgb);
+
+        rom[HEADER_CGB_FLAG] = 0x80;
+        assert_eq!(CgbSupport::from_header(&rom), CgbSupport::Enhanced);
+        assert_eq!(Model::preferred_for(&rom), Model::Cgb);
+        assert!(Model::Agb.is_cgb_mode(CgbSupport::Enhanced));
+        assert!(!Model::Dmg.is_cgb_mode(CgbSupport::Only));
+
+        rom[HEADER_TITLE][..4].copy_from_slice(b"ABCD");
+        rom[HEADER_CGB_FLAG - 1] = b'E';
+        assert_eq!(header_title(&rom), "ABCD");
+
+        rom[HEADER_CGB_FLAG] = 0xC0;
+        assert_eq!(CgbSupport::from_header(&rom), CgbSupport::Only);
+        assert_eq!(CgbSupport::from_header(&[]), CgbSupport::None);
+    }
+}
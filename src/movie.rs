@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::joypad::Joypad;
+use crate::mmu::Mmu;
+
+/// Identificador al inicio de los ficheros de movie
+const MOVIE_MAGIC: &[u8; 4] = b"GBMV";
+
+/// Versión del formato, se incrementa en cualquier cambio incompatible
+const MOVIE_VERSION: u8 = 1;
+
+/// Grabación determinista de la entrada: el estado de los botones en cada
+/// frame junto con la semilla de la que se deriva el estado inicial, de forma
+/// que si se reproduce desde el mismo estado la ejecución es idéntica
+///
+/// El formato en disco es:
+/// - 4 bytes de magic `GBMV`
+/// - 1 byte de versión
+/// - 8 bytes de semilla (little endian)
+/// - 4 bytes con el número de frames (little endian)
+/// - 1 byte por frame con el estado de los botones (`Joypad::state`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Movie {
+    /// Semilla del estado inicial
+    seed: u64,
+
+    /// Estado de los botones en cada frame
+    frames: Vec<u8>,
+}
+
+impl Movie {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            frames: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Número de frames grabados
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Grabar el estado de los botones del siguiente frame
+    #[inline]
+    pub fn record(&mut self, joypad: &Joypad) {
+        self.frames.push(joypad.state());
+    }
+
+    /// Estado de los botones grabado del frame `frame`
+    #[inline]
+    pub fn frame(&self, frame: usize) -> Option<u8> {
+        self.frames.get(frame).copied()
+    }
+
+    /// Aplicar a la MMU los botones del frame `frame`, devuelve `None` si la
+    /// grabación ya terminó
+    pub fn apply(&self, frame: usize, mmu: &mut Mmu) -> Option<()> {
+        mmu.set_joypad_state(self.frame(frame)?);
+        Some(())
+    }
+
+    /// Serializar la grabación
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let len: u32 = self.frames.len().try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+                "Movie has too many frames"))?;
+
+        writer.write_all(MOVIE_MAGIC)?;
+        writer.write_all(&[MOVIE_VERSION])?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(&self.frames)?;
+        writer.flush()
+    }
+
+    /// Deserializar una grabación escrita con `write_to`
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MOVIE_MAGIC {
+            return Err(invalid("Not a movie file"));
+        }
+
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != MOVIE_VERSION {
+            return Err(invalid("Unsupported movie version"));
+        }
+
+        let mut seed = [0; 8];
+        reader.read_exact(&mut seed)?;
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+
+        // No reservar según la cabecera: con un fichero corrupto podría pedir
+        // hasta 4 GiB, se lee lo que haya y se comprueba que está completo
+        let len = u32::from_le_bytes(len) as u64;
+        let mut frames = Vec::new();
+        reader.take(len).read_to_end(&mut frames)?;
+        if frames.len() as u64 != len {
+            return Err(invalid("Truncated movie file"));
+        }
+
+        Ok(Self {
+            seed: u64::from_le_bytes(seed),
+            frames,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joypad::Button;
+
+    #[test]
+    fn roundtrip() {
+        let mut joypad = Joypad::new();
+        let mut movie = Movie::new(0xDEADBEEF);
+        movie.record(&joypad);
+        joypad.set_button(Button::Start, true);
+        movie.record(&joypad);
+
+        let mut bytes = Vec::new();
+        movie.write_to(&mut bytes).unwrap();
+        let loaded = Movie::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(loaded, movie);
+
+        let mut mmu = Mmu::new();
+        loaded.apply(1, &mut mmu).unwrap();
+        assert!(mmu.joypad().is_pressed(Button::Start));
+        assert!(loaded.apply(2, &mut mmu).is_none());
+
+        bytes[0] = b'X';
+        assert!(Movie::read_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        // La cabecera dice u32::MAX frames pero solo hay uno
+        let mut bytes = Vec::new();
+        Movie::new(1).write_to(&mut bytes).unwrap();
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes.push(0);
+
+        let err = Movie::read_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
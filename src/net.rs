@@ -0,0 +1,245 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::serial::SerialLink;
+
+/// Versión del protocolo, los dos extremos deben coincidir
+const PROTOCOL_VERSION: u8 = 2;
+
+/// Tipos de mensaje, cada mensaje se envía como `[longitud, tipo, datos..]`
+/// donde la longitud cuenta el tipo y los datos
+const MSG_HELLO: u8 = 0;
+const MSG_OFFER: u8 = 1;
+const MSG_TRANSFER: u8 = 2;
+
+/// Lo que espera el maestro al `OFFER` del otro extremo antes de dar la
+/// transferencia por perdida, como si no hubiera nadie al otro lado
+const EXCHANGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Papel de cada extremo del cable, se negocia en el `HELLO` y los dos
+/// extremos tienen que pedir papeles distintos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkRole {
+    /// Genera el reloj, espera al `OFFER` del otro extremo en cada
+    /// transferencia
+    Master,
+
+    /// Espera el reloj externo, nunca bloquea
+    Slave,
+}
+
+/// Réplica local de lo que ha anunciado el otro extremo, la mantiene
+/// actualizada el hilo que lee del socket
+#[derive(Debug, Default)]
+struct RemoteState {
+    /// Byte que ofrece el otro extremo mientras espera reloj externo
+    offered: Option<u8>,
+
+    /// Byte que nos ha enviado el otro extremo al generar el reloj
+    delivered: Option<u8>,
+
+    /// Se cerró la conexión
+    closed: bool,
+}
+
+/// Cable link sobre TCP para conectar emuladores en distintas máquinas
+///
+/// Al conectar ambos lados intercambian un `HELLO` con la versión del
+/// protocolo y su `LinkRole`. El esclavo se anuncia con un `OFFER` mientras
+/// espera el reloj externo y el maestro, al iniciar una transferencia,
+/// espera hasta `EXCHANGE_TIMEOUT` a que llegue ese `OFFER` para que la
+/// latencia de la red no la pierda. Si el juego del esclavo es el que genera
+/// el reloj la transferencia solo se hace si el `OFFER` del maestro ya llegó
+#[derive(Debug)]
+pub struct TcpLink {
+    stream: TcpStream,
+    role: LinkRole,
+
+    /// Estado del otro extremo, la condición se notifica con cada mensaje
+    remote: Arc<(Mutex<RemoteState>, Condvar)>,
+
+    /// Último byte anunciado con `OFFER`, para no repetir mensajes
+    last_offer: Option<u8>,
+}
+
+impl TcpLink {
+    /// Conectar al emulador que escucha en `addr`
+    pub fn connect(addr: impl ToSocketAddrs, role: LinkRole) -> io::Result<Self> {
+        Self::from_stream(TcpStream::connect(addr)?, role)
+    }
+
+    /// Esperar a que se conecte un emulador en `addr`
+    pub fn listen(addr: impl ToSocketAddrs, role: LinkRole) -> io::Result<Self> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        Self::from_stream(stream, role)
+    }
+
+    /// Hacer el handshake sobre una conexión ya establecida, falla si el
+    /// otro extremo pidió el mismo papel
+    pub fn from_stream(mut stream: TcpStream, role: LinkRole) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        stream.set_nodelay(true)?;
+
+        write_message(&mut stream, MSG_HELLO, &[PROTOCOL_VERSION, role as u8])?;
+        let (kind, data) = read_message(&mut stream)?;
+        match (kind, data.as_slice()) {
+            (MSG_HELLO, [PROTOCOL_VERSION, peer, ..]) => {
+                if *peer == role as u8 {
+                    return Err(invalid("Both link ends requested the same role"));
+                }
+            },
+            (MSG_HELLO, _) => return Err(invalid("Link protocol version mismatch")),
+            _ => return Err(invalid("Expected link HELLO message")),
+        }
+
+        let remote = Arc::new((Mutex::new(RemoteState::default()), Condvar::new()));
+
+        // Hilo que va aplicando los mensajes del otro extremo
+        let mut reader = stream.try_clone()?;
+        let thread_remote = remote.clone();
+        thread::spawn(move || {
+            let (state, changed) = &*thread_remote;
+            while let Ok((kind, data)) = read_message(&mut reader) {
+                let mut remote = state.lock().unwrap();
+                match (kind, data.as_slice()) {
+                    (MSG_OFFER, [byte, ..]) => remote.offered = Some(*byte),
+                    (MSG_TRANSFER, [byte, ..]) => remote.delivered = Some(*byte),
+                    _ => break,
+                }
+                changed.notify_all();
+            }
+            state.lock().unwrap().closed = true;
+            changed.notify_all();
+        });
+
+        Ok(Self {
+            stream,
+            role,
+            remote,
+            last_offer: None,
+        })
+    }
+
+    #[inline]
+    pub fn role(&self) -> LinkRole {
+        self.role
+    }
+
+    /// El otro extremo cerró la conexión
+    pub fn is_closed(&self) -> bool {
+        self.remote.0.lock().unwrap().closed
+    }
+}
+
+impl SerialLink for TcpLink {
+    fn exchange(&mut self, byte: u8) -> Option<u8> {
+        let (state, changed) = &*self.remote;
+        let mut remote = state.lock().unwrap();
+
+        // El maestro espera a que llegue el `OFFER`, el esclavo solo usa el
+        // que ya haya llegado
+        if self.role == LinkRole::Master {
+            remote = changed.wait_timeout_while(remote, EXCHANGE_TIMEOUT,
+                |remote| remote.offered.is_none() && !remote.closed).unwrap().0;
+        }
+        let reply = remote.offered.take()?;
+        drop(remote);
+
+        write_message(&mut self.stream, MSG_TRANSFER, &[byte]).ok()?;
+        Some(reply)
+    }
+
+    fn offer(&mut self, byte: u8) -> Option<u8> {
+        if let Some(received) = self.remote.0.lock().unwrap().delivered.take() {
+            self.last_offer = None;
+            return Some(received);
+        }
+
+        if self.last_offer != Some(byte) {
+            write_message(&mut self.stream, MSG_OFFER, &[byte]).ok()?;
+            self.last_offer = Some(byte);
+        }
+        None
+    }
+}
+
+impl Drop for TcpLink {
+    fn drop(&mut self) {
+        // Desbloquea el hilo lector
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn write_message(stream: &mut TcpStream, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut message = vec![data.len() as u8 + 1, kind];
+    message.extend_from_slice(data);
+    stream.write_all(&message)
+}
+
+/// Leer un mensaje, devuelve su tipo y sus datos
+fn read_message(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut len = [0; 1];
+    stream.read_exact(&mut len)?;
+
+    let mut payload = vec![0; len[0] as usize];
+    stream.read_exact(&mut payload)?;
+    match payload.split_first() {
+        Some((kind, data)) if !data.is_empty() => Ok((*kind, data.to_vec())),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData,
+            "Truncated link message")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Par de extremos conectados por localhost
+    fn pair(server_role: LinkRole, client_role: LinkRole)
+        -> (io::Result<TcpLink>, io::Result<TcpLink>)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            TcpLink::from_stream(listener.accept().unwrap().0, server_role)
+        });
+        let client = TcpLink::connect(addr, client_role);
+        (server.join().unwrap(), client)
+    }
+
+    #[test]
+    fn exchange_over_tcp() {
+        let (slave, master) = pair(LinkRole::Slave, LinkRole::Master);
+        let (mut slave, mut master) = (slave.unwrap(), master.unwrap());
+        assert_eq!(master.role(), LinkRole::Master);
+
+        // El maestro espera al `OFFER` aunque llegue después de empezar
+        let slave = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(slave.offer(0x12), None);
+            loop {
+                if let Some(received) = slave.offer(0x12) {
+                    break (slave, received);
+                }
+                thread::yield_now();
+            }
+        });
+        assert_eq!(master.exchange(0x34), Some(0x12));
+        let (slave, received) = slave.join().unwrap();
+        assert_eq!(received, 0x34);
+
+        // Sin nadie esperando el reloj la transferencia se pierde
+        assert_eq!(master.exchange(0x56), None);
+        assert!(!slave.is_closed());
+    }
+
+    #[test]
+    fn same_role_rejected() {
+        let (server, client) = pair(LinkRole::Master, LinkRole::Master);
+        assert_eq!(server.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(client.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
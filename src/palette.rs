@@ -0,0 +1,286 @@
+use crate::joypad::{Button, Joypad};
+
+/// Elige la paleta de un juego de DMG a partir de su ROM, ver
+/// `GameBoy::set_compat_palette_hook`
+pub(crate) type PaletteHook = Box<dyn Fn(&[u8]) -> Option<CompatPalette> + Send>;
+
+/// Capa de la imagen a la que se aplica una paleta de DMG
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    /// Fondo y ventana, usan BGP
+    Background,
+
+    /// Sprites con OBP0
+    Object0,
+
+    /// Sprites con OBP1
+    Object1,
+}
+
+/// Colores con los que la CGB muestra un juego de DMG en modo compatibilidad,
+/// 4 colores RGBA por capa ordenados del más claro al más oscuro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompatPalette {
+    pub bg: [[u8; 4]; 4],
+    pub obj0: [[u8; 4]; 4],
+    pub obj1: [[u8; 4]; 4],
+}
+
+/// Pasar un color 0xRRGGBB a RGBA opaco
+const fn rgb(color: u32) -> [u8; 4] {
+    [(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xFF]
+}
+
+/// Pasar un color BGR555 de la CGB a RGBA opaco
+fn bgr555(color: u16) -> [u8; 4] {
+    let channel = |shift: u16| ((((color >> shift) & 0x1F) as u32 * 255 + 15) / 31) as u8;
+    [channel(0), channel(5), channel(10), 0xFF]
+}
+
+const fn colors(colors: [u32; 4]) -> [[u8; 4]; 4] {
+    [rgb(colors[0]), rgb(colors[1]), rgb(colors[2]), rgb(colors[3])]
+}
+
+/// Las mismas paletas para las tres capas
+const fn uniform(bg: [u32; 4]) -> CompatPalette {
+    CompatPalette { bg: colors(bg), obj0: colors(bg), obj1: colors(bg) }
+}
+
+const WHITE_RED: [u32; 4] = [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000];
+const WHITE_GREEN: [u32; 4] = [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000];
+const WHITE_BLUE: [u32; 4] = [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000];
+const WHITE_BROWN: [u32; 4] = [0xFFFFFF, 0xFFAD63, 0x843100, 0x000000];
+
+/// Colores de la boot ROM de la CGB en BGR555, de 4 en 4. Las combinaciones
+/// indexan colores sueltos y no paletas porque algunas empiezan a mitad de
+/// una paleta y toman colores de la siguiente
+const BOOT_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, 0x639F, 0x4279, 0x15B0, 0x04CB,
+    0x7FFF, 0x6E31, 0x454A, 0x0000, 0x7FFF, 0x1BEF, 0x0200, 0x0000,
+    0x7FFF, 0x421F, 0x1CF2, 0x0000, 0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000, 0x7FFF, 0x03EF, 0x01D6, 0x0000,
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000, 0x7E74, 0x03FF, 0x0180, 0x0000,
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, 0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, 0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0,
+    0x03ED, 0x7FFF, 0x255F, 0x0000, 0x036A, 0x021F, 0x03FF, 0x7FFF,
+    0x7FFF, 0x01DF, 0x0112, 0x0000, 0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000, 0x299F, 0x001A, 0x000C, 0x0000,
+    0x7FFF, 0x027F, 0x001F, 0x0000, 0x7FFF, 0x03E0, 0x0206, 0x0120,
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00, 0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000, 0x03FF, 0x001F, 0x000C, 0x0000,
+    0x7FFF, 0x033F, 0x0193, 0x0000, 0x0000, 0x4200, 0x037F, 0x7FFF,
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000, 0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+/// Combinaciones de la boot ROM: primer color de OBJ0, OBJ1 y fondo
+const BOOT_COMBINATIONS: [[u8; 3]; 51] = [
+    [16, 16, 116], [72, 72, 72], [80, 80, 80], [96, 96, 96], [36, 36, 36],
+    [0, 0, 0], [108, 108, 108], [20, 20, 20], [48, 48, 48], [104, 104, 104],
+    [64, 32, 32], [16, 112, 112], [16, 8, 8], [12, 16, 16], [16, 116, 116],
+    [112, 16, 112], [8, 68, 8], [64, 64, 32], [16, 16, 28], [16, 16, 72],
+    [16, 16, 80], [76, 76, 36], [15, 15, 44], [68, 68, 8], [16, 16, 8],
+    [16, 16, 12], [112, 112, 0], [12, 12, 0], [0, 0, 4], [72, 88, 72],
+    [80, 88, 80], [96, 88, 96], [64, 88, 32], [68, 16, 52], [111, 0, 56],
+    [111, 16, 60], [76, 91, 36], [64, 112, 40], [16, 92, 112], [68, 88, 8],
+    [16, 0, 8], [16, 112, 12], [112, 12, 0], [12, 112, 16], [84, 112, 16],
+    [12, 112, 0], [100, 12, 112], [0, 112, 32], [16, 12, 112], [112, 12, 24],
+    [16, 112, 116],
+];
+
+/// Suma de los bytes del título de los juegos que reconoce la boot ROM. Los
+/// de `TITLE_SUMS_WITH_DUPLICATES` en adelante se repiten y se distinguen
+/// por la cuarta letra del título
+const TITLE_SUMS: [u8; 93] = [
+    0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58,
+    0xC9, 0x3E, 0x70, 0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75,
+    0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97, 0x4B, 0x90, 0x17, 0x10, 0x39,
+    0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE, 0x0C,
+    0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D,
+    0x6D, 0x67, 0x3F, 0x6B,
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF,
+    0x0D, 0xF4, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66,
+    0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+const TITLE_SUMS_WITH_DUPLICATES: usize = 64;
+
+/// Cuarta letra del título de los que tienen la suma repetida
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// Combinación que usa cada juego de `TITLE_SUMS`
+const TITLE_COMBINATIONS: [u8; 93] = [
+    4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5,
+    33, 13, 14, 5, 29, 5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36,
+    38, 26, 42, 30, 41, 34, 34, 5, 42, 6, 5, 33, 25, 42, 42, 40, 2, 16, 25, 42,
+    42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50, 17, 46,
+    6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+/// Código de licencia antiguo de Nintendo, o el que indica que se mire el
+/// nuevo. La boot ROM solo colorea los juegos de Nintendo
+const NINTENDO_LICENSEE: u8 = 0x01;
+const NEW_LICENSEE: u8 = 0x33;
+
+impl CompatPalette {
+    /// Escala de grises, lo más parecido a una DMG
+    pub const GRAYSCALE: Self = uniform([0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000]);
+
+    /// La que asigna la boot ROM a los juegos que no reconoce
+    pub const DEFAULT: Self = Self::DARK_GREEN;
+
+    // Las 12 paletas que se pueden elegir manteniendo una dirección y
+    // opcionalmente A o B mientras la boot ROM muestra el logo
+    pub const BROWN: Self = uniform(WHITE_BROWN);
+    pub const RED: Self = CompatPalette {
+        bg: colors(WHITE_RED), obj0: colors(WHITE_GREEN), obj1: colors(WHITE_BLUE),
+    };
+    pub const DARK_BROWN: Self = CompatPalette {
+        bg: colors([0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108]),
+        obj0: colors(WHITE_BROWN), obj1: colors(WHITE_BROWN),
+    };
+    pub const PASTEL_MIX: Self = uniform([0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000]);
+    pub const ORANGE: Self = uniform([0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000]);
+    pub const YELLOW: Self = CompatPalette {
+        bg: colors([0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000]),
+        obj0: colors(WHITE_BLUE), obj1: colors(WHITE_GREEN),
+    };
+    pub const BLUE: Self = CompatPalette {
+        bg: colors(WHITE_BLUE), obj0: colors(WHITE_RED), obj1: colors(WHITE_GREEN),
+    };
+    pub const DARK_BLUE: Self = CompatPalette {
+        bg: colors([0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000]),
+        obj0: colors(WHITE_RED), obj1: colors(WHITE_BROWN),
+    };
+    pub const GREEN: Self = uniform([0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000]);
+    pub const DARK_GREEN: Self = CompatPalette {
+        bg: colors([0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000]),
+        obj0: colors(WHITE_RED), obj1: colors(WHITE_RED),
+    };
+    pub const REVERSE: Self = uniform([0x000000, 0x008484, 0xFFDE00, 0xFFFFFF]);
+
+    /// Paleta que elige la boot ROM según los botones mantenidos durante el
+    /// logo, `None` si no hay ninguna dirección pulsada
+    pub fn from_buttons(joypad: &Joypad) -> Option<Self> {
+        let a = joypad.is_pressed(Button::A);
+        let b = joypad.is_pressed(Button::B);
+        let pick = |plain, with_a, with_b| match (a, b) {
+            (true, _) => with_a,
+            (_, true) => with_b,
+            _ => plain,
+        };
+
+        if joypad.is_pressed(Button::Up) {
+            Some(pick(Self::BROWN, Self::RED, Self::DARK_BROWN))
+        } else if joypad.is_pressed(Button::Down) {
+            Some(pick(Self::PASTEL_MIX, Self::ORANGE, Self::YELLOW))
+        } else if joypad.is_pressed(Button::Left) {
+            Some(pick(Self::BLUE, Self::DARK_BLUE, Self::GRAYSCALE))
+        } else if joypad.is_pressed(Button::Right) {
+            Some(pick(Self::GREEN, Self::DARK_GREEN, Self::REVERSE))
+        } else {
+            None
+        }
+    }
+
+    /// Paleta que asigna la boot ROM a un juego de DMG según la suma de los
+    /// bytes de su título, `None` si no es uno de los juegos de Nintendo que
+    /// reconoce y le toca `DEFAULT`
+    pub fn from_title(rom: &[u8]) -> Option<Self> {
+        let header = rom.get(0x0134..0x0150)?;
+        let nintendo = match header[0x014B - 0x0134] {
+            NINTENDO_LICENSEE => true,
+            NEW_LICENSEE => header[0x0144 - 0x0134..0x0146 - 0x0134] == *b"01",
+            _ => false,
+        };
+        if !nintendo {
+            return None;
+        }
+
+        let title = &header[..16];
+        let sum = title.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let game = TITLE_SUMS.iter().enumerate().position(|(i, game_sum)| {
+            *game_sum == sum && (i < TITLE_SUMS_WITH_DUPLICATES
+                || FOURTH_LETTERS[i - TITLE_SUMS_WITH_DUPLICATES] == title[3])
+        })?;
+        Some(Self::from_combination(TITLE_COMBINATIONS[game]))
+    }
+
+    fn from_combination(combination: u8) -> Self {
+        let palette = |first: u8| {
+            let colors = &BOOT_COLORS[first as usize..][..4];
+            [bgr555(colors[0]), bgr555(colors[1]), bgr555(colors[2]), bgr555(colors[3])]
+        };
+        let [obj0, obj1, bg] = BOOT_COMBINATIONS[combination as usize];
+        CompatPalette { bg: palette(bg), obj0: palette(obj0), obj1: palette(obj1) }
+    }
+
+    /// Color del índice `color` (0-3) de un tile de la capa `layer`, pasando
+    /// antes por el registro de paleta de DMG (BGP, OBP0 u OBP1) `register`
+    pub fn color(&self, layer: Layer, register: u8, color: u8) -> [u8; 4] {
+        let shade = (register >> ((color & 0b11) * 2)) & 0b11;
+        let colors = match layer {
+            Layer::Background => &self.bg,
+            Layer::Object0 => &self.obj0,
+            Layer::Object1 => &self.obj1,
+        };
+        colors[shade as usize]
+    }
+}
+
+impl Default for CompatPalette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_selection() {
+        let mut joypad = Joypad::new();
+        assert_eq!(CompatPalette::from_buttons(&joypad), None);
+
+        joypad.set_button(Button::Left, true);
+        joypad.set_button(Button::B, true);
+        let palette = CompatPalette::from_buttons(&joypad).unwrap();
+        assert_eq!(palette, CompatPalette::GRAYSCALE);
+
+        // BGP = 0xE4 es la identidad, 0x1B la invierte
+        assert_eq!(palette.color(Layer::Background, 0xE4, 0), [0xFF; 4]);
+        assert_eq!(palette.color(Layer::Background, 0x1B, 0), [0, 0, 0, 0xFF]);
+        assert_eq!(CompatPalette::DEFAULT.color(Layer::Object1, 0xE4, 1),
+            [0xFF, 0x84, 0x84, 0xFF]);
+    }
+
+    fn rom_with_title(title: &[u8], licensee: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x014B] = licensee;
+        rom
+    }
+
+    #[test]
+    fn title_table() {
+        let tetris = rom_with_title(b"TETRIS", NINTENDO_LICENSEE);
+        assert_eq!(CompatPalette::from_title(&tetris), Some(CompatPalette::ORANGE));
+        assert_eq!(CompatPalette::from_title(&rom_with_title(b"TETRIS", 0x08)), None);
+
+        // Licencia nueva "01"
+        let mut zelda = rom_with_title(b"ZELDA", NEW_LICENSEE);
+        assert_eq!(CompatPalette::from_title(&zelda), None);
+        zelda[0x0144..0x0146].copy_from_slice(b"01");
+        assert!(CompatPalette::from_title(&zelda).is_some());
+
+        // Misma suma que otros juegos, se distingue por la cuarta letra. Su
+        // paleta de OBJ0 empieza en el último color de otra
+        let mario = CompatPalette::from_title(
+            &rom_with_title(b"SUPER MARIOLAND", NINTENDO_LICENSEE)).unwrap();
+        assert_eq!(mario.obj0[0], [0, 0, 0, 0xFF]);
+        assert_eq!(mario.bg[0], [0xB5, 0xB5, 0xFF, 0xFF]);
+        assert_eq!(CompatPalette::from_title(
+            &rom_with_title(b"SUPDR MARIOLANE", NINTENDO_LICENSEE)), None);
+    }
+}
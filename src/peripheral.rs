@@ -0,0 +1,36 @@
+/// Interrupciones que piden los periféricos durante un `tick`, al terminar se
+/// activan en IF. Los bits son los de IF (`INT_VBLANK`, `INT_TIMER`...)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterruptFlags(u8);
+
+impl InterruptFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pedir las interrupciones de `mask`
+    #[inline]
+    pub fn request(&mut self, mask: u8) {
+        self.0 |= mask;
+    }
+
+    #[inline]
+    pub fn is_requested(&self, mask: u8) -> bool {
+        self.0 & mask != 0
+    }
+
+    #[inline]
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Componente que avanza al ritmo del reloj del sistema. Se conecta con
+/// `Mmu::connect_peripheral` y el MMU lo avanza después de cada instrucción
+/// con los T-cycles que tardó, para enchufar hardware propio (un sensor del
+/// cartucho, un reloj de pruebas...) sin tocar el emulador
+pub trait Peripheral: Send {
+    /// Avanzar `cycles` T-cycles, pidiendo en `irq` las interrupciones que
+    /// se generen
+    fn tick(&mut self, cycles: u32, irq: &mut InterruptFlags);
+}
@@ -0,0 +1,339 @@
+use std::sync::{Arc, Mutex};
+
+use crate::serial::SerialLink;
+
+/// Comandos del protocolo de la Game Boy Printer
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0F;
+
+/// Bits del byte de estado que responde la impresora
+pub const STATUS_CHECKSUM_ERROR: u8 = 1 << 0;
+pub const STATUS_PRINTING: u8 = 1 << 1;
+pub const STATUS_IMAGE_FULL: u8 = 1 << 2;
+pub const STATUS_UNPROCESSED: u8 = 1 << 3;
+pub const STATUS_PACKET_ERROR: u8 = 1 << 4;
+
+/// Byte con el que responde la impresora para indicar que está conectada
+const PRINTER_ALIVE: u8 = 0x81;
+
+/// La impresora imprime 160 píxeles de ancho, es decir 20 tiles
+const PRINTER_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PRINTER_WIDTH / 8;
+
+/// La memoria de la impresora admite 9 bandas de 2 filas de tiles
+const PRINTER_MAX_DATA: usize = 9 * 2 * TILES_PER_ROW * 16;
+
+/// Imagen impresa en escala de grises, un byte por píxel donde 0xFF es blanco
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl PrintedImage {
+    /// Guardar la imagen como PNG en escala de grises
+    #[cfg(feature = "image")]
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>)
+        -> std::io::Result<()>
+    {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+/// En qué parte del paquete está la impresora
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PacketState {
+    #[default]
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LenLow,
+    LenHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+#[derive(Debug, Default)]
+struct PrinterState {
+    state: PacketState,
+
+    /// Campos del paquete que se está recibiendo
+    command: u8,
+    compressed: bool,
+    len: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+
+    /// Datos de tiles recibidos desde el último INIT
+    buffer: Vec<u8>,
+
+    /// Byte de estado que se envía al final de cada paquete
+    status: u8,
+
+    /// Imágenes impresas hasta el momento
+    images: Vec<PrintedImage>,
+}
+
+impl PrinterState {
+    /// Procesar un byte del paquete devolviendo la respuesta de la impresora
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+
+        // Todos los bytes entre la cabecera y los datos entran en el checksum
+        if matches!(self.state, PacketState::Command | PacketState::Compression
+                | PacketState::LenLow | PacketState::LenHigh | PacketState::Data)
+        {
+            self.checksum = self.checksum.wrapping_add(byte as u16);
+        }
+
+        self.state = match self.state {
+            PacketState::Magic1 if byte == 0x88 => PacketState::Magic2,
+            PacketState::Magic1 => PacketState::Magic1,
+            PacketState::Magic2 if byte == 0x33 => {
+                self.checksum = 0;
+                self.data.clear();
+                PacketState::Command
+            },
+            PacketState::Magic2 => PacketState::Magic1,
+            PacketState::Command => {
+                self.command = byte;
+                PacketState::Compression
+            },
+            PacketState::Compression => {
+                self.compressed = byte & 1 != 0;
+                PacketState::LenLow
+            },
+            PacketState::LenLow => {
+                self.len = byte as u16;
+                PacketState::LenHigh
+            },
+            PacketState::LenHigh => {
+                self.len |= (byte as u16) << 8;
+                if self.len == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            },
+            PacketState::Data => {
+                self.data.push(byte);
+                if self.data.len() == self.len as usize {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            },
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHigh
+            },
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                PacketState::Alive
+            },
+            PacketState::Alive => {
+                reply = PRINTER_ALIVE;
+                PacketState::Status
+            },
+            PacketState::Status => {
+                reply = self.status;
+                self.process_packet();
+                PacketState::Magic1
+            },
+        };
+
+        reply
+    }
+
+    /// Ejecutar el comando del paquete recibido, el resultado se verá en el
+    /// byte de estado del siguiente paquete
+    fn process_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            CMD_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            },
+            CMD_DATA => {
+                let data = std::mem::take(&mut self.data);
+                if self.compressed {
+                    decompress(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(PRINTER_MAX_DATA);
+
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.buffer.len() == PRINTER_MAX_DATA {
+                    self.status |= STATUS_IMAGE_FULL;
+                }
+            },
+            CMD_PRINT => {
+                // Los datos son hojas, márgenes, paleta y exposición
+                let palette = match self.data.get(2) {
+                    Some(0) | None => 0xE4,
+                    Some(palette) => *palette,
+                };
+                let image = render(&self.buffer, palette);
+                self.images.push(image);
+                self.buffer.clear();
+
+                // La impresión se da por terminada tras el siguiente estado
+                self.status = STATUS_PRINTING;
+            },
+            CMD_STATUS => {
+                self.status &= !STATUS_PRINTING;
+            },
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+}
+
+/// Descomprimir el RLE de los paquetes de datos: un byte de control con el
+/// bit 7 a 1 repite el siguiente byte `(control & 0x7F) + 2` veces, si no le
+/// siguen `control + 1` bytes literales
+fn decompress(mut data: &[u8], out: &mut Vec<u8>) {
+    while let [control, rest @ ..] = data {
+        if control & 0x80 != 0 {
+            let Some(&value) = rest.first() else { break };
+            out.extend(std::iter::repeat_n(value, (control & 0x7F) as usize + 2));
+            data = &rest[1..];
+        } else {
+            let len = (*control as usize + 1).min(rest.len());
+            out.extend_from_slice(&rest[..len]);
+            data = &rest[len..];
+        }
+    }
+}
+
+/// Convertir los tiles 2bpp del buffer en una imagen de 160 píxeles de ancho
+fn render(buffer: &[u8], palette: u8) -> PrintedImage {
+    let tile_rows = buffer.len() / 16 / TILES_PER_ROW;
+    let height = tile_rows * 8;
+    let mut pixels = vec![0xFF; PRINTER_WIDTH * height];
+
+    for (tile_index, tile) in buffer.chunks_exact(16).enumerate() {
+        let tile_x = (tile_index % TILES_PER_ROW) * 8;
+        let tile_y = (tile_index / TILES_PER_ROW) * 8;
+        if tile_y >= height {
+            break;
+        }
+
+        for (row, bytes) in tile.chunks_exact(2).enumerate() {
+            for col in 0..8 {
+                let bit = 7 - col;
+                let color = ((bytes[0] >> bit) & 1) | (((bytes[1] >> bit) & 1) << 1);
+                let shade = (palette >> (color * 2)) & 0b11;
+                pixels[(tile_y + row) * PRINTER_WIDTH + tile_x + col] =
+                    0xFF - shade * 0x55;
+            }
+        }
+    }
+
+    PrintedImage {
+        width: PRINTER_WIDTH as u32,
+        height: height as u32,
+        pixels,
+    }
+}
+
+/// Game Boy Printer conectada al puerto serie, la Game Boy siempre genera el
+/// reloj por lo que la impresora solo responde a `exchange`. Los clones
+/// comparten el estado, así que se puede conectar uno a la MMU y recoger las
+/// imágenes impresas desde otro
+#[derive(Debug, Clone, Default)]
+pub struct Printer {
+    state: Arc<Mutex<PrinterState>>,
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sacar las imágenes impresas desde la última llamada
+    pub fn take_images(&self) -> Vec<PrintedImage> {
+        std::mem::take(&mut self.state.lock().unwrap().images)
+    }
+}
+
+impl SerialLink for Printer {
+    fn exchange(&mut self, byte: u8) -> Option<u8> {
+        Some(self.state.lock().unwrap().receive(byte))
+    }
+
+    fn offer(&mut self, _byte: u8) -> Option<u8> {
+        // La impresora nunca genera el reloj
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Enviar un paquete completo y devolver las respuestas a los dos últimos
+    /// bytes (alive y estado)
+    fn send(printer: &mut Printer, command: u8, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![0x88, 0x33, command, 0];
+        packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        packet.extend_from_slice(data);
+        let checksum = packet[2..].iter().fold(0u16, |a, b| a.wrapping_add(*b as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        let replies = packet.iter()
+            .map(|byte| printer.exchange(*byte).unwrap())
+            .collect::<Vec<_>>();
+        (replies[replies.len() - 2], replies[replies.len() - 1])
+    }
+
+    #[test]
+    fn print_one_band() {
+        let mut printer = Printer::new();
+        assert_eq!(send(&mut printer, CMD_INIT, &[]), (PRINTER_ALIVE, 0));
+
+        // Una banda entera de tiles con color 3 (negro)
+        let band = vec![0xFF; 2 * TILES_PER_ROW * 16];
+        send(&mut printer, CMD_DATA, &band);
+        send(&mut printer, CMD_DATA, &[]);
+        let (_, status) = send(&mut printer, CMD_STATUS, &[]);
+        assert_eq!(status, STATUS_UNPROCESSED);
+
+        send(&mut printer, CMD_PRINT, &[1, 0x13, 0xE4, 0x40]);
+        let (_, status) = send(&mut printer, CMD_STATUS, &[]);
+        assert_eq!(status, STATUS_PRINTING);
+
+        let images = printer.take_images();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].width, images[0].height), (160, 16));
+        assert!(images[0].pixels.iter().all(|p| *p == 0x00));
+    }
+
+    #[test]
+    fn rle_decompression() {
+        let mut out = Vec::new();
+        decompress(&[0x81, 0xAA, 0x01, 0x10, 0x20], &mut out);
+        assert_eq!(out, [0xAA, 0xAA, 0xAA, 0x10, 0x20]);
+    }
+}
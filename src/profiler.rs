@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+/// Contadores de una dirección
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hotspot {
+    /// Banco de la ROM mapeado al ejecutarla, ver `Mmu::rom_bank`
+    pub bank: u16,
+    pub pc: u16,
+
+    /// Veces que se ejecutó la instrucción
+    pub count: u64,
+
+    /// T-cycles que se pasaron en ella
+    pub cycles: u64,
+}
+
+/// Perfilador de instrucciones que se conecta con `GameBoy::set_profiler`:
+/// cuenta las ejecuciones y los T-cycles de cada dirección (por banco) y de
+/// cada opcode. Sirve tanto para buscar dónde pasa el tiempo un juego como
+/// para saber qué partes de la ROM son código
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profiler {
+    /// Ejecuciones y T-cycles por banco y dirección
+    pcs: HashMap<(u16, u16), (u64, u64)>,
+
+    /// Ejecuciones y T-cycles por opcode, los 256 primeros son los normales
+    /// y los siguientes los del prefijo CB
+    opcodes: Box<[(u64, u64); 512]>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            pcs: HashMap::new(),
+            opcodes: Box::new([(0, 0); 512]),
+        }
+    }
+
+    /// Apuntar una instrucción, `cb` es el segundo byte si el opcode es 0xCB
+    pub(crate) fn record(&mut self, bank: u16, pc: u16, opcode: u8, cb: u8, cycles: u32) {
+        let entry = self.pcs.entry((bank, pc)).or_default();
+        entry.0 += 1;
+        entry.1 += cycles as u64;
+
+        let index = match opcode {
+            0xCB => 0x100 + cb as usize,
+            _ => opcode as usize,
+        };
+        self.opcodes[index].0 += 1;
+        self.opcodes[index].1 += cycles as u64;
+    }
+
+    /// Las `n` direcciones en las que más tiempo se pasó, de más a menos
+    pub fn top_hotspots(&self, n: usize) -> Vec<Hotspot> {
+        let mut hotspots = self.hotspots().collect::<Vec<_>>();
+        hotspots.sort_by(|a, b| {
+            b.cycles.cmp(&a.cycles)
+                .then(b.count.cmp(&a.count))
+                .then((a.bank, a.pc).cmp(&(b.bank, b.pc)))
+        });
+        hotspots.truncate(n);
+        hotspots
+    }
+
+    /// Todas las direcciones ejecutadas alguna vez, sin orden
+    pub fn hotspots(&self) -> impl Iterator<Item = Hotspot> + '_ {
+        self.pcs.iter().map(|(&(bank, pc), &(count, cycles))| {
+            Hotspot { bank, pc, count, cycles }
+        })
+    }
+
+    /// Se ejecutó alguna vez la dirección
+    pub fn is_covered(&self, bank: u16, pc: u16) -> bool {
+        self.pcs.contains_key(&(bank, pc))
+    }
+
+    /// Ejecuciones y T-cycles de un opcode
+    pub fn opcode(&self, opcode: u8) -> (u64, u64) {
+        self.opcodes[opcode as usize]
+    }
+
+    /// Ejecuciones y T-cycles de un opcode del prefijo CB
+    pub fn cb_opcode(&self, opcode: u8) -> (u64, u64) {
+        self.opcodes[0x100 + opcode as usize]
+    }
+
+    /// Volver a empezar a contar
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoy;
+
+    #[test]
+    fn count_hotspots() {
+        // NOP y un bucle infinito con JR
+        let mut rom = vec![0; 0x8000];
+        rom[0x101..0x103].copy_from_slice(&[0x18, 0xFE]);
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+        gb.set_profiler(Some(Profiler::new()));
+        gb.run_cycles(4 + 8 * 10);
+
+        let profiler = gb.profiler().unwrap();
+        assert_eq!(profiler.top_hotspots(5), [
+            Hotspot { bank: 0, pc: 0x0101, count: 10, cycles: 80 },
+            Hotspot { bank: 0, pc: 0x0100, count: 1, cycles: 4 },
+        ]);
+        assert_eq!(profiler.opcode(0x18), (10, 80));
+        assert!(profiler.is_covered(0, 0x0100));
+        assert!(!profiler.is_covered(0, 0x0102));
+    }
+}
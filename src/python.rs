@@ -0,0 +1,135 @@
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::frame::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::GameBoy;
+use crate::joypad::Button;
+use crate::mmu::Addr;
+
+/// Botones por el nombre que se usa desde Python
+const BUTTONS: [(&str, Button); 8] = [("right", Button::Right), ("left", Button::Left),
+    ("up", Button::Up), ("down", Button::Down), ("a", Button::A), ("b", Button::B),
+    ("select", Button::Select), ("start", Button::Start)];
+
+fn button(name: &str) -> PyResult<Button> {
+    BUTTONS.iter()
+        .find(|(button, _)| button.eq_ignore_ascii_case(name))
+        .map(|&(_, button)| button)
+        .ok_or_else(|| PyValueError::new_err(format!("Botón desconocido: {name}")))
+}
+
+/// La `GameBoy` exportada a Python, pensada para agentes de aprendizaje por
+/// refuerzo y scripts de automatización:
+///
+/// ```python
+/// gb = gameboi.GameBoi(open("tetris.gb", "rb").read())
+/// gb.button("start", True)
+/// gb.run_frame(4)
+/// screen = np.frombuffer(gb.framebuffer(), np.uint8).reshape(gb.shape)
+/// lines = gb.peek(0xFF44)
+/// ```
+#[pyclass(name = "GameBoi", module = "gameboi", unsendable)]
+pub struct PyGameBoi {
+    gb: GameBoy,
+}
+
+#[pymethods]
+impl PyGameBoi {
+    #[new]
+    fn new(rom: &[u8]) -> PyResult<Self> {
+        let mut gb = GameBoy::new();
+        gb.load_rom(rom).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(Self { gb })
+    }
+
+    /// Emular `frames` frames completos, uno por defecto. Para saltar frames
+    /// entre acciones sin cruzar a Python en cada uno
+    #[pyo3(signature = (frames = 1))]
+    fn run_frame(&mut self, frames: u32) -> PyResult<()> {
+        for _ in 0..frames {
+            self.gb.step_frame().map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Píxeles RGBA del último frame, una copia de `shape` bytes que se pasa
+    /// tal cual a `np.frombuffer`
+    fn framebuffer<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.gb.frame().pixels())
+    }
+
+    /// Forma del framebuffer como array de numpy: filas, columnas y canales
+    #[getter]
+    fn shape(&self) -> (usize, usize, usize) {
+        (SCREEN_HEIGHT, SCREEN_WIDTH, 4)
+    }
+
+    /// Frames emulados desde el arranque
+    #[getter]
+    fn frame_count(&self) -> u64 {
+        self.gb.frame_count()
+    }
+
+    /// Leer un byte del bus como lo vería la CPU
+    fn peek(&self, addr: u16) -> u8 {
+        self.gb.mmu().read_word(Addr(addr))
+    }
+
+    /// Escribir un byte en el bus como lo haría la CPU, con los efectos de
+    /// escribir en los registros de entrada y salida
+    fn poke(&mut self, addr: u16, value: u8) {
+        self.gb.mmu_mut().write_word(Addr(addr), value);
+    }
+
+    /// Pulsar o soltar un botón por su nombre: "right", "left", "up",
+    /// "down", "a", "b", "select" o "start"
+    fn button(&mut self, name: &str, pressed: bool) -> PyResult<()> {
+        self.gb.set_button(button(name)?, pressed);
+        Ok(())
+    }
+
+    /// Soltar todos los botones
+    fn release_all(&mut self) {
+        for button in Button::ALL {
+            self.gb.set_button(button, false);
+        }
+    }
+}
+
+/// Módulo `gameboi` de Python, se compila con `maturin develop`, que activa
+/// el feature desde `pyproject.toml`
+#[pymodule]
+fn gameboi(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyGameBoi>()?;
+    module.add("SCREEN_WIDTH", SCREEN_WIDTH)?;
+    module.add("SCREEN_HEIGHT", SCREEN_HEIGHT)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn python_api() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut rom = vec![0; 0x8000];
+            rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+            let mut gb = PyGameBoi::new(&rom).unwrap();
+            gb.run_frame(3).unwrap();
+            assert_eq!(gb.frame_count(), 3);
+
+            let (height, width, channels) = gb.shape();
+            assert_eq!(gb.framebuffer(py).as_bytes().len(), height * width * channels);
+
+            gb.poke(0xC000, 0x42);
+            assert_eq!(gb.peek(0xC000), 0x42);
+
+            gb.button("Start", true).unwrap();
+            assert!(gb.button("turbo", true).is_err());
+            assert!(PyGameBoi::new(&[0; 0x8001]).is_err());
+        });
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::doctor::doctor_line;
+use crate::gameboy::GameBoy;
+use crate::watch::wide_value;
+use crate::Reg;
+
+/// Dato de la CPU que aparece en una columna de la traza de referencia
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraceField {
+    A, F, B, C, D, E, H, L,
+    AF, BC, DE, HL,
+    Sp,
+    Pc,
+}
+
+impl TraceField {
+    fn value(self, gb: &GameBoy) -> u16 {
+        let cpu = gb.cpu();
+        let reg = match self {
+            TraceField::A => Reg::A,
+            TraceField::F => Reg::F,
+            TraceField::B => Reg::B,
+            TraceField::C => Reg::C,
+            TraceField::D => Reg::D,
+            TraceField::E => Reg::E,
+            TraceField::H => Reg::H,
+            TraceField::L => Reg::L,
+            TraceField::AF => return wide_value(cpu, Reg::AF),
+            TraceField::BC => return wide_value(cpu, Reg::BC),
+            TraceField::DE => return wide_value(cpu, Reg::DE),
+            TraceField::HL => return wide_value(cpu, Reg::HL),
+            TraceField::Sp => return wide_value(cpu, Reg::SP),
+            TraceField::Pc => return cpu.pc(),
+        };
+        cpu.read_reg(reg) as u16
+    }
+}
+
+/// Dónde se encuentra una columna en cada línea
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Column {
+    /// Token `CLAVE:valor` o `CLAVE=valor` en cualquier posición
+    Key(String),
+
+    /// Token en la posición dada, contando desde 0
+    Position(usize),
+}
+
+/// Formato de las líneas de la traza de otro emulador: qué columnas
+/// comparar y dónde están. Los valores se leen en hexadecimal, con o sin
+/// `$` o `0x` delante, y los tokens se separan por espacios o comas
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceFormat {
+    columns: Vec<(Column, TraceField)>,
+}
+
+impl TraceFormat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// El formato de Gameboy Doctor, que también saben escribir muchos
+    /// emuladores: `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100`
+    pub fn doctor() -> Self {
+        [("A", TraceField::A), ("F", TraceField::F), ("B", TraceField::B),
+            ("C", TraceField::C), ("D", TraceField::D), ("E", TraceField::E),
+            ("H", TraceField::H), ("L", TraceField::L), ("SP", TraceField::Sp),
+            ("PC", TraceField::Pc)]
+            .into_iter()
+            .fold(Self::new(), |format, (key, field)| format.key(key, field))
+    }
+
+    /// Columna `key:valor` o `key=valor`, la clave no distingue mayúsculas
+    pub fn key(mut self, key: &str, field: TraceField) -> Self {
+        self.columns.push((Column::Key(key.to_ascii_uppercase()), field));
+        self
+    }
+
+    /// Columna sin clave en la posición `index` de la línea
+    pub fn position(mut self, index: usize, field: TraceField) -> Self {
+        self.columns.push((Column::Position(index), field));
+        self
+    }
+
+    /// Valores de las columnas de una línea, `None` si falta alguna o no es
+    /// un número, las líneas así se saltan
+    pub fn parse_line(&self, line: &str) -> Option<Vec<(TraceField, u16)>> {
+        let tokens = line.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .collect::<Vec<_>>();
+        self.columns.iter()
+            .map(|(column, field)| {
+                let text = match column {
+                    Column::Position(index) => *tokens.get(*index)?,
+                    Column::Key(key) => tokens.iter().find_map(|token| {
+                        let (name, value) = token.split_once([':', '='])?;
+                        name.eq_ignore_ascii_case(key).then_some(value)
+                    })?,
+                };
+                Some((*field, parse_hex(text)?))
+            })
+            .collect()
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let hex = text.strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Primera línea de la traza de referencia que no coincide con nuestra
+/// ejecución
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceDivergence {
+    /// Línea de la traza, contando desde 1
+    pub line: usize,
+
+    /// Instrucciones ejecutadas antes de divergir
+    pub step: u64,
+
+    /// Primera columna distinta con el valor esperado y el nuestro, `None`
+    /// si nuestra CPU encontró un opcode inválido
+    pub field: Option<(TraceField, u16, u16)>,
+
+    /// Línea de la referencia y la nuestra anteriores, donde aún coincidían
+    pub previous: Option<(String, String)>,
+
+    pub expected: String,
+
+    /// Nuestro estado en formato de Gameboy Doctor
+    pub actual: String,
+}
+
+impl fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Divergencia en la línea {} tras {} instrucciones: ", self.line, self.step)?;
+        match self.field {
+            Some((field, expected, actual)) => {
+                writeln!(f, "{field:?} = {actual:#X}, se esperaba {expected:#X}")?
+            },
+            None => writeln!(f, "opcode inválido")?,
+        }
+        if let Some((expected, actual)) = &self.previous {
+            writeln!(f, "anterior ref: {expected}")?;
+            writeln!(f, "anterior gb:  {actual}")?;
+        }
+        writeln!(f, "ref: {}", self.expected)?;
+        write!(f, "gb:  {}", self.actual)
+    }
+}
+
+/// Comparar la ejecución de `gb` con la traza de otro emulador, cada línea
+/// es el estado antes de ejecutar una instrucción. Tras comprobar cada
+/// línea se ejecuta una instrucción, hasta que se acaba la traza (`Ok(None)`)
+/// o se encuentra la primera divergencia
+pub fn compare_trace<R: BufRead>(gb: &mut GameBoy, reference: R,
+    format: &TraceFormat) -> io::Result<Option<TraceDivergence>>
+{
+    let mut previous = None;
+    let mut step = 0;
+    for (index, line) in reference.lines().enumerate() {
+        let line = line?;
+        let Some(values) = format.parse_line(&line) else {
+            continue;
+        };
+        let actual = doctor_line(gb.cpu(), gb.mmu());
+        let field = values.iter()
+            .find(|(field, value)| field.value(gb) != *value)
+            .map(|&(field, value)| (field, value, field.value(gb)));
+        if field.is_some() {
+            return Ok(Some(TraceDivergence {
+                line: index + 1,
+                step,
+                field,
+                previous,
+                expected: line,
+                actual,
+            }));
+        }
+
+        if gb.step().is_err() {
+            return Ok(Some(TraceDivergence {
+                line: index + 1,
+                step,
+                field: None,
+                previous,
+                expected: line,
+                actual,
+            }));
+        }
+        step += 1;
+        previous = Some((line, actual));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_with_reference() {
+        // LD B, $12 y luego NOPs
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x06, 0x12]);
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+
+        let format = TraceFormat::new()
+            .position(0, TraceField::Pc)
+            .key("bc", TraceField::BC);
+        assert_eq!(format.parse_line("$0150 AF=01B0 BC=0013"),
+            Some(vec![(TraceField::Pc, 0x150), (TraceField::BC, 0x13)]));
+        assert_eq!(format.parse_line("sin columnas"), None);
+
+        let trace = "0100 BC=0013\n\
+            ; comentario\n\
+            0102 BC=1213\n\
+            0103 BC=1213\n\
+            0105 BC=1213\n";
+        let divergence = compare_trace(&mut gb, trace.as_bytes(), &format).unwrap().unwrap();
+        assert_eq!(divergence.line, 5);
+        assert_eq!(divergence.step, 3);
+        assert_eq!(divergence.field, Some((TraceField::Pc, 0x105, 0x104)));
+        assert_eq!(divergence.previous.unwrap().0, "0103 BC=1213");
+        assert!(divergence.actual.contains("PC:0104"));
+    }
+}
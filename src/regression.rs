@@ -0,0 +1,226 @@
+use std::fs;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use crate::batch::run_batch;
+use crate::gameboy::GameBoy;
+use crate::model::Model;
+use crate::serial::SerialCapture;
+
+/// Cómo terminó una ROM de `run_regression`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RomStatus {
+    /// Llegó a los frames pedidos
+    Completed,
+
+    /// No se pudo leer o no se pudo cargar, por ejemplo por usar un mapper
+    /// no soportado
+    LoadError(String),
+
+    /// La emulación devolvió un error o hizo panic en el frame `frame`
+    Crashed { frame: u32, error: String },
+}
+
+/// Resultado de una ROM de `run_regression`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomResult {
+    /// Nombre del fichero dentro del directorio
+    pub rom: String,
+    pub status: RomStatus,
+
+    /// Frames completados
+    pub frames: u32,
+
+    /// `Frame::shade_hash` del último frame, `None` sin la feature `ppu` o
+    /// si no se llegó a ejecutar
+    pub frame_hash: Option<u64>,
+
+    /// Lo que imprimió por el puerto serie
+    pub serial: String,
+}
+
+/// Resultados de `run_regression` ordenados por el nombre de la ROM, para
+/// que los informes de dos versiones del crate se puedan comparar con `diff`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegressionReport {
+    pub results: Vec<RomResult>,
+}
+
+impl RegressionReport {
+    /// ROMs que llegaron al final sin errores
+    pub fn completed(&self) -> usize {
+        self.results.iter()
+            .filter(|result| result.status == RomStatus::Completed)
+            .count()
+    }
+
+    /// Escribir el informe como CSV con una ROM por línea. Los saltos de
+    /// línea de la salida serie se escriben como `\n` para que cada ROM
+    /// ocupe una sola línea
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "rom,status,frames,frame_hash,serial")?;
+        for result in &self.results {
+            let status = match &result.status {
+                RomStatus::Completed => "completed".to_string(),
+                RomStatus::LoadError(err) => format!("load error: {err}"),
+                RomStatus::Crashed { frame, error } => format!("crashed at {frame}: {error}"),
+            };
+            let hash = result.frame_hash.map(|hash| format!("{hash:016x}")).unwrap_or_default();
+            writeln!(writer, "{},{},{},{hash},{}",
+                csv_field(&result.rom), csv_field(&status), result.frames,
+                csv_field(&result.serial))?;
+        }
+        writer.flush()
+    }
+
+    /// El informe en JSON
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("El informe siempre se puede serializar")
+    }
+}
+
+/// Entrecomillar un campo de CSV, escapando las comillas y los saltos de
+/// línea
+fn csv_field(text: &str) -> String {
+    let text = text.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Ejecutar sin pantalla cada ROM (`.gb` y `.gbc`) de `dir` durante `frames`
+/// frames en el modelo que prefiera su cabecera, repartiéndolas entre
+/// `threads` hilos como `run_batch`, y recoger el hash del último frame, la
+/// salida serie y si terminó o falló. Un panic de la emulación se recoge
+/// como fallo de esa ROM, aunque el hook de panic lo siga mostrando. Falla
+/// si no se puede leer el directorio
+pub fn run_regression(dir: impl AsRef<Path>, frames: u32, threads: usize)
+    -> io::Result<RegressionReport>
+{
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_rom = path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gb") || ext.eq_ignore_ascii_case("gbc"));
+        if is_rom && path.is_file() {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+
+    let mut results = Vec::new();
+    let mut instances = Vec::new();
+    for path in roms {
+        let rom = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let loaded = fs::read(&path).map_err(|err| err.to_string()).and_then(|data| {
+            GameBoy::builder()
+                .model(Model::preferred_for(&data))
+                .rom(data)
+                .build()
+                .map_err(|err| err.to_string())
+        });
+        let mut result = RomResult {
+            rom,
+            status: RomStatus::Completed,
+            frames: 0,
+            frame_hash: None,
+            serial: String::new(),
+        };
+        match loaded {
+            Ok(mut gb) => {
+                let capture = SerialCapture::new();
+                gb.mmu_mut().connect_link(Box::new(capture.clone()));
+                instances.push(gb);
+                results.push((result, Some(capture)));
+            },
+            Err(err) => {
+                result.status = RomStatus::LoadError(err);
+                results.push((result, None));
+            },
+        }
+    }
+
+    let mut runs = run_batch(instances, threads, |gb| run_rom(gb, frames)).into_iter();
+    let results = results.into_iter()
+        .map(|(mut result, capture)| {
+            if let Some(capture) = capture {
+                let (status, frames, frame_hash) = runs.next().expect("Una ejecución por ROM cargada");
+                result.status = status;
+                result.frames = frames;
+                result.frame_hash = frame_hash;
+                result.serial = capture.output();
+            }
+            result
+        })
+        .collect();
+    Ok(RegressionReport { results })
+}
+
+fn run_rom(mut gb: GameBoy, frames: u32) -> (RomStatus, u32, Option<u64>) {
+    let mut done = 0;
+    let run = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), crate::error::Error> {
+        while done < frames {
+            gb.step_frame()?;
+            done += 1;
+        }
+        Ok(())
+    }));
+    let status = match run {
+        Ok(Ok(())) => RomStatus::Completed,
+        Ok(Err(err)) => RomStatus::Crashed { frame: done, error: err.to_string() },
+        Err(payload) => {
+            let error = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".into());
+            RomStatus::Crashed { frame: done, error }
+        },
+    };
+
+    #[cfg(feature = "ppu")]
+    let hash = (done > 0).then(|| gb.frame().shade_hash());
+    #[cfg(not(feature = "ppu"))]
+    let hash = None;
+    (status, done, hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regression_report() {
+        let dir = std::env::temp_dir()
+            .join(format!("gameboi-regression-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // JR -2 en el punto de entrada
+        let mut spin = vec![0; 0x8000];
+        spin[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        fs::write(dir.join("spin.gb"), &spin).unwrap();
+        // Opcode inválido en el punto de entrada
+        spin[0x0100] = 0xD3;
+        fs::write(dir.join("invalid.gbc"), &spin).unwrap();
+        fs::write(dir.join("huge.gb"), vec![0; 0x10000]).unwrap();
+        fs::write(dir.join("notes.txt"), "no es una ROM").unwrap();
+
+        let report = run_regression(&dir, 3, 2).unwrap();
+        let names = report.results.iter().map(|result| result.rom.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["huge.gb", "invalid.gbc", "spin.gb"]);
+        assert!(matches!(report.results[0].status, RomStatus::LoadError(_)));
+        assert!(matches!(report.results[1].status, RomStatus::Crashed { frame: 0, .. }));
+        assert_eq!(report.results[2].status, RomStatus::Completed);
+        assert_eq!(report.results[2].frames, 3);
+        assert_eq!(report.completed(), 1);
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(3).unwrap().starts_with("\"spin.gb\",\"completed\",3,"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
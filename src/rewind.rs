@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+
+use crate::debugger::CallFrame;
+use crate::joypad::Button;
+
+/// Snapshot tomado después de ejecutar `at` instrucciones
+pub(crate) struct Snapshot {
+    pub at: u64,
+    pub state: Vec<u8>,
+
+    /// La pila de llamadas del depurador no forma parte del save state
+    pub call_stack: Option<Vec<CallFrame>>,
+}
+
+/// Historial para volver atrás en el tiempo, se conecta con
+/// `GameBoy::set_rewind`. Cada `interval` instrucciones se guarda un save
+/// state y entre medias se apuntan los botones pulsados con
+/// `GameBoy::set_button`, así `GameBoy::step_back` puede cargar el snapshot
+/// anterior y reejecutar hasta la instrucción previa obteniendo exactamente
+/// el mismo estado. Lo que entra por otros caminos (el cable link,
+/// `Autofire` o escribir directamente en la MMU) no se reproduce
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+
+    /// Botones pulsados o soltados después de ejecutar tantas instrucciones
+    inputs: Vec<(u64, Button, bool)>,
+}
+
+impl Rewind {
+    /// Guardar un snapshot cada `interval` instrucciones y conservar los
+    /// `capacity` más recientes, un intervalo más corto gasta más memoria
+    /// pero vuelve atrás más rápido
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            snapshots: VecDeque::with_capacity(capacity),
+            inputs: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Cuántos snapshots se conservan como mucho
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Número de snapshots guardados
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Instrucción más antigua a la que se puede volver
+    pub fn oldest(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.at)
+    }
+
+    /// Toca guardar un snapshot tras `at` instrucciones
+    #[inline]
+    pub(crate) fn is_due(&self, at: u64) -> bool {
+        at.is_multiple_of(self.interval)
+            && self.snapshots.back().is_none_or(|snapshot| snapshot.at < at)
+    }
+
+    pub(crate) fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+            // Las entradas anteriores al snapshot más antiguo ya no se usan
+            let oldest = self.oldest().unwrap_or(snapshot.at);
+            self.inputs.retain(|(at, ..)| *at >= oldest);
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub(crate) fn record_input(&mut self, at: u64, button: Button, pressed: bool) {
+        self.inputs.push((at, button, pressed));
+    }
+
+    /// Snapshot más reciente desde el que se puede llegar a `target`
+    pub(crate) fn snapshot_before(&self, target: u64) -> Option<&Snapshot> {
+        self.snapshots.iter().rev().find(|snapshot| snapshot.at <= target)
+    }
+
+    /// Entradas hechas entre las instrucciones `from` y `to`, sin incluir
+    /// las hechas después de `to`
+    pub(crate) fn inputs_between(&self, from: u64, to: u64) -> Vec<(u64, Button, bool)> {
+        self.inputs.iter()
+            .filter(|(at, ..)| (from..to).contains(at))
+            .copied()
+            .collect()
+    }
+
+    /// Olvidar el futuro de `at` al volver a él, a partir de ahí la
+    /// ejecución puede ser distinta
+    pub(crate) fn truncate(&mut self, at: u64) {
+        while self.snapshots.back().is_some_and(|snapshot| snapshot.at > at) {
+            self.snapshots.pop_back();
+        }
+        self.inputs.retain(|(time, ..)| *time < at);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+
+use crate::error::Error;
+use crate::gameboy::GameBoy;
+use crate::serial::{PairedLink, PairedState};
+
+/// Versión del protocolo, los paquetes de otra versión se ignoran
+const PROTOCOL_VERSION: u8 = 1;
+
+/// Frames que se puede adelantar la simulación al último confirmado del otro
+/// jugador, a partir de ahí `advance_frame` espera
+pub const MAX_ROLLBACK_FRAMES: usize = 8;
+
+/// Entradas que caben como mucho en un paquete
+const MAX_PACKET_INPUTS: usize = 64;
+
+/// Cabecera de los paquetes: versión, frames confirmados del otro extremo,
+/// primer frame que se envía y número de entradas
+const HEADER_LEN: usize = 1 + 4 + 4 + 1;
+
+/// Estado de las dos consolas y del cable al inicio de un frame
+struct Snapshot {
+    consoles: [Vec<u8>; 2],
+    cable: PairedState,
+}
+
+/// Sesión de juego a dos por cable link a través de internet con rollback
+///
+/// Cada máquina simula las dos Game Boy conectadas por un cable dentro del
+/// proceso y solo se intercambian por UDP los botones de cada frame. La
+/// entrada del otro jugador que todavía no ha llegado se predice repitiendo
+/// la última conocida; si al llegar no coincide se vuelve al estado del
+/// frame equivocado y se resimula hasta el actual. Las dos consolas tienen
+/// que empezar igual en las dos máquinas (misma ROM y `GameBoy::with_seed`
+/// con la misma semilla), el resto lo garantiza el determinismo
+///
+/// Cada paquete repite todas las entradas locales que el otro extremo aún no
+/// ha confirmado, así que los paquetes perdidos no hace falta reenviarlos
+pub struct RollbackSession {
+    consoles: [GameBoy; 2],
+    cable: PairedLink,
+    socket: UdpSocket,
+
+    /// Consola que controla este extremo, 0 o 1
+    local: usize,
+
+    /// Entrada local de cada frame simulado, en el formato de
+    /// `Joypad::state`
+    local_inputs: Vec<u8>,
+
+    /// Entradas del otro jugador confirmadas, siempre consecutivas desde el
+    /// frame 0
+    remote_inputs: Vec<u8>,
+
+    /// Frames simulados con la entrada confirmada del otro jugador, ya no
+    /// pueden cambiar
+    verified: usize,
+
+    /// Entrada del otro jugador con la que se simuló cada frame desde
+    /// `verified`
+    predicted: VecDeque<u8>,
+
+    /// Entradas locales que el otro extremo ya tiene
+    peer_ack: usize,
+
+    /// Estado al inicio de cada frame desde `verified`
+    snapshots: VecDeque<Snapshot>,
+    rollbacks: u64,
+}
+
+impl RollbackSession {
+    /// Empezar una sesión sobre `socket`, que ya tiene que estar asociado a
+    /// un puerto local, con el otro jugador en `peer`. `consoles` son las
+    /// dos Game Boy en el orden de los jugadores y `local` la que controla
+    /// este extremo. Se conectan entre sí con un cable nuevo
+    ///
+    /// Entra en pánico si `local` no es 0 ni 1
+    pub fn new(
+        mut consoles: [GameBoy; 2],
+        local: usize,
+        socket: UdpSocket,
+        peer: impl ToSocketAddrs,
+    ) -> io::Result<Self> {
+        assert!(local < 2, "player {local} out of range");
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+
+        let (a, b) = PairedLink::pair();
+        let cable = a.clone();
+        consoles[0].mmu_mut().connect_link(Box::new(a));
+        consoles[1].mmu_mut().connect_link(Box::new(b));
+
+        Ok(Self {
+            consoles,
+            cable,
+            socket,
+            local,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            verified: 0,
+            predicted: VecDeque::new(),
+            peer_ack: 0,
+            snapshots: VecDeque::new(),
+            rollbacks: 0,
+        })
+    }
+
+    /// Simular el siguiente frame con `input` (en el formato de
+    /// `Joypad::state`) como entrada local. Si el otro jugador va demasiado
+    /// atrasado no se avanza y devuelve `false`, el frontend debe volver a
+    /// llamar en el siguiente frame con la misma entrada. No espera a
+    /// velocidad real, de eso se encarga el frontend
+    pub fn advance_frame(&mut self, input: u8) -> Result<bool, Error> {
+        self.poll()?;
+        if self.frame().saturating_sub(self.confirmed_frame()) >= MAX_ROLLBACK_FRAMES {
+            return Ok(false);
+        }
+
+        let frame = self.frame();
+        self.local_inputs.push(input);
+        self.simulate(frame)?;
+        self.confirm();
+        self.send();
+        Ok(true)
+    }
+
+    /// Recibir las entradas que hayan llegado, resimulando si alguna no era
+    /// la prevista, y enviar las locales pendientes. `advance_frame` ya lo
+    /// llama, pero mientras se espera al otro jugador hay que seguir
+    /// llamándolo
+    pub fn poll(&mut self) -> Result<(), Error> {
+        self.receive();
+
+        // Primer frame simulado con una entrada distinta de la que llegó
+        let verified = self.verified;
+        let mispredicted = (verified..self.confirmed_frame().min(self.frame()))
+            .find(|&frame| self.predicted[frame - verified] != self.remote_inputs[frame]);
+        if let Some(frame) = mispredicted {
+            self.rollback(frame)?;
+        }
+        self.confirm();
+        self.send();
+        Ok(())
+    }
+
+    /// Frames simulados
+    #[inline]
+    pub fn frame(&self) -> usize {
+        self.local_inputs.len()
+    }
+
+    /// Frames de los que ya se tiene la entrada del otro jugador, hasta aquí
+    /// (o hasta `frame` si va por detrás) la simulación es definitiva
+    #[inline]
+    pub fn confirmed_frame(&self) -> usize {
+        self.remote_inputs.len()
+    }
+
+    /// Veces que hubo que resimular por una predicción equivocada
+    #[inline]
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    #[inline]
+    pub fn local_player(&self) -> usize {
+        self.local
+    }
+
+    /// Consola del jugador `player`, en el estado del último frame simulado
+    #[inline]
+    pub fn console(&self, player: usize) -> &GameBoy {
+        &self.consoles[player]
+    }
+
+    pub fn into_consoles(self) -> [GameBoy; 2] {
+        self.consoles
+    }
+
+    /// Simular el frame `frame`, que tiene que ser el siguiente, con la
+    /// entrada del otro jugador si ya llegó o una predicción si no. Antes se
+    /// guarda el estado para poder volver a él
+    fn simulate(&mut self, frame: usize) -> Result<(), Error> {
+        // Sin más información los botones suelen mantenerse varios frames
+        let remote = match self.remote_inputs.get(frame) {
+            Some(&remote) => remote,
+            None => self.remote_inputs.last().copied().unwrap_or(0),
+        };
+        self.predicted.push_back(remote);
+        self.snapshots.push_back(Snapshot {
+            consoles: [self.consoles[0].save_state(), self.consoles[1].save_state()],
+            cable: self.cable.save(),
+        });
+
+        let mut inputs = [remote; 2];
+        inputs[self.local] = self.local_inputs[frame];
+        for (gb, input) in self.consoles.iter_mut().zip(inputs) {
+            gb.mmu_mut().set_joypad_state(input);
+        }
+
+        // Las dos consolas avanzan intercaladas por su reloj para que las
+        // transferencias por el cable ocurran siempre en el mismo punto
+        let targets = self.consoles.each_ref().map(|gb| gb.frame_count() + 1);
+        while let Some(next) = (0..2)
+            .filter(|&i| self.consoles[i].frame_count() < targets[i])
+            .min_by_key(|&i| self.consoles[i].mmu().now())
+        {
+            self.consoles[next].step()?;
+        }
+        Ok(())
+    }
+
+    /// Volver al inicio de `frame` y resimular hasta el frame actual
+    fn rollback(&mut self, frame: usize) -> Result<(), Error> {
+        self.rollbacks += 1;
+        let index = frame - self.verified;
+        let snapshot = &self.snapshots[index];
+        for (gb, state) in self.consoles.iter_mut().zip(&snapshot.consoles) {
+            gb.load_state(state)?;
+        }
+        self.cable.restore(snapshot.cable);
+        self.snapshots.truncate(index);
+        self.predicted.truncate(index);
+
+        for frame in frame..self.frame() {
+            self.simulate(frame)?;
+        }
+        Ok(())
+    }
+
+    /// Descartar los estados de los frames que ya no pueden cambiar
+    fn confirm(&mut self) {
+        let verified = self.confirmed_frame().min(self.frame());
+        let done = verified.saturating_sub(self.verified);
+        self.snapshots.drain(..done);
+        self.predicted.drain(..done);
+        self.verified = self.verified.max(verified);
+    }
+
+    /// Leer todos los paquetes que hayan llegado
+    fn receive(&mut self) {
+        let mut packet = [0; HEADER_LEN + MAX_PACKET_INPUTS];
+        // Los errores (el otro extremo aún no escucha, por ejemplo) se
+        // tratan como paquetes perdidos
+        while let Ok(len) = self.socket.recv(&mut packet) {
+            let Some((ack, start, inputs)) = parse_packet(&packet[..len]) else {
+                continue;
+            };
+            self.peer_ack = self.peer_ack.max(ack);
+            let known = self.remote_inputs.len();
+            if start <= known && start + inputs.len() > known {
+                self.remote_inputs.extend_from_slice(&inputs[known - start..]);
+            }
+        }
+    }
+
+    /// Enviar las entradas locales que el otro extremo no ha confirmado
+    fn send(&mut self) {
+        let start = self.peer_ack.min(self.local_inputs.len());
+        let inputs = &self.local_inputs[start..];
+        let inputs = &inputs[..inputs.len().min(MAX_PACKET_INPUTS)];
+
+        let mut packet = Vec::with_capacity(HEADER_LEN + inputs.len());
+        packet.push(PROTOCOL_VERSION);
+        packet.extend_from_slice(&(self.remote_inputs.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&(start as u32).to_le_bytes());
+        packet.push(inputs.len() as u8);
+        packet.extend_from_slice(inputs);
+        let _ = self.socket.send(&packet);
+    }
+}
+
+/// Frames confirmados del otro extremo, primer frame de las entradas y las
+/// entradas, `None` si el paquete no es válido
+fn parse_packet(packet: &[u8]) -> Option<(usize, usize, &[u8])> {
+    let (header, inputs) = packet.split_at_checked(HEADER_LEN)?;
+    if header[0] != PROTOCOL_VERSION || header[9] as usize != inputs.len() {
+        return None;
+    }
+    let ack = u32::from_le_bytes(header[1..5].try_into().unwrap());
+    let start = u32::from_le_bytes(header[5..9].try_into().unwrap());
+    Some((ack as usize, start as usize, inputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn consoles() -> [GameBoy; 2] {
+        // JR -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        [(); 2].map(|_| {
+            let mut gb = GameBoy::with_seed(7);
+            gb.load_rom(&rom).unwrap();
+            gb
+        })
+    }
+
+    fn input(player: usize, frame: usize) -> u8 {
+        (frame / (3 + player)) as u8 & 0x0F
+    }
+
+    #[test]
+    fn sessions_converge() {
+        let socket_a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket_b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr_a = socket_a.local_addr().unwrap();
+        let addr_b = socket_b.local_addr().unwrap();
+        let mut a = RollbackSession::new(consoles(), 0, socket_a, addr_b).unwrap();
+        let mut b = RollbackSession::new(consoles(), 1, socket_b, addr_a).unwrap();
+
+        // `a` va por delante prediciendo las entradas de `b`, y se equivoca
+        for frame in 0..4 {
+            assert!(a.advance_frame(input(0, frame)).unwrap());
+        }
+        for frame in 0..30 {
+            assert!(b.advance_frame(input(1, frame)).unwrap());
+            if frame + 4 < 30 {
+                assert!(a.advance_frame(input(0, frame + 4)).unwrap());
+            }
+        }
+        for _ in 0..1000 {
+            if a.confirmed_frame() == 30 && b.confirmed_frame() == 30 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+            a.poll().unwrap();
+            b.poll().unwrap();
+        }
+
+        assert_eq!((a.confirmed_frame(), b.confirmed_frame()), (30, 30));
+        assert!(a.rollbacks() > 0);
+        for player in 0..2 {
+            assert_eq!(a.console(player).save_state(), b.console(player).save_state());
+            assert_eq!(a.console(player).joypad().state(), input(player, 29));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::error::Error;
+
+/// Un volcado conocido de la base de datos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomEntry {
+    /// Nombre canónico, por ejemplo `Tetris (World) (Rev 1)`
+    pub name: String,
+
+    /// Lo que hay en el primer paréntesis del nombre, en los DATs de
+    /// No-Intro es la región: `World`, `USA, Europe`, `Japan`...
+    pub region: Option<String>,
+
+    pub size: u64,
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+
+    /// Volcado marcado como malo (`status="baddump"` o `[b]` en el nombre),
+    /// es normal que no funcione bien
+    pub bad_dump: bool,
+}
+
+/// Base de datos de volcados buscable por SHA1 o por CRC32 y tamaño, para
+/// mostrar nombres canónicos en las listas de juegos de los frontends. Se
+/// carga de DATs en el formato XML de No-Intro
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    entries: Vec<RomEntry>,
+    by_sha1: HashMap<[u8; 20], usize>,
+    by_crc32: HashMap<(u32, u64), usize>,
+}
+
+impl RomDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leer un DAT de No-Intro, falla si alguna ROM no tiene un tamaño, CRC32
+    /// o SHA1 válidos
+    pub fn from_dat(dat: &str) -> Result<Self, Error> {
+        let mut db = Self::new();
+        for game in dat.split("<game ").skip(1) {
+            let game = game.split("</game>").next().unwrap_or_default();
+            let name = attribute(game, "name")
+                .ok_or_else(|| Error::RomDatabase("juego sin nombre".into()))?;
+
+            for rom in game.split("<rom ").skip(1) {
+                let rom = rom.split('>').next().unwrap_or_default();
+                let invalid = |field| Error::RomDatabase(format!("{field} inválido en {name}"));
+                let size = attribute(rom, "size").and_then(|size| size.parse().ok())
+                    .ok_or_else(|| invalid("size"))?;
+                let crc32 = attribute(rom, "crc")
+                    .and_then(|crc| u32::from_str_radix(&crc, 16).ok())
+                    .ok_or_else(|| invalid("crc"))?;
+                let sha1 = attribute(rom, "sha1").as_deref().and_then(parse_sha1)
+                    .ok_or_else(|| invalid("sha1"))?;
+                let bad_dump = attribute(rom, "status").as_deref() == Some("baddump")
+                    || name.contains("[b]");
+
+                db.insert(RomEntry {
+                    region: region(&name),
+                    name: name.clone(),
+                    size,
+                    crc32,
+                    sha1,
+                    bad_dump,
+                });
+            }
+        }
+        Ok(db)
+    }
+
+    /// Añadir un volcado, reemplaza al que tenga el mismo SHA1
+    pub fn insert(&mut self, entry: RomEntry) {
+        if let Some(&i) = self.by_sha1.get(&entry.sha1) {
+            self.by_crc32.remove(&(self.entries[i].crc32, self.entries[i].size));
+            self.by_crc32.insert((entry.crc32, entry.size), i);
+            self.entries[i] = entry;
+            return;
+        }
+        let i = self.entries.len();
+        self.by_sha1.insert(entry.sha1, i);
+        self.by_crc32.insert((entry.crc32, entry.size), i);
+        self.entries.push(entry);
+    }
+
+    /// Buscar una ROM por su SHA1
+    pub fn identify(&self, rom: &[u8]) -> Option<&RomEntry> {
+        let sha1 = sha1_smol::Sha1::from(rom).digest().bytes();
+        self.by_sha1.get(&sha1).map(|&i| &self.entries[i])
+    }
+
+    /// Buscar por CRC32 y tamaño, para los listados que no leen la ROM
+    /// entera (el CRC32 viene en los ZIP, por ejemplo)
+    pub fn find_crc32(&self, crc32: u32, size: u64) -> Option<&RomEntry> {
+        self.by_crc32.get(&(crc32, size)).map(|&i| &self.entries[i])
+    }
+
+    #[inline]
+    pub fn entries(&self) -> &[RomEntry] {
+        &self.entries
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// DAT incluido al compilar con `GAMEBOI_ROMDB`, ver `build.rs`
+const BUNDLED_DAT: &str = include_str!(concat!(env!("OUT_DIR"), "/romdb.dat"));
+
+/// Buscar una ROM en el DAT incluido al compilar, sin él nunca encuentra
+/// nada. Para DATs que se cargan en ejecución está `RomDatabase`
+pub fn identify_rom(rom: &[u8]) -> Option<&'static RomEntry> {
+    static BUNDLED: OnceLock<RomDatabase> = OnceLock::new();
+    BUNDLED.get_or_init(|| RomDatabase::from_dat(BUNDLED_DAT).expect("DAT incluido inválido"))
+        .identify(rom)
+}
+
+/// CRC32 de una ROM, el que usan los DATs
+pub fn rom_crc32(rom: &[u8]) -> u32 {
+    crc32fast::hash(rom)
+}
+
+/// Valor del atributo `name` de una etiqueta XML, sin las entidades
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{name}=\"");
+    let (start, _) = tag.match_indices(&pattern)
+        .find(|(i, _)| *i == 0 || tag.as_bytes()[i - 1].is_ascii_whitespace())?;
+    let value = &tag[start + pattern.len()..];
+    let value = &value[..value.find('"')?];
+    Some(value.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&"))
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (byte, digits) in sha1.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(sha1)
+}
+
+fn region(name: &str) -> Option<String> {
+    let start = name.find('(')? + 1;
+    let end = start + name[start..].find(')')?;
+    Some(name[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_identify() {
+        let rom = b"not really a rom";
+        let sha1 = sha1_smol::Sha1::from(rom).digest().to_string();
+        let dat = format!(r#"<?xml version="1.0"?>
+            <datafile>
+                <game name="Tom &amp; Jerry (USA, Europe)">
+                    <description>Tom &amp; Jerry (USA, Europe)</description>
+                    <rom name="Tom &amp; Jerry (USA, Europe).gb" size="16" crc="{:08x}" sha1="{sha1}" status="verified"/>
+                </game>
+                <game name="Broken (Japan) [b]">
+                    <rom name="Broken (Japan) [b].gb" size="32768" crc="DEADBEEF" sha1="{}"/>
+                </game>
+            </datafile>"#, rom_crc32(rom), "0".repeat(40));
+
+        let db = RomDatabase::from_dat(&dat).unwrap();
+        assert_eq!(db.len(), 2);
+        let entry = db.identify(rom).unwrap();
+        assert_eq!(entry.name, "Tom & Jerry (USA, Europe)");
+        assert_eq!(entry.region.as_deref(), Some("USA, Europe"));
+        assert!(!entry.bad_dump);
+        assert_eq!(db.find_crc32(rom_crc32(rom), 16), Some(entry));
+
+        let broken = db.find_crc32(0xDEADBEEF, 32768).unwrap();
+        assert!(broken.bad_dump);
+        assert_eq!(db.identify(b"otra"), None);
+
+        assert!(matches!(RomDatabase::from_dat(r#"<game name="x"><rom size="1" crc="zz" sha1=""/></game>"#),
+            Err(Error::RomDatabase(_))));
+    }
+}
@@ -0,0 +1,199 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Registros del reloj de un cartucho MBC3
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+
+    /// Contador de días de 9 bits
+    pub days: u16,
+
+    /// Bit 6 de DH, con el reloj parado no avanza
+    pub halted: bool,
+
+    /// Bit 7 de DH, el contador de días se desbordó
+    pub day_carry: bool,
+}
+
+impl RtcRegisters {
+    /// Avanzar el reloj, parado no cambia. Los valores fuera de rango que
+    /// haya escrito el juego se normalizan
+    pub fn advance(&mut self, seconds: u64) {
+        if self.halted {
+            return;
+        }
+        let total = self.seconds as u64 + seconds;
+        self.seconds = (total % 60) as u8;
+        let total = self.minutes as u64 + total / 60;
+        self.minutes = (total % 60) as u8;
+        let total = self.hours as u64 + total / 60;
+        self.hours = (total % 24) as u8;
+        let days = self.days as u64 + total / 24;
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    /// Los 5 registros como los ve el juego: S, M, H, DL y DH
+    pub fn to_bytes(&self) -> [u8; 5] {
+        let dh = (self.days >> 8) as u8 & 1
+            | (self.halted as u8) << 6
+            | (self.day_carry as u8) << 7;
+        [self.seconds, self.minutes, self.hours, self.days as u8, dh]
+    }
+
+    pub fn from_bytes(bytes: [u8; 5]) -> Self {
+        let [seconds, minutes, hours, dl, dh] = bytes;
+        Self {
+            seconds,
+            minutes,
+            hours,
+            days: (dh as u16 & 1) << 8 | dl as u16,
+            halted: dh & 0x40 != 0,
+            day_carry: dh & 0x80 != 0,
+        }
+    }
+}
+
+/// Cuánto avanza el reloj al cargar una partida por el tiempo real que pasó
+/// desde que se guardó
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RtcCatchUp {
+    /// Todo el tiempo, como un cartucho de verdad con su pila
+    #[default]
+    Elapsed,
+
+    /// Como mucho la duración dada, para que no se pierdan días de juego
+    /// tras meses sin jugar
+    Capped(Duration),
+
+    /// El reloj sigue donde se quedó, como si se hubiera parado
+    Disabled,
+}
+
+/// Estado del reloj guardado junto a la RAM del cartucho, en el formato de 48
+/// bytes que añaden al final del `.sav` VBA-M, BGB o mGBA: los registros y
+/// los latcheados como u32 y la hora real del guardado como u64, todo en
+/// little endian. Se leen también los de 44 bytes con la hora en u32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcSave {
+    pub clock: RtcRegisters,
+
+    /// Lo último latcheado, lo que lee el juego
+    pub latched: RtcRegisters,
+
+    /// Segundos desde el epoch Unix al guardar
+    pub timestamp: u64,
+}
+
+impl RtcSave {
+    pub const SIZE: usize = 48;
+
+    /// Tamaño del formato antiguo con la hora en 32 bits
+    pub const SIZE_32: usize = 44;
+
+    /// Guardar los registros con la hora actual
+    pub fn new(clock: RtcRegisters, latched: RtcRegisters) -> Self {
+        Self { clock, latched, timestamp: unix_time(SystemTime::now()) }
+    }
+
+    /// Leer un estado de 48 o 44 bytes, `None` con cualquier otro tamaño
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let timestamp = match bytes.len() {
+            Self::SIZE => u64::from_le_bytes(bytes[40..48].try_into().ok()?),
+            Self::SIZE_32 => u32::from_le_bytes(bytes[40..44].try_into().ok()?) as u64,
+            _ => return None,
+        };
+        // Los registros se guardan en u32 pero solo importa el byte bajo
+        let registers = |start: usize| RtcRegisters::from_bytes(
+            std::array::from_fn(|i| bytes[start + i * 4]));
+        Some(Self { clock: registers(0), latched: registers(20), timestamp })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        let registers = self.clock.to_bytes().into_iter()
+            .chain(self.latched.to_bytes());
+        for (i, register) in registers.enumerate() {
+            bytes[i * 4] = register;
+        }
+        bytes[40..].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes
+    }
+
+    /// Separar el estado del reloj del final de un `.sav`, si lo lleva. La
+    /// RAM de los cartuchos mide múltiplos de 512 bytes, lo que sobre es el
+    /// reloj
+    pub fn split_sav(sav: &[u8]) -> (&[u8], Option<Self>) {
+        let footer = match sav.len() % 512 {
+            Self::SIZE => Self::SIZE,
+            Self::SIZE_32 => Self::SIZE_32,
+            _ => return (sav, None),
+        };
+        let (sram, footer) = sav.split_at(sav.len() - footer);
+        (sram, Self::from_bytes(footer))
+    }
+
+    /// Avanzar el reloj por el tiempo real transcurrido hasta `now` según
+    /// `policy` y apuntar `now` como la nueva hora del guardado, devuelve lo
+    /// que se avanzó. Si el reloj del sistema va por detrás del guardado no
+    /// se avanza nada
+    pub fn catch_up(&mut self, now: SystemTime, policy: RtcCatchUp) -> Duration {
+        let now = unix_time(now);
+        let elapsed = Duration::from_secs(now.saturating_sub(self.timestamp));
+        let elapsed = match policy {
+            RtcCatchUp::Elapsed => elapsed,
+            RtcCatchUp::Capped(cap) => elapsed.min(cap),
+            RtcCatchUp::Disabled => Duration::ZERO,
+        };
+        self.clock.advance(elapsed.as_secs());
+        self.timestamp = now;
+        elapsed
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catch_up() {
+        let clock = RtcRegisters { seconds: 50, minutes: 59, hours: 23, days: 0x1FF, ..Default::default() };
+        let mut save = RtcSave { clock, latched: clock, timestamp: 1_000_000 };
+        let bytes = save.to_bytes();
+        assert_eq!(RtcSave::from_bytes(&bytes), Some(save));
+
+        let mut sav = vec![0xAA; 0x2000];
+        sav.extend(bytes);
+        assert_eq!(RtcSave::split_sav(&sav), (&sav[..0x2000], Some(save)));
+        assert_eq!(RtcSave::split_sav(&sav[..0x2000]).1, None);
+
+        // 10 segundos desbordan el contador de días
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_010);
+        assert_eq!(save.catch_up(now, RtcCatchUp::Elapsed), Duration::from_secs(10));
+        assert_eq!(save.clock, RtcRegisters { day_carry: true, ..Default::default() });
+        assert_eq!(save.latched, clock);
+        assert_eq!(save.timestamp, 1_000_010);
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_010 + 3 * 86400);
+        let capped = RtcCatchUp::Capped(Duration::from_secs(3600));
+        assert_eq!(save.catch_up(now, capped), Duration::from_secs(3600));
+        assert_eq!(save.clock.hours, 1);
+
+        // Parado o desactivado no avanza, pero se apunta la hora
+        save.clock.halted = true;
+        let later = now + Duration::from_secs(60);
+        save.catch_up(later, RtcCatchUp::Elapsed);
+        assert_eq!(save.clock.minutes, 0);
+        assert_eq!(save.catch_up(later + Duration::from_secs(5), RtcCatchUp::Disabled),
+            Duration::ZERO);
+        assert_eq!(save.timestamp, 1_000_010 + 3 * 86400 + 65);
+    }
+}
@@ -0,0 +1,327 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::gameboy::GameBoy;
+use crate::header::RomHeader;
+use crate::model::header_title;
+use crate::rtc::{RtcCatchUp, RtcSave};
+use crate::sink::BatterySink;
+#[cfg(feature = "serde")]
+use crate::slots::SlotManager;
+
+/// Ficheros de guardado de una ROM dentro de un directorio raíz común:
+///
+/// ```text
+/// <raíz>/<TÍTULO>-<checksum>/<TÍTULO>.sav   RAM del cartucho
+/// <raíz>/<TÍTULO>-<checksum>/<TÍTULO>.rtc   reloj del cartucho
+/// <raíz>/<TÍTULO>-<checksum>/states/        slots de save states
+/// ```
+///
+/// El identificador usa el título y el checksum global de la cabecera, que no
+/// cambian al modificar la ROM por partes como el hash de los save states,
+/// así una partida sigue encontrándose tras aplicar un parche de traducción
+/// que no toque el checksum. Los directorios no se crean hasta que se guarda
+/// algo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveManager {
+    dir: PathBuf,
+
+    /// Título limpio para usarlo en nombres de fichero
+    title: String,
+}
+
+impl SaveManager {
+    /// Ficheros de la ROM cargada en `gb` dentro de `root`
+    pub fn new(root: impl AsRef<Path>, gb: &GameBoy) -> Self {
+        let title = file_title(gb);
+        let checksum = RomHeader::parse(gb.mmu().memory())
+            .map_or(0, |header| header.global_checksum);
+        Self {
+            dir: root.as_ref().join(format!("{title}-{checksum:04x}")),
+            title,
+        }
+    }
+
+    /// Identificador de la ROM, el nombre de su directorio
+    pub fn id(&self) -> &str {
+        self.dir.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+    }
+
+    /// Directorio con todos los ficheros de la ROM
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn sram_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.sav", self.title))
+    }
+
+    pub fn rtc_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.rtc", self.title))
+    }
+
+    /// Slots de save states de la ROM, en `states/`
+    #[cfg(feature = "serde")]
+    pub fn slots(&self) -> SlotManager {
+        SlotManager::in_dir(self.dir.join("states"))
+    }
+
+    /// Cargar la RAM del cartucho guardada, devuelve `false` si todavía no
+    /// había ninguna. Una que no quepa en el cartucho da `InvalidData`. Si
+    /// el `.sav` lleva el reloj al final se ignora, ver `load_rtc`
+    pub fn load_sram(&self, gb: &mut GameBoy) -> io::Result<bool> {
+        let Some(sav) = read_optional(&self.sram_path())? else {
+            return Ok(false);
+        };
+        let (sram, _) = RtcSave::split_sav(&sav);
+        gb.mmu_mut().load_sram(sram)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(true)
+    }
+
+    /// Cargar el reloj guardado en `rtc_path`, o al final del `.sav` de otro
+    /// emulador, y avanzarlo según `policy` por el tiempo real que pasó desde
+    /// que se guardó, así los días del juego siguen pasando entre partidas.
+    /// `None` si no hay ninguno
+    // TODO: Todavía no hay mapper con RTC, de momento el frontend guarda el
+    // estado devuelto y lo vuelve a pasar a `save_rtc`
+    pub fn load_rtc(&self, policy: RtcCatchUp) -> io::Result<Option<RtcSave>> {
+        let rtc = match read_optional(&self.rtc_path())? {
+            Some(bytes) => Some(RtcSave::from_bytes(&bytes).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "estado del reloj inválido")
+            })?),
+            None => read_optional(&self.sram_path())?
+                .and_then(|sav| RtcSave::split_sav(&sav).1),
+        };
+        Ok(rtc.map(|mut rtc| {
+            rtc.catch_up(SystemTime::now(), policy);
+            rtc
+        }))
+    }
+
+    /// Guardar el reloj en `rtc_path`, con la hora en la que se guardó
+    pub fn save_rtc(&self, rtc: &RtcSave) -> io::Result<()> {
+        BatteryFile::new(self.rtc_path()).flush(&rtc.to_bytes())
+    }
+
+    /// Sink que guarda la RAM del cartucho en `sram_path`, para conectarlo
+    /// con `GameBoy::set_battery_sink`
+    pub fn battery_sink(&self) -> BatteryFile {
+        BatteryFile { path: self.sram_path() }
+    }
+
+    /// Respaldar la RAM del cartucho de `gb` con `sram_path` mapeado en
+    /// memoria en vez de con un `BatterySink`, ver `MappedSram`. Carga lo que
+    /// ya hubiera guardado, así que sustituye a `load_sram`
+    #[cfg(feature = "mmap")]
+    pub fn map_sram(&self, gb: &mut GameBoy) -> io::Result<()> {
+        let map = MappedSram::open(self.sram_path(), gb.mmu().sram().len())?;
+        gb.mmu_mut().map_sram(map);
+        Ok(())
+    }
+}
+
+/// RAM del cartucho respaldada directamente por un `.sav` mapeado en
+/// memoria, se conecta con `Mmu::map_sram`. Cada escritura del juego va
+/// también al mapeo, así que si el emulador se cuelga la partida ya está en
+/// el fichero sin esperar a un `flush_sram`. Solo un corte de luz antes de
+/// que el sistema operativo lo escriba a disco la perdería, para eso está
+/// `flush`
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MappedSram {
+    path: PathBuf,
+    map: memmap2::MmapMut,
+    size: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedSram {
+    /// Mapear los primeros `size` bytes de `path`, creándolo si no existe y
+    /// ampliándolo con ceros si es más corto. Lo que haya después, como el
+    /// reloj al final del `.sav` de otros emuladores, se deja como está
+    pub fn open(path: impl Into<PathBuf>, size: usize) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.metadata()?.len() < size as u64 {
+            file.set_len(size as u64)?;
+        }
+        // SAFETY: Si otro proceso modifica el fichero mientras está mapeado
+        // solo cambian los bytes de la partida, se leen siempre como `u8`
+        let map = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        Ok(Self { path, map, size })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// RAM del cartucho tal como está en el fichero
+    #[inline]
+    pub fn contents(&self) -> &[u8] {
+        &self.map[..self.size]
+    }
+
+    /// Escribir lo mapeado a disco y esperar a que termine
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    #[inline]
+    pub(crate) fn write(&mut self, offset: usize, value: u8) {
+        if let Some(byte) = self.map[..self.size].get_mut(offset) {
+            *byte = value;
+        }
+    }
+
+    pub(crate) fn copy_from(&mut self, sram: &[u8]) {
+        let len = sram.len().min(self.size);
+        self.map[..len].copy_from_slice(&sram[..len]);
+    }
+}
+
+/// `BatterySink` que escribe la RAM del cartucho en un fichero, primero en
+/// uno temporal para no perder la partida anterior si algo falla a mitad
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatteryFile {
+    path: PathBuf,
+}
+
+impl BatteryFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl BatterySink for BatteryFile {
+    fn flush(&mut self, sram: &[u8]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, sram)?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+/// Leer un fichero que puede no existir todavía
+fn read_optional(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        bytes => bytes.map(Some),
+    }
+}
+
+/// Título de la cabecera con solo caracteres que valen en cualquier sistema
+/// de ficheros
+pub(crate) fn file_title(gb: &GameBoy) -> String {
+    let title = header_title(gb.mmu().memory())
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if title.is_empty() { "UNTITLED".into() } else { title }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::Addr;
+
+    #[test]
+    fn sram_round_trip() {
+        let root = std::env::temp_dir()
+            .join(format!("gameboi-saves-{}", std::process::id()));
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x013C].copy_from_slice(b"TEST ROM");
+        rom[0x014E..0x0150].copy_from_slice(&[0xBE, 0xEF]);
+
+        let mut gb = GameBoy::builder().rom(rom.clone()).build().unwrap();
+        let saves = SaveManager::new(&root, &gb);
+        assert_eq!(saves.id(), "TEST_ROM-beef");
+        assert_eq!(saves.sram_path(), root.join("TEST_ROM-beef").join("TEST_ROM.sav"));
+        assert!(!saves.load_sram(&mut gb).unwrap());
+
+        gb.set_battery_sink(Some(Box::new(saves.battery_sink())));
+        gb.mmu_mut().write_word(Addr(0xA010), 0x42);
+        assert!(gb.flush_sram().unwrap());
+
+        let mut restored = GameBoy::builder().rom(rom).build().unwrap();
+        assert!(saves.load_sram(&mut restored).unwrap());
+        assert_eq!(restored.mmu().read_word(Addr(0xA010)), 0x42);
+
+        // Un `.sav` de otro emulador con el reloj al final, guardado hace una
+        // hora
+        assert_eq!(saves.load_rtc(RtcCatchUp::Elapsed).unwrap(), None);
+        let mut rtc = RtcSave::new(Default::default(), Default::default());
+        rtc.timestamp -= 3600;
+        let mut sav = vec![0x42; 0x2000];
+        sav.extend(rtc.to_bytes());
+        fs::write(saves.sram_path(), sav).unwrap();
+        assert!(saves.load_sram(&mut restored).unwrap());
+        let rtc = saves.load_rtc(RtcCatchUp::Elapsed).unwrap().unwrap();
+        assert!(rtc.clock.hours == 1 || rtc.clock.minutes == 59);
+
+        // El `.rtc` propio tiene prioridad
+        saves.save_rtc(&RtcSave::new(Default::default(), Default::default())).unwrap();
+        let rtc = saves.load_rtc(RtcCatchUp::Disabled).unwrap().unwrap();
+        assert_eq!(rtc.clock.hours, 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn mapped_sram() {
+        let root = std::env::temp_dir()
+            .join(format!("gameboi-mapped-{}", std::process::id()));
+        let rom = vec![0; 0x8000];
+        let mut gb = GameBoy::builder().rom(rom.clone()).build().unwrap();
+        let saves = SaveManager::new(&root, &gb);
+
+        // Un `.sav` con el reloj al final, que no se toca
+        let mut sav = vec![0x11; 0x2000];
+        sav.extend([0xEE; RtcSave::SIZE]);
+        fs::create_dir_all(saves.dir()).unwrap();
+        fs::write(saves.sram_path(), &sav).unwrap();
+        saves.map_sram(&mut gb).unwrap();
+        assert_eq!(gb.mmu().read_word(Addr(0xA000)), 0x11);
+
+        // Cada escritura llega al fichero sin `flush_sram`
+        gb.mmu_mut().write_word(Addr(0xA010), 0x42);
+        gb.mmu_mut().write_word(Addr(0xBFFF), 0x43);
+        let sav = fs::read(saves.sram_path()).unwrap();
+        assert_eq!(sav.len(), 0x2000 + RtcSave::SIZE);
+        assert_eq!((sav[0x10], sav[0x1FFF], sav[0x2000]), (0x42, 0x43, 0xEE));
+
+        gb.mmu_mut().load_sram(&[0x55]).unwrap();
+        assert_eq!(fs::read(saves.sram_path()).unwrap()[0], 0x55);
+        let map = gb.mmu_mut().unmap_sram().unwrap();
+        map.flush().unwrap();
+        drop(map);
+        gb.mmu_mut().write_word(Addr(0xA000), 0x99);
+        assert_eq!(fs::read(saves.sram_path()).unwrap()[0], 0x55);
+
+        // Uno nuevo se crea con el tamaño de la RAM
+        fs::remove_dir_all(&root).unwrap();
+        let mut gb = GameBoy::builder().rom(rom).build().unwrap();
+        saves.map_sram(&mut gb).unwrap();
+        assert_eq!(fs::read(saves.sram_path()).unwrap(), vec![0; 0x2000]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
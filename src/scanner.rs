@@ -0,0 +1,126 @@
+use std::ops::RangeInclusive;
+
+use crate::mmu::{Mmu, SRAM};
+
+/// Work RAM, donde los juegos guardan casi todo su estado
+pub const WRAM: RangeInclusive<u16> = 0xC000..=0xDFFF;
+
+/// Criterio con el que se descartan direcciones en cada pasada
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanFilter {
+    /// El valor actual es exactamente este
+    Equals(u8),
+
+    /// Mayor que en la pasada anterior
+    Increased,
+
+    /// Menor que en la pasada anterior
+    Decreased,
+
+    /// Igual que en la pasada anterior
+    Unchanged,
+
+    /// Distinto que en la pasada anterior
+    Changed,
+}
+
+/// Buscador de direcciones para trucos: se empieza con todas las de la WRAM
+/// y la SRAM y en cada pasada se quedan solo las que cumplen el filtro
+/// comparando con el valor que tenían en la pasada anterior
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryScanner {
+    /// Direcciones que siguen siendo candidatas y su valor en la última
+    /// pasada, en paralelo
+    addrs: Vec<u16>,
+    values: Vec<u8>,
+}
+
+impl MemoryScanner {
+    /// Empezar una búsqueda con todas las direcciones de la WRAM y la SRAM
+    pub fn new(mmu: &Mmu) -> Self {
+        Self::with_regions(mmu, &[WRAM, SRAM])
+    }
+
+    /// Empezar una búsqueda en unas regiones concretas
+    pub fn with_regions(mmu: &Mmu, regions: &[RangeInclusive<u16>]) -> Self {
+        let addrs = regions.iter().cloned().flatten().collect::<Vec<_>>();
+        let memory = mmu.memory();
+        let values = addrs.iter().map(|addr| memory[*addr as usize]).collect();
+        Self { addrs, values }
+    }
+
+    /// Descartar las direcciones que no cumplen `filter` con el estado actual
+    /// de la memoria, devuelve cuántas quedan
+    pub fn filter(&mut self, mmu: &Mmu, filter: ScanFilter) -> usize {
+        let memory = mmu.memory();
+        let mut kept = 0;
+        for i in 0..self.addrs.len() {
+            let addr = self.addrs[i];
+            let old = self.values[i];
+            let new = memory[addr as usize];
+
+            let keep = match filter {
+                ScanFilter::Equals(value) => new == value,
+                ScanFilter::Increased => new > old,
+                ScanFilter::Decreased => new < old,
+                ScanFilter::Unchanged => new == old,
+                ScanFilter::Changed => new != old,
+            };
+
+            // Se compacta en el sitio para no reservar memoria en cada pasada
+            if keep {
+                self.addrs[kept] = addr;
+                self.values[kept] = new;
+                kept += 1;
+            }
+        }
+        self.addrs.truncate(kept);
+        self.values.truncate(kept);
+        kept
+    }
+
+    /// Número de direcciones candidatas
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    /// Direcciones candidatas con su valor en la última pasada
+    pub fn results(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.addrs.iter().copied().zip(self.values.iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Addr;
+
+    #[test]
+    fn find_counter() {
+        let mut mmu = Mmu::new();
+        mmu.write_word(Addr(0xC123), 3);
+        mmu.write_word(Addr(0xC456), 3);
+
+        let mut scanner = MemoryScanner::new(&mmu);
+        assert_eq!(scanner.len(), 0x4000);
+        assert_eq!(scanner.filter(&mmu, ScanFilter::Equals(3)), 2);
+
+        // Solo una de las dos baja, como un contador de vidas
+        mmu.write_word(Addr(0xC123), 2);
+        assert_eq!(scanner.filter(&mmu, ScanFilter::Decreased), 1);
+        assert_eq!(scanner.results().collect::<Vec<_>>(), [(0xC123, 2)]);
+
+        assert_eq!(scanner.filter(&mmu, ScanFilter::Unchanged), 1);
+        mmu.write_word(Addr(0xC123), 5);
+        assert_eq!(scanner.filter(&mmu, ScanFilter::Increased), 1);
+        mmu.write_word(Addr(0xC123), 5);
+        assert_eq!(scanner.filter(&mmu, ScanFilter::Changed), 0);
+        assert!(scanner.is_empty());
+    }
+}
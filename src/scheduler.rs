@@ -0,0 +1,103 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Eventos que pueden programar los periféricos
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// El puerto serie desplaza el siguiente bit
+    SerialBit,
+
+    /// Comprobar si el otro extremo del cable inició la transferencia
+    SerialPoll,
+}
+
+/// Planificador de eventos ordenados por el instante (en T-cycles) en el que
+/// ocurren, en vez de avanzar cada periférico en cada instrucción solo se
+/// hace trabajo cuando llega su siguiente evento
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scheduler {
+    /// Instante actual
+    now: u64,
+
+    /// Eventos pendientes, a igualdad de instante sale antes el que tenga
+    /// menor `Event`
+    events: BinaryHeap<Reverse<(u64, Event)>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instante actual en T-cycles
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Avanzar el tiempo, los eventos que venzan se sacan con `pop_due`
+    #[inline]
+    pub fn advance(&mut self, cycles: u32) {
+        self.now += cycles as u64;
+    }
+
+    /// Programar `event` para dentro de `delay` T-cycles
+    #[inline]
+    pub fn schedule_in(&mut self, delay: u32, event: Event) {
+        self.schedule_at(self.now + delay as u64, event);
+    }
+
+    /// Programar `event` en el instante absoluto `at`
+    #[inline]
+    pub fn schedule_at(&mut self, at: u64, event: Event) {
+        self.events.push(Reverse((at, event)));
+    }
+
+    /// Quitar todas las apariciones pendientes de `event`
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|Reverse((_, e))| *e != event);
+    }
+
+    /// Instante del siguiente evento pendiente
+    #[inline]
+    pub fn next_at(&self) -> Option<u64> {
+        self.events.peek().map(|Reverse((at, _))| *at)
+    }
+
+    /// Sacar el siguiente evento si ya ha vencido, junto con el instante en el
+    /// que estaba programado
+    #[inline]
+    pub fn pop_due(&mut self) -> Option<(u64, Event)> {
+        if self.next_at()? > self.now {
+            return None;
+        }
+        self.events.pop().map(|Reverse(event)| event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_in_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_in(100, Event::SerialPoll);
+        scheduler.schedule_in(50, Event::SerialBit);
+        scheduler.schedule_in(100, Event::SerialBit);
+        assert_eq!(scheduler.next_at(), Some(50));
+
+        scheduler.advance(49);
+        assert_eq!(scheduler.pop_due(), None);
+        scheduler.advance(2);
+        assert_eq!(scheduler.pop_due(), Some((50, Event::SerialBit)));
+        assert_eq!(scheduler.pop_due(), None);
+
+        scheduler.cancel(Event::SerialBit);
+        scheduler.advance(49);
+        assert_eq!(scheduler.pop_due(), Some((100, Event::SerialPoll)));
+        assert_eq!(scheduler.pop_due(), None);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::error::Error;
+use crate::joypad::Button;
+
+/// Frames que se sueltan los botones después de un `press`, para que dos
+/// pulsaciones seguidas del mismo botón cuenten como dos
+const PRESS_RELEASE_FRAMES: u32 = 1;
+
+/// Guion de entrada frame a frame, para reproducir bugs o pasar menús en los
+/// tests sin grabar un `Movie`. Se escribe como órdenes separadas por `;` o
+/// saltos de línea, `#` empieza un comentario:
+///
+/// ```text
+/// wait 120          # 120 frames sin cambiar nada
+/// press A 2         # A durante 2 frames y 1 frame suelto
+/// press START+A     # varios botones a la vez, 1 frame por defecto
+/// hold RIGHT 60     # RIGHT durante 60 frames y se suelta sin esperar
+/// hold B            # B queda pulsado hasta un `release`
+/// release B
+/// ```
+///
+/// Los botones mantenidos con `hold` sin duración se siguen viendo en todas
+/// las órdenes siguientes. Los nombres de los botones no distinguen
+/// mayúsculas
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InputScript {
+    /// Estado de los botones en cada frame, como `Joypad::state`
+    frames: Vec<u8>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut frames = Vec::new();
+        let mut held = 0u8;
+        let commands = text.lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split(';'))
+            .map(str::trim)
+            .filter(|command| !command.is_empty());
+
+        for command in commands {
+            let invalid = |message: &str| Error::InputScript(format!("`{command}`: {message}"));
+            let mut words = command.split_whitespace();
+            let name = words.next().unwrap_or_default().to_ascii_lowercase();
+            let mask = match name.as_str() {
+                "wait" => 0,
+                "press" | "hold" | "release" => {
+                    let names = words.next().ok_or_else(|| invalid("faltan los botones"))?;
+                    names.split('+').try_fold(0, |mask, name| -> Result<u8, Error> {
+                        let button = parse_button(name)
+                            .ok_or_else(|| invalid(&format!("botón desconocido {name}")))?;
+                        Ok(mask | 1 << button as u8)
+                    })?
+                },
+                _ => return Err(invalid("orden desconocida")),
+            };
+            let count = words.next().map(|count| count.parse::<u32>()
+                    .map_err(|_| invalid(&format!("número de frames inválido {count}"))))
+                .transpose()?;
+            if words.next().is_some() {
+                return Err(invalid("sobran argumentos"));
+            }
+
+            let mut push = |state: u8, count: u32| {
+                frames.extend(std::iter::repeat_n(state, count as usize));
+            };
+            match (name.as_str(), count) {
+                ("wait", count) => push(held, count.ok_or_else(|| invalid("faltan los frames"))?),
+                ("press", count) => {
+                    push(held | mask, count.unwrap_or(1));
+                    push(held & !mask, PRESS_RELEASE_FRAMES);
+                },
+                ("hold", Some(count)) => push(held | mask, count),
+                ("hold", None) => held |= mask,
+                ("release", None) => held &= !mask,
+                _ => return Err(invalid("sobran argumentos")),
+            }
+        }
+        Ok(Self { frames })
+    }
+
+    /// Frames que dura el guion
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Estado de los botones en el frame `frame`, en el formato de
+    /// `Joypad::state`
+    #[inline]
+    pub fn frame(&self, frame: usize) -> Option<u8> {
+        self.frames.get(frame).copied()
+    }
+
+    #[inline]
+    pub fn frames(&self) -> &[u8] {
+        &self.frames
+    }
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    const NAMES: [(&str, Button); 8] = [
+        ("right", Button::Right), ("left", Button::Left),
+        ("up", Button::Up), ("down", Button::Down),
+        ("a", Button::A), ("b", Button::B),
+        ("select", Button::Select), ("start", Button::Start),
+    ];
+    NAMES.iter()
+        .find(|(button, _)| button.eq_ignore_ascii_case(name))
+        .map(|(_, button)| *button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let a = 1 << Button::A as u8;
+        let right = 1 << Button::Right as u8;
+        let start = 1 << Button::Start as u8;
+
+        let script = InputScript::parse("wait 2; press a 2\n\
+            hold RIGHT   # hasta el release\n\
+            press Start+A; hold a 1; release right; wait 1").unwrap();
+        assert_eq!(script.frames(), [
+            0, 0, a, a, 0,
+            right | start | a, right,
+            right | a,
+            0,
+        ]);
+        assert_eq!(script.len(), 9);
+        assert_eq!(script.frame(9), None);
+
+        for bad in ["jump 3", "press X", "wait", "wait x", "press A 1 2", "release A 3"] {
+            assert!(matches!(InputScript::parse(bad), Err(Error::InputScript(_))), "{bad}");
+        }
+    }
+}
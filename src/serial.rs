@@ -0,0 +1,240 @@
+use std::sync::{Arc, Mutex};
+
+/// Dirección del registro SB (dato que se transfiere)
+pub const SB: u16 = 0xFF01;
+
+/// Dirección del registro SC (control de la transferencia)
+pub const SC: u16 = 0xFF02;
+
+/// Bit de SC que inicia la transferencia y se limpia al terminar
+pub const SC_TRANSFER: u8 = 1 << 7;
+
+/// Bit de SC que indica que esta Game Boy genera el reloj
+pub const SC_INTERNAL_CLOCK: u8 = 1 << 0;
+
+/// El reloj interno va a 8192Hz, es decir un bit cada 512 T-cycles
+pub const CYCLES_PER_BIT: u32 = 512;
+
+/// Estado de la transferencia en curso por el puerto serie
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Serial {
+    /// Bits que faltan por transferir, 0 si no hay ninguna transferencia
+    bits_left: u8,
+
+    /// Byte que envía el otro extremo, sin cable conectado las líneas están
+    /// a alto por lo que se recibe 0xFF
+    incoming: u8,
+
+    /// Se pidió una transferencia con reloj externo y se está esperando a que
+    /// el otro extremo la inicie
+    waiting_external: bool,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            bits_left: 0,
+            incoming: 0xFF,
+            waiting_external: false,
+        }
+    }
+
+    /// Hay una transferencia en curso
+    #[inline]
+    pub fn is_transferring(&self) -> bool {
+        self.bits_left != 0
+    }
+
+    /// Se está esperando a que el otro extremo genere el reloj
+    #[inline]
+    pub fn is_waiting_external(&self) -> bool {
+        self.waiting_external
+    }
+
+    /// Se llama cuando la CPU escribe en SC, devuelve `true` si se activó la
+    /// transferencia con reloj interno, en cuyo caso se debe llamar a `start`
+    /// con el byte recibido del otro extremo. Con reloj externo se queda
+    /// esperando hasta que el otro extremo inicie la transferencia, sin cable
+    /// conectado esto nunca ocurre
+    pub fn write_control(&mut self, sc: u8) -> bool {
+        self.bits_left = 0;
+        self.waiting_external = false;
+
+        match sc & (SC_TRANSFER | SC_INTERNAL_CLOCK) {
+            c if c == SC_TRANSFER | SC_INTERNAL_CLOCK => true,
+            SC_TRANSFER => {
+                self.waiting_external = true;
+                false
+            },
+            _ => false,
+        }
+    }
+
+    /// Empezar a desplazar bits recibiendo `incoming` del otro extremo, el
+    /// primer bit se debe desplazar tras `CYCLES_PER_BIT` ciclos
+    pub fn start(&mut self, incoming: u8) {
+        self.bits_left = 8;
+        self.incoming = incoming;
+        self.waiting_external = false;
+    }
+
+    /// Desplazar el siguiente bit de la transferencia, devuelve `true` si la
+    /// transferencia terminó y se debe solicitar la interrupción
+    pub fn shift_bit(&mut self, sb: &mut u8, sc: &mut u8) -> bool {
+        if !self.is_transferring() {
+            return false;
+        }
+
+        // Por cada bit que sale por el bit 7 entra uno del otro extremo
+        self.bits_left -= 1;
+        *sb = (*sb << 1) | ((self.incoming >> self.bits_left) & 1);
+
+        if self.bits_left == 0 {
+            *sc &= !SC_TRANSFER;
+            return true;
+        }
+
+        false
+    }
+}
+
+/// Abstracción del cable link, cada Game Boy tiene un extremo. El intercambio
+/// se hace a nivel de byte en vez de bit a bit, el desplazamiento con su
+/// temporización lo sigue haciendo `Serial` en cada lado
+pub trait SerialLink: Send {
+    /// Lo llama el lado que genera el reloj al iniciar una transferencia,
+    /// envía `byte` y devuelve el byte del otro extremo o `None` si no hay
+    /// nadie esperando al otro lado
+    fn exchange(&mut self, byte: u8) -> Option<u8>;
+
+    /// Lo llama periódicamente el lado que espera el reloj externo, ofrece
+    /// `byte` como respuesta y devuelve el byte recibido si el otro extremo
+    /// ya hizo la transferencia
+    fn offer(&mut self, byte: u8) -> Option<u8>;
+}
+
+/// Estado compartido entre los dos extremos de un `PairedLink`, los índices
+/// son el del lado que recibe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct PairedState {
+    /// Byte que ofrece cada lado mientras espera reloj externo
+    offered: [Option<u8>; 2],
+
+    /// Byte entregado a cada lado tras una transferencia del otro
+    delivered: [Option<u8>; 2],
+}
+
+/// Cable link entre dos emuladores del mismo proceso, se crean siempre por
+/// pares con `PairedLink::pair`
+#[derive(Debug, Clone)]
+pub struct PairedLink {
+    state: Arc<Mutex<PairedState>>,
+    side: usize,
+}
+
+impl PairedLink {
+    /// Crear los dos extremos de un cable
+    pub fn pair() -> (Self, Self) {
+        let state = Arc::new(Mutex::new(PairedState::default()));
+        let a = Self { state: state.clone(), side: 0 };
+        let b = Self { state, side: 1 };
+        (a, b)
+    }
+
+    /// Bytes en vuelo por el cable, no forman parte de los save states de
+    /// ninguna de las dos Game Boy
+    #[cfg(all(feature = "net", feature = "serde"))]
+    pub(crate) fn save(&self) -> PairedState {
+        *self.state.lock().unwrap()
+    }
+
+    #[cfg(all(feature = "net", feature = "serde"))]
+    pub(crate) fn restore(&self, state: PairedState) {
+        *self.state.lock().unwrap() = state;
+    }
+}
+
+impl SerialLink for PairedLink {
+    fn exchange(&mut self, byte: u8) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+        let peer = 1 - self.side;
+
+        let reply = state.offered[peer].take()?;
+        state.delivered[peer] = Some(byte);
+        Some(reply)
+    }
+
+    fn offer(&mut self, byte: u8) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(received) = state.delivered[self.side].take() {
+            return Some(received);
+        }
+        state.offered[self.side] = Some(byte);
+        None
+    }
+}
+
+/// Resultado que imprimen por el puerto serie los tests de blargg
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+}
+
+/// Extremo del cable que no contesta pero guarda todos los bytes que envía la
+/// Game Boy con reloj interno (SC=0x81), que es como imprimen su salida los
+/// tests de blargg. Los clones comparten el buffer, así que se puede conectar
+/// uno a la MMU y leer la salida desde otro
+#[derive(Debug, Clone, Default)]
+pub struct SerialCapture {
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes recibidos hasta el momento
+    pub fn bytes(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+
+    /// Salida recibida hasta el momento como texto
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output.lock().unwrap()).into_owned()
+    }
+
+    /// Descartar la salida recibida
+    pub fn clear(&self) {
+        self.output.lock().unwrap().clear();
+    }
+
+    /// Buscar en la salida el resultado del test, `None` si todavía no ha
+    /// terminado
+    pub fn outcome(&self) -> Option<TestOutcome> {
+        let output = self.output();
+        if output.contains("Passed") {
+            Some(TestOutcome::Passed)
+        } else if output.contains("Failed") {
+            Some(TestOutcome::Failed)
+        } else {
+            None
+        }
+    }
+}
+
+impl SerialLink for SerialCapture {
+    fn exchange(&mut self, byte: u8) -> Option<u8> {
+        self.output.lock().unwrap().push(byte);
+
+        // No hay nadie al otro lado
+        None
+    }
+
+    fn offer(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
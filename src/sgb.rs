@@ -0,0 +1,544 @@
+#[cfg(feature = "ppu")]
+use crate::frame::{Frame, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::joypad::{JOYP_SELECT_BUTTONS, JOYP_SELECT_DPAD};
+
+/// Tamaño de la imagen del Super Game Boy, la pantalla de la Game Boy queda
+/// centrada dentro del borde
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+
+/// Posición de la pantalla de la Game Boy dentro de la imagen del SGB
+#[cfg(feature = "ppu")]
+const SCREEN_X: usize = 48;
+#[cfg(feature = "ppu")]
+const SCREEN_Y: usize = 40;
+
+/// La pantalla se divide en celdas de 8x8 para asignar paletas
+const ATTR_WIDTH: usize = 20;
+const ATTR_HEIGHT: usize = 18;
+
+/// Comandos del protocolo, en los 5 bits altos del primer byte del paquete
+const CMD_PAL01: u8 = 0x00;
+const CMD_PAL23: u8 = 0x01;
+const CMD_PAL03: u8 = 0x02;
+const CMD_PAL12: u8 = 0x03;
+const CMD_ATTR_BLK: u8 = 0x04;
+const CMD_ATTR_LIN: u8 = 0x05;
+const CMD_ATTR_DIV: u8 = 0x06;
+const CMD_ATTR_CHR: u8 = 0x07;
+const CMD_PAL_SET: u8 = 0x0A;
+const CMD_PAL_TRN: u8 = 0x0B;
+const CMD_MLT_REQ: u8 = 0x11;
+const CMD_CHR_TRN: u8 = 0x13;
+const CMD_PCT_TRN: u8 = 0x14;
+const CMD_MASK_EN: u8 = 0x17;
+
+/// Bytes que se copian de VRAM en las transferencias `*_TRN`
+const TRANSFER_SIZE: usize = 0x1000;
+
+/// Qué muestra el SGB en lugar de la pantalla de la Game Boy (`MASK_EN`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SgbMask {
+    #[default]
+    Cancel,
+
+    /// Se congela la última imagen mostrada
+    Freeze,
+
+    /// Pantalla en negro
+    Black,
+
+    /// Pantalla del color 0
+    Color0,
+}
+
+/// Estado del Super Game Boy: recibe los paquetes de comandos que manda el
+/// juego bit a bit por las líneas de selección de JOYP y guarda las paletas,
+/// el mapa de atributos y el borde que configuran
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sgb {
+    /// Bits del paquete en curso, `None` si no se está recibiendo
+    bit: Option<usize>,
+    packet: [u8; 16],
+
+    /// Paquetes recibidos del comando en curso
+    packets: Vec<[u8; 16]>,
+
+    /// Últimas líneas de selección escritas, los bits solo cuentan al bajar
+    /// una línea tras haber estado las dos a 1
+    last_select: u8,
+
+    /// 4 paletas de 4 colores BGR555, el color 0 es compartido
+    palettes: [[u16; 4]; 4],
+
+    /// Paletas del sistema transferidas con `PAL_TRN`
+    system_palettes: Vec<[u16; 4]>,
+
+    /// Paleta de cada celda de 8x8 de la pantalla
+    attributes: Vec<u8>,
+
+    mask: SgbMask,
+
+    /// Imagen congelada con `SgbMask::Freeze`, en tonos de DMG
+    #[cfg(feature = "ppu")]
+    frozen: Option<Vec<u8>>,
+
+    /// Número de mandos (`MLT_REQ`) y mando que se lee en JOYP
+    players: u8,
+    player: u8,
+
+    /// Tiles del borde en formato 4bpp de SNES
+    chr: Vec<u8>,
+
+    /// Mapa de tiles del borde (32x32 entradas de 16 bits) seguido de sus
+    /// paletas, tal como llega con `PCT_TRN`
+    pct: Vec<u8>,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        // Sin configurar muestra la escala de grises de la DMG
+        let gray = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+        Self {
+            bit: None,
+            packet: [0; 16],
+            packets: Vec::new(),
+            last_select: JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS,
+            palettes: [gray; 4],
+            system_palettes: Vec::new(),
+            attributes: vec![0; ATTR_WIDTH * ATTR_HEIGHT],
+            mask: SgbMask::Cancel,
+            #[cfg(feature = "ppu")]
+            frozen: None,
+            players: 1,
+            player: 0,
+            chr: vec![0; 2 * TRANSFER_SIZE],
+            pct: vec![0; TRANSFER_SIZE],
+        }
+    }
+
+    /// Procesar una escritura del juego en JOYP, `vram` son los 4KB desde
+    /// 0x8000 para las transferencias
+    // TODO: El hardware copia lo que se muestra en pantalla, no la VRAM, los
+    // juegos lo preparan para que coincida con los tiles desde 0x8000
+    pub fn write_joyp(&mut self, value: u8, vram: &[u8]) {
+        let select = value & (JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS);
+        let last = std::mem::replace(&mut self.last_select, select);
+        let idle = last == JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS;
+
+        match select {
+            // Pulso de reset, empieza un paquete
+            0 => {
+                self.bit = Some(0);
+                self.packet = [0; 16];
+            },
+            // Con varios mandos el siguiente se selecciona al soltar P15
+            _ if select == JOYP_SELECT_DPAD | JOYP_SELECT_BUTTONS => {
+                if last == JOYP_SELECT_DPAD && self.bit.is_none() && self.players > 1 {
+                    self.player = (self.player + 1) % self.players;
+                }
+            },
+            _ if !idle => {},
+            _ => {
+                if let Some(bit) = self.bit {
+                    self.receive_bit(bit, select == JOYP_SELECT_DPAD, vram);
+                }
+            },
+        }
+    }
+
+    /// P14 a 0 es un bit a 0 y P15 a 0 un bit a 1, tras los 128 bits del
+    /// paquete va un bit de parada a 0
+    fn receive_bit(&mut self, bit: usize, one: bool, vram: &[u8]) {
+        if bit == 128 {
+            self.bit = None;
+            return;
+        }
+        if one {
+            self.packet[bit / 8] |= 1 << (bit % 8);
+        }
+        self.bit = Some(bit + 1);
+
+        if bit == 127 {
+            self.packets.push(self.packet);
+            let len = (self.packets[0][0] & 0b111).max(1) as usize;
+            if self.packets.len() >= len {
+                let packets = std::mem::take(&mut self.packets);
+                self.execute(&packets, vram);
+            }
+        }
+    }
+
+    fn execute(&mut self, packets: &[[u8; 16]], vram: &[u8]) {
+        let command = packets[0][0] >> 3;
+        let data = packets.iter().flatten().skip(1).copied().collect::<Vec<_>>();
+        let vram = &vram[..TRANSFER_SIZE.min(vram.len())];
+
+        match command {
+            CMD_PAL01 => self.set_palettes(0, 1, &data),
+            CMD_PAL23 => self.set_palettes(2, 3, &data),
+            CMD_PAL03 => self.set_palettes(0, 3, &data),
+            CMD_PAL12 => self.set_palettes(1, 2, &data),
+            CMD_ATTR_BLK => self.attr_blk(&data),
+            CMD_ATTR_LIN => self.attr_lin(&data),
+            CMD_ATTR_DIV => self.attr_div(&data),
+            CMD_ATTR_CHR => self.attr_chr(&data),
+            CMD_PAL_SET => self.pal_set(&data),
+            CMD_PAL_TRN => {
+                self.system_palettes = vram.chunks_exact(8)
+                    .map(|colors| std::array::from_fn(|i| {
+                        u16::from_le_bytes([colors[i * 2], colors[i * 2 + 1]])
+                    }))
+                    .collect();
+            },
+            CMD_MLT_REQ => {
+                self.players = match data[0] & 0b11 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            },
+            CMD_CHR_TRN => {
+                let start = (data[0] & 1) as usize * TRANSFER_SIZE;
+                self.chr[start..start + vram.len()].copy_from_slice(vram);
+            },
+            CMD_PCT_TRN => self.pct[..vram.len()].copy_from_slice(vram),
+            CMD_MASK_EN => {
+                self.mask = match data[0] & 0b11 {
+                    1 => SgbMask::Freeze,
+                    2 => SgbMask::Black,
+                    3 => SgbMask::Color0,
+                    _ => SgbMask::Cancel,
+                };
+                #[cfg(feature = "ppu")]
+                {
+                    self.frozen = None;
+                }
+            },
+            // TODO: ATTR_TRN, ATTR_SET, sonido y el resto de comandos
+            _ => {},
+        }
+    }
+
+    fn set_palettes(&mut self, a: usize, b: usize, data: &[u8]) {
+        let color = |i: usize| u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[a][i] = color(i);
+            self.palettes[b][i] = color(i + 3);
+        }
+    }
+
+    /// Cambiar la paleta de la celda `(x, y)` si está dentro de la pantalla
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < ATTR_WIDTH && y < ATTR_HEIGHT {
+            self.attributes[y * ATTR_WIDTH + x] = palette & 0b11;
+        }
+    }
+
+    fn attr_blk(&mut self, data: &[u8]) {
+        let count = data[0] as usize;
+        for block in data[1..].chunks_exact(6).take(count) {
+            let [control, palettes, x1, y1, x2, y2] = block.try_into().unwrap();
+            let (x1, y1, x2, y2) = (x1 as usize, y1 as usize, x2 as usize, y2 as usize);
+            let inside = palettes & 0b11;
+            let border = (palettes >> 2) & 0b11;
+            let outside = (palettes >> 4) & 0b11;
+
+            // Si solo se pide dentro o fuera el borde toma esa misma paleta
+            let (border_on, border) = match control & 0b111 {
+                0b001 => (true, inside),
+                0b100 => (true, outside),
+                control => (control & 0b010 != 0, border),
+            };
+
+            for y in 0..ATTR_HEIGHT {
+                for x in 0..ATTR_WIDTH {
+                    let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_border = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                    if on_border {
+                        if border_on {
+                            self.set_attribute(x, y, border);
+                        }
+                    } else if within {
+                        if control & 0b001 != 0 {
+                            self.set_attribute(x, y, inside);
+                        }
+                    } else if control & 0b100 != 0 {
+                        self.set_attribute(x, y, outside);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attr_lin(&mut self, data: &[u8]) {
+        let count = data[0] as usize;
+        for &line in data[1..].iter().take(count) {
+            let index = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0b11;
+            if line & 0x80 != 0 {
+                (0..ATTR_WIDTH).for_each(|x| self.set_attribute(x, index, palette));
+            } else {
+                (0..ATTR_HEIGHT).for_each(|y| self.set_attribute(index, y, palette));
+            }
+        }
+    }
+
+    fn attr_div(&mut self, data: &[u8]) {
+        let after = data[0] & 0b11;
+        let before = (data[0] >> 2) & 0b11;
+        let on_line = (data[0] >> 4) & 0b11;
+        let horizontal = data[0] & 0x40 != 0;
+        let line = data[1] as usize;
+
+        for y in 0..ATTR_HEIGHT {
+            for x in 0..ATTR_WIDTH {
+                let position = if horizontal { y } else { x };
+                let palette = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    fn attr_chr(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[0] as usize, data[1] as usize);
+        let count = u16::from_le_bytes([data[2], data[3]]) as usize;
+        let vertical = data[4] & 1 != 0;
+
+        let palettes = data[5..].iter()
+            .flat_map(|byte| (0..4).rev().map(move |i| (byte >> (i * 2)) & 0b11));
+        for palette in palettes.take(count) {
+            self.set_attribute(x, y, palette);
+            if vertical {
+                y += 1;
+                if y == ATTR_HEIGHT { y = 0; x += 1; }
+            } else {
+                x += 1;
+                if x == ATTR_WIDTH { x = 0; y += 1; }
+            }
+        }
+    }
+
+    fn pal_set(&mut self, data: &[u8]) {
+        for (i, palette) in data[..8].chunks_exact(2).enumerate() {
+            let index = u16::from_le_bytes([palette[0], palette[1]]) as usize & 0x1FF;
+            if let Some(colors) = self.system_palettes.get(index) {
+                self.palettes[i] = *colors;
+            }
+        }
+        // El color 0 de la primera paleta es el compartido
+        let shared = self.palettes[0][0];
+        self.palettes.iter_mut().for_each(|palette| palette[0] = shared);
+
+        if data[8] & 0x40 != 0 {
+            self.mask = SgbMask::Cancel;
+        }
+    }
+
+    /// Colores BGR555 de la paleta `index`
+    #[inline]
+    pub fn palette(&self, index: usize) -> [u16; 4] {
+        self.palettes[index]
+    }
+
+    /// Paleta asignada a la celda de 8x8 `(x, y)` de la pantalla
+    #[inline]
+    pub fn attribute(&self, x: usize, y: usize) -> u8 {
+        self.attributes[y * ATTR_WIDTH + x]
+    }
+
+    #[inline]
+    pub fn mask(&self) -> SgbMask {
+        self.mask
+    }
+
+    /// Número de mandos pedido con `MLT_REQ`
+    #[inline]
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    /// Valor que lee el juego de JOYP sin ninguna fila seleccionada, con
+    /// varios mandos los 4 bits bajos indican cuál está seleccionado
+    #[inline]
+    pub fn joypad_id(&self) -> u8 {
+        0xFF - self.player
+    }
+
+    /// Componer la imagen de 256x224 RGBA con el borde y la pantalla de la
+    /// Game Boy coloreada con las paletas del SGB. Los tonos de `frame` se
+    /// sacan de su brillo, por lo que debe estar renderizado en grises
+    #[cfg(feature = "ppu")]
+    pub fn render(&mut self, frame: &Frame) -> Vec<u8> {
+        let mut pixels = vec![0; SGB_WIDTH * SGB_HEIGHT * 4];
+        let backdrop = bgr555_to_rgba(self.palettes[0][0]);
+        self.render_border(&mut pixels, backdrop);
+
+        let shades = (0..SCREEN_HEIGHT)
+            .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| 3 - (frame.pixel(x, y)[0] >> 6))
+            .collect::<Vec<_>>();
+        let shades = match self.mask {
+            SgbMask::Freeze => self.frozen.get_or_insert(shades).clone(),
+            _ => shades,
+        };
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let color = match self.mask {
+                    SgbMask::Black => [0, 0, 0, 0xFF],
+                    SgbMask::Color0 => backdrop,
+                    SgbMask::Cancel | SgbMask::Freeze => {
+                        let palette = self.attribute(x / 8, y / 8) as usize;
+                        let shade = shades[y * SCREEN_WIDTH + x] as usize;
+                        bgr555_to_rgba(self.palettes[palette][shade])
+                    },
+                };
+                let i = ((SCREEN_Y + y) * SGB_WIDTH + SCREEN_X + x) * 4;
+                pixels[i..i + 4].copy_from_slice(&color);
+            }
+        }
+        pixels
+    }
+
+    /// Dibujar el borde con los tiles de `CHR_TRN` y el mapa de `PCT_TRN`, el
+    /// color 0 de los tiles es transparente y deja ver `backdrop`
+    #[cfg(feature = "ppu")]
+    fn render_border(&self, pixels: &mut [u8], backdrop: [u8; 4]) {
+        for ty in 0..SGB_HEIGHT / 8 {
+            for tx in 0..SGB_WIDTH / 8 {
+                let i = (ty * 32 + tx) * 2;
+                let entry = u16::from_le_bytes([self.pct[i], self.pct[i + 1]]);
+                let tile = &self.chr[(entry & 0xFF) as usize * 32..][..32];
+                let palette = (((entry >> 10) & 0b111) as usize).saturating_sub(4);
+                let flip_x = entry & 0x4000 != 0;
+                let flip_y = entry & 0x8000 != 0;
+
+                for row in 0..8 {
+                    let src_row = if flip_y { 7 - row } else { row };
+                    let planes = [
+                        tile[src_row * 2], tile[src_row * 2 + 1],
+                        tile[16 + src_row * 2], tile[16 + src_row * 2 + 1],
+                    ];
+                    for col in 0..8 {
+                        let bit = if flip_x { col } else { 7 - col };
+                        let color = planes.iter().enumerate()
+                            .fold(0, |color, (plane, byte)| {
+                                color | (((byte >> bit) & 1) as usize) << plane
+                            });
+                        let rgba = match color {
+                            0 => backdrop,
+                            color => {
+                                let offset = 0x800 + (palette * 16 + color) * 2;
+                                bgr555_to_rgba(u16::from_le_bytes(
+                                    [self.pct[offset], self.pct[offset + 1]]))
+                            },
+                        };
+                        let p = ((ty * 8 + row) * SGB_WIDTH + tx * 8 + col) * 4;
+                        pixels[p..p + 4].copy_from_slice(&rgba);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pasar un color de 15 bits de la SNES a RGBA de 8 bits por canal
+#[cfg(feature = "ppu")]
+fn bgr555_to_rgba(color: u16) -> [u8; 4] {
+    let channel = |shift: u16| {
+        let value = ((color >> shift) & 0x1F) as u8;
+        (value << 3) | (value >> 2)
+    };
+    [channel(0), channel(5), channel(10), 0xFF]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mandar un comando como lo haría un juego escribiendo en JOYP
+    fn send(sgb: &mut Sgb, packets: &[[u8; 16]]) {
+        for packet in packets {
+            sgb.write_joyp(0x00, &[]);
+            sgb.write_joyp(0x30, &[]);
+            for bit in 0..128 {
+                let one = packet[bit / 8] & (1 << (bit % 8)) != 0;
+                sgb.write_joyp(if one { 0x10 } else { 0x20 }, &[]);
+                sgb.write_joyp(0x30, &[]);
+            }
+            sgb.write_joyp(0x20, &[]);
+            sgb.write_joyp(0x30, &[]);
+        }
+    }
+
+    #[test]
+    fn palette_and_attribute_packets() {
+        let mut sgb = Sgb::new();
+
+        let mut pal01 = [0; 16];
+        pal01[0] = (CMD_PAL01 << 3) | 1;
+        pal01[1..3].copy_from_slice(&0x001Fu16.to_le_bytes());
+        pal01[9..11].copy_from_slice(&0x7C00u16.to_le_bytes());
+        send(&mut sgb, &[pal01]);
+        assert_eq!(sgb.palette(0)[0], 0x001F);
+        assert_eq!(sgb.palette(3)[0], 0x001F);
+        assert_eq!(sgb.palette(1)[1], 0x7C00);
+
+        // Un bloque de (2, 2) a (5, 5) con el interior en la paleta 1 y el
+        // borde en la 2
+        let mut attr = [0; 16];
+        attr[0] = (CMD_ATTR_BLK << 3) | 1;
+        attr[1..8].copy_from_slice(&[1, 0b011, 0b1001, 2, 2, 5, 5]);
+        send(&mut sgb, &[attr]);
+        assert_eq!(sgb.attribute(3, 3), 1);
+        assert_eq!(sgb.attribute(2, 4), 2);
+        assert_eq!(sgb.attribute(0, 0), 0);
+
+        let mut mlt = [0; 16];
+        mlt[0] = (CMD_MLT_REQ << 3) | 1;
+        mlt[1] = 1;
+        send(&mut sgb, &[mlt]);
+        assert_eq!(sgb.players(), 2);
+        assert_eq!(sgb.joypad_id(), 0xFF);
+        sgb.write_joyp(0x10, &[]);
+        sgb.write_joyp(0x30, &[]);
+        assert_eq!(sgb.joypad_id(), 0xFE);
+    }
+
+    #[cfg(feature = "ppu")]
+    #[test]
+    fn render_with_mask() {
+        let mut sgb = Sgb::new();
+        let mut mask = [0; 16];
+        mask[0] = (CMD_MASK_EN << 3) | 1;
+        mask[1] = 2;
+        send(&mut sgb, &[mask]);
+        assert_eq!(sgb.mask(), SgbMask::Black);
+
+        let pixels = sgb.render(&Frame::new());
+        assert_eq!(pixels.len(), SGB_WIDTH * SGB_HEIGHT * 4);
+        let i = (SCREEN_Y * SGB_WIDTH + SCREEN_X) * 4;
+        assert_eq!(pixels[i..i + 4], [0, 0, 0, 0xFF]);
+
+        // El borde vacío muestra el color 0, blanco por defecto
+        assert_eq!(pixels[..4], [0xFF; 4]);
+    }
+}
@@ -0,0 +1,30 @@
+#[cfg(feature = "ppu")]
+use crate::frame::Frame;
+
+#[cfg(feature = "ppu")]
+/// Destino de los frames, lo implementa el frontend para mostrarlos en
+/// pantalla, guardarlos, enviarlos por red...
+pub trait VideoSink: Send {
+    /// Se llama al terminar cada frame que se renderiza
+    fn present(&mut self, frame: &Frame);
+}
+
+/// Destino del audio generado, muestras estéreo intercaladas (izquierda,
+/// derecha)
+/// Destino de la RAM del cartucho con batería, lo llama
+/// `GameBoy::flush_sram` para que el frontend la guarde donde quiera
+pub trait BatterySink: Send {
+    fn flush(&mut self, sram: &[u8]) -> std::io::Result<()>;
+}
+
+#[cfg(feature = "apu")]
+// TODO: Todavía no hay APU, por lo que de momento no recibe muestras
+pub trait AudioSink: Send {
+    fn queue_samples(&mut self, samples: &[i16]);
+
+    /// Muestras encoladas que todavía no se han reproducido, para
+    /// `FrameTiming::audio_buffered`. `None` si el sink no lo sabe
+    fn buffered(&self) -> Option<usize> {
+        None
+    }
+}
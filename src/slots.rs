@@ -0,0 +1,148 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::gameboy::GameBoy;
+use crate::saves::file_title;
+
+/// Extensión de los ficheros de cada slot
+const SLOT_EXTENSION: &str = "gbst";
+
+/// Información de un slot ocupado
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub slot: u32,
+    pub path: PathBuf,
+
+    /// Última vez que se guardó
+    pub modified: SystemTime,
+
+    /// Tamaño del save state en bytes
+    pub size: u64,
+}
+
+/// Slots numerados de save states de una ROM, cada uno es un fichero
+/// `slot<N>.gbst` dentro de un directorio propio de la ROM con el nombre
+/// `<TÍTULO>-<hash>`, así varias ROMs con el mismo título no se pisan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotManager {
+    dir: PathBuf,
+}
+
+impl SlotManager {
+    /// Slots de la ROM cargada en `gb` dentro de `base`, el directorio no se
+    /// crea hasta que se guarda el primer slot
+    pub fn new(base: impl AsRef<Path>, gb: &GameBoy) -> Self {
+        let title = file_title(gb);
+        Self::in_dir(base.as_ref().join(format!("{title}-{:016x}", gb.rom_hash())))
+    }
+
+    /// Slots directamente en `dir`, ver `SaveManager::slots`
+    pub(crate) fn in_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Directorio con los slots de la ROM
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, slot: u32) -> PathBuf {
+        self.dir.join(format!("slot{slot}.{SLOT_EXTENSION}"))
+    }
+
+    /// Guardar el estado en el slot, se escribe primero en un fichero
+    /// temporal para no perder el anterior si algo falla a mitad
+    pub fn save(&self, slot: u32, gb: &GameBoy) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(slot);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, gb.save_state())?;
+        fs::rename(tmp, path)
+    }
+
+    /// Cargar el estado del slot, un state inválido da `InvalidData`
+    pub fn load(&self, slot: u32, gb: &mut GameBoy) -> io::Result<()> {
+        let state = fs::read(self.path(slot))?;
+        gb.load_state(&state)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Borrar el slot, no es un error si ya estaba vacío
+    pub fn delete(&self, slot: u32) -> io::Result<()> {
+        match fs::remove_file(self.path(slot)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Slots ocupados ordenados por número
+    pub fn list(&self) -> io::Result<Vec<SlotInfo>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            entries => entries?,
+        };
+
+        let mut slots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SLOT_EXTENSION) {
+                continue;
+            }
+            let Some(slot) = path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.strip_prefix("slot"))
+                .and_then(|slot| slot.parse().ok())
+            else {
+                continue;
+            };
+
+            let metadata = fs::metadata(&path)?;
+            slots.push(SlotInfo {
+                slot,
+                path,
+                modified: metadata.modified()?,
+                size: metadata.len(),
+            });
+        }
+        slots.sort_by_key(|info| info.slot);
+        Ok(slots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_list_load() {
+        let base = std::env::temp_dir()
+            .join(format!("gameboi-slots-{}", std::process::id()));
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x013C].copy_from_slice(b"TEST ROM");
+
+        let mut gb = GameBoy::builder().rom(rom).build().unwrap();
+        let slots = SlotManager::new(&base, &gb);
+        assert!(slots.dir().file_name().unwrap().to_str().unwrap()
+            .starts_with("TEST_ROM-"));
+        assert!(slots.list().unwrap().is_empty());
+
+        slots.save(3, &gb).unwrap();
+        gb.run_cycles(1000);
+        slots.save(1, &gb).unwrap();
+        let listed = slots.list().unwrap().iter().map(|info| info.slot).collect::<Vec<_>>();
+        assert_eq!(listed, [1, 3]);
+
+        slots.load(3, &mut gb).unwrap();
+        assert_eq!(gb.cycles(), 0);
+
+        slots.delete(3).unwrap();
+        slots.delete(3).unwrap();
+        assert_eq!(slots.list().unwrap().len(), 1);
+        assert_eq!(slots.load(3, &mut gb).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
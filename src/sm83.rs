@@ -0,0 +1,198 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use serde::Deserialize;
+
+use crate::access::{AccessKind, LoggedBus};
+use crate::{Cpu, Error, Reg};
+
+/// Estado de la CPU y la RAM de un caso
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Sm83State {
+    pub pc: u16,
+    pub sp: u16,
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub f: u8,
+    pub h: u8,
+    pub l: u8,
+
+    /// La CPU todavía no tiene IME, se ignora
+    // TODO: Compararlo cuando se implementen las interrupciones
+    #[serde(default)]
+    pub ime: Option<u8>,
+
+    /// IE, se escribe en 0xFFFF
+    #[serde(default)]
+    pub ie: Option<u8>,
+
+    /// Bytes de RAM como pares `[dirección, valor]`
+    pub ram: Vec<(u16, u8)>,
+}
+
+/// Un caso de los tests JSON de una sola instrucción de la comunidad
+/// (GameboyCPUTests o SingleStepTests/sm83)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Sm83Case {
+    pub name: String,
+    pub initial: Sm83State,
+    #[serde(rename = "final")]
+    pub expected: Sm83State,
+
+    /// Un elemento por M-cycle, `null` o `[dirección, valor, tipo]`
+    pub cycles: Vec<serde_json::Value>,
+}
+
+/// Resultado de un fichero de casos
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sm83Summary {
+    pub passed: usize,
+
+    /// Nombre del caso y qué falló
+    pub failures: Vec<(String, String)>,
+}
+
+impl Sm83Summary {
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.passed + self.failures.len()
+    }
+}
+
+impl Sm83Case {
+    /// Ejecutar el caso sobre 64 KiB de RAM plana y comparar el estado final,
+    /// los T-cycles y las escrituras en memoria en orden. Las lecturas no se
+    /// comparan porque el decode lee el opcode y los inmediatos por fuera
+    /// del bus que se vigila. Con `prefetched` el `pc` de los estados apunta
+    /// después del opcode, como en SingleStepTests
+    pub fn run(&self, prefetched: bool) -> Result<(), String> {
+        let mut ram = vec![0; 0x10000];
+        let mut cpu = Cpu::new();
+        load_state(&mut cpu, &mut ram, &self.initial, prefetched);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let instr = cpu.decode(ram.as_slice())?;
+            let pc = cpu.pc();
+            let mut bus = LoggedBus::new(ram.as_mut_slice(), pc, 0);
+            let cycles = cpu.execute_instr(instr, &mut bus)?;
+            Ok::<_, Error>((cycles, bus.into_accesses()))
+        }));
+        let (cycles, accesses) = match result {
+            Ok(Ok(result)) => result,
+            Ok(Err(err)) => return Err(err.to_string()),
+            Err(_) => return Err("panic al ejecutar".into()),
+        };
+
+        let expected = &self.expected;
+        let pc = if prefetched { expected.pc.wrapping_sub(1) } else { expected.pc };
+        let registers = [
+            ("a", Reg::A, expected.a), ("f", Reg::F, expected.f),
+            ("b", Reg::B, expected.b), ("c", Reg::C, expected.c),
+            ("d", Reg::D, expected.d), ("e", Reg::E, expected.e),
+            ("h", Reg::H, expected.h), ("l", Reg::L, expected.l),
+        ];
+        for (name, reg, value) in registers {
+            let actual = cpu.read_reg(reg);
+            if actual != value {
+                return Err(format!("{name} = {actual:#04X}, se esperaba {value:#04X}"));
+            }
+        }
+        if cpu.read_widereg(Reg::SP) != expected.sp {
+            return Err(format!("sp = {:#06X}, se esperaba {:#06X}",
+                cpu.read_widereg(Reg::SP), expected.sp));
+        }
+        if cpu.pc() != pc {
+            return Err(format!("pc = {:#06X}, se esperaba {pc:#06X}", cpu.pc()));
+        }
+        for &(addr, value) in &expected.ram {
+            if ram[addr as usize] != value {
+                return Err(format!("[{addr:#06X}] = {:#04X}, se esperaba {value:#04X}",
+                    ram[addr as usize]));
+            }
+        }
+
+        if cycles as usize != self.cycles.len() * 4 {
+            return Err(format!("{cycles} T-cycles, se esperaban {}", self.cycles.len() * 4));
+        }
+        let writes = accesses.iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .map(|access| (access.addr, access.value))
+            .collect::<Vec<_>>();
+        let expected_writes = self.cycles.iter().filter_map(cycle_write).collect::<Vec<_>>();
+        if writes != expected_writes {
+            return Err(format!("escrituras {writes:X?}, se esperaban {expected_writes:X?}"));
+        }
+        Ok(())
+    }
+}
+
+fn load_state(cpu: &mut Cpu, ram: &mut [u8], state: &Sm83State, prefetched: bool) {
+    for (reg, value) in [(Reg::A, state.a), (Reg::F, state.f), (Reg::B, state.b),
+        (Reg::C, state.c), (Reg::D, state.d), (Reg::E, state.e), (Reg::H, state.h),
+        (Reg::L, state.l)]
+    {
+        cpu.write_reg(reg, value);
+    }
+    cpu.write_widereg(Reg::SP, state.sp);
+    cpu.set_pc(if prefetched { state.pc.wrapping_sub(1) } else { state.pc });
+    for &(addr, value) in &state.ram {
+        ram[addr as usize] = value;
+    }
+    if let Some(ie) = state.ie {
+        ram[0xFFFF] = ie;
+    }
+}
+
+/// Escritura de un M-cycle, los tipos son `write` o como `-wm`
+fn cycle_write(cycle: &serde_json::Value) -> Option<(u16, u8)> {
+    let [addr, value, kind] = cycle.as_array()?.as_slice() else {
+        return None;
+    };
+    if !kind.as_str()?.contains('w') {
+        return None;
+    }
+    Some((addr.as_u64()? as u16, value.as_u64()? as u8))
+}
+
+/// Ejecutar todos los casos de un fichero JSON (un array de casos, uno por
+/// opcode en los repositorios de tests)
+pub fn run_sm83_json(json: &str, prefetched: bool) -> serde_json::Result<Sm83Summary> {
+    let cases: Vec<Sm83Case> = serde_json::from_str(json)?;
+    let mut summary = Sm83Summary::default();
+    for case in &cases {
+        match case.run(prefetched) {
+            Ok(()) => summary.passed += 1,
+            Err(reason) => summary.failures.push((case.name.clone(), reason)),
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_cases() {
+        // LD B, n con el pc en el opcode, el segundo caso espera otro valor
+        let case = |pc: u16, b: u8| format!(r#"
+            {{"name": "06 {b:02x}",
+             "initial": {{"pc": {pc}, "sp": 65534, "a": 1, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 176, "h": 0, "l": 0, "ime": 0, "ie": 0, "ram": [[256, 6], [257, 18]]}},
+             "final": {{"pc": {}, "sp": 65534, "a": 1, "b": {b}, "c": 0, "d": 0, "e": 0,
+                "f": 176, "h": 0, "l": 0, "ime": 0, "ram": [[256, 6], [257, 18]]}},
+             "cycles": [[256, 6, "r-m"], null]}}"#, pc + 2);
+        let json = format!("[{}, {}]", case(256, 18), case(256, 19));
+        let summary = run_sm83_json(&json, false).unwrap();
+        assert_eq!(summary.passed, 1);
+        assert_eq!(summary.failures, [("06 13".into(), "b = 0x12, se esperaba 0x13".into())]);
+
+        // En SingleStepTests el opcode ya se leyó
+        let summary = run_sm83_json(&format!("[{}]", case(257, 18)), true).unwrap();
+        assert_eq!((summary.passed, summary.total()), (1, 1));
+        assert_eq!(cycle_write(&serde_json::json!([49152, 42, "-wm"])), Some((0xC000, 42)));
+        assert_eq!(cycle_write(&serde_json::json!([49152, 42, "read"])), None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Identificador al inicio de los save states
+const STATE_MAGIC: &[u8; 4] = b"GBST";
+
+/// Versión del formato, solo se incrementa en cambios incompatibles. Añadir
+/// información nueva se hace con chunks nuevos, que las versiones anteriores
+/// del crate simplemente saltan
+pub const STATE_VERSION: u16 = 1;
+
+/// Tamaño de la cabecera: magic, versión y hash de la ROM
+const HEADER_LEN: usize = 4 + 2 + 8;
+
+/// Bit alto de la longitud de un chunk que indica que está comprimido
+const CHUNK_COMPRESSED: u32 = 1 << 31;
+
+/// Etiquetas de los chunks de cada subsistema
+pub(crate) const CHUNK_CPU: [u8; 4] = *b"CPU ";
+pub(crate) const CHUNK_MMU: [u8; 4] = *b"MMU ";
+pub(crate) const CHUNK_FACADE: [u8; 4] = *b"GBOY";
+pub(crate) const CHUNK_IME: [u8; 4] = *b"IME ";
+#[cfg(feature = "ppu")]
+pub(crate) const CHUNK_FRAME: [u8; 4] = *b"FRAM";
+#[cfg(feature = "ppu")]
+pub(crate) const CHUNK_PALETTE: [u8; 4] = *b"PALT";
+
+/// Por qué no se pudo cargar un save state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// Se acabaron los datos en mitad de la cabecera o de un chunk
+    Truncated,
+
+    /// No empieza por el magic, no es un save state
+    BadMagic,
+
+    /// Lo generó una versión del crate con un formato incompatible
+    UnsupportedVersion(u16),
+
+    /// Se guardó con otra ROM
+    RomMismatch { expected: u64, found: u64 },
+
+    /// Falta un chunk obligatorio
+    MissingChunk([u8; 4]),
+
+    /// El contenido de un chunk no es válido
+    Corrupt([u8; 4]),
+
+    /// El chunk está comprimido y el crate se compiló sin `compression`
+    CompressionUnsupported([u8; 4]),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = |tag: &[u8; 4]| String::from_utf8_lossy(tag).into_owned();
+        match self {
+            StateError::Truncated => write!(f, "save state truncado"),
+            StateError::BadMagic => write!(f, "no es un save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "versión de save state {version} no soportada \
+                    (se soporta hasta la {STATE_VERSION})")
+            },
+            StateError::RomMismatch { expected, found } => {
+                write!(f, "el save state es de otra ROM (hash {found:016X}, \
+                    la cargada es {expected:016X})")
+            },
+            StateError::MissingChunk(t) => write!(f, "falta el chunk {:?}", tag(t)),
+            StateError::Corrupt(t) => write!(f, "chunk {:?} corrupto", tag(t)),
+            StateError::CompressionUnsupported(t) => {
+                write!(f, "chunk {:?} comprimido, hace falta la feature \
+                    `compression`", tag(t))
+            },
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Hash FNV-1a de la ROM con el que se comprueba que un save state
+/// corresponde al juego cargado
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Genera un save state:
+/// - 4 bytes de magic `GBST`
+/// - 2 bytes de versión (little endian)
+/// - 8 bytes con el hash de la ROM (little endian)
+/// - Chunks con 4 bytes de etiqueta, 4 de longitud (little endian) y los
+///   datos del subsistema. Si el bit alto de la longitud está a 1 los datos
+///   están comprimidos con LZ4 precedidos de su tamaño sin comprimir, con la
+///   feature `compression` se comprimen los chunks que así ocupan menos
+pub(crate) struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_hash: u64) -> Self {
+        let mut buffer = Vec::with_capacity(0x11000);
+        buffer.extend_from_slice(STATE_MAGIC);
+        buffer.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&rom_hash.to_le_bytes());
+        Self { buffer }
+    }
+
+    pub fn chunk<T: Serialize + ?Sized>(&mut self, tag: [u8; 4], value: &T) {
+        // Todos los subsistemas tienen tamaño conocido, no puede fallar
+        let data = bincode::serialize(value).unwrap();
+
+        // La memoria es casi toda ceros, comprimida ocupa una fracción
+        #[cfg(feature = "compression")]
+        let (data, flags) = match lz4_flex::compress_prepend_size(&data) {
+            compressed if compressed.len() < data.len() => (compressed, CHUNK_COMPRESSED),
+            _ => (data, 0),
+        };
+        #[cfg(not(feature = "compression"))]
+        let flags = 0;
+
+        self.buffer.extend_from_slice(&tag);
+        self.buffer.extend_from_slice(&(data.len() as u32 | flags).to_le_bytes());
+        self.buffer.extend_from_slice(&data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Lee los chunks de un save state validando la cabecera
+pub(crate) struct StateReader<'a> {
+    chunks: Vec<([u8; 4], bool, &'a [u8])>,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(mut data: &'a [u8], rom_hash: u64) -> Result<Self, StateError> {
+        let header = data.get(..HEADER_LEN).ok_or(StateError::Truncated)?;
+        if &header[..4] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version == 0 || version > STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let found = u64::from_le_bytes(header[6..14].try_into().unwrap());
+        if found != rom_hash {
+            return Err(StateError::RomMismatch { expected: rom_hash, found });
+        }
+
+        data = &data[HEADER_LEN..];
+        let mut chunks = Vec::new();
+        while !data.is_empty() {
+            let header = data.get(..8).ok_or(StateError::Truncated)?;
+            let tag = header[..4].try_into().unwrap();
+            let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+            let compressed = len & CHUNK_COMPRESSED != 0;
+            let len = (len & !CHUNK_COMPRESSED) as usize;
+            let chunk = data.get(8..8 + len).ok_or(StateError::Truncated)?;
+            chunks.push((tag, compressed, chunk));
+            data = &data[8 + len..];
+        }
+        Ok(Self { chunks })
+    }
+
+    /// Leer un chunk que puede no estar, por ejemplo porque el state lo
+    /// generó un build sin esa feature
+    pub fn optional<T: DeserializeOwned>(&self, tag: [u8; 4])
+        -> Result<Option<T>, StateError>
+    {
+        let Some(&(_, compressed, data)) = self.chunks.iter().find(|(t, ..)| *t == tag)
+        else {
+            return Ok(None);
+        };
+
+        let decompressed;
+        let data = if compressed {
+            decompressed = decompress(tag, data)?;
+            &decompressed[..]
+        } else {
+            data
+        };
+        bincode::deserialize(data).map(Some).map_err(|_| StateError::Corrupt(tag))
+    }
+
+    pub fn chunk<T: DeserializeOwned>(&self, tag: [u8; 4]) -> Result<T, StateError> {
+        self.optional(tag)?.ok_or(StateError::MissingChunk(tag))
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decompress(tag: [u8; 4], data: &[u8]) -> Result<Vec<u8>, StateError> {
+    lz4_flex::decompress_size_prepended(data).map_err(|_| StateError::Corrupt(tag))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(tag: [u8; 4], _data: &[u8]) -> Result<Vec<u8>, StateError> {
+    Err(StateError::CompressionUnsupported(tag))
+}
+
+/// serde solo implementa arrays de hasta 32 elementos, los más grandes se
+/// guardan como una secuencia de bytes de la longitud exacta
+pub(crate) mod boxed_array {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(array: &[u8; N], serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        serializer.serialize_bytes(array)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D)
+        -> Result<Box<[u8; N]>, D::Error>
+    {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        let len = bytes.len();
+        bytes.try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"un array de bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_validation() {
+        let mut writer = StateWriter::new(1);
+        writer.chunk(*b"NEW!", &[1u8, 2, 3]);
+        writer.chunk(CHUNK_CPU, &42u32);
+        let state = writer.finish();
+
+        // Los chunks desconocidos se saltan
+        let reader = StateReader::new(&state, 1).unwrap();
+        assert_eq!(reader.chunk::<u32>(CHUNK_CPU), Ok(42));
+        assert_eq!(reader.chunk::<u32>(CHUNK_MMU),
+            Err(StateError::MissingChunk(CHUNK_MMU)));
+
+        assert_eq!(StateReader::new(&state, 2).err(),
+            Some(StateError::RomMismatch { expected: 2, found: 1 }));
+        assert_eq!(StateReader::new(&state[..state.len() - 1], 1).err(),
+            Some(StateError::Truncated));
+
+        let mut future = state.clone();
+        future[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(StateReader::new(&future, 1).err(),
+            Some(StateError::UnsupportedVersion(STATE_VERSION + 1)));
+        assert_eq!(StateReader::new(b"GBMV", 1).err(), Some(StateError::Truncated));
+    }
+
+    #[test]
+    fn compressed_chunks() {
+        let zeros = vec![0u8; 0x10000];
+        let mut writer = StateWriter::new(0);
+        writer.chunk(CHUNK_MMU, &zeros);
+        let state = writer.finish();
+
+        let reader = StateReader::new(&state, 0).unwrap();
+        assert_eq!(reader.chunk::<Vec<u8>>(CHUNK_MMU), Ok(zeros));
+        assert_eq!(state.len() < 0x1000, cfg!(feature = "compression"));
+
+        // Un chunk marcado como comprimido sin datos válidos
+        let mut corrupt = state[..HEADER_LEN].to_vec();
+        corrupt.extend_from_slice(&CHUNK_CPU);
+        corrupt.extend_from_slice(&(2 | CHUNK_COMPRESSED).to_le_bytes());
+        corrupt.extend_from_slice(&[0xFF, 0xFF]);
+        let reader = StateReader::new(&corrupt, 0).unwrap();
+        assert!(matches!(reader.chunk::<u8>(CHUNK_CPU),
+            Err(StateError::Corrupt(_) | StateError::CompressionUnsupported(_))));
+    }
+}
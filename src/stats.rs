@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Tiempo del host que se pasó en cada subsistema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubsystemTimes {
+    /// Decodificar y ejecutar instrucciones, incluidos sus accesos a memoria
+    pub cpu: Duration,
+
+    /// Entregar el frame al `VideoSink`
+    pub ppu: Duration,
+
+    /// Todavía no hay APU, siempre es cero
+    pub apu: Duration,
+
+    /// Avanzar los periféricos del MMU: timer, serie, RTC, DMA...
+    pub mmu: Duration,
+}
+
+impl SubsystemTimes {
+    #[inline]
+    pub fn total(&self) -> Duration {
+        self.cpu + self.ppu + self.apu + self.mmu
+    }
+
+    fn add(&mut self, other: &SubsystemTimes) {
+        self.cpu += other.cpu;
+        self.ppu += other.ppu;
+        self.apu += other.apu;
+        self.mmu += other.mmu;
+    }
+}
+
+/// Contadores de tiempo por subsistema que se conectan con
+/// `GameBoy::set_subsystem_stats`, para ver cuál hace que una plataforma no
+/// llegue a los 60 frames por segundo. Medir cuesta un par de lecturas del
+/// reloj por instrucción, así que solo se mide mientras está conectado
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubsystemStats {
+    /// Lo que va del frame en curso
+    current: SubsystemTimes,
+    last_frame: SubsystemTimes,
+
+    /// El frame terminado que más tardó
+    worst_frame: SubsystemTimes,
+    total: SubsystemTimes,
+    frames: u64,
+}
+
+impl SubsystemStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub(crate) fn add_cpu(&mut self, time: Duration) {
+        self.current.cpu += time;
+    }
+
+    /// Sin la feature `ppu` no hay frames que entregar
+    #[inline]
+    #[cfg_attr(not(feature = "ppu"), allow(dead_code))]
+    pub(crate) fn add_ppu(&mut self, time: Duration) {
+        self.current.ppu += time;
+    }
+
+    #[inline]
+    pub(crate) fn add_mmu(&mut self, time: Duration) {
+        self.current.mmu += time;
+    }
+
+    /// Cerrar el frame en curso
+    pub(crate) fn end_frame(&mut self) {
+        let frame = std::mem::take(&mut self.current);
+        self.total.add(&frame);
+        if frame.total() > self.worst_frame.total() {
+            self.worst_frame = frame;
+        }
+        self.last_frame = frame;
+        self.frames += 1;
+    }
+
+    /// Tiempos del último frame terminado
+    #[inline]
+    pub fn last_frame(&self) -> SubsystemTimes {
+        self.last_frame
+    }
+
+    #[inline]
+    pub fn worst_frame(&self) -> SubsystemTimes {
+        self.worst_frame
+    }
+
+    /// Tiempos acumulados de todos los frames terminados
+    #[inline]
+    pub fn total(&self) -> SubsystemTimes {
+        self.total
+    }
+
+    /// Frames terminados desde que se conectó o se reinició
+    #[inline]
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Media por frame, cero si todavía no terminó ninguno
+    pub fn average(&self) -> SubsystemTimes {
+        let frames = self.frames.clamp(1, u32::MAX as u64) as u32;
+        SubsystemTimes {
+            cpu: self.total.cpu / frames,
+            ppu: self.total.ppu / frames,
+            apu: self.total.apu / frames,
+            mmu: self.total.mmu / frames,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Métricas de un frame terminado, ver `FrameStats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// `GameBoy::frame_count` al terminarlo
+    pub frame: u64,
+
+    /// T-cycles emulados, oscila alrededor de `CYCLES_PER_FRAME` porque el
+    /// frame termina con la instrucción que lo cruza
+    pub cycles: u32,
+
+    /// Tiempo del host emulando el frame, sin lo que pasa entre frames en el
+    /// frontend ni la espera del limitador
+    pub emulation: Duration,
+
+    /// Lo que esperó el limitador después del frame
+    pub sleep: Duration,
+
+    /// El limitador esperó, si no lo hizo el frame llegó tarde. Fuera de
+    /// `run_frame_realtime` o en modo turbo siempre es `false`
+    pub slept: bool,
+
+    /// Muestras en el buffer del `AudioSink` al terminar el frame, `None`
+    /// si no hay sink o no lo informa
+    pub audio_buffered: Option<usize>,
+}
+
+/// Métricas de los últimos frames que se conectan con
+/// `GameBoy::set_frame_stats`, para dibujar una gráfica de rendimiento en el
+/// frontend o ver de dónde vienen los tirones: un frame que tarda en
+/// emularse, un limitador que no llega a esperar o un buffer de audio que se
+/// vacía
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    capacity: usize,
+    frames: VecDeque<FrameTiming>,
+
+    /// Instante y ciclo en los que empezó a emularse el frame en curso
+    start: Option<(Instant, u64)>,
+}
+
+impl FrameStats {
+    /// Guardar como mucho los `capacity` últimos frames
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            start: None,
+        }
+    }
+
+    /// Frames guardados del más antiguo al más reciente
+    pub fn frames(&self) -> impl DoubleEndedIterator<Item = &FrameTiming> {
+        self.frames.iter()
+    }
+
+    #[inline]
+    pub fn last(&self) -> Option<&FrameTiming> {
+        self.frames.back()
+    }
+
+    /// Tiempo de emulación medio de los frames guardados
+    pub fn average_emulation(&self) -> Duration {
+        let frames = self.frames.len().clamp(1, u32::MAX as usize) as u32;
+        self.frames.iter().map(|frame| frame.emulation).sum::<Duration>() / frames
+    }
+
+    /// Frames guardados en los que el limitador no llegó a esperar
+    pub fn late_frames(&self) -> usize {
+        self.frames.iter().filter(|frame| !frame.slept).count()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.start = None;
+    }
+
+    /// Apuntar el inicio del frame si es su primera instrucción
+    #[inline]
+    pub(crate) fn begin_frame(&mut self, cycle: u64) {
+        if self.start.is_none() {
+            self.start = Some((Instant::now(), cycle));
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self, frame: u64, cycle: u64, audio_buffered: Option<usize>) {
+        let Some((start, start_cycle)) = self.start.take() else {
+            return;
+        };
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(FrameTiming {
+            frame,
+            cycles: (cycle - start_cycle) as u32,
+            emulation: start.elapsed(),
+            sleep: Duration::ZERO,
+            slept: false,
+            audio_buffered,
+        });
+    }
+
+    /// Apuntar la espera del limitador en el último frame
+    pub(crate) fn record_sleep(&mut self, sleep: Duration, slept: bool) {
+        if let Some(frame) = self.frames.back_mut() {
+            frame.sleep = sleep;
+            frame.slept = slept;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_accounting() {
+        let ms = Duration::from_millis;
+        let mut stats = SubsystemStats::new();
+        assert_eq!(stats.average(), SubsystemTimes::default());
+
+        stats.add_cpu(ms(4));
+        stats.add_cpu(ms(2));
+        stats.add_mmu(ms(1));
+        stats.add_ppu(ms(3));
+        stats.end_frame();
+        stats.add_cpu(ms(2));
+        stats.add_mmu(ms(1));
+        stats.end_frame();
+        // El frame en curso no cuenta hasta que termina
+        stats.add_cpu(ms(100));
+
+        let first = SubsystemTimes { cpu: ms(6), ppu: ms(3), apu: ms(0), mmu: ms(1) };
+        assert_eq!(stats.frames(), 2);
+        assert_eq!(stats.worst_frame(), first);
+        assert_eq!(stats.last_frame(), SubsystemTimes { cpu: ms(2), mmu: ms(1), ..Default::default() });
+        assert_eq!(stats.total().total(), ms(13));
+        assert_eq!(stats.average(), SubsystemTimes {
+            cpu: ms(4), ppu: ms(1) + Duration::from_micros(500), apu: ms(0), mmu: ms(1),
+        });
+
+        stats.reset();
+        assert_eq!(stats, SubsystemStats::new());
+    }
+
+    #[test]
+    fn frame_timing() {
+        let mut stats = FrameStats::new(2);
+        assert_eq!(stats.average_emulation(), Duration::ZERO);
+        // Sin haber empezado no hay frame que cerrar
+        stats.end_frame(1, 100, None);
+        assert_eq!(stats.last(), None);
+
+        for frame in 1..=3 {
+            stats.begin_frame(frame * 70224);
+            stats.begin_frame(frame * 70224 + 8);
+            stats.end_frame(frame, (frame + 1) * 70224 + 4, Some(800));
+        }
+        stats.record_sleep(Duration::from_millis(5), true);
+
+        let frames = stats.frames().map(|timing| (timing.frame, timing.cycles, timing.slept))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [(2, 70228, false), (3, 70228, true)]);
+        assert_eq!(stats.last().unwrap().audio_buffered, Some(800));
+        assert_eq!(stats.last().unwrap().sleep, Duration::from_millis(5));
+        assert_eq!(stats.late_frames(), 1);
+        stats.clear();
+        assert_eq!(stats.frames().count(), 0);
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Distancia máxima a la etiqueta anterior para mostrar `Etiqueta+$n`, más
+/// lejos probablemente ya es otra rutina sin nombre
+const MAX_SYMBOL_OFFSET: u16 = 0x100;
+
+/// Símbolos de un fichero `.sym` de RGBDS, con líneas como `01:4000 Main`.
+/// Los usan el desensamblador, las trazas y el depurador para mostrar
+/// nombres en vez de direcciones
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SymbolTable {
+    by_addr: BTreeMap<(u16, u16), String>,
+    by_name: HashMap<String, (u16, u16)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interpretar el contenido de un `.sym`, se ignoran los comentarios
+    /// (`;`) y las líneas que no tengan el formato `banco:dirección nombre`
+    pub fn parse(text: &str) -> Self {
+        let mut table = Self::new();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default();
+            let mut parts = line.split_whitespace();
+            let (Some(location), Some(name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some((bank, addr)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(addr)) = (u16::from_str_radix(bank, 16), u16::from_str_radix(addr, 16))
+            else {
+                continue;
+            };
+            table.insert(bank, addr, name);
+        }
+        table
+    }
+
+    /// Leer un `.sym` de disco
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn insert(&mut self, bank: u16, addr: u16, name: &str) {
+        self.by_addr.insert((bank, addr), name.to_string());
+        self.by_name.insert(name.to_string(), (bank, addr));
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.by_addr.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+
+    /// Nombre exacto de la dirección. Fuera de la ROM el banco de la RAM no
+    /// siempre se conoce, si no hay símbolo en ese banco se busca en todos
+    pub fn name(&self, bank: u16, addr: u16) -> Option<&str> {
+        if let Some(name) = self.by_addr.get(&(bank, addr)) {
+            return Some(name);
+        }
+        if addr < 0x8000 {
+            return None;
+        }
+        self.by_addr.iter()
+            .find(|((_, a), _)| *a == addr)
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Banco y dirección de un símbolo
+    pub fn lookup(&self, name: &str) -> Option<(u16, u16)> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Texto para mostrar una dirección: el nombre exacto, `Etiqueta+$n` si
+    /// está poco después de una, o `$1234` si no hay ninguna cerca
+    pub fn symbolize(&self, bank: u16, addr: u16) -> String {
+        if let Some(name) = self.name(bank, addr) {
+            return name.to_string();
+        }
+        let previous = self.by_addr.range((bank, 0)..(bank, addr)).next_back();
+        match previous {
+            Some((&(_, start), name)) if addr - start < MAX_SYMBOL_OFFSET => {
+                format!("{name}+${:X}", addr - start)
+            },
+            _ => format!("${addr:04X}"),
+        }
+    }
+}
+
+/// Banco en el que buscar el símbolo de `addr` cuando la ROM conmutable
+/// tiene mapeado `bank`
+#[inline]
+pub(crate) fn bank_of(addr: u16, bank: u16) -> u16 {
+    match addr {
+        0x4000..=0x7FFF => bank,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sym() {
+        let table = SymbolTable::parse("\
+            ; File generated by rgblink\n\
+            00:0150 Main\n\
+            00:0160 Main.loop\n\
+            01:4000 UpdateOAM ; comentario\n\
+            00:c000 wCounter\n\
+            basura\n");
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.lookup("UpdateOAM"), Some((1, 0x4000)));
+        assert_eq!(table.name(1, 0x4000), Some("UpdateOAM"));
+        assert_eq!(table.name(2, 0x4000), None);
+        assert_eq!(table.name(1, 0xC000), Some("wCounter"));
+
+        assert_eq!(table.symbolize(0, 0x0163), "Main.loop+$3");
+        assert_eq!(table.symbolize(1, 0x4000), "UpdateOAM");
+        assert_eq!(table.symbolize(0, 0x3000), "$3000");
+    }
+}
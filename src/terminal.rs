@@ -0,0 +1,134 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::frame::{Frame, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::sink::VideoSink;
+
+/// Cómo se dibujan los píxeles en el terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TerminalMode {
+    /// Dos píxeles por carácter con `▀`, el de arriba como color del texto y
+    /// el de abajo como fondo. Necesita un terminal con color de 24 bits
+    #[default]
+    HalfBlock,
+
+    /// 2x4 píxeles por carácter con los puntos de braille, sin color: un
+    /// punto por cada píxel oscuro. Funciona en cualquier terminal con UTF-8
+    Braille,
+}
+
+/// Sink que dibuja cada frame en un terminal con secuencias ANSI, para
+/// enseñar el emulador por SSH o usarlo en máquinas sin pantalla. Los
+/// errores de escritura se ignoran para no interrumpir la emulación
+pub struct TerminalSink<W: Write + Send> {
+    writer: W,
+    mode: TerminalMode,
+
+    /// Se reutiliza entre frames para hacer una sola escritura por frame
+    buffer: String,
+}
+
+impl<W: Write + Send> TerminalSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, mode: TerminalMode::default(), buffer: String::new() }
+    }
+
+    pub fn mode(mut self, mode: TerminalMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl TerminalSink<io::Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write + Send> VideoSink for TerminalSink<W> {
+    fn present(&mut self, frame: &Frame) {
+        self.buffer.clear();
+        // Volver a la esquina superior izquierda para sobrescribir el frame
+        // anterior en vez de hacer scroll
+        self.buffer.push_str("\x1b[H");
+        match self.mode {
+            TerminalMode::HalfBlock => half_blocks(frame, &mut self.buffer),
+            TerminalMode::Braille => braille(frame, &mut self.buffer),
+        }
+        let _ = self.writer.write_all(self.buffer.as_bytes());
+        let _ = self.writer.flush();
+    }
+}
+
+fn half_blocks(frame: &Frame, out: &mut String) {
+    for y in (0..SCREEN_HEIGHT).step_by(2) {
+        // Solo se cambia de color cuando hace falta, la mayor parte de la
+        // pantalla suelen ser zonas del mismo color
+        let mut colors = None;
+        for x in 0..SCREEN_WIDTH {
+            let [tr, tg, tb, _] = frame.pixel(x, y);
+            let [br, bg, bb, _] = frame.pixel(x, y + 1);
+            let pair = ([tr, tg, tb], [br, bg, bb]);
+            if colors != Some(pair) {
+                let _ = write!(out, "\x1b[38;2;{tr};{tg};{tb};48;2;{br};{bg};{bb}m");
+                colors = Some(pair);
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+}
+
+/// Bit de cada punto de un carácter braille, por columna y fila
+const BRAILLE_DOTS: [[u8; 4]; 2] = [
+    [0x01, 0x02, 0x04, 0x40],
+    [0x08, 0x10, 0x20, 0x80],
+];
+
+fn braille(frame: &Frame, out: &mut String) {
+    for y in (0..SCREEN_HEIGHT).step_by(4) {
+        for x in (0..SCREEN_WIDTH).step_by(2) {
+            let mut dots = 0;
+            for (dx, column) in BRAILLE_DOTS.iter().enumerate() {
+                for (dy, bit) in column.iter().enumerate() {
+                    if is_dark(frame.pixel(x + dx, y + dy)) {
+                        dots |= bit;
+                    }
+                }
+            }
+            out.push(char::from_u32(0x2800 + dots as u32).unwrap());
+        }
+        out.push('\n');
+    }
+}
+
+/// El píxel está por debajo de la mitad de luminancia
+#[inline]
+fn is_dark([r, g, b, _]: [u8; 4]) -> bool {
+    (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000 < 128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_modes() {
+        let mut frame = Frame::new();
+        frame.set_pixel(0, 1, [0, 0, 0, 255]);
+        frame.set_pixel(1, 3, [0, 0, 0, 255]);
+
+        let mut sink = TerminalSink::new(Vec::new());
+        sink.present(&frame);
+        let text = String::from_utf8(sink.writer.clone()).unwrap();
+        assert_eq!(text.lines().count(), SCREEN_HEIGHT / 2);
+        assert!(text.starts_with("\x1b[H\x1b[38;2;255;255;255;48;2;0;0;0m▀\x1b[38;2;255;255;255;48;2;255;255;255m▀▀"));
+
+        let mut sink = TerminalSink::new(Vec::new()).mode(TerminalMode::Braille);
+        sink.present(&frame);
+        let text = String::from_utf8(sink.writer).unwrap();
+        assert_eq!(text.lines().count(), SCREEN_HEIGHT / 4);
+        assert!(text.starts_with("\x1b[H\u{2882}\u{2800}"));
+        assert_eq!(text.lines().next().unwrap().chars().count(), 3 + SCREEN_WIDTH / 2);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::error::Error;
+use crate::gameboy::GameBoy;
+use crate::model::Model;
+use crate::serial::{SerialCapture, TestOutcome};
+use crate::{Instr, Reg};
+
+/// Frames tras los que se da por colgado un test de blargg, los más lentos
+/// de `cpu_instrs` tardan alrededor de medio minuto
+pub const BLARGG_MAX_FRAMES: u32 = 60 * 60;
+
+/// Frames tras los que se da por colgado un test de mooneye, los de
+/// `acceptance` terminan en unos pocos segundos
+pub const MOONEYE_MAX_FRAMES: u32 = 60 * 20;
+
+/// Registros B, C, D, E, H y L con los que terminan los tests de mooneye que
+/// pasan, los que fallan los dejan todos a 0x42
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: u8 = 0x42;
+
+/// Resultado de `run_blargg`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlarggReport {
+    /// `None` si se agotaron los frames sin que imprimiera el resultado
+    pub outcome: Option<TestOutcome>,
+
+    /// Todo lo que imprimió por el puerto serie
+    pub output: String,
+
+    /// Frames ejecutados
+    pub frames: u32,
+}
+
+impl BlarggReport {
+    #[inline]
+    pub fn passed(&self) -> bool {
+        self.outcome == Some(TestOutcome::Passed)
+    }
+}
+
+/// Ejecutar sin pantalla un test de blargg (como los de `cpu_instrs`)
+/// capturando lo que imprime por el puerto serie, hasta que imprime
+/// `Passed` o `Failed` o pasan `max_frames`. Falla si la ROM no cabe o la
+/// CPU encuentra un opcode inválido
+pub fn run_blargg(rom: &[u8], max_frames: u32) -> Result<BlarggReport, Error> {
+    let mut gb = GameBoy::builder().rom(rom.to_vec()).rendering(false).build()?;
+    let capture = SerialCapture::new();
+    gb.mmu_mut().connect_link(Box::new(capture.clone()));
+
+    let mut frames = 0;
+    while capture.outcome().is_none() && frames < max_frames {
+        gb.step_frame()?;
+        frames += 1;
+    }
+    Ok(BlarggReport {
+        outcome: capture.outcome(),
+        output: capture.output(),
+        frames,
+    })
+}
+
+/// Resultado de `run_mooneye`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MooneyeReport {
+    /// `None` si se agotaron los frames sin llegar al `LD B, B` final o
+    /// terminó con otros registros
+    pub outcome: Option<TestOutcome>,
+
+    /// B, C, D, E, H y L al terminar
+    pub registers: [u8; 6],
+
+    /// Frames ejecutados
+    pub frames: u64,
+}
+
+impl MooneyeReport {
+    #[inline]
+    pub fn passed(&self) -> bool {
+        self.outcome == Some(TestOutcome::Passed)
+    }
+}
+
+/// Ejecutar sin pantalla un test de mooneye-gb en `model` hasta su
+/// breakpoint software (`LD B, B`) o hasta que pasen `max_frames`. El
+/// resultado se lee de los registros: la sucesión de Fibonacci
+/// 3, 5, 8, 13, 21, 34 en B-L si pasa. Falla si la ROM no cabe o la CPU
+/// encuentra un opcode inválido
+pub fn run_mooneye(rom: &[u8], model: Model, max_frames: u32) -> Result<MooneyeReport, Error> {
+    let mut gb = GameBoy::builder().rom(rom.to_vec()).model(model).rendering(false).build()?;
+    run_mooneye_on(&mut gb, max_frames)
+}
+
+fn run_mooneye_on(gb: &mut GameBoy, max_frames: u32) -> Result<MooneyeReport, Error> {
+    let mut finished = false;
+    while !finished && gb.frame_count() < max_frames as u64 {
+        finished = gb.step_instruction()?.instr == (Instr::LdRegReg { src: Reg::B, dst: Reg::B });
+    }
+
+    let registers = [Reg::B, Reg::C, Reg::D, Reg::E, Reg::H, Reg::L]
+        .map(|reg| gb.cpu().read_reg(reg));
+    let outcome = match registers {
+        _ if !finished => None,
+        MOONEYE_PASS => Some(TestOutcome::Passed),
+        _ if registers.iter().all(|&reg| reg == MOONEYE_FAIL) => Some(TestOutcome::Failed),
+        _ => None,
+    };
+    Ok(MooneyeReport { outcome, registers, frames: gb.frame_count() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blargg_timeout() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        let report = run_blargg(&rom, 2).unwrap();
+        assert_eq!(report, BlarggReport { outcome: None, output: String::new(), frames: 2 });
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn mooneye_registers() {
+        // LD B, 3; LD D, 8; LD H, 21; LD B, B y un bucle infinito, C, E y L
+        // ya tienen su valor
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x109].copy_from_slice(&[0x06, 3, 0x16, 8, 0x26, 21, 0x40, 0x18, 0xFE]);
+        let run = |rom: &[u8]| {
+            let mut gb = GameBoy::builder().rom(rom.to_vec()).build().unwrap();
+            for (reg, value) in [(Reg::C, 5), (Reg::E, 13), (Reg::L, 34)] {
+                gb.cpu_mut().write_reg(reg, value);
+            }
+            run_mooneye_on(&mut gb, 2).unwrap()
+        };
+        let report = run(&rom);
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(report.frames, 0);
+
+        // Sin LD B, B no termina nunca
+        rom[0x106] = 0x00;
+        let report = run(&rom);
+        assert_eq!((report.outcome, report.frames), (None, 2));
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::disasm::format_instr;
+use crate::gameboy::StepInfo;
+use crate::symbols::{bank_of, SymbolTable};
+use crate::InstrClass;
+
+/// Destino de las instrucciones que pasan el filtro de un `Tracer`
+pub trait TraceSink: Send {
+    fn record(&mut self, info: &StepInfo);
+}
+
+/// Qué instrucciones se trazan, por defecto todas. Los filtros se combinan,
+/// una instrucción tiene que cumplirlos todos
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceFilter {
+    /// Rangos de direcciones de la instrucción, vacío para cualquiera
+    ranges: Vec<RangeInclusive<u16>>,
+
+    /// Grupos de instrucciones, vacío para cualquiera
+    classes: Vec<InstrClass>,
+
+    /// Solo las instrucciones que escriben en memoria
+    writes_only: bool,
+
+    /// De las que cumplen el resto de filtros solo se traza 1 de cada N
+    every: u32,
+}
+
+impl TraceFilter {
+    pub fn new() -> Self {
+        Self {
+            ranges: Vec::new(),
+            classes: Vec::new(),
+            writes_only: false,
+            every: 1,
+        }
+    }
+
+    /// Añadir un rango de direcciones a trazar
+    pub fn range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Añadir un grupo de instrucciones a trazar
+    pub fn class(mut self, class: InstrClass) -> Self {
+        self.classes.push(class);
+        self
+    }
+
+    pub fn writes_only(mut self, enabled: bool) -> Self {
+        self.writes_only = enabled;
+        self
+    }
+
+    /// Trazar solo 1 de cada `n` instrucciones, 0 se trata como 1
+    pub fn every(mut self, n: u32) -> Self {
+        self.every = n.max(1);
+        self
+    }
+
+    fn matches(&self, info: &StepInfo) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&info.addr)))
+            && (self.classes.is_empty() || self.classes.contains(&info.instr.class()))
+            && (!self.writes_only || info.instr.writes_memory())
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Traza de ejecución, se conecta con `GameBoy::set_tracer` y recibe cada
+/// instrucción ejecutada. Una traza completa de un solo frame ocupa decenas
+/// de megas, por eso se filtra antes de llegar al sink
+pub struct Tracer {
+    filter: TraceFilter,
+    sink: Box<dyn TraceSink>,
+
+    /// Instrucciones que han pasado el filtro, para `every`
+    matched: u64,
+}
+
+impl Tracer {
+    pub fn new(filter: TraceFilter, sink: impl TraceSink + 'static) -> Self {
+        Self { filter, sink: Box::new(sink), matched: 0 }
+    }
+
+    #[inline]
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub(crate) fn trace(&mut self, info: &StepInfo) {
+        if !self.filter.matches(info) {
+            return;
+        }
+        self.matched += 1;
+        if (self.matched - 1).is_multiple_of(self.filter.every as u64) {
+            self.sink.record(info);
+        }
+    }
+}
+
+/// Línea de texto por instrucción, como `0150 Main: ld b, $12 (8)`
+pub(crate) fn trace_line(info: &StepInfo, symbols: Option<&SymbolTable>) -> String {
+    let text = format_instr(&info.instr, info.addr, &[], info.bank, symbols);
+    match symbols.and_then(|symbols| symbols.name(bank_of(info.addr, info.bank), info.addr)) {
+        Some(label) => format!("{:04X} {label}: {text} ({})", info.addr, info.cycles),
+        None => format!("{:04X}: {text} ({})", info.addr, info.cycles),
+    }
+}
+
+/// Escribe la traza como texto en cualquier `Write`, los errores de
+/// escritura se ignoran para no interrumpir la emulación
+pub struct WriteTrace<W: Write + Send> {
+    writer: W,
+    symbols: Option<Arc<SymbolTable>>,
+}
+
+impl<W: Write + Send> WriteTrace<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, symbols: None }
+    }
+
+    /// Mostrar los nombres de los símbolos en vez de direcciones
+    pub fn symbols(mut self, symbols: Arc<SymbolTable>) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+}
+
+impl WriteTrace<io::Stderr> {
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl WriteTrace<BufWriter<File>> {
+    /// Crear (o vaciar) el fichero en `path` y escribir en él la traza
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Send> TraceSink for WriteTrace<W> {
+    fn record(&mut self, info: &StepInfo) {
+        let _ = writeln!(self.writer, "{}", trace_line(info, self.symbols.as_deref()));
+    }
+}
+
+/// Guarda en memoria las últimas instrucciones trazadas, se clona antes de
+/// dársela al `Tracer` para poder leerla después, ambas copias comparten
+/// las entradas
+#[derive(Debug, Clone)]
+pub struct RingTrace {
+    capacity: usize,
+    entries: Arc<Mutex<VecDeque<StepInfo>>>,
+}
+
+impl RingTrace {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Entradas guardadas de la más antigua a la más reciente
+    pub fn entries(&self) -> Vec<StepInfo> {
+        self.entries.lock().unwrap().iter().copied().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl TraceSink for RingTrace {
+    fn record(&mut self, info: &StepInfo) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(*info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoy;
+
+    #[test]
+    fn filter_and_ring() {
+        // Cuatro NOPs, un LD B, 0x12 y un bucle infinito
+        let mut rom = vec![0; 0x8000];
+        rom[0x104..0x108].copy_from_slice(&[0x06, 0x12, 0x18, 0xFE]);
+        let mut gb = GameBoy::new();
+        gb.load_rom(&rom).unwrap();
+
+        let ring = RingTrace::new(3);
+        let filter = TraceFilter::new().range(0x0100..=0x0105).every(2);
+        gb.set_tracer(Some(Tracer::new(filter, ring.clone())));
+        gb.run_cycles(100);
+
+        let addrs = ring.entries().iter().map(|info| info.addr).collect::<Vec<_>>();
+        assert_eq!(addrs, [0x0100, 0x0102, 0x0104]);
+        assert_eq!(trace_line(&ring.entries()[2], None), "0104: ld b, $12 (8)");
+        let symbols = SymbolTable::parse("00:0104 SetB");
+        assert_eq!(trace_line(&ring.entries()[2], Some(&symbols)), "0104 SetB: ld b, $12 (8)");
+
+        ring.clear();
+        let filter = TraceFilter::new().class(InstrClass::Jump);
+        gb.set_tracer(Some(Tracer::new(filter, ring.clone())));
+        gb.run_cycles(120);
+        assert_eq!(ring.entries().len(), 3);
+        assert!(ring.entries().iter().all(|info| info.addr == 0x0106));
+    }
+}
@@ -0,0 +1,330 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::error::Error;
+use crate::gameboy::GameBoy;
+use crate::model::Model;
+use crate::script::InputScript;
+use crate::sink::AudioSink;
+
+/// Base y primo de FNV-1a de 64 bits, los mismos de `Frame::shade_hash`
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Hashes de la imagen y del audio en un frame, ver `AvManifest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AvVector {
+    /// Frames completados
+    pub frame: u64,
+
+    /// `Frame::shade_hash` del último frame
+    pub video: u64,
+
+    /// FNV-1a de las muestras generadas desde el vector anterior, cada una
+    /// como 2 bytes en little endian
+    pub audio: u64,
+
+    /// Número de muestras que entran en `audio`
+    pub samples: u64,
+}
+
+impl AvVector {
+    /// Leer una línea del manifiesto, `None` si no tiene el formato
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let frame = fields.next()?.parse().ok()?;
+        let video = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let audio = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let samples = fields.next()?.parse().ok()?;
+        fields.next().is_none().then_some(Self { frame, video, audio, samples })
+    }
+}
+
+/// Una línea del manifiesto: `frame vídeo audio muestras`, los hashes en
+/// hexadecimal
+impl fmt::Display for AvVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:016x} {:016x} {}", self.frame, self.video, self.audio, self.samples)
+    }
+}
+
+/// Por qué una ejecución no coincide con un `AvManifest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AvMismatch {
+    /// El hash de la ROM no es el del manifiesto
+    Rom { expected: u64, actual: u64 },
+
+    /// El hash de la entrada no es el del manifiesto
+    Input { expected: u64, actual: u64 },
+
+    /// Primer vector distinto
+    Vector { expected: AvVector, actual: AvVector },
+}
+
+impl fmt::Display for AvMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AvMismatch::Rom { expected, actual } => {
+                write!(f, "la ROM no es la del manifiesto: {actual:016x} en vez de {expected:016x}")
+            },
+            AvMismatch::Input { expected, actual } => {
+                write!(f, "la entrada no es la del manifiesto: {actual:016x} en vez de {expected:016x}")
+            },
+            AvMismatch::Vector { expected, actual } => {
+                writeln!(f, "Los vectores divergen en el frame {}:", expected.frame)?;
+                writeln!(f, "esperado: {expected}")?;
+                write!(f, "actual:   {actual}")
+            },
+        }
+    }
+}
+
+/// Vectores de prueba de audio y vídeo: la ROM se ejecuta con una entrada
+/// guionizada y cada `interval` frames se apunta el hash del frame y el del
+/// audio generado. El manifiesto es texto para que lo puedan generar o
+/// comprobar otros emuladores, o esta biblioteca en otra versión:
+///
+/// ```text
+/// # gameboi av vectors
+/// rom 5a1f03c2d47e9b60
+/// input 0000000000000000
+/// model cgb
+/// frames 120
+/// interval 60
+/// # frame vídeo audio muestras
+/// 60 8d2b4e1a90c3f577 cbf29ce484222325 0
+/// 120 8d2b4e1a90c3f577 cbf29ce484222325 0
+/// ```
+///
+/// `rom` es el FNV-1a de 64 bits de la ROM e `input` el del estado de los
+/// botones de cada frame (`Joypad::state`), el vídeo es
+/// `Frame::shade_hash`. Se arranca sin boot ROM y con la RAM a cero, y tras
+/// el guion se sueltan todos los botones
+// TODO: Todavía no hay APU, hasta entonces el audio de todos los vectores es
+// el hash vacío
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvManifest {
+    pub rom: u64,
+    pub input: u64,
+    pub model: Model,
+    pub frames: u64,
+    pub interval: u64,
+    vectors: Vec<AvVector>,
+}
+
+impl AvManifest {
+    /// Ejecutar `frames` frames de `rom` en `model` con `script` apuntando un
+    /// vector cada `interval` frames (que tiene que ser mayor que 0)
+    pub fn generate(rom: &[u8], model: Model, script: &InputScript, frames: u64, interval: u64)
+        -> Result<Self, Error>
+    {
+        assert!(interval > 0, "El intervalo tiene que ser de al menos un frame");
+        let mut gb = GameBoy::builder()
+            .model(model)
+            .allow_cgb_only(true)
+            .rom(rom)
+            .build()?;
+        let audio = AudioHash::default();
+        gb.set_audio_sink(Some(Box::new(audio.clone())));
+
+        let mut vectors = Vec::new();
+        for frame in 0..frames {
+            gb.set_joypad_state(script.frame(frame as usize).unwrap_or(0));
+            gb.step_frame()?;
+            if (frame + 1) % interval == 0 {
+                let (audio, samples) = audio.take();
+                vectors.push(AvVector { frame: frame + 1, video: gb.frame().shade_hash(), audio, samples });
+            }
+        }
+        Ok(Self {
+            rom: fnv(rom.iter().copied()),
+            input: input_hash(script, frames),
+            model,
+            frames,
+            interval,
+            vectors,
+        })
+    }
+
+    /// Repetir la ejecución del manifiesto con `rom` y `script` y compararla,
+    /// `None` si coincide entera
+    pub fn verify(&self, rom: &[u8], script: &InputScript) -> Result<Option<AvMismatch>, Error> {
+        let actual = fnv(rom.iter().copied());
+        if actual != self.rom {
+            return Ok(Some(AvMismatch::Rom { expected: self.rom, actual }));
+        }
+        let actual = input_hash(script, self.frames);
+        if actual != self.input {
+            return Ok(Some(AvMismatch::Input { expected: self.input, actual }));
+        }
+        let run = Self::generate(rom, self.model, script, self.frames, self.interval)?;
+        Ok(self.vectors.iter().zip(&run.vectors)
+            .find(|(expected, actual)| expected != actual)
+            .map(|(&expected, &actual)| AvMismatch::Vector { expected, actual }))
+    }
+
+    #[inline]
+    pub fn vectors(&self) -> &[AvVector] {
+        &self.vectors
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "# gameboi av vectors")?;
+        writeln!(writer, "rom {:016x}", self.rom)?;
+        writeln!(writer, "input {:016x}", self.input)?;
+        writeln!(writer, "model {}", model_name(self.model))?;
+        writeln!(writer, "frames {}", self.frames)?;
+        writeln!(writer, "interval {}", self.interval)?;
+        writeln!(writer, "# frame vídeo audio muestras")?;
+        for vector in &self.vectors {
+            writeln!(writer, "{vector}")?;
+        }
+        Ok(())
+    }
+
+    /// Leer un manifiesto escrito con `write`, las líneas que empiezan por
+    /// `#` son comentarios
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut fields = [None; 4];
+        let mut model = None;
+        let mut vectors = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+                format!("línea {} inválida: {line}", index + 1));
+            let (key, value) = line.split_once(' ').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key {
+                "rom" | "input" => {
+                    let field = if key == "rom" { 0 } else { 1 };
+                    fields[field] = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?);
+                },
+                "frames" | "interval" => {
+                    let field = if key == "frames" { 2 } else { 3 };
+                    fields[field] = Some(value.parse().map_err(|_| invalid())?);
+                },
+                "model" => model = Some(parse_model(value).ok_or_else(invalid)?),
+                _ => vectors.push(AvVector::parse(line).ok_or_else(invalid)?),
+            }
+        }
+        let missing = |field| io::Error::new(io::ErrorKind::InvalidData, format!("falta `{field}`"));
+        let [rom, input, frames, interval] = fields;
+        Ok(Self {
+            rom: rom.ok_or_else(|| missing("rom"))?,
+            input: input.ok_or_else(|| missing("input"))?,
+            model: model.ok_or_else(|| missing("model"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            interval: interval.filter(|interval| *interval > 0).ok_or_else(|| missing("interval"))?,
+            vectors,
+        })
+    }
+
+    /// Crear (o vaciar) el fichero en `path` y escribir en él el manifiesto
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Hash de las muestras recibidas desde el último `take`
+#[derive(Debug, Clone)]
+struct AudioHash(Arc<Mutex<(u64, u64)>>);
+
+impl Default for AudioHash {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new((FNV_OFFSET, 0))))
+    }
+}
+
+impl AudioHash {
+    fn take(&self) -> (u64, u64) {
+        std::mem::replace(&mut *self.0.lock().unwrap(), (FNV_OFFSET, 0))
+    }
+}
+
+impl AudioSink for AudioHash {
+    fn queue_samples(&mut self, samples: &[i16]) {
+        let mut state = self.0.lock().unwrap();
+        let (hash, count) = &mut *state;
+        *hash = fnv_continue(*hash, samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        *count += samples.len() as u64;
+    }
+}
+
+fn fnv(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    fnv_continue(FNV_OFFSET, bytes)
+}
+
+fn fnv_continue(hash: u64, bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(hash, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// FNV-1a del estado de los botones de los `frames` primeros frames
+fn input_hash(script: &InputScript, frames: u64) -> u64 {
+    fnv((0..frames as usize).map(|frame| script.frame(frame).unwrap_or(0)))
+}
+
+fn model_name(model: Model) -> &'static str {
+    match model {
+        Model::Dmg => "dmg",
+        Model::Mgb => "mgb",
+        Model::Sgb => "sgb",
+        Model::Cgb => "cgb",
+        Model::Agb => "agb",
+    }
+}
+
+fn parse_model(name: &str) -> Option<Model> {
+    [Model::Dmg, Model::Mgb, Model::Sgb, Model::Cgb, Model::Agb].into_iter()
+        .find(|model| model_name(*model) == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_and_verify() {
+        // JR -2 en el punto de entrada
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        let script = InputScript::parse("wait 2; press A").unwrap();
+
+        let manifest = AvManifest::generate(&rom, Model::Dmg, &script, 10, 4).unwrap();
+        let frames = manifest.vectors().iter().map(|vector| vector.frame).collect::<Vec<_>>();
+        assert_eq!(frames, [4, 8]);
+        assert_eq!(manifest.vectors()[0].samples, 0);
+        assert_eq!(manifest.verify(&rom, &script).unwrap(), None);
+
+        let mut text = Vec::new();
+        manifest.write(&mut text).unwrap();
+        assert_eq!(AvManifest::read(text.as_slice()).unwrap(), manifest);
+        assert!(AvManifest::read("rom 1\nmodel gbc\n".as_bytes()).is_err());
+        assert!(AvManifest::read("rom 1\ninput 2\nmodel dmg\nframes 1\n".as_bytes()).is_err());
+
+        let other = InputScript::parse("wait 3; press A").unwrap();
+        assert!(matches!(manifest.verify(&rom, &other).unwrap(), Some(AvMismatch::Input { .. })));
+        rom[0x0150] = 1;
+        assert!(matches!(manifest.verify(&rom, &script).unwrap(), Some(AvMismatch::Rom { .. })));
+
+        // Un vector cambiado se detecta como divergencia
+        rom[0x0150] = 0;
+        let mut changed = manifest.clone();
+        changed.vectors[1].video ^= 1;
+        let mismatch = changed.verify(&rom, &script).unwrap().unwrap();
+        assert!(matches!(mismatch, AvMismatch::Vector { expected, .. } if expected.frame == 8));
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::{Arc, Mutex};
+
+use js_sys::Uint8ClampedArray;
+use wasm_bindgen::prelude::*;
+
+use crate::frame::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::gameboy::GameBoy;
+use crate::joypad::Button;
+use crate::sink::AudioSink;
+
+/// Muestras que se guardan como mucho si la página no las recoge, al
+/// llenarse se descartan las más antiguas
+const MAX_QUEUED_SAMPLES: usize = 1 << 16;
+
+/// Cola del audio generado hasta que la página lo recoge con `take_audio`
+#[derive(Debug, Clone, Default)]
+struct AudioQueue(Arc<Mutex<Vec<i16>>>);
+
+impl AudioSink for AudioQueue {
+    fn queue_samples(&mut self, samples: &[i16]) {
+        let mut queue = self.0.lock().unwrap();
+        queue.extend_from_slice(samples);
+        let excess = queue.len().saturating_sub(MAX_QUEUED_SAMPLES);
+        queue.drain(..excess);
+    }
+
+    fn buffered(&self) -> Option<usize> {
+        Some(self.0.lock().unwrap().len())
+    }
+}
+
+/// La `GameBoy` exportada a JavaScript con wasm-bindgen:
+///
+/// ```js
+/// const gb = new GameBoy(new Uint8Array(await rom.arrayBuffer()));
+/// gb.step_frame();
+/// ctx.putImageData(new ImageData(gb.frame(), gb.width, gb.height), 0, 0);
+/// ```
+#[wasm_bindgen(js_name = GameBoy)]
+pub struct WebGameBoy {
+    gb: GameBoy,
+    audio: AudioQueue,
+}
+
+#[wasm_bindgen(js_class = GameBoy)]
+impl WebGameBoy {
+    /// Arrancar con la ROM de un `Uint8Array`
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WebGameBoy, JsError> {
+        let audio = AudioQueue::default();
+        let gb = GameBoy::builder()
+            .rom(rom.to_vec())
+            .audio_sink(audio.clone())
+            .build()?;
+        Ok(Self { gb, audio })
+    }
+
+    /// Emular un frame completo
+    pub fn step_frame(&mut self) -> Result<(), JsError> {
+        Ok(self.gb.step_frame()?)
+    }
+
+    /// Píxeles RGBA del último frame como vista sobre la memoria de wasm,
+    /// listos para `new ImageData(...)`. La vista deja de ser válida al
+    /// volver a llamar a cualquier método, hay que usarla o copiarla antes
+    pub fn frame(&self) -> Uint8ClampedArray {
+        // SAFETY: La vista solo se usa hasta la siguiente llamada a wasm,
+        // que es lo único que puede mover o liberar el framebuffer
+        unsafe { Uint8ClampedArray::view(self.gb.frame().pixels()) }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        SCREEN_WIDTH as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        SCREEN_HEIGHT as u32
+    }
+
+    /// Pulsar o soltar un botón por su bit en el estado del joypad: derecha,
+    /// izquierda, arriba, abajo, A, B, Select y Start
+    pub fn set_button(&mut self, button: u8, pressed: bool) -> Result<(), JsError> {
+        let button = *Button::ALL.get(button as usize)
+            .ok_or_else(|| JsError::new("Botón inválido"))?;
+        self.gb.set_button(button, pressed);
+        Ok(())
+    }
+
+    /// Estado de todos los botones de una vez, un bit por botón en el orden
+    /// de `set_button` y a 1 los pulsados
+    pub fn set_buttons(&mut self, state: u8) {
+        for (bit, button) in Button::ALL.into_iter().enumerate() {
+            self.gb.set_button(button, state & (1 << bit) != 0);
+        }
+    }
+
+    /// Recoger el audio generado desde la última llamada, muestras estéreo
+    /// intercaladas entre -1 y 1 como las que espera Web Audio
+    pub fn take_audio(&mut self) -> Vec<f32> {
+        let samples = std::mem::take(&mut *self.audio.0.lock().unwrap());
+        to_f32(&samples)
+    }
+}
+
+fn to_f32(samples: &[i16]) -> Vec<f32> {
+    samples.iter().map(|&sample| sample as f32 / i16::MAX as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_queue() {
+        let mut queue = AudioQueue::default();
+        queue.queue_samples(&[0, i16::MAX, -i16::MAX]);
+        assert_eq!(to_f32(&queue.0.lock().unwrap()), [0.0, 1.0, -1.0]);
+
+        queue.queue_samples(&vec![1; MAX_QUEUED_SAMPLES]);
+        let queued = queue.0.lock().unwrap();
+        assert_eq!(queued.len(), MAX_QUEUED_SAMPLES);
+        assert!(queued.iter().all(|&sample| sample == 1));
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+
+use crate::mmu::Bus;
+use crate::{Cpu, Reg};
+
+/// Expresión que vigila un watch del `Debugger`: registros de 8 o 16 bits,
+/// números (decimales, `0x` o `$` en hexadecimal), sumas y restas, y lecturas
+/// de memoria de un byte entre corchetes, como `HL`, `[0xFF40]` o `[BC+2]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WatchExpr {
+    Reg(Reg),
+    WideReg(Reg),
+    Pc,
+    Const(u16),
+    Mem(Box<WatchExpr>),
+    Add(Box<WatchExpr>, Box<WatchExpr>),
+    Sub(Box<WatchExpr>, Box<WatchExpr>),
+}
+
+impl WatchExpr {
+    /// Interpretar una expresión, `None` si no es válida
+    pub fn parse(text: &str) -> Option<Self> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let expr = parser.expr()?;
+        (parser.pos == tokens.len()).then_some(expr)
+    }
+
+    /// Valor actual, las sumas y restas dan la vuelta en 16 bits
+    pub fn eval<B: Bus + ?Sized>(&self, cpu: &Cpu, bus: &B) -> u16 {
+        match self {
+            WatchExpr::Reg(reg) => cpu.read_reg(*reg) as u16,
+            WatchExpr::WideReg(reg) => wide_value(cpu, *reg),
+            WatchExpr::Pc => cpu.pc(),
+            WatchExpr::Const(value) => *value,
+            WatchExpr::Mem(addr) => bus.read(addr.eval(cpu, bus)) as u16,
+            WatchExpr::Add(a, b) => a.eval(cpu, bus).wrapping_add(b.eval(cpu, bus)),
+            WatchExpr::Sub(a, b) => a.eval(cpu, bus).wrapping_sub(b.eval(cpu, bus)),
+        }
+    }
+}
+
+/// Valor de un par de registros con el primero como byte alto, como en el
+/// SM83 (`BC` es `B << 8 | C`)
+pub(crate) fn wide_value(cpu: &Cpu, reg: Reg) -> u16 {
+    match reg {
+        Reg::SP => cpu.read_widereg(Reg::SP),
+        _ => {
+            let low = Reg::from_u8(reg as u8 + 1);
+            (cpu.read_reg(reg) as u16) << 8 | cpu.read_reg(low) as u16
+        },
+    }
+}
+
+impl fmt::Display for WatchExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchExpr::Reg(reg) => write!(f, "{reg:?}"),
+            WatchExpr::WideReg(Reg::SP) => write!(f, "SP"),
+            WatchExpr::WideReg(reg) => {
+                let name = match reg {
+                    Reg::A => "AF",
+                    Reg::B => "BC",
+                    Reg::D => "DE",
+                    _ => "HL",
+                };
+                write!(f, "{name}")
+            },
+            WatchExpr::Pc => write!(f, "PC"),
+            WatchExpr::Const(value) => write!(f, "0x{value:X}"),
+            WatchExpr::Mem(addr) => write!(f, "[{addr}]"),
+            WatchExpr::Add(a, b) => write!(f, "{a}+{b}"),
+            WatchExpr::Sub(a, b) => write!(f, "{a}-{b}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Leaf(Leaf),
+    Number(u16),
+    Plus,
+    Minus,
+    Open,
+    Close,
+}
+
+/// Registro nombrado en la expresión
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Leaf {
+    Reg(Reg),
+    WideReg(Reg),
+    Pc,
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => { chars.next(); },
+            '+' => { chars.next(); tokens.push(Token::Plus); },
+            '-' => { chars.next(); tokens.push(Token::Minus); },
+            '[' => { chars.next(); tokens.push(Token::Open); },
+            ']' => { chars.next(); tokens.push(Token::Close); },
+            c if c.is_ascii_alphanumeric() || c == '$' => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '$') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(word(&text[start..end])?);
+            },
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+fn word(word: &str) -> Option<Token> {
+    let upper = word.to_ascii_uppercase();
+    let leaf = match upper.as_str() {
+        "A" => Leaf::Reg(Reg::A),
+        "F" => Leaf::Reg(Reg::F),
+        "B" => Leaf::Reg(Reg::B),
+        "C" => Leaf::Reg(Reg::C),
+        "D" => Leaf::Reg(Reg::D),
+        "E" => Leaf::Reg(Reg::E),
+        "H" => Leaf::Reg(Reg::H),
+        "L" => Leaf::Reg(Reg::L),
+        "AF" => Leaf::WideReg(Reg::AF),
+        "BC" => Leaf::WideReg(Reg::BC),
+        "DE" => Leaf::WideReg(Reg::DE),
+        "HL" => Leaf::WideReg(Reg::HL),
+        "SP" => Leaf::WideReg(Reg::SP),
+        "PC" => Leaf::Pc,
+        _ => {
+            let value = if let Some(hex) = upper.strip_prefix("0X").or(upper.strip_prefix('$')) {
+                u16::from_str_radix(hex, 16).ok()?
+            } else {
+                upper.parse().ok()?
+            };
+            return Some(Token::Number(value));
+        },
+    };
+    Some(Token::Leaf(leaf))
+}
+
+/// Descenso recursivo: `expr := term (('+' | '-') term)*` y
+/// `term := registro | número | '[' expr ']'`
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn expr(&mut self) -> Option<WatchExpr> {
+        let mut expr = self.term()?;
+        loop {
+            match self.tokens.get(self.pos) {
+                Some(Token::Plus) => {
+                    self.pos += 1;
+                    expr = WatchExpr::Add(Box::new(expr), Box::new(self.term()?));
+                },
+                Some(Token::Minus) => {
+                    self.pos += 1;
+                    expr = WatchExpr::Sub(Box::new(expr), Box::new(self.term()?));
+                },
+                _ => return Some(expr),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Option<WatchExpr> {
+        match self.next()? {
+            Token::Leaf(Leaf::Reg(reg)) => Some(WatchExpr::Reg(reg)),
+            Token::Leaf(Leaf::WideReg(reg)) => Some(WatchExpr::WideReg(reg)),
+            Token::Leaf(Leaf::Pc) => Some(WatchExpr::Pc),
+            Token::Number(value) => Some(WatchExpr::Const(value)),
+            Token::Open => {
+                let addr = self.expr()?;
+                (self.next()? == Token::Close).then(|| WatchExpr::Mem(Box::new(addr)))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_eval() {
+        let mut cpu = Cpu::new();
+        cpu.write_reg(Reg::B, 0x12);
+        cpu.write_reg(Reg::C, 0x34);
+        cpu.write_reg(Reg::H, 0xC0);
+        cpu.write_reg(Reg::L, 0x01);
+        let mut memory = vec![0; 0x10000];
+        memory[0x1236] = 0xAB;
+        memory[0xFF40] = 0x91;
+
+        let eval = |text: &str| WatchExpr::parse(text).unwrap().eval(&cpu, memory.as_slice());
+        assert_eq!(eval("HL"), 0xC001);
+        assert_eq!(eval("bc + 2"), 0x1236);
+        assert_eq!(eval("[0xFF40]"), 0x91);
+        assert_eq!(eval("[BC+$2]"), 0xAB);
+        assert_eq!(eval("B - 0x13"), 0xFFFF);
+
+        assert_eq!(WatchExpr::parse("[BC+2]").unwrap().to_string(), "[BC+0x2]");
+        for invalid in ["", "HL +", "[HL", "XY", "0x10000", "HL HL"] {
+            assert_eq!(WatchExpr::parse(invalid), None, "{invalid}");
+        }
+    }
+}
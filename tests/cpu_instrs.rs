@@ -0,0 +1,79 @@
+//! Tests `cpu_instrs` de blargg según el manifiesto `cpu_instrs.txt`. Las
+//! ROMs no se distribuyen con el emulador, se ejecutan si
+//! `GAMEBOI_CPU_INSTRS` apunta al directorio de `cpu_instrs` (el que
+//! contiene `individual/`) y si no se saltan
+//!
+//! `GAMEBOI_CPU_INSTRS=~/roms/cpu_instrs cargo test --test cpu_instrs`
+
+use std::path::PathBuf;
+
+use gameboi::{run_blargg, BLARGG_MAX_FRAMES};
+
+const MANIFEST: &str = include_str!("cpu_instrs.txt");
+
+/// Una línea del manifiesto, los nombres de las ROMs tienen espacios así
+/// que el resultado esperado es la última columna
+struct Entry<'a> {
+    name: &'a str,
+    pass: bool,
+}
+
+fn manifest() -> Vec<Entry<'static>> {
+    MANIFEST.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let Some((name, expected)) = line.rsplit_once(char::is_whitespace) else {
+                panic!("Línea del manifiesto inválida: {line}");
+            };
+            let pass = match expected {
+                "pass" => true,
+                "fail" => false,
+                _ => panic!("Resultado desconocido en el manifiesto: {expected}"),
+            };
+            Entry { name: name.trim_end(), pass }
+        })
+        .collect()
+}
+
+#[test]
+fn individual() {
+    let entries = manifest();
+    let Some(dir) = std::env::var_os("GAMEBOI_CPU_INSTRS") else {
+        eprintln!("GAMEBOI_CPU_INSTRS no está definida, se saltan {} tests", entries.len());
+        return;
+    };
+    let dir = PathBuf::from(dir).join("individual");
+
+    let mut regressions = Vec::new();
+    for entry in entries {
+        let path = dir.join(entry.name);
+        let rom = std::fs::read(&path)
+            .unwrap_or_else(|err| panic!("No se pudo leer {}: {err}", path.display()));
+        let passed = match run_blargg(&rom, BLARGG_MAX_FRAMES) {
+            Ok(report) if report.passed() => true,
+            Ok(report) => {
+                eprintln!("{} tras {} frames:\n{}", entry.name, report.frames, report.output);
+                false
+            },
+            Err(err) => {
+                eprintln!("{}: {err}", entry.name);
+                false
+            },
+        };
+        match (entry.pass, passed) {
+            (true, false) => regressions.push(entry.name),
+            (false, true) => eprintln!("{} ya pasa, márcalo como `pass` en cpu_instrs.txt",
+                entry.name),
+            _ => {},
+        }
+    }
+    assert!(regressions.is_empty(), "Tests que deberían pasar:\n{}", regressions.join("\n"));
+}
+
+#[test]
+fn manifest_is_valid() {
+    let entries = manifest();
+    assert_eq!(entries.len(), 11);
+    assert!(entries.iter().all(|entry| entry.name.ends_with(".gb")));
+}
@@ -0,0 +1,300 @@
+//! Fuzzing diferencial: programas cortos aleatorios sobre el núcleo y sobre
+//! el intérprete de referencia de `reference.rs`, comparando registros,
+//! ciclos y memoria al terminar. Cada divergencia se minimiza quitando
+//! instrucciones y poniendo a cero registros e inmediatos mientras siga
+//! divergiendo, y se imprime como caso que falla
+//!
+//! Por defecto se prueban `DEFAULT_PROGRAMS` programas con la semilla 1 y
+//! sin los opcodes de `KNOWN_DIVERGENT`, para más
+//!
+//! `GAMEBOI_DIFF_PROGRAMS=10000 cargo test --features differential --test differential -- --nocapture`
+//!
+//! con `GAMEBOI_DIFF_SEED` para cambiar la semilla
+#![cfg(feature = "differential")]
+
+mod reference;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use gameboi::{Cpu, Reg};
+use reference::Machine;
+
+/// Instrucciones como mucho por programa
+const MAX_INSTRS: usize = 16;
+
+/// Programas que se prueban sin `GAMEBOI_DIFF_PROGRAMS`
+const DEFAULT_PROGRAMS: usize = 200;
+
+/// Opcodes sin prefijo que todavía divergen de la referencia, el generador
+/// no los usa. Cuando se arregla uno hay que quitarlo de aquí,
+/// `known_divergent` falla si alguno ya no diverge
+const KNOWN_DIVERGENT: [u8; 98] = [
+    // El núcleo todavía no los decodifica y devuelve `Error::Unimplemented`,
+    // o los decodifica pero todavía no los emula bien
+    0x02, 0x03, 0x04, 0x05, 0x07, 0x08, 0x0A, 0x0C, 0x0D, 0x0E, 0x0F,
+    0x12, 0x13, 0x14, 0x15, 0x17, 0x1A, 0x1C, 0x1D, 0x1E, 0x1F,
+    0x22, 0x23, 0x24, 0x25, 0x27, 0x2A, 0x2C, 0x2D, 0x2E, 0x2F,
+    0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x3A, 0x3C, 0x3D, 0x3E, 0x3F,
+    0x46, 0x4E, 0x56, 0x5E, 0x66, 0x6E, 0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x77, 0x7E,
+    0x86, 0x8E, 0x96, 0x9E, 0xA6, 0xA8, 0xA9, 0xAA, 0xAB, 0xAC, 0xAD, 0xAE, 0xAF,
+    0xB6, 0xB8, 0xB9, 0xBA, 0xBB, 0xBC, 0xBD, 0xBE, 0xBF,
+    0xC1, 0xC5, 0xC6, 0xD1, 0xD5, 0xE0, 0xE1, 0xE2, 0xE5, 0xE8, 0xEA, 0xEE,
+    0xF0, 0xF1, 0xF2, 0xF5, 0xF8, 0xF9, 0xFA, 0xFE,
+];
+
+/// Lo mismo para el segundo byte de las instrucciones con prefijo: las que
+/// operan sobre (HL) no se emulan todavía
+fn known_divergent_cb(cb: u8) -> bool {
+    cb & 0x07 == 0x06
+}
+
+/// Registros en el orden de `Case::registers`
+const REGISTERS: [(&str, Reg); 8] = [("A", Reg::A), ("F", Reg::F), ("B", Reg::B),
+    ("C", Reg::C), ("D", Reg::D), ("E", Reg::E), ("H", Reg::H), ("L", Reg::L)];
+
+/// Programa empezando en 0x0000 con el estado inicial de los registros
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Case {
+    /// A, F, B, C, D, E, H y L
+    registers: [u8; 8],
+    sp: u16,
+
+    /// Bytes de cada instrucción
+    program: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ((name, _), value) in REGISTERS.iter().zip(self.registers) {
+            write!(f, "{name}={value:02X} ")?;
+        }
+        write!(f, "SP={:04X} |", self.sp)?;
+        for instr in &self.program {
+            write!(f, " {}", instr.iter().map(|byte| format!("{byte:02X}"))
+                .collect::<Vec<_>>().join(" "))?;
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+impl Case {
+    /// RAM inicial: el programa sobre un patrón fijo, para que las lecturas
+    /// no den siempre 0
+    fn memory(&self) -> Vec<u8> {
+        let mut mem = (0..0x10000u32).map(|addr| (addr ^ addr >> 8) as u8).collect::<Vec<_>>();
+        let program = self.program.concat();
+        mem[..program.len()].copy_from_slice(&program);
+        mem
+    }
+
+    /// Ejecutar en los dos y describir la primera diferencia
+    fn diverges(&self) -> Option<String> {
+        let mut machine = Machine::new(self.memory());
+        let [a, f, b, c, d, e, h, l] = self.registers;
+        (machine.a, machine.f, machine.b, machine.c) = (a, f, b, c);
+        (machine.d, machine.e, machine.h, machine.l) = (d, e, h, l);
+        machine.sp = self.sp;
+        for _ in &self.program {
+            machine.step().expect("el generador solo usa opcodes cubiertos");
+        }
+
+        let mut mem = self.memory();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut cpu = Cpu::new();
+            for ((_, reg), value) in REGISTERS.iter().zip(self.registers) {
+                cpu.write_reg(*reg, value);
+            }
+            cpu.write_widereg(Reg::SP, self.sp);
+            for _ in &self.program {
+                cpu.execute(mem.as_mut_slice()).ok()?;
+            }
+            Some(cpu)
+        }));
+        let cpu = match result {
+            Ok(Some(cpu)) => cpu,
+            Ok(None) => return Some("opcode sin emular".into()),
+            Err(_) => return Some("panic".into()),
+        };
+
+        let expected = [machine.a, machine.f, machine.b, machine.c, machine.d, machine.e,
+            machine.h, machine.l];
+        for ((name, reg), value) in REGISTERS.iter().zip(expected) {
+            let actual = cpu.read_reg(*reg);
+            if actual != value {
+                return Some(format!("{name} = {actual:02X}, se esperaba {value:02X}"));
+            }
+        }
+        let words = [("SP", cpu.read_widereg(Reg::SP), machine.sp), ("PC", cpu.pc(), machine.pc)];
+        for (name, actual, value) in words {
+            if actual != value {
+                return Some(format!("{name} = {actual:04X}, se esperaba {value:04X}"));
+            }
+        }
+        if cpu.cycles() != machine.cycles {
+            return Some(format!("{} T-cycles, se esperaban {}", cpu.cycles(), machine.cycles));
+        }
+        let addr = (0..mem.len()).find(|&addr| mem[addr] != machine.mem[addr])?;
+        Some(format!("[{addr:04X}] = {:02X}, se esperaba {:02X}", mem[addr], machine.mem[addr]))
+    }
+}
+
+/// Reducir un caso mientras `diverges` siga siendo cierto: quitar
+/// instrucciones y poner a cero los registros y los inmediatos
+fn minimize(case: &Case, diverges: impl Fn(&Case) -> bool) -> Case {
+    let mut case = case.clone();
+    loop {
+        let mut candidates = Vec::new();
+        for index in 0..case.program.len() {
+            let mut smaller = case.clone();
+            smaller.program.remove(index);
+            candidates.push(smaller);
+        }
+        for index in 0..case.registers.len() {
+            let mut smaller = case.clone();
+            smaller.registers[index] = 0;
+            candidates.push(smaller);
+        }
+        let mut smaller = case.clone();
+        smaller.sp = 0;
+        candidates.push(smaller);
+        for (index, instr) in case.program.iter().enumerate() {
+            // El byte del prefijo CB es parte del opcode
+            let first = if instr[0] == 0xCB { 2 } else { 1 };
+            for byte in first..instr.len() {
+                let mut smaller = case.clone();
+                smaller.program[index][byte] = 0;
+                candidates.push(smaller);
+            }
+        }
+
+        match candidates.into_iter().find(|smaller| *smaller != case && diverges(smaller)) {
+            Some(smaller) => case = smaller,
+            None => return case,
+        }
+    }
+}
+
+/// xorshift64*, el de la crate no es público
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+}
+
+/// Bytes de inmediatos tras el opcode
+fn operands(opcode: u8) -> usize {
+    match opcode {
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 | 0xEA | 0xFA => 2,
+        0xCB | 0xE0 | 0xF0 | 0xE8 | 0xF8 => 1,
+        _ if opcode & 0xC7 == 0x06 || opcode & 0xC7 == 0xC6 => 1,
+        _ => 0,
+    }
+}
+
+/// Si `cb_filter` es cierto para el segundo byte de una instrucción con
+/// prefijo se vuelve a sortear
+fn random_case(rng: &mut Rng, opcodes: &[u8], cb_filter: fn(u8) -> bool) -> Case {
+    let len = 1 + rng.next() as usize % MAX_INSTRS;
+    let program = (0..len)
+        .map(|_| {
+            let opcode = opcodes[rng.next() as usize % opcodes.len()];
+            let mut instr = std::iter::once(opcode)
+                .chain((0..operands(opcode)).map(|_| rng.byte()))
+                .collect::<Vec<_>>();
+            while opcode == 0xCB && cb_filter(instr[1]) {
+                instr[1] = rng.byte();
+            }
+            instr
+        })
+        .collect();
+    let mut registers = [0; 8].map(|_| rng.byte());
+    registers[1] &= 0xF0;
+    Case { registers, sp: rng.next() as u16, program }
+}
+
+#[test]
+fn random_programs() {
+    let programs = std::env::var("GAMEBOI_DIFF_PROGRAMS").ok().map_or(DEFAULT_PROGRAMS,
+        |programs| programs.parse::<usize>().expect("GAMEBOI_DIFF_PROGRAMS no es un número"));
+    let seed = std::env::var("GAMEBOI_DIFF_SEED").ok()
+        .map_or(1, |seed| seed.parse::<u64>().expect("GAMEBOI_DIFF_SEED no es un número"));
+
+    let opcodes = (0..=0xFF)
+        .filter(|&opcode| Machine::supports(opcode) && !KNOWN_DIVERGENT.contains(&opcode))
+        .collect::<Vec<u8>>();
+    let mut rng = Rng(seed.max(1));
+
+    // Los panics del núcleo se esperan, no ensuciar la salida con ellos
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut failures = BTreeMap::new();
+    for _ in 0..programs {
+        let case = random_case(&mut rng, &opcodes, known_divergent_cb);
+        if case.diverges().is_some() {
+            // Un caso por programa minimizado, los registros cambian poco
+            let case = minimize(&case, |case| case.diverges().is_some());
+            failures.entry(case.program.clone()).or_insert_with(|| {
+                let reason = case.diverges().unwrap();
+                (case, reason)
+            });
+        }
+    }
+    panic::set_hook(hook);
+
+    for (case, reason) in failures.values() {
+        println!("{case} {reason}");
+    }
+    assert!(failures.is_empty(), "{} casos minimizados divergen con la semilla {seed}",
+        failures.len());
+}
+
+#[test]
+fn minimize_divergence() {
+    // Divergencia de mentira: el programa tiene INC A
+    let mut rng = Rng(7);
+    let opcodes = [0x3C, 0x80, 0x06, 0xCB, 0x01];
+    let case = (0..).map(|_| random_case(&mut rng, &opcodes, |_| false))
+        .find(|case| case.program.len() > 4 && case.program.iter().any(|instr| instr[0] == 0x3C))
+        .unwrap();
+    let minimized = minimize(&case, |case| case.program.iter().any(|instr| instr[0] == 0x3C));
+    assert_eq!(minimized, Case { registers: [0; 8], sp: 0, program: vec![vec![0x3C]] });
+    assert_eq!(minimized.to_string(),
+        "A=00 F=00 B=00 C=00 D=00 E=00 H=00 L=00 SP=0000 | 3C;");
+
+    assert_eq!(Case { registers: [0; 8], sp: 0, program: vec![vec![0x00]] }.diverges(), None);
+}
+
+#[test]
+fn known_divergent() {
+    // Cada opcode de la lista tiene que divergir en alguno de los primeros
+    // programas de una instrucción, si no ya está arreglado
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut rng = Rng(1);
+    let cb = (0..=0xFF).filter(|&cb| known_divergent_cb(cb)).map(|cb| vec![0xCB, cb]);
+    let fixed = KNOWN_DIVERGENT.iter().map(|&opcode| vec![opcode]).chain(cb)
+        .filter(|prefix| {
+            !(0..500).any(|_| {
+                let mut case = random_case(&mut rng, &prefix[..1], |_| false);
+                case.program.truncate(1);
+                case.program[0][..prefix.len()].copy_from_slice(prefix);
+                case.diverges().is_some()
+            })
+        })
+        .collect::<Vec<_>>();
+    panic::set_hook(hook);
+
+    assert!(fixed.is_empty(), "ya no divergen, hay que quitarlos de la lista: {fixed:02X?}");
+}
@@ -0,0 +1,421 @@
+//! Intérprete SM83 de referencia, escrito de la forma más directa posible a
+//! partir de la tabla de opcodes de Pan Docs y sin compartir nada con el
+//! núcleo. Solo cubre lo que generan los programas aleatorios: todo salvo
+//! saltos, llamadas, HALT, STOP, DI/EI y las interrupciones
+
+const Z: u8 = 0x80;
+const N: u8 = 0x40;
+const H: u8 = 0x20;
+const C: u8 = 0x10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+
+    /// T-cycles ejecutados
+    pub cycles: u64,
+
+    /// 64 KiB de RAM plana
+    pub mem: Vec<u8>,
+}
+
+impl Machine {
+    pub fn new(mem: Vec<u8>) -> Self {
+        assert_eq!(mem.len(), 0x10000);
+        Self { a: 0, f: 0, b: 0, c: 0, d: 0, e: 0, h: 0, l: 0, sp: 0, pc: 0, cycles: 0, mem }
+    }
+
+    /// El opcode está cubierto por el intérprete, con el prefijo CB lo
+    /// están todos
+    pub fn supports(opcode: u8) -> bool {
+        let mut mem = vec![0; 0x10000];
+        mem[0] = opcode;
+        Machine::new(mem).step().is_some()
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        self.mem[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.mem[addr as usize] = value;
+    }
+
+    fn fetch(&mut self) -> u8 {
+        let value = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16(&mut self) -> u16 {
+        let low = self.fetch() as u16;
+        (self.fetch() as u16) << 8 | low
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.f & flag != 0
+    }
+
+    fn set_flags(&mut self, z: bool, n: bool, h: bool, c: bool) {
+        self.f = (z as u8 * Z) | (n as u8 * N) | (h as u8 * H) | (c as u8 * C);
+    }
+
+    fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.h, self.l])
+    }
+
+    fn set_hl(&mut self, value: u16) {
+        [self.h, self.l] = value.to_be_bytes();
+    }
+
+    /// Pares `rp` de la tabla: BC, DE, HL, SP
+    fn rp(&self, index: u8) -> u16 {
+        match index {
+            0 => u16::from_be_bytes([self.b, self.c]),
+            1 => u16::from_be_bytes([self.d, self.e]),
+            2 => self.hl(),
+            _ => self.sp,
+        }
+    }
+
+    fn set_rp(&mut self, index: u8, value: u16) {
+        match index {
+            0 => [self.b, self.c] = value.to_be_bytes(),
+            1 => [self.d, self.e] = value.to_be_bytes(),
+            2 => self.set_hl(value),
+            _ => self.sp = value,
+        }
+    }
+
+    /// Registros `r` de la tabla: B, C, D, E, H, L, (HL), A
+    fn r(&self, index: u8) -> u8 {
+        match index {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => self.h,
+            5 => self.l,
+            6 => self.read(self.hl()),
+            _ => self.a,
+        }
+    }
+
+    fn set_r(&mut self, index: u8, value: u8) {
+        match index {
+            0 => self.b = value,
+            1 => self.c = value,
+            2 => self.d = value,
+            3 => self.e = value,
+            4 => self.h = value,
+            5 => self.l = value,
+            6 => self.write(self.hl(), value),
+            _ => self.a = value,
+        }
+    }
+
+    /// ADD, ADC, SUB, SBC, AND, XOR, OR y CP sobre A
+    fn alu(&mut self, op: u8, value: u8) {
+        let a = self.a as u16;
+        let v = value as u16;
+        let carry = self.flag(C) as u16;
+        match op {
+            0 | 1 => {
+                let carry = if op == 1 { carry } else { 0 };
+                let res = a + v + carry;
+                let half = (a & 0xF) + (v & 0xF) + carry > 0xF;
+                self.a = res as u8;
+                self.set_flags(res as u8 == 0, false, half, res > 0xFF);
+            },
+            2 | 3 | 7 => {
+                let carry = if op == 3 { carry } else { 0 };
+                let res = a.wrapping_sub(v).wrapping_sub(carry);
+                let half = (a & 0xF) < (v & 0xF) + carry;
+                self.set_flags(res as u8 == 0, true, half, a < v + carry);
+                if op != 7 {
+                    self.a = res as u8;
+                }
+            },
+            4 => {
+                self.a &= value;
+                self.set_flags(self.a == 0, false, true, false);
+            },
+            5 => {
+                self.a ^= value;
+                self.set_flags(self.a == 0, false, false, false);
+            },
+            _ => {
+                self.a |= value;
+                self.set_flags(self.a == 0, false, false, false);
+            },
+        }
+    }
+
+    /// Rotaciones y desplazamientos del prefijo CB, devuelve el resultado y
+    /// el bit que sale hacia C
+    fn rotate(&self, op: u8, value: u8) -> (u8, bool) {
+        let carry = self.flag(C) as u8;
+        match op {
+            0 => (value.rotate_left(1), value & 0x80 != 0),
+            1 => (value.rotate_right(1), value & 1 != 0),
+            2 => (value << 1 | carry, value & 0x80 != 0),
+            3 => (value >> 1 | carry << 7, value & 1 != 0),
+            4 => (value << 1, value & 0x80 != 0),
+            5 => (value >> 1 | (value & 0x80), value & 1 != 0),
+            6 => (value.rotate_left(4), false),
+            _ => (value >> 1, value & 1 != 0),
+        }
+    }
+
+    /// SP más un desplazamiento con signo, los flags salen del byte bajo
+    fn sp_offset(&mut self) -> u16 {
+        let offset = self.fetch();
+        let sp = self.sp;
+        let half = (sp & 0xF) + (offset as u16 & 0xF) > 0xF;
+        let carry = (sp & 0xFF) + offset as u16 > 0xFF;
+        self.set_flags(false, false, half, carry);
+        sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn push(&mut self, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, high);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, low);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let low = self.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = self.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_be_bytes([high, low])
+    }
+
+    /// Ejecutar una instrucción, `None` si no está cubierta
+    pub fn step(&mut self) -> Option<()> {
+        let opcode = self.fetch();
+        let (x, y, z) = (opcode >> 6, opcode >> 3 & 7, opcode & 7);
+        let (p, q) = (y >> 1, y & 1);
+        let cycles = match (x, z) {
+            (0, 0) if y == 0 => 4,
+            (0, 0) if y == 1 => {
+                let addr = self.fetch16();
+                let [high, low] = self.sp.to_be_bytes();
+                self.write(addr, low);
+                self.write(addr.wrapping_add(1), high);
+                20
+            },
+            (0, 1) if q == 0 => {
+                let value = self.fetch16();
+                self.set_rp(p, value);
+                12
+            },
+            (0, 1) => {
+                let (hl, value) = (self.hl() as u32, self.rp(p) as u32);
+                let res = hl + value;
+                let half = (hl & 0xFFF) + (value & 0xFFF) > 0xFFF;
+                self.set_flags(self.flag(Z), false, half, res > 0xFFFF);
+                self.set_hl(res as u16);
+                8
+            },
+            (0, 2) => {
+                let addr = match p {
+                    0 | 1 => self.rp(p),
+                    _ => self.hl(),
+                };
+                match p {
+                    2 => self.set_hl(addr.wrapping_add(1)),
+                    3 => self.set_hl(addr.wrapping_sub(1)),
+                    _ => (),
+                }
+                if q == 0 {
+                    self.write(addr, self.a);
+                } else {
+                    self.a = self.read(addr);
+                }
+                8
+            },
+            (0, 3) => {
+                let value = if q == 0 {
+                    self.rp(p).wrapping_add(1)
+                } else {
+                    self.rp(p).wrapping_sub(1)
+                };
+                self.set_rp(p, value);
+                8
+            },
+            (0, 4) | (0, 5) => {
+                let value = self.r(y);
+                let (res, half) = if z == 4 {
+                    (value.wrapping_add(1), value & 0xF == 0xF)
+                } else {
+                    (value.wrapping_sub(1), value & 0xF == 0)
+                };
+                self.set_r(y, res);
+                self.set_flags(res == 0, z == 5, half, self.flag(C));
+                if y == 6 { 12 } else { 4 }
+            },
+            (0, 6) => {
+                let value = self.fetch();
+                self.set_r(y, value);
+                if y == 6 { 12 } else { 8 }
+            },
+            (0, 7) => {
+                match y {
+                    0..=3 => {
+                        let (res, carry) = self.rotate(y, self.a);
+                        self.a = res;
+                        self.set_flags(false, false, false, carry);
+                    },
+                    4 => {
+                        let (mut adjust, mut carry) = (0, self.flag(C));
+                        if self.flag(H) || (!self.flag(N) && self.a & 0xF > 9) {
+                            adjust |= 0x06;
+                        }
+                        if carry || (!self.flag(N) && self.a > 0x99) {
+                            adjust |= 0x60;
+                            carry = true;
+                        }
+                        self.a = if self.flag(N) {
+                            self.a.wrapping_sub(adjust)
+                        } else {
+                            self.a.wrapping_add(adjust)
+                        };
+                        self.set_flags(self.a == 0, self.flag(N), false, carry);
+                    },
+                    5 => {
+                        self.a = !self.a;
+                        self.f |= N | H;
+                    },
+                    6 => self.set_flags(self.flag(Z), false, false, true),
+                    _ => self.set_flags(self.flag(Z), false, false, !self.flag(C)),
+                }
+                4
+            },
+            (1, _) if opcode == 0x76 => return None,
+            (1, _) => {
+                self.set_r(y, self.r(z));
+                if y == 6 || z == 6 { 8 } else { 4 }
+            },
+            (2, _) => {
+                self.alu(y, self.r(z));
+                if z == 6 { 8 } else { 4 }
+            },
+            (3, 0) if y >= 4 => match y {
+                4 => {
+                    let addr = 0xFF00 | self.fetch() as u16;
+                    self.write(addr, self.a);
+                    12
+                },
+                5 => {
+                    self.sp = self.sp_offset();
+                    16
+                },
+                6 => {
+                    let addr = 0xFF00 | self.fetch() as u16;
+                    self.a = self.read(addr);
+                    12
+                },
+                _ => {
+                    let value = self.sp_offset();
+                    self.set_hl(value);
+                    12
+                },
+            },
+            (3, 1) if q == 0 => {
+                let value = self.pop();
+                match p {
+                    3 => {
+                        let [a, f] = value.to_be_bytes();
+                        (self.a, self.f) = (a, f & 0xF0);
+                    },
+                    _ => self.set_rp(p, value),
+                }
+                12
+            },
+            (3, 1) if p == 3 => {
+                self.sp = self.hl();
+                8
+            },
+            (3, 2) if y >= 4 => {
+                let addr = match y {
+                    4 | 6 => 0xFF00 | self.c as u16,
+                    _ => self.fetch16(),
+                };
+                if y < 6 {
+                    self.write(addr, self.a);
+                } else {
+                    self.a = self.read(addr);
+                }
+                if y % 2 == 0 { 8 } else { 16 }
+            },
+            (3, 3) if y == 1 => {
+                let cb = self.fetch();
+                let (x, y, z) = (cb >> 6, cb >> 3 & 7, cb & 7);
+                let value = self.r(z);
+                match x {
+                    0 => {
+                        let (res, carry) = self.rotate(y, value);
+                        self.set_r(z, res);
+                        self.set_flags(res == 0, false, false, carry);
+                    },
+                    1 => self.set_flags(value & (1 << y) == 0, false, true, self.flag(C)),
+                    2 => self.set_r(z, value & !(1 << y)),
+                    _ => self.set_r(z, value | (1 << y)),
+                }
+                match (x, z) {
+                    (1, 6) => 12,
+                    (_, 6) => 16,
+                    _ => 8,
+                }
+            },
+            (3, 5) if q == 0 => {
+                let value = match p {
+                    3 => u16::from_be_bytes([self.a, self.f]),
+                    _ => self.rp(p),
+                };
+                self.push(value);
+                16
+            },
+            (3, 6) => {
+                let value = self.fetch();
+                self.alu(y, value);
+                8
+            },
+            _ => return None,
+        };
+        self.cycles += cycles;
+        Some(())
+    }
+}
+
+#[test]
+fn known_results() {
+    // ADD A, $3A con A = $C6: 0, Z, H y C; DAA tras sumar 15 + 27 en BCD
+    let mut mem = vec![0; 0x10000];
+    mem[..8].copy_from_slice(&[0xC6, 0x3A, 0x3E, 0x15, 0xC6, 0x27, 0x27, 0xCB]);
+    mem[8] = 0x37;
+    let mut machine = Machine::new(mem);
+    machine.a = 0xC6;
+    machine.step().unwrap();
+    assert_eq!((machine.a, machine.f), (0x00, Z | H | C));
+    for _ in 0..3 {
+        machine.step().unwrap();
+    }
+    assert_eq!(machine.a, 0x42);
+
+    // SWAP A
+    machine.step().unwrap();
+    assert_eq!((machine.a, machine.cycles), (0x24, 8 + 8 + 8 + 4 + 8));
+}
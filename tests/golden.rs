@@ -0,0 +1,57 @@
+//! Compara la ejecución de una ROM pequeña con la traza grabada en
+//! `tests/golden`, cualquier cambio de comportamiento del núcleo la rompe.
+//! Si el cambio es intencionado se vuelve a grabar con
+//!
+//! `GAMEBOI_BLESS=1 cargo test --test golden`
+
+use std::path::Path;
+
+use gameboi::{GameBoy, GoldenTrace};
+
+/// Instrucciones que se ejecutan, unas cuantas vueltas del bucle
+const STEPS: usize = 400;
+
+/// Un bucle con cargas, aritmética de 8 bits y un salto, solo con
+/// instrucciones que la CPU ya emula
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    let program = [
+        0x31, 0xFE, 0xFF,   // 0100: LD SP, $FFFE
+        0x06, 0x05,         // 0103: LD B, 5
+        0x16, 0x03,         // 0105: LD D, 3
+        0x78,               // 0107: LD A, B
+        0x82,               // 0108: ADD A, D
+        0x88,               // 0109: ADC A, B
+        0x5F,               // 010A: LD E, A
+        0x78,               // 010B: LD A, B
+        0xD6, 0x01,         // 010C: SUB 1
+        0x47,               // 010E: LD B, A
+        0x18, 0xF6,         // 010F: JR $0107
+    ];
+    rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+fn check(name: &str, rom: &[u8]) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    let boot = || {
+        let mut gb = GameBoy::new();
+        gb.load_rom(rom).unwrap();
+        gb
+    };
+
+    if std::env::var_os("GAMEBOI_BLESS").is_some() {
+        GoldenTrace::record(&mut boot(), STEPS).save(&path).unwrap();
+        return;
+    }
+    let trace = GoldenTrace::load(&path).unwrap();
+    assert_eq!(trace.len(), STEPS + 1, "traza incompleta, vuelve a grabarla");
+    if let Some(mismatch) = trace.replay(&mut boot()) {
+        panic!("{mismatch}");
+    }
+}
+
+#[test]
+fn core_loop() {
+    check("core_loop.trace", &rom());
+}
@@ -0,0 +1,77 @@
+//! Tests de aceptación de mooneye-gb según el manifiesto `mooneye.txt`. Las
+//! ROMs no se distribuyen con el emulador, se ejecutan si `GAMEBOI_MOONEYE`
+//! apunta al directorio con las ROMs compiladas y si no se saltan
+//!
+//! `GAMEBOI_MOONEYE=~/roms/mooneye-test-suite cargo test --test mooneye`
+
+use std::path::PathBuf;
+
+use gameboi::{run_mooneye, Model, MOONEYE_MAX_FRAMES};
+
+const MANIFEST: &str = include_str!("mooneye.txt");
+
+/// Una línea del manifiesto
+struct Entry<'a> {
+    path: &'a str,
+    model: Model,
+    pass: bool,
+}
+
+fn manifest() -> Vec<Entry<'static>> {
+    MANIFEST.lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let [path, model, expected] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                panic!("Línea del manifiesto inválida: {line}");
+            };
+            let model = match model {
+                "dmg" => Model::Dmg,
+                "mgb" => Model::Mgb,
+                "sgb" => Model::Sgb,
+                "cgb" => Model::Cgb,
+                "agb" => Model::Agb,
+                _ => panic!("Modelo desconocido en el manifiesto: {model}"),
+            };
+            let pass = match expected {
+                "pass" => true,
+                "fail" => false,
+                _ => panic!("Resultado desconocido en el manifiesto: {expected}"),
+            };
+            Entry { path, model, pass }
+        })
+        .collect()
+}
+
+#[test]
+fn acceptance() {
+    let entries = manifest();
+    let Some(dir) = std::env::var_os("GAMEBOI_MOONEYE") else {
+        eprintln!("GAMEBOI_MOONEYE no está definida, se saltan {} tests", entries.len());
+        return;
+    };
+    let dir = PathBuf::from(dir);
+
+    let mut regressions = Vec::new();
+    for entry in entries {
+        let path = dir.join(entry.path);
+        let Ok(rom) = std::fs::read(&path) else {
+            eprintln!("No se encuentra {}, se salta", path.display());
+            continue;
+        };
+        let passed = run_mooneye(&rom, entry.model, MOONEYE_MAX_FRAMES)
+            .is_ok_and(|report| report.passed());
+        match (entry.pass, passed) {
+            (true, false) => regressions.push(format!("{} ({:?})", entry.path, entry.model)),
+            (false, true) => eprintln!("{} ({:?}) ya pasa, márcalo como `pass` en mooneye.txt",
+                entry.path, entry.model),
+            _ => {},
+        }
+    }
+    assert!(regressions.is_empty(), "Tests que deberían pasar:\n{}", regressions.join("\n"));
+}
+
+#[test]
+fn manifest_is_valid() {
+    assert!(!manifest().is_empty());
+}
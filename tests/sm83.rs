@@ -0,0 +1,43 @@
+//! Tests JSON de una sola instrucción (GameboyCPUTests o SingleStepTests).
+//! Se ejecutan si `GAMEBOI_SM83_TESTS` apunta al directorio con un `.json`
+//! por opcode, con `GAMEBOI_SM83_PREFETCHED=1` para el formato de
+//! SingleStepTests. Imprime la cobertura por opcode y los primeros fallos
+//!
+//! `GAMEBOI_SM83_TESTS=~/sm83/v1 cargo test --features json --test sm83 -- --nocapture`
+#![cfg(feature = "json")]
+
+use std::path::PathBuf;
+
+use gameboi::run_sm83_json;
+
+#[test]
+fn sm83_json() {
+    let Some(dir) = std::env::var_os("GAMEBOI_SM83_TESTS") else {
+        eprintln!("GAMEBOI_SM83_TESTS no está definida, se salta");
+        return;
+    };
+    let prefetched = std::env::var_os("GAMEBOI_SM83_PREFETCHED").is_some_and(|value| value == "1");
+
+    let mut files = std::fs::read_dir(PathBuf::from(dir))
+        .expect("No se pudo leer el directorio de los tests")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let (mut passed, mut complete) = (0, 0);
+    for path in &files {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let json = std::fs::read_to_string(path).unwrap();
+        let summary = run_sm83_json(&json, prefetched)
+            .unwrap_or_else(|err| panic!("{}: JSON inválido: {err}", path.display()));
+        passed += summary.passed;
+        if summary.failures.is_empty() {
+            complete += 1;
+        } else {
+            let (case, reason) = &summary.failures[0];
+            println!("{name}: {}/{} ({case}: {reason})", summary.passed, summary.total());
+        }
+    }
+    println!("{complete}/{} opcodes completos, {passed} casos correctos", files.len());
+}